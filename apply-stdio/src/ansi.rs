@@ -0,0 +1,61 @@
+const ESC: char = '\u{1b}';
+const BEL: char = '\u{07}';
+
+/// Remove ANSI escape sequences (SGR colors, cursor movement, OSC titles)
+/// from a string, leaving only the printable text.
+pub fn strip_ansi(input: &str) -> String {
+    let mut output = String::with_capacity(input.len());
+    let mut chars = input.chars().peekable();
+
+    while let Some(ch) = chars.next() {
+        if ch != ESC {
+            output.push(ch);
+            continue;
+        }
+
+        match chars.next() {
+            // CSI: ESC [ params... final byte in 0x40..=0x7E
+            Some('[') => {
+                for next in chars.by_ref() {
+                    if ('\u{40}'..='\u{7e}').contains(&next) {
+                        break;
+                    }
+                }
+            }
+            // OSC: ESC ] ... terminated by BEL or ESC \
+            Some(']') => {
+                while let Some(next) = chars.next() {
+                    if next == BEL {
+                        break;
+                    }
+                    if next == ESC && chars.peek() == Some(&'\\') {
+                        chars.next();
+                        break;
+                    }
+                }
+            }
+            // Other two-byte escapes (e.g. ESC c, ESC =)
+            Some(_) | None => {}
+        }
+    }
+
+    output
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_strip_ansi_sgr() {
+        let input = "\u{1b}[1;32mok\u{1b}[0m: \u{1b}[31merror\u{1b}[m done";
+        assert_eq!(strip_ansi(input), "ok: error done");
+    }
+
+    #[test]
+    fn test_strip_ansi_osc_and_plain() {
+        let input = "\u{1b}]0;title\u{07}plain text";
+        assert_eq!(strip_ansi(input), "plain text");
+        assert_eq!(strip_ansi("no escapes"), "no escapes");
+    }
+}
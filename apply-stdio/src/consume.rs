@@ -0,0 +1,102 @@
+use thiserror::Error;
+use tokio::io::{AsyncBufReadExt, AsyncRead, BufReader};
+use tracing::trace;
+
+use crate::AppUpdate;
+
+/// Reading the JSON-lines update stream from `lusid-apply` failed.
+#[derive(Debug, Error)]
+pub enum UpdateStreamError {
+    #[error("failed to read stdout from apply")]
+    Read(#[source] std::io::Error),
+
+    #[error("failed to parse apply stdout as json: {0}")]
+    Parse(#[source] serde_json::Error),
+}
+
+/// Somewhere for apply updates to go as they are read: a printer, a view.
+pub trait UpdateSink {
+    type Error: From<UpdateStreamError>;
+
+    fn update(&mut self, update: AppUpdate) -> Result<(), Self::Error>;
+}
+
+impl<S: UpdateSink + ?Sized> UpdateSink for &mut S {
+    type Error = S::Error;
+
+    fn update(&mut self, update: AppUpdate) -> Result<(), Self::Error> {
+        (**self).update(update)
+    }
+}
+
+/// Read one [`AppUpdate`] per line from `reader` into `sink`, until the
+/// reader ends. Blank lines are skipped.
+pub async fn consume_updates<R, S>(reader: R, mut sink: S) -> Result<(), S::Error>
+where
+    R: AsyncRead + Unpin,
+    S: UpdateSink,
+{
+    let mut lines = BufReader::new(reader).lines();
+    while let Some(line) = lines.next_line().await.map_err(UpdateStreamError::Read)? {
+        if line.trim().is_empty() {
+            continue;
+        }
+        let update: AppUpdate = serde_json::from_str(&line).map_err(UpdateStreamError::Parse)?;
+        trace!(?update, "received apply update");
+        sink.update(update)?;
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[derive(Default)]
+    struct Recording(Vec<AppUpdate>);
+
+    impl UpdateSink for Recording {
+        type Error = UpdateStreamError;
+
+        fn update(&mut self, update: AppUpdate) -> Result<(), Self::Error> {
+            self.0.push(update);
+            Ok(())
+        }
+    }
+
+    #[tokio::test]
+    async fn forwards_each_line_to_the_sink() {
+        let lines = [
+            serde_json::to_string(&AppUpdate::ResourcesStart).unwrap(),
+            String::new(),
+            serde_json::to_string(&AppUpdate::ResourcesNodeStart { index: 1 }).unwrap(),
+            serde_json::to_string(&AppUpdate::ResourcesComplete).unwrap(),
+        ];
+        let script = lines.join("\n");
+
+        let mut sink = Recording::default();
+        consume_updates(script.as_bytes(), &mut sink).await.unwrap();
+
+        assert!(matches!(
+            sink.0.as_slice(),
+            [
+                AppUpdate::ResourcesStart,
+                AppUpdate::ResourcesNodeStart { index: 1 },
+                AppUpdate::ResourcesComplete,
+            ]
+        ));
+    }
+
+    #[tokio::test]
+    async fn stops_at_a_malformed_line() {
+        let script = "\"ResourcesStart\"\nnot json\n\"ResourcesComplete\"\n";
+
+        let mut sink = Recording::default();
+        let error = consume_updates(script.as_bytes(), &mut sink)
+            .await
+            .unwrap_err();
+
+        assert!(matches!(error, UpdateStreamError::Parse(_)));
+        assert_eq!(sink.0.len(), 1);
+    }
+}
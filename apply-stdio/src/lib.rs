@@ -17,8 +17,18 @@
 //! AppView:
 //! - AppView is an enum with a variant per phase. Each subsequent phase
 //!   accumulates data. The state machine is driven by AppUpdate inputs.
-//! - AppView::try_update returns Result for correct error handling.
-//!   AppView::update keeps backward compatibility by ignoring errors.
+//! - AppView::update returns Result for correct error handling. An update
+//!   that doesn't fit the current phase (a dropped or reordered line) hands
+//!   the view back unchanged in AppViewError::UnexpectedUpdate, so consumers
+//!   can log it and carry on.
+
+mod ansi;
+mod consume;
+mod result;
+
+pub use crate::ansi::strip_ansi;
+pub use crate::consume::*;
+pub use crate::result::*;
 
 use lusid_view::{Fragment, Render, View, ViewTree};
 use serde::{Deserialize, Serialize};
@@ -66,6 +76,8 @@ pub enum FlatViewTreeError {
     NodeMissing(usize),
     #[error("expected leaf at index {0}")]
     NotALeaf(usize),
+    #[error("expected branch at index {0}")]
+    NotABranch(usize),
 }
 
 impl FlatViewTree {
@@ -118,6 +130,25 @@ impl FlatViewTree {
         replace_view_tree_nodes(&mut self.nodes, Some(view_tree), root_index);
     }
 
+    /// Append a completed subtree as the last child of the branch at `parent_index`.
+    ///
+    /// Appending children one by one yields the same indices as inserting the
+    /// whole branch at once.
+    pub fn append_child_completed(
+        &mut self,
+        parent_index: usize,
+        view_tree: ViewTree,
+    ) -> Result<usize, FlatViewTreeError> {
+        if !matches!(self.get(parent_index)?, FlatViewTreeNode::Branch { .. }) {
+            return Err(FlatViewTreeError::NotABranch(parent_index));
+        }
+        let child_index = append_view_tree_nodes(&mut self.nodes, view_tree);
+        if let Some(FlatViewTreeNode::Branch { children, .. }) = self.nodes[parent_index].as_mut() {
+            children.push(child_index);
+        }
+        Ok(child_index)
+    }
+
     /// Mark a leaf as started.
     pub fn set_leaf_started(&mut self, index: usize) -> Result<(), FlatViewTreeError> {
         self.set_leaf_view(index, ViewNode::Started)
@@ -265,10 +296,17 @@ pub enum AppUpdate {
     },
 
     ResourcesStart,
+    ResourcesNodeStart {
+        index: usize,
+    },
     ResourcesNode {
         index: usize,
         tree: ViewTree,
     },
+    ResourcesNodeChild {
+        index: usize,
+        tree: ViewTree,
+    },
     ResourcesComplete,
 
     ResourceStatesStart,
@@ -279,6 +317,10 @@ pub enum AppUpdate {
         index: usize,
         node: View,
     },
+    ResourceStatesProgress {
+        done: usize,
+        total: usize,
+    },
     ResourceStatesComplete,
 
     ResourceChangesStart,
@@ -313,17 +355,131 @@ pub enum AppUpdate {
     },
     OperationApplyComplete {
         index: (usize, usize),
+        /// Wall-clock time the operation took to apply.
+        #[serde(default)]
+        duration_ms: u64,
+    },
+    OperationApplyFailed {
+        index: (usize, usize),
+        error: String,
     },
     OperationsApplyComplete,
+
+    Summary {
+        changed: usize,
+        unchanged: usize,
+        failed: usize,
+        duration_ms: u64,
+    },
+}
+
+/// How many of a known number of items have finished.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Progress {
+    pub done: usize,
+    pub total: usize,
+}
+
+impl Progress {
+    /// Advance to `next`, never moving backwards.
+    fn advance(self, next: Progress) -> Progress {
+        Progress {
+            done: self.done.max(next.done).min(next.total),
+            total: next.total,
+        }
+    }
+}
+
+impl std::fmt::Display for Progress {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}/{}", self.done, self.total)
+    }
+}
+
+/// A final summary of an apply run.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub struct ApplySummary {
+    pub changed: usize,
+    pub unchanged: usize,
+    pub failed: usize,
+    pub duration_ms: u64,
+}
+
+impl std::fmt::Display for ApplySummary {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let Self {
+            changed,
+            unchanged,
+            failed,
+            duration_ms,
+        } = self;
+        let seconds = *duration_ms as f64 / 1000.0;
+        write!(
+            f,
+            "{changed} changed, {unchanged} unchanged, {failed} failed in {seconds:.1}s"
+        )
+    }
+}
+
+/// The apply status of a single operation.
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub enum OperationStatus {
+    #[default]
+    Pending,
+    Running,
+    Succeeded,
+    Failed(String),
 }
 
+impl OperationStatus {
+    pub fn is_finished(&self) -> bool {
+        matches!(
+            self,
+            OperationStatus::Succeeded | OperationStatus::Failed(_)
+        )
+    }
+
+    pub fn is_failed(&self) -> bool {
+        matches!(self, OperationStatus::Failed(_))
+    }
+}
+
+impl Render for OperationStatus {
+    fn render(&self) -> View {
+        match self {
+            OperationStatus::Pending => View::Span("🟩".into()),
+            OperationStatus::Running => View::Span("⌛".into()),
+            OperationStatus::Succeeded => View::Span("✅".into()),
+            OperationStatus::Failed(error) => View::Fragment(Fragment::new(vec![
+                View::Span("❌ ".into()),
+                View::Span(error.as_str().into()),
+            ])),
+        }
+    }
+}
+
+/// Most bytes of each of an operation's stdout and stderr that
+/// [`AppView::update`] keeps; older output is dropped.
+pub const DEFAULT_OUTPUT_LIMIT: usize = 64 * 1024;
+
 /// A single operation's live view.
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct OperationView {
     pub label: View,
+    /// Newest stdout, after a truncation notice if older output was dropped.
     pub stdout: String,
+    /// Newest stderr, after a truncation notice if older output was dropped.
     pub stderr: String,
-    pub is_complete: bool,
+    pub status: OperationStatus,
+    /// Bytes of stdout dropped to stay under the output limit.
+    #[serde(default)]
+    pub stdout_dropped: usize,
+    /// Bytes of stderr dropped to stay under the output limit.
+    #[serde(default)]
+    pub stderr_dropped: usize,
+    /// Wall-clock time the operation took to apply, once it has succeeded.
+    #[serde(default)]
+    pub duration_ms: Option<u64>,
 }
 
 impl OperationView {
@@ -332,9 +488,51 @@ impl OperationView {
             label,
             stdout: String::new(),
             stderr: String::new(),
-            is_complete: false,
+            status: OperationStatus::Pending,
+            stdout_dropped: 0,
+            stderr_dropped: 0,
+            duration_ms: None,
         }
     }
+
+    fn clear_output(&mut self) {
+        self.stdout.clear();
+        self.stderr.clear();
+        self.stdout_dropped = 0;
+        self.stderr_dropped = 0;
+    }
+
+    fn push_stdout(&mut self, text: &str, limit: usize) {
+        push_bounded(&mut self.stdout, &mut self.stdout_dropped, text, limit);
+    }
+
+    fn push_stderr(&mut self, text: &str, limit: usize) {
+        push_bounded(&mut self.stderr, &mut self.stderr_dropped, text, limit);
+    }
+}
+
+fn truncation_notice(dropped: usize) -> String {
+    format!("[... {dropped} earlier bytes truncated ...]\n")
+}
+
+/// Append `text` to `buffer`, keeping only the newest `limit` bytes of output
+/// behind a notice of how many bytes have been `dropped` so far.
+fn push_bounded(buffer: &mut String, dropped: &mut usize, text: &str, limit: usize) {
+    if *dropped > 0 {
+        buffer.drain(..truncation_notice(*dropped).len());
+    }
+    buffer.push_str(text);
+    if buffer.len() > limit {
+        let mut cut = buffer.len() - limit;
+        while !buffer.is_char_boundary(cut) {
+            cut += 1;
+        }
+        buffer.drain(..cut);
+        *dropped += cut;
+    }
+    if *dropped > 0 {
+        buffer.insert_str(0, &truncation_notice(*dropped));
+    }
 }
 
 /// AppView phases, accumulating data at each step.
@@ -353,6 +551,7 @@ pub enum AppView {
         resource_params: FlatViewTree,
         resources: FlatViewTree,
         resource_states: FlatViewTree,
+        progress: Option<Progress>,
     },
     ResourceChanges {
         resource_params: FlatViewTree,
@@ -386,13 +585,19 @@ pub enum AppView {
         has_changes: Option<bool>,
         operations_tree: FlatViewTree,
         operations_epochs: Vec<Vec<OperationView>>,
+        summary: Option<ApplySummary>,
     },
 }
 
 #[derive(Debug, Error)]
 pub enum AppViewError {
-    #[error("invalid transition: {from} -> {update}")]
-    InvalidTransition { from: String, update: String },
+    #[error("unexpected update in {phase} phase: {update:?}")]
+    UnexpectedUpdate {
+        phase: &'static str,
+        update: Box<AppUpdate>,
+        /// The view as it was before the update.
+        view: Box<AppView>,
+    },
 
     #[error(transparent)]
     FlatTree(#[from] FlatViewTreeError),
@@ -402,8 +607,28 @@ pub enum AppViewError {
 }
 
 impl AppView {
+    /// Drive a fresh view through `updates` in order, stopping at the first
+    /// update that fails.
+    pub fn from_updates(
+        updates: impl IntoIterator<Item = AppUpdate>,
+    ) -> Result<Self, AppViewError> {
+        updates
+            .into_iter()
+            .try_fold(AppView::default(), AppView::update)
+    }
+
     /// State machine update with error handling.
     pub fn update(self, update: AppUpdate) -> Result<Self, AppViewError> {
+        self.update_with_output_limit(update, DEFAULT_OUTPUT_LIMIT)
+    }
+
+    /// Like [`AppView::update`], keeping at most `output_limit` bytes of
+    /// each operation's stdout and stderr.
+    pub fn update_with_output_limit(
+        self,
+        update: AppUpdate,
+        output_limit: usize,
+    ) -> Result<Self, AppViewError> {
         use AppUpdate::*;
         match (self, update) {
             // Phase: Start -> ResourceParams
@@ -421,6 +646,19 @@ impl AppView {
             }
 
             // Phase: Resources
+            (
+                AppView::Resources {
+                    resource_params,
+                    mut resources,
+                },
+                ResourcesNodeStart { index },
+            ) => {
+                resources.set_leaf_started(index)?;
+                Ok(AppView::Resources {
+                    resource_params,
+                    resources,
+                })
+            }
             (
                 AppView::Resources {
                     resource_params,
@@ -434,6 +672,19 @@ impl AppView {
                     resources,
                 })
             }
+            (
+                AppView::Resources {
+                    resource_params,
+                    mut resources,
+                },
+                ResourcesNodeChild { index, tree },
+            ) => {
+                resources.append_child_completed(index, tree)?;
+                Ok(AppView::Resources {
+                    resource_params,
+                    resources,
+                })
+            }
             (
                 AppView::Resources {
                     resource_params,
@@ -458,6 +709,7 @@ impl AppView {
                     resource_params,
                     resources,
                     resource_states,
+                    progress: None,
                 })
             }
 
@@ -467,6 +719,7 @@ impl AppView {
                     resource_params,
                     resources,
                     mut resource_states,
+                    progress,
                 },
                 ResourceStatesNodeStart { index },
             ) => {
@@ -475,6 +728,7 @@ impl AppView {
                     resource_params,
                     resources,
                     resource_states,
+                    progress,
                 })
             }
             (
@@ -482,6 +736,7 @@ impl AppView {
                     resource_params,
                     resources,
                     mut resource_states,
+                    progress,
                 },
                 ResourceStatesNodeComplete { index, node },
             ) => {
@@ -490,6 +745,7 @@ impl AppView {
                     resource_params,
                     resources,
                     resource_states,
+                    progress,
                 })
             }
             (
@@ -497,6 +753,24 @@ impl AppView {
                     resource_params,
                     resources,
                     resource_states,
+                    progress,
+                },
+                ResourceStatesProgress { done, total },
+            ) => {
+                let next = Progress { done, total };
+                Ok(AppView::ResourceStates {
+                    resource_params,
+                    resources,
+                    resource_states,
+                    progress: Some(progress.unwrap_or_default().advance(next)),
+                })
+            }
+            (
+                AppView::ResourceStates {
+                    resource_params,
+                    resources,
+                    resource_states,
+                    progress,
                 },
                 ResourceStatesComplete,
             ) => {
@@ -505,6 +779,7 @@ impl AppView {
                     resource_params,
                     resources,
                     resource_states,
+                    progress,
                 })
             }
 
@@ -514,6 +789,7 @@ impl AppView {
                     resource_params,
                     resources,
                     resource_states,
+                    progress: _,
                 },
                 ResourceChangesStart,
             ) => {
@@ -678,9 +954,9 @@ impl AppView {
                 let op = epoch
                     .get_mut(o)
                     .ok_or(AppViewError::OperationIndexOutOfBounds(e, o))?;
-                op.stdout.clear();
-                op.stderr.clear();
-                op.is_complete = false;
+                op.clear_output();
+                op.status = OperationStatus::Running;
+                op.duration_ms = None;
                 Ok(AppView::OperationsApply {
                     resource_params,
                     resources,
@@ -712,7 +988,7 @@ impl AppView {
                 let op = epoch
                     .get_mut(o)
                     .ok_or(AppViewError::OperationIndexOutOfBounds(e, o))?;
-                op.stdout.push_str(&stdout);
+                op.push_stdout(&stdout, output_limit);
                 Ok(AppView::OperationsApply {
                     resource_params,
                     resources,
@@ -744,7 +1020,7 @@ impl AppView {
                 let op = epoch
                     .get_mut(o)
                     .ok_or(AppViewError::OperationIndexOutOfBounds(e, o))?;
-                op.stderr.push_str(&stderr);
+                op.push_stderr(&stderr, output_limit);
                 Ok(AppView::OperationsApply {
                     resource_params,
                     resources,
@@ -765,7 +1041,10 @@ impl AppView {
                     operations_tree,
                     mut operations_epochs,
                 },
-                OperationApplyComplete { index: (e, o) },
+                OperationApplyComplete {
+                    index: (e, o),
+                    duration_ms,
+                },
             ) => {
                 let epoch = operations_epochs
                     .get_mut(e)
@@ -773,7 +1052,40 @@ impl AppView {
                 let op = epoch
                     .get_mut(o)
                     .ok_or(AppViewError::OperationIndexOutOfBounds(e, o))?;
-                op.is_complete = true;
+                op.status = OperationStatus::Succeeded;
+                op.duration_ms = Some(duration_ms);
+                Ok(AppView::OperationsApply {
+                    resource_params,
+                    resources,
+                    resource_states,
+                    resource_changes,
+                    has_changes,
+                    operations_tree,
+                    operations_epochs,
+                })
+            }
+            (
+                AppView::OperationsApply {
+                    resource_params,
+                    resources,
+                    resource_states,
+                    resource_changes,
+                    has_changes,
+                    operations_tree,
+                    mut operations_epochs,
+                },
+                OperationApplyFailed {
+                    index: (e, o),
+                    error,
+                },
+            ) => {
+                let epoch = operations_epochs
+                    .get_mut(e)
+                    .ok_or(AppViewError::OperationIndexOutOfBounds(e, o))?;
+                let op = epoch
+                    .get_mut(o)
+                    .ok_or(AppViewError::OperationIndexOutOfBounds(e, o))?;
+                op.status = OperationStatus::Failed(error);
                 Ok(AppView::OperationsApply {
                     resource_params,
                     resources,
@@ -803,15 +1115,128 @@ impl AppView {
                 has_changes,
                 operations_tree,
                 operations_epochs,
+                summary: None,
             }),
 
-            (state, update) => Err(AppViewError::InvalidTransition {
-                from: format!("{state:?}"),
-                update: format!("{update:?}"),
+            // Phase: ResourceChanges -> Done (no changes to apply)
+            (
+                AppView::ResourceChanges {
+                    resource_params,
+                    resources,
+                    resource_states,
+                    resource_changes,
+                    has_changes: Some(false),
+                },
+                Summary {
+                    changed,
+                    unchanged,
+                    failed,
+                    duration_ms,
+                },
+            ) => Ok(AppView::Done {
+                resource_params,
+                resources,
+                resource_states,
+                resource_changes,
+                has_changes: Some(false),
+                operations_tree: FlatViewTree::default(),
+                operations_epochs: Vec::new(),
+                summary: Some(ApplySummary {
+                    changed,
+                    unchanged,
+                    failed,
+                    duration_ms,
+                }),
+            }),
+
+            // Phase: Operations -> Done (operations came to nothing to apply)
+            (
+                AppView::Operations {
+                    resource_params,
+                    resources,
+                    resource_states,
+                    resource_changes,
+                    has_changes,
+                    operations_tree,
+                },
+                Summary {
+                    changed,
+                    unchanged,
+                    failed,
+                    duration_ms,
+                },
+            ) => Ok(AppView::Done {
+                resource_params,
+                resources,
+                resource_states,
+                resource_changes,
+                has_changes,
+                operations_tree,
+                operations_epochs: Vec::new(),
+                summary: Some(ApplySummary {
+                    changed,
+                    unchanged,
+                    failed,
+                    duration_ms,
+                }),
+            }),
+
+            // Phase: Done
+            (
+                AppView::Done {
+                    resource_params,
+                    resources,
+                    resource_states,
+                    resource_changes,
+                    has_changes,
+                    operations_tree,
+                    operations_epochs,
+                    summary: _,
+                },
+                Summary {
+                    changed,
+                    unchanged,
+                    failed,
+                    duration_ms,
+                },
+            ) => Ok(AppView::Done {
+                resource_params,
+                resources,
+                resource_states,
+                resource_changes,
+                has_changes,
+                operations_tree,
+                operations_epochs,
+                summary: Some(ApplySummary {
+                    changed,
+                    unchanged,
+                    failed,
+                    duration_ms,
+                }),
+            }),
+
+            (view, update) => Err(AppViewError::UnexpectedUpdate {
+                phase: view.phase(),
+                update: Box::new(update),
+                view: Box::new(view),
             }),
         }
     }
 
+    /// The name of the current phase.
+    pub fn phase(&self) -> &'static str {
+        match self {
+            Self::Start => "Start",
+            Self::ResourceParams { .. } => "ResourceParams",
+            Self::Resources { .. } => "Resources",
+            Self::ResourceStates { .. } => "ResourceStates",
+            Self::ResourceChanges { .. } => "ResourceChanges",
+            Self::Operations { .. } => "Operations",
+            Self::OperationsApply { .. } => "OperationsApply",
+            Self::Done { .. } => "Done",
+        }
+    }
+
     pub fn resource_params(&self) -> Option<&FlatViewTree> {
         match self {
             Self::Start => None,
@@ -926,6 +1351,47 @@ impl AppView {
             } => Some(operations_epochs),
         }
     }
+
+    /// Progress fetching resource states, while they're being fetched.
+    pub fn resource_states_progress(&self) -> Option<&Progress> {
+        match self {
+            AppView::ResourceStates { progress, .. } => progress.as_ref(),
+            _ => None,
+        }
+    }
+
+    pub fn summary(&self) -> Option<&ApplySummary> {
+        match self {
+            AppView::Done { summary, .. } => summary.as_ref(),
+            _ => None,
+        }
+    }
+
+    /// Whether any operation failed: either an operation reported failure, the
+    /// summary reports failures, or apply finished while an operation never
+    /// completed.
+    pub fn had_failures(&self) -> bool {
+        match self {
+            AppView::Done {
+                operations_epochs,
+                summary,
+                ..
+            } => {
+                summary.is_some_and(|summary| summary.failed > 0)
+                    || operations_epochs
+                        .iter()
+                        .flatten()
+                        .any(|operation| !matches!(operation.status, OperationStatus::Succeeded))
+            }
+            AppView::OperationsApply {
+                operations_epochs, ..
+            } => operations_epochs
+                .iter()
+                .flatten()
+                .any(|operation| operation.status.is_failed()),
+            _ => false,
+        }
+    }
 }
 
 /// Lenient conversion to nested ViewTree:
@@ -970,3 +1436,506 @@ impl std::fmt::Display for FlatViewTree {
         }
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn leaf(label: &str) -> ViewTree {
+        ViewTree::Leaf {
+            view: View::Span(label.into()),
+        }
+    }
+
+    #[test]
+    fn test_from_updates_resources() -> Result<(), AppViewError> {
+        let view = AppView::from_updates([
+            AppUpdate::ResourceParams {
+                resource_params: leaf("params"),
+            },
+            AppUpdate::ResourcesStart,
+            AppUpdate::ResourcesNode {
+                index: 0,
+                tree: leaf("resource"),
+            },
+            AppUpdate::ResourcesComplete,
+        ])?;
+
+        assert!(matches!(view, AppView::Resources { .. }));
+        let resources = view.resources().expect("resources");
+        let FlatViewTreeNode::Leaf {
+            view: ViewNode::Complete(resource),
+        } = resources.get(0)?
+        else {
+            panic!("expected a completed resource leaf");
+        };
+        assert_eq!(resource.to_string(), "resource");
+        assert!(view.resource_states().is_none());
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_streamed_resources_match_bulk_insert() -> Result<(), AppViewError> {
+        let branch = |label: &str, children| ViewTree::Branch {
+            view: View::Span(label.into()),
+            children,
+        };
+        let curl = || branch("curl", vec![leaf("libcurl")]);
+        let apt = || branch("apt", vec![curl(), leaf("git")]);
+        let file = || branch("file", vec![leaf("/etc/motd")]);
+
+        let mut view = AppView::from_updates([
+            AppUpdate::ResourceParams {
+                resource_params: branch("plan", vec![leaf("apt"), leaf("file")]),
+            },
+            AppUpdate::ResourcesStart,
+            AppUpdate::ResourcesNodeStart { index: 1 },
+        ])?;
+        let resources = view.resources().expect("resources");
+        assert!(matches!(
+            resources.get(1)?,
+            FlatViewTreeNode::Leaf {
+                view: ViewNode::Started
+            }
+        ));
+
+        for update in [
+            AppUpdate::ResourcesNode {
+                index: 1,
+                tree: branch("apt", vec![]),
+            },
+            AppUpdate::ResourcesNodeChild {
+                index: 1,
+                tree: curl(),
+            },
+            AppUpdate::ResourcesNodeChild {
+                index: 1,
+                tree: leaf("git"),
+            },
+            AppUpdate::ResourcesNodeStart { index: 2 },
+            AppUpdate::ResourcesNode {
+                index: 2,
+                tree: file(),
+            },
+            AppUpdate::ResourcesComplete,
+        ] {
+            view = view.update(update)?;
+        }
+
+        let bulk = FlatViewTree::from_view_tree_completed(branch("plan", vec![apt(), file()]));
+        assert_eq!(
+            view.resources().expect("resources").to_string(),
+            bulk.to_string()
+        );
+
+        // Streaming one resource at a time must land on the same indices as
+        // inserting the whole subtree, as later phases address nodes by index.
+        let inserted = AppView::from_updates([
+            AppUpdate::ResourceParams {
+                resource_params: branch("plan", vec![leaf("apt"), leaf("file")]),
+            },
+            AppUpdate::ResourcesStart,
+            AppUpdate::ResourcesNode {
+                index: 1,
+                tree: apt(),
+            },
+            AppUpdate::ResourcesNode {
+                index: 2,
+                tree: file(),
+            },
+        ])?;
+        assert_eq!(
+            serde_json::to_string(view.resources().expect("resources")).unwrap(),
+            serde_json::to_string(inserted.resources().expect("resources")).unwrap()
+        );
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_from_updates_out_of_order() {
+        let error = AppView::from_updates([
+            AppUpdate::ResourceParams {
+                resource_params: leaf("params"),
+            },
+            AppUpdate::ResourcesNode {
+                index: 0,
+                tree: leaf("resource"),
+            },
+        ])
+        .unwrap_err();
+        assert!(matches!(error, AppViewError::UnexpectedUpdate { .. }));
+
+        let error =
+            AppView::from_updates([AppUpdate::OperationApplyStart { index: (0, 0) }]).unwrap_err();
+        assert!(matches!(error, AppViewError::UnexpectedUpdate { .. }));
+    }
+
+    #[test]
+    fn test_unexpected_update_hands_back_view() -> Result<(), AppViewError> {
+        let view = AppView::from_updates([AppUpdate::ResourceParams {
+            resource_params: leaf("params"),
+        }])?;
+
+        let error = view
+            .update(AppUpdate::ResourcesNode {
+                index: 0,
+                tree: leaf("resource"),
+            })
+            .unwrap_err();
+        let AppViewError::UnexpectedUpdate {
+            phase,
+            update,
+            view,
+        } = error
+        else {
+            panic!("expected an unexpected update error");
+        };
+        assert_eq!(phase, "ResourceParams");
+        assert!(matches!(*update, AppUpdate::ResourcesNode { index: 0, .. }));
+        assert!(matches!(*view, AppView::ResourceParams { .. }));
+
+        // The returned view carries on as if the update never arrived.
+        let view = view.update(AppUpdate::ResourcesStart)?;
+        assert!(matches!(view, AppView::Resources { .. }));
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_summary_after_apply() -> Result<(), AppViewError> {
+        let updates = vec![
+            AppUpdate::ResourceParams {
+                resource_params: leaf("params"),
+            },
+            AppUpdate::ResourcesStart,
+            AppUpdate::ResourcesNode {
+                index: 0,
+                tree: leaf("resource"),
+            },
+            AppUpdate::ResourcesComplete,
+            AppUpdate::ResourceStatesStart,
+            AppUpdate::ResourceStatesNodeStart { index: 0 },
+            AppUpdate::ResourceStatesNodeComplete {
+                index: 0,
+                node: View::Span("state".into()),
+            },
+            AppUpdate::ResourceStatesComplete,
+            AppUpdate::ResourceChangesStart,
+            AppUpdate::ResourceChangesNode {
+                index: 0,
+                node: Some(View::Span("change".into())),
+            },
+            AppUpdate::ResourceChangesComplete { has_changes: true },
+            AppUpdate::OperationsStart,
+            AppUpdate::OperationsNode {
+                index: 0,
+                operations: leaf("operation"),
+            },
+            AppUpdate::OperationsComplete,
+            AppUpdate::OperationsApplyStart {
+                operations: vec![vec![View::Span("operation".into())]],
+            },
+            AppUpdate::OperationApplyStart { index: (0, 0) },
+            AppUpdate::OperationApplyComplete {
+                index: (0, 0),
+                duration_ms: 1200,
+            },
+            AppUpdate::OperationsApplyComplete,
+            AppUpdate::Summary {
+                changed: 1,
+                unchanged: 0,
+                failed: 0,
+                duration_ms: 1500,
+            },
+        ];
+
+        let view = AppView::from_updates(updates)?;
+
+        let expected = ApplySummary {
+            changed: 1,
+            unchanged: 0,
+            failed: 0,
+            duration_ms: 1500,
+        };
+        assert_eq!(view.summary(), Some(&expected));
+        assert_eq!(
+            expected.to_string(),
+            "1 changed, 0 unchanged, 0 failed in 1.5s"
+        );
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_summary_when_operations_have_nothing_to_apply() -> Result<(), AppViewError> {
+        let updates = vec![
+            AppUpdate::ResourceParams {
+                resource_params: leaf("params"),
+            },
+            AppUpdate::ResourcesStart,
+            AppUpdate::ResourcesNode {
+                index: 0,
+                tree: leaf("resource"),
+            },
+            AppUpdate::ResourcesComplete,
+            AppUpdate::ResourceStatesStart,
+            AppUpdate::ResourceStatesNodeStart { index: 0 },
+            AppUpdate::ResourceStatesNodeComplete {
+                index: 0,
+                node: View::Span("state".into()),
+            },
+            AppUpdate::ResourceStatesComplete,
+            AppUpdate::ResourceChangesStart,
+            AppUpdate::ResourceChangesNode {
+                index: 0,
+                node: Some(View::Span("change".into())),
+            },
+            AppUpdate::ResourceChangesComplete { has_changes: true },
+            AppUpdate::OperationsStart,
+            AppUpdate::OperationsNode {
+                index: 0,
+                operations: leaf("operation"),
+            },
+            AppUpdate::OperationsComplete,
+            // The changes came to no operations, e.g. none were toward the
+            // target, so the apply phase is empty.
+            AppUpdate::OperationsApplyStart {
+                operations: Vec::new(),
+            },
+            AppUpdate::OperationsApplyComplete,
+            AppUpdate::Summary {
+                changed: 0,
+                unchanged: 1,
+                failed: 0,
+                duration_ms: 10,
+            },
+        ];
+
+        let view = AppView::from_updates(updates)?;
+
+        assert_eq!(
+            view.summary(),
+            Some(&ApplySummary {
+                changed: 0,
+                unchanged: 1,
+                failed: 0,
+                duration_ms: 10,
+            })
+        );
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_summary_straight_after_operations() -> Result<(), AppViewError> {
+        let view = AppView::from_updates([
+            AppUpdate::ResourceParams {
+                resource_params: leaf("params"),
+            },
+            AppUpdate::ResourcesStart,
+            AppUpdate::ResourcesNode {
+                index: 0,
+                tree: leaf("resource"),
+            },
+            AppUpdate::ResourcesComplete,
+            AppUpdate::ResourceStatesStart,
+            AppUpdate::ResourceStatesComplete,
+            AppUpdate::ResourceChangesStart,
+            AppUpdate::ResourceChangesComplete { has_changes: true },
+            AppUpdate::OperationsStart,
+            AppUpdate::OperationsComplete,
+            // Nothing to apply, so the summary comes without an apply phase.
+            AppUpdate::Summary {
+                changed: 0,
+                unchanged: 1,
+                failed: 0,
+                duration_ms: 10,
+            },
+        ])?;
+
+        assert!(matches!(view, AppView::Done { .. }));
+        assert_eq!(view.summary().map(|summary| summary.changed), Some(0));
+        assert!(!view.had_failures());
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_had_failures_with_incomplete_operation() -> Result<(), AppViewError> {
+        let updates = vec![
+            AppUpdate::ResourceParams {
+                resource_params: leaf("params"),
+            },
+            AppUpdate::ResourcesStart,
+            AppUpdate::ResourceStatesStart,
+            AppUpdate::ResourceChangesStart,
+            AppUpdate::ResourceChangesComplete { has_changes: true },
+            AppUpdate::OperationsStart,
+            AppUpdate::OperationsApplyStart {
+                operations: vec![vec![
+                    View::Span("succeeds".into()),
+                    View::Span("fails".into()),
+                ]],
+            },
+            AppUpdate::OperationApplyStart { index: (0, 0) },
+            AppUpdate::OperationApplyComplete {
+                index: (0, 0),
+                duration_ms: 1200,
+            },
+            AppUpdate::OperationApplyStart { index: (0, 1) },
+        ];
+
+        let view = AppView::from_updates(updates)?;
+        assert!(!view.had_failures());
+
+        let view = view.update(AppUpdate::OperationsApplyComplete)?;
+        assert!(view.had_failures());
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_operation_apply_failed() -> Result<(), AppViewError> {
+        let updates = vec![
+            AppUpdate::ResourceParams {
+                resource_params: leaf("params"),
+            },
+            AppUpdate::ResourcesStart,
+            AppUpdate::ResourceStatesStart,
+            AppUpdate::ResourceChangesStart,
+            AppUpdate::OperationsStart,
+            AppUpdate::OperationsApplyStart {
+                operations: vec![vec![View::Span("operation".into())]],
+            },
+            AppUpdate::OperationApplyStart { index: (0, 0) },
+            AppUpdate::OperationApplyFailed {
+                index: (0, 0),
+                error: "exit status 100".into(),
+            },
+        ];
+
+        let view = AppView::from_updates(updates)?;
+
+        let operation = &view.operations_epochs().expect("operations epochs")[0][0];
+        assert_eq!(
+            operation.status,
+            OperationStatus::Failed("exit status 100".into())
+        );
+        assert_eq!(operation.status.render().to_string(), "❌ exit status 100");
+        assert!(view.had_failures());
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_operation_apply_complete_records_duration() -> Result<(), AppViewError> {
+        let updates = vec![
+            AppUpdate::ResourceParams {
+                resource_params: leaf("params"),
+            },
+            AppUpdate::ResourcesStart,
+            AppUpdate::ResourceStatesStart,
+            AppUpdate::ResourceChangesStart,
+            AppUpdate::OperationsStart,
+            AppUpdate::OperationsApplyStart {
+                operations: vec![vec![View::Span("operation".into())]],
+            },
+            AppUpdate::OperationApplyStart { index: (0, 0) },
+        ];
+
+        let view = AppView::from_updates(updates)?;
+        let operation = &view.operations_epochs().expect("operations epochs")[0][0];
+        assert_eq!(operation.duration_ms, None);
+
+        let view = view.update(AppUpdate::OperationApplyComplete {
+            index: (0, 0),
+            duration_ms: 1200,
+        })?;
+        let operation = &view.operations_epochs().expect("operations epochs")[0][0];
+        assert_eq!(operation.status, OperationStatus::Succeeded);
+        assert_eq!(operation.duration_ms, Some(1200));
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_operation_apply_complete_without_duration() {
+        let update: AppUpdate =
+            serde_json::from_str(r#"{"OperationApplyComplete":{"index":[0,1]}}"#).unwrap();
+        assert!(matches!(
+            update,
+            AppUpdate::OperationApplyComplete {
+                index: (0, 1),
+                duration_ms: 0,
+            }
+        ));
+    }
+
+    #[test]
+    fn test_resource_states_progress() -> Result<(), AppViewError> {
+        let mut view = AppView::from_updates([
+            AppUpdate::ResourceParams {
+                resource_params: leaf("params"),
+            },
+            AppUpdate::ResourcesStart,
+            AppUpdate::ResourceStatesStart,
+        ])?;
+        assert_eq!(view.resource_states_progress(), None);
+
+        let mut seen = Vec::new();
+        for done in [0, 1, 3, 2, 4] {
+            view = view.update(AppUpdate::ResourceStatesProgress { done, total: 4 })?;
+            seen.push(*view.resource_states_progress().expect("progress"));
+        }
+
+        assert!(seen.windows(2).all(|pair| pair[0].done <= pair[1].done));
+        assert!(seen.iter().all(|progress| progress.total == 4));
+        assert_eq!(
+            seen.last().map(ToString::to_string),
+            Some("4/4".to_string())
+        );
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_operation_output_is_bounded() -> Result<(), AppViewError> {
+        let mut view = AppView::from_updates([
+            AppUpdate::ResourceParams {
+                resource_params: leaf("params"),
+            },
+            AppUpdate::ResourcesStart,
+            AppUpdate::ResourceStatesStart,
+            AppUpdate::ResourceChangesStart,
+            AppUpdate::OperationsStart,
+            AppUpdate::OperationsApplyStart {
+                operations: vec![vec![View::Span("chatty".into())]],
+            },
+            AppUpdate::OperationApplyStart { index: (0, 0) },
+        ])?;
+
+        let limit = 100;
+        for line in 0..50 {
+            view = view.update_with_output_limit(
+                AppUpdate::OperationApplyStdout {
+                    index: (0, 0),
+                    stdout: format!("line {line:02}\n"),
+                },
+                limit,
+            )?;
+        }
+
+        let operation = &view.operations_epochs().expect("operations epochs")[0][0];
+        // 50 lines of 8 bytes, of which the newest 100 bytes are kept.
+        assert_eq!(operation.stdout_dropped, 300);
+        let notice = "[... 300 earlier bytes truncated ...]\n";
+        assert!(operation.stdout.starts_with(notice));
+        assert_eq!(operation.stdout.len(), notice.len() + limit);
+        assert!(operation.stdout.ends_with("line 49\n"));
+        assert!(operation.stderr.is_empty());
+
+        Ok(())
+    }
+}
@@ -0,0 +1,203 @@
+use serde::{Deserialize, Serialize};
+
+use crate::{AppView, ApplySummary, FlatViewTree, FlatViewTreeNode, OperationStatus, ViewNode};
+
+/// Version of the [`ApplyResult`] schema, bumped on breaking changes.
+pub const APPLY_RESULT_VERSION: u32 = 1;
+
+/// Structured result of an apply run, for machine consumption (e.g. CI).
+///
+/// Unlike [`AppView`], this is a stable schema: views are rendered to plain text.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct ApplyResult {
+    pub version: u32,
+    pub outcome: ApplyOutcome,
+    pub summary: Option<ApplySummary>,
+    pub resources: Vec<String>,
+    pub changes: Vec<String>,
+    pub operations: Vec<OperationResult>,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum ApplyOutcome {
+    /// Nothing needed to change.
+    Unchanged,
+    /// All operations succeeded.
+    Succeeded,
+    /// At least one operation failed.
+    Failed,
+    /// Apply stopped before finishing.
+    Incomplete,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct OperationResult {
+    pub epoch: usize,
+    pub index: usize,
+    pub label: String,
+    pub status: OperationResultStatus,
+    pub error: Option<String>,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum OperationResultStatus {
+    Pending,
+    Running,
+    Succeeded,
+    Failed,
+}
+
+impl From<&AppView> for ApplyResult {
+    fn from(view: &AppView) -> Self {
+        let operations: Vec<OperationResult> = view
+            .operations_epochs()
+            .into_iter()
+            .flatten()
+            .enumerate()
+            .flat_map(|(epoch, operations)| {
+                operations
+                    .iter()
+                    .enumerate()
+                    .map(move |(index, operation)| {
+                        let (status, error) = match &operation.status {
+                            OperationStatus::Pending => (OperationResultStatus::Pending, None),
+                            OperationStatus::Running => (OperationResultStatus::Running, None),
+                            OperationStatus::Succeeded => (OperationResultStatus::Succeeded, None),
+                            OperationStatus::Failed(error) => {
+                                (OperationResultStatus::Failed, Some(error.clone()))
+                            }
+                        };
+                        OperationResult {
+                            epoch,
+                            index,
+                            label: operation.label.to_string(),
+                            status,
+                            error,
+                        }
+                    })
+            })
+            .collect();
+
+        let outcome = match view {
+            _ if view.had_failures() => ApplyOutcome::Failed,
+            AppView::Done { .. } if operations.is_empty() => ApplyOutcome::Unchanged,
+            AppView::Done { .. } => ApplyOutcome::Succeeded,
+            _ => ApplyOutcome::Incomplete,
+        };
+
+        ApplyResult {
+            version: APPLY_RESULT_VERSION,
+            outcome,
+            summary: view.summary().copied(),
+            resources: view.resources().map(leaf_labels).unwrap_or_default(),
+            changes: view.resource_changes().map(leaf_labels).unwrap_or_default(),
+            operations,
+        }
+    }
+}
+
+/// Labels of completed leaves, in tree order.
+fn leaf_labels(tree: &FlatViewTree) -> Vec<String> {
+    tree.nodes()
+        .filter_map(|node| match node {
+            Some(FlatViewTreeNode::Leaf {
+                view: ViewNode::Complete(view),
+            }) => Some(view.to_string()),
+            _ => None,
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use lusid_view::{View, ViewTree};
+
+    use super::*;
+    use crate::{AppUpdate, AppViewError};
+
+    fn leaf(label: &str) -> ViewTree {
+        ViewTree::Leaf {
+            view: View::Span(label.into()),
+        }
+    }
+
+    #[test]
+    fn result_from_failed_apply() -> Result<(), AppViewError> {
+        let updates = vec![
+            AppUpdate::ResourceParams {
+                resource_params: leaf("params"),
+            },
+            AppUpdate::ResourcesStart,
+            AppUpdate::ResourcesNode {
+                index: 0,
+                tree: leaf("file /etc/motd"),
+            },
+            AppUpdate::ResourcesComplete,
+            AppUpdate::ResourceStatesStart,
+            AppUpdate::ResourceStatesNodeStart { index: 0 },
+            AppUpdate::ResourceStatesNodeComplete {
+                index: 0,
+                node: View::Span("missing".into()),
+            },
+            AppUpdate::ResourceStatesComplete,
+            AppUpdate::ResourceChangesStart,
+            AppUpdate::ResourceChangesNode {
+                index: 0,
+                node: Some(View::Span("create /etc/motd".into())),
+            },
+            AppUpdate::ResourceChangesComplete { has_changes: true },
+            AppUpdate::OperationsStart,
+            AppUpdate::OperationsNode {
+                index: 0,
+                operations: leaf("write /etc/motd"),
+            },
+            AppUpdate::OperationsComplete,
+            AppUpdate::OperationsApplyStart {
+                operations: vec![vec![View::Span("write /etc/motd".into())]],
+            },
+            AppUpdate::OperationApplyStart { index: (0, 0) },
+            AppUpdate::OperationApplyFailed {
+                index: (0, 0),
+                error: "permission denied".into(),
+            },
+            AppUpdate::OperationsApplyComplete,
+            AppUpdate::Summary {
+                changed: 1,
+                unchanged: 0,
+                failed: 1,
+                duration_ms: 20,
+            },
+        ];
+
+        let mut view = AppView::default();
+        for update in updates {
+            view = view.update(update)?;
+        }
+
+        let result = ApplyResult::from(&view);
+
+        assert_eq!(result.version, APPLY_RESULT_VERSION);
+        assert_eq!(result.outcome, ApplyOutcome::Failed);
+        assert_eq!(result.summary.map(|summary| summary.failed), Some(1));
+        assert_eq!(result.resources, vec!["file /etc/motd".to_string()]);
+        assert_eq!(result.changes, vec!["create /etc/motd".to_string()]);
+        assert_eq!(
+            result.operations,
+            vec![OperationResult {
+                epoch: 0,
+                index: 0,
+                label: "write /etc/motd".into(),
+                status: OperationResultStatus::Failed,
+                error: Some("permission denied".into()),
+            }]
+        );
+
+        let json = serde_json::to_value(&result).unwrap();
+        assert_eq!(json["outcome"], "failed");
+        assert_eq!(json["operations"][0]["status"], "failed");
+
+        Ok(())
+    }
+}
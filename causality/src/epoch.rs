@@ -19,6 +19,9 @@ pub enum EpochError<NodeId> {
 
     #[error("Cycle detected in dependency graph (remaining nodes: {remaining})")]
     CycleDetected { remaining: usize },
+
+    #[error("No node matches the target")]
+    NoTarget,
 }
 
 /// Compute dependency layers of resource specs (Kahn's algorithm).
@@ -26,6 +29,30 @@ pub enum EpochError<NodeId> {
 pub fn compute_epochs<Node, NodeId>(
     tree: CausalityTree<Node, NodeId>,
 ) -> Result<Vec<Vec<Node>>, EpochError<NodeId>>
+where
+    Node: Clone,
+    NodeId: Clone + Eq + Hash,
+{
+    compute_epochs_inner(tree, None::<fn(&NodeId) -> bool>)
+}
+
+/// Like [`compute_epochs`], but only for the nodes with an id matching
+/// `is_target` and the nodes they depend on.
+pub fn compute_target_epochs<Node, NodeId>(
+    tree: CausalityTree<Node, NodeId>,
+    is_target: impl Fn(&NodeId) -> bool,
+) -> Result<Vec<Vec<Node>>, EpochError<NodeId>>
+where
+    Node: Clone,
+    NodeId: Clone + Eq + Hash,
+{
+    compute_epochs_inner(tree, Some(is_target))
+}
+
+fn compute_epochs_inner<Node, NodeId>(
+    tree: CausalityTree<Node, NodeId>,
+    is_target: Option<impl Fn(&NodeId) -> bool>,
+) -> Result<Vec<Vec<Node>>, EpochError<NodeId>>
 where
     Node: Clone,
     NodeId: Clone + Eq + Hash,
@@ -168,10 +195,44 @@ where
         }
     }
 
+    // Restrict to the targets and everything that must happen before them.
+    // Included nodes only have included predecessors, so indegrees still hold.
+    let included = match is_target {
+        None => vec![true; n],
+        Some(is_target) => {
+            let mut incoming: Vec<Vec<usize>> = vec![Vec::new(); n];
+            for (i, targets) in outgoing.iter().enumerate() {
+                for &j in targets {
+                    incoming[j].push(i);
+                }
+            }
+
+            let mut included = vec![false; n];
+            let targets: Vec<&Vec<usize>> = id_to_leaves
+                .iter()
+                .filter_map(|(id, leaves)| is_target(id).then_some(leaves))
+                .collect();
+            if targets.is_empty() {
+                return Err(EpochError::NoTarget);
+            }
+
+            // Anything a target depends on points into it, so walk edges backwards.
+            let mut stack: Vec<usize> = targets.into_iter().flatten().copied().collect();
+            while let Some(i) = stack.pop() {
+                if !included[i] {
+                    included[i] = true;
+                    stack.extend(incoming[i].iter().copied());
+                }
+            }
+            included
+        }
+    };
+    let n = included.iter().filter(|&&included| included).count();
+
     let mut queue: VecDeque<usize> = indegree
         .iter()
         .enumerate()
-        .filter_map(|(i, &d)| (d == 0).then_some(i))
+        .filter_map(|(i, &d)| (included[i] && d == 0).then_some(i))
         .collect();
 
     let mut seen = 0usize;
@@ -191,6 +252,9 @@ where
         let mut next_wave: Vec<usize> = Vec::new();
         for i in current_wave {
             for &j in &outgoing[i] {
+                if !included[j] {
+                    continue;
+                }
                 indegree_mut[j] -= 1;
                 if indegree_mut[j] == 0 {
                     next_wave.push(j);
@@ -207,3 +271,51 @@ where
 
     Ok(epochs)
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn leaf(
+        node: &'static str,
+        before: &[&'static str],
+        after: &[&'static str],
+    ) -> CausalityTree<&'static str, &'static str> {
+        CausalityTree::leaf(
+            CausalityMeta {
+                id: Some(node),
+                before: before.to_vec(),
+                after: after.to_vec(),
+            },
+            node,
+        )
+    }
+
+    fn tree() -> CausalityTree<&'static str, &'static str> {
+        CausalityTree::branch(
+            CausalityMeta::default(),
+            vec![
+                leaf("key", &[], &["update"]),
+                leaf("update", &[], &[]),
+                leaf("git", &["update"], &[]),
+                leaf("curl", &["update"], &[]),
+                leaf("clone", &["git"], &[]),
+            ],
+        )
+    }
+
+    #[test]
+    fn target_includes_its_dependencies() {
+        let epochs = compute_target_epochs(tree(), |id| *id == "clone").unwrap();
+        assert_eq!(
+            epochs,
+            vec![vec!["key"], vec!["update"], vec!["git"], vec!["clone"]]
+        );
+    }
+
+    #[test]
+    fn unknown_target_errors() {
+        let error = compute_target_epochs(tree(), |id| *id == "missing").unwrap_err();
+        assert!(matches!(error, EpochError::NoTarget));
+    }
+}
@@ -1,22 +1,34 @@
 use std::ffi::{OsStr, OsString};
 use std::fmt::Display;
-use std::path::Path;
+use std::path::{Path, PathBuf};
 use std::pin::Pin;
-use std::process::{ExitStatus, Stdio};
-use tokio::io::AsyncReadExt;
+use std::process::{CommandEnvs, ExitStatus, Stdio};
+use std::str::FromStr;
+use std::sync::{Arc, Mutex};
+use std::task::{Context, Poll};
+use tokio::io::{AsyncRead, AsyncReadExt, ReadBuf};
 use tokio::process::{Child, ChildStderr, ChildStdout, Command as BaseCommand};
+use tokio::sync::watch;
 
+use serde::{Deserialize, Serialize};
 use thiserror::Error;
 
 #[derive(Error, Debug)]
 pub enum CommandError {
-    #[error("failed to spawn command: {command}")]
+    #[error("failed to spawn command: {command}{}", in_dir(.current_dir))]
     Spawn {
         command: String,
+        current_dir: Option<PathBuf>,
         #[source]
         error: tokio::io::Error,
     },
 
+    #[error("working directory {} does not exist for command: {command}", .current_dir.display())]
+    WorkingDirMissing {
+        command: String,
+        current_dir: PathBuf,
+    },
+
     #[error("failed to get command output: {command}")]
     Output {
         command: String,
@@ -27,6 +39,12 @@ pub enum CommandError {
     #[error("command failed: {command}\n{stderr}")]
     Failure { command: String, stderr: String },
 
+    #[error(
+        "command needs a sudo password: {command}\n\
+         allow it without one (NOPASSWD in sudoers), or run as a user who can"
+    )]
+    SudoPasswordRequired { command: String },
+
     #[error("unable to capture stdout")]
     NoStdout,
 
@@ -40,6 +58,66 @@ pub enum CommandError {
     ReadStderr(#[source] tokio::io::Error),
 }
 
+fn in_dir(current_dir: &Option<PathBuf>) -> String {
+    match current_dir {
+        Some(dir) => format!(" (in {})", dir.display()),
+        None => String::new(),
+    }
+}
+
+impl CommandError {
+    /// Error for a command that exited unsuccessfully, recognizing failures
+    /// with a known cause from its stderr.
+    pub fn failure(command: String, stderr: String) -> Self {
+        if stderr.contains("sudo: a password is required") {
+            return CommandError::SudoPasswordRequired { command };
+        }
+        CommandError::Failure { command, stderr }
+    }
+}
+
+/// How to run commands that need root.
+#[derive(
+    Debug, Clone, Copy, Default, PartialEq, Eq, PartialOrd, Ord, Hash, Serialize, Deserialize,
+)]
+#[serde(rename_all = "lowercase")]
+pub enum Privilege {
+    /// Run as is: already root, or nothing needs it.
+    None,
+    /// Wrap with `sudo -n`.
+    #[default]
+    Sudo,
+    /// Wrap with `doas`.
+    Doas,
+}
+
+impl Display for Privilege {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Privilege::None => write!(f, "none"),
+            Privilege::Sudo => write!(f, "sudo"),
+            Privilege::Doas => write!(f, "doas"),
+        }
+    }
+}
+
+#[derive(Error, Debug)]
+#[error("unknown privilege escalation: {0} (expected none, sudo, or doas)")]
+pub struct ParsePrivilegeError(String);
+
+impl FromStr for Privilege {
+    type Err = ParsePrivilegeError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "none" => Ok(Privilege::None),
+            "sudo" => Ok(Privilege::Sudo),
+            "doas" => Ok(Privilege::Doas),
+            _ => Err(ParsePrivilegeError(s.to_string())),
+        }
+    }
+}
+
 #[derive(Debug)]
 pub struct Command {
     cmd: BaseCommand,
@@ -106,11 +184,20 @@ impl Command {
         self
     }
 
+    /// Variables explicitly set or removed for this command.
+    pub fn get_envs(&self) -> CommandEnvs<'_> {
+        self.cmd.as_std().get_envs()
+    }
+
     pub fn current_dir<P: AsRef<Path>>(&mut self, dir: P) -> &mut Command {
         self.cmd.current_dir(dir);
         self
     }
 
+    pub fn get_current_dir(&self) -> Option<&Path> {
+        self.cmd.as_std().get_current_dir()
+    }
+
     pub fn stdout(&mut self, stdout: bool) -> &mut Command {
         self.stdout = stdout;
         self
@@ -130,22 +217,61 @@ impl Command {
     }
 
     pub fn sudo(self) -> Self {
-        let mut privileged_cmd = Command::new("sudo");
-
-        let cmd = self.cmd.as_std();
+        self.privileged(Privilege::Sudo)
+    }
 
-        privileged_cmd.arg("-n"); // non-interactive
+    /// Wrap this command to run as root, carrying over its environment,
+    /// working directory, and output settings.
+    pub fn privileged(self, privilege: Privilege) -> Self {
+        self.privileged_with_env(privilege, &[])
+    }
 
-        for env in cmd.get_envs() {
-            if let (key, Some(value)) = env {
-                let mut env_arg = OsString::new();
-                env_arg.push(key);
-                env_arg.push("=");
-                env_arg.push(value);
-                privileged_cmd.arg(env_arg);
+    /// Like [`Command::privileged`], also carrying over the variables named in
+    /// `preserve_env` from this process, which sudo and doas would otherwise reset.
+    ///
+    /// Values go in the environment, never on argv, where ps and errors would
+    /// show them. sudo is told to keep them by name. doas has no such flag, so
+    /// doas.conf must keep them, with `keepenv` or `setenv { NAME }`.
+    pub fn privileged_with_env(self, privilege: Privilege, preserve_env: &[String]) -> Self {
+        let mut envs: Vec<(OsString, OsString)> = self
+            .cmd
+            .as_std()
+            .get_envs()
+            .filter_map(|(key, value)| Some((key.to_owned(), value?.to_owned())))
+            .collect();
+        for key in preserve_env {
+            if let Some(value) = std::env::var_os(key) {
+                envs.push((key.into(), value));
             }
         }
 
+        let mut privileged_cmd = match privilege {
+            Privilege::None => return self,
+            Privilege::Sudo => {
+                let mut sudo = Command::new("sudo");
+                sudo.arg("-n"); // non-interactive
+                if !envs.is_empty() {
+                    let mut preserve_arg = OsString::from("--preserve-env=");
+                    for (index, (key, _)) in envs.iter().enumerate() {
+                        if index > 0 {
+                            preserve_arg.push(",");
+                        }
+                        preserve_arg.push(key);
+                    }
+                    sudo.arg(preserve_arg);
+                }
+                sudo
+            }
+            Privilege::Doas => {
+                let mut doas = Command::new("doas");
+                doas.arg("-n"); // non-interactive
+                doas
+            }
+        };
+        privileged_cmd.envs(envs);
+
+        let cmd = self.cmd.as_std();
+
         privileged_cmd
             .arg(cmd.get_program())
             .args(cmd.get_args())
@@ -160,6 +286,17 @@ impl Command {
     }
 
     pub fn spawn(&mut self) -> Result<Child, CommandError> {
+        let current_dir = self.get_current_dir().map(Path::to_path_buf);
+        // Otherwise spawning fails as if the program were missing.
+        match &current_dir {
+            Some(dir) if !dir.is_dir() => {
+                return Err(CommandError::WorkingDirMissing {
+                    command: self.to_string(),
+                    current_dir: dir.clone(),
+                });
+            }
+            _ => {}
+        }
         self.cmd
             .stdin(Stdio::piped())
             .stdout(if self.stdout {
@@ -175,6 +312,7 @@ impl Command {
             .spawn()
             .map_err(|error| CommandError::Spawn {
                 command: self.to_string(),
+                current_dir,
                 error,
             })
     }
@@ -186,6 +324,42 @@ pub struct CommandOutput {
     pub status: Pin<Box<dyn Future<Output = Result<ExitStatus, CommandError>> + Send + 'static>>,
 }
 
+pub struct CheckedOutput {
+    pub stdout: ChildStdout,
+    pub stderr: CapturedStderr,
+    pub status: Pin<Box<dyn Future<Output = Result<ExitStatus, CommandError>> + Send + 'static>>,
+}
+
+/// A command's stderr, kept as it is read, so a failure can report it.
+#[derive(Debug)]
+pub struct CapturedStderr {
+    inner: ChildStderr,
+    captured: Arc<Mutex<Vec<u8>>>,
+    done: watch::Sender<bool>,
+}
+
+impl AsyncRead for CapturedStderr {
+    fn poll_read(
+        self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+        buf: &mut ReadBuf<'_>,
+    ) -> Poll<std::io::Result<()>> {
+        let this = self.get_mut();
+        let filled = buf.filled().len();
+        let had_room = buf.remaining() > 0;
+        let poll = Pin::new(&mut this.inner).poll_read(cx, buf);
+        if let Poll::Ready(Ok(())) = poll {
+            let read = &buf.filled()[filled..];
+            if read.is_empty() && had_room {
+                this.done.send_replace(true);
+            } else {
+                this.captured.lock().unwrap().extend_from_slice(read);
+            }
+        }
+        poll
+    }
+}
+
 impl Command {
     pub async fn output(&mut self) -> Result<CommandOutput, CommandError> {
         // NOTE (mw): we use spawn() because output() doesn't work
@@ -217,6 +391,45 @@ impl Command {
         })
     }
 
+    /// Like [`Command::output`], but the status fails with [`CommandError::failure`]
+    /// when the command exits unsuccessfully, classified by what it wrote to stderr.
+    ///
+    /// Stderr is still streamed. The status waits for it to be read to the end,
+    /// or dropped, so read it alongside rather than after.
+    pub async fn output_checked(&mut self) -> Result<CheckedOutput, CommandError> {
+        let CommandOutput {
+            stdout,
+            stderr,
+            status,
+        } = self.output().await?;
+
+        let captured = Arc::new(Mutex::new(Vec::new()));
+        let (done_tx, mut done_rx) = watch::channel(false);
+        let stderr = CapturedStderr {
+            inner: stderr,
+            captured: captured.clone(),
+            done: done_tx,
+        };
+
+        let command = self.to_string();
+        let status = Box::pin(async move {
+            let status = status.await?;
+            if status.success() {
+                return Ok(status);
+            }
+            // Errs once the reader is dropped, which also means no more stderr.
+            let _ = done_rx.wait_for(|done| *done).await;
+            let stderr = String::from_utf8_lossy(&captured.lock().unwrap()).to_string();
+            Err(CommandError::failure(command, stderr))
+        });
+
+        Ok(CheckedOutput {
+            stdout,
+            stderr,
+            status,
+        })
+    }
+
     pub async fn run(&mut self) -> Result<ExitStatus, CommandError> {
         let mut output = self.output().await?;
         let status = output.status.await?;
@@ -229,10 +442,7 @@ impl Command {
                 .read_to_string(&mut stderr)
                 .await
                 .map_err(CommandError::ReadStderr)?;
-            Err(CommandError::Failure {
-                command: self.to_string(),
-                stderr,
-            })
+            Err(CommandError::failure(self.to_string(), stderr))
         }
     }
 
@@ -267,10 +477,10 @@ impl Command {
         match stderr_handler(&stderr) {
             Err(error) => Ok(Err(error)),
             Ok(Some(value)) => Ok(Ok(value)),
-            Ok(None) => Err(CommandError::Failure {
-                command: self.to_string(),
-                stderr: String::from_utf8_lossy(&stderr).to_string(),
-            }),
+            Ok(None) => Err(CommandError::failure(
+                self.to_string(),
+                String::from_utf8_lossy(&stderr).to_string(),
+            )),
         }
     }
 }
@@ -296,4 +506,165 @@ mod tests {
             "lusid -a -b"
         )
     }
+
+    #[test]
+    fn test_spawn_in_missing_dir() {
+        let mut cmd = Command::new("true");
+        cmd.current_dir("/nonexistent/lusid");
+        let error = cmd.spawn().unwrap_err();
+        assert!(matches!(
+            &error,
+            CommandError::WorkingDirMissing { current_dir, .. }
+                if current_dir == Path::new("/nonexistent/lusid")
+        ));
+        assert_eq!(
+            error.to_string(),
+            "working directory /nonexistent/lusid does not exist for command: true"
+        );
+    }
+
+    #[tokio::test]
+    async fn test_spawn_error_names_dir() {
+        let mut cmd = Command::new("lusid-nonexistent-program");
+        cmd.current_dir("/");
+        let error = cmd.spawn().unwrap_err();
+        assert!(matches!(error, CommandError::Spawn { .. }));
+        assert_eq!(
+            error.to_string(),
+            "failed to spawn command: lusid-nonexistent-program (in /)"
+        );
+    }
+
+    fn apt_update() -> Command {
+        let mut cmd = Command::new("apt-get");
+        cmd.env("DEBIAN_FRONTEND", "noninteractive").arg("update");
+        cmd
+    }
+
+    #[test]
+    fn test_sudo_wraps_with_sudo_non_interactive() {
+        let cmd = apt_update().privileged(Privilege::Sudo);
+        assert_eq!(
+            cmd.to_string(),
+            "sudo -n --preserve-env=DEBIAN_FRONTEND apt-get update"
+        );
+        let envs: Vec<_> = cmd.get_envs().collect();
+        assert_eq!(
+            envs,
+            vec![(
+                OsStr::new("DEBIAN_FRONTEND"),
+                Some(OsStr::new("noninteractive"))
+            )]
+        );
+    }
+
+    #[test]
+    fn test_doas_wraps_with_doas() {
+        let cmd = apt_update().privileged(Privilege::Doas);
+        assert_eq!(cmd.to_string(), "doas -n apt-get update");
+    }
+
+    #[test]
+    fn test_sudo_preserves_env_by_name() {
+        let cmd = apt_update().privileged_with_env(
+            Privilege::Sudo,
+            &["PATH".to_string(), "LUSID_UNSET_VARIABLE".to_string()],
+        );
+        assert_eq!(
+            cmd.to_string(),
+            "sudo -n --preserve-env=DEBIAN_FRONTEND,PATH apt-get update"
+        );
+    }
+
+    #[test]
+    fn test_doas_keeps_preserved_env_off_argv() {
+        let path = std::env::var("PATH").unwrap();
+        let cmd = apt_update().privileged_with_env(
+            Privilege::Doas,
+            &["PATH".to_string(), "LUSID_UNSET_VARIABLE".to_string()],
+        );
+        assert_eq!(cmd.to_string(), "doas -n apt-get update");
+        let std_cmd = cmd.cmd.as_std();
+        assert!(std_cmd
+            .get_args()
+            .all(|arg| !arg.to_string_lossy().contains(&path)));
+        let envs: Vec<_> = cmd.get_envs().collect();
+        assert_eq!(
+            envs,
+            vec![
+                (
+                    OsStr::new("DEBIAN_FRONTEND"),
+                    Some(OsStr::new("noninteractive"))
+                ),
+                (OsStr::new("PATH"), Some(OsStr::new(&path))),
+            ]
+        );
+    }
+
+    #[test]
+    fn test_none_leaves_command_alone() {
+        let cmd = apt_update().privileged(Privilege::None);
+        assert_eq!(cmd.to_string(), "apt-get update");
+    }
+
+    #[test]
+    fn test_privilege_round_trips_through_strings() {
+        for privilege in [Privilege::None, Privilege::Sudo, Privilege::Doas] {
+            assert_eq!(
+                privilege.to_string().parse::<Privilege>().unwrap(),
+                privilege
+            );
+        }
+        assert!("su".parse::<Privilege>().is_err());
+    }
+
+    fn sh(script: &str) -> Command {
+        let mut cmd = Command::new("sh");
+        cmd.args(["-c", script]);
+        cmd
+    }
+
+    #[tokio::test]
+    async fn test_output_checked_fails_with_stderr() {
+        let mut output = sh("echo oops >&2; exit 3").output_checked().await.unwrap();
+        let mut streamed = String::new();
+        let (status, read) =
+            tokio::join!(output.status, output.stderr.read_to_string(&mut streamed));
+        read.unwrap();
+        assert_eq!(streamed, "oops\n");
+        assert!(matches!(
+            status,
+            Err(CommandError::Failure { ref command, ref stderr })
+                if command == "sh -c echo oops >&2; exit 3" && stderr == "oops\n"
+        ));
+    }
+
+    #[tokio::test]
+    async fn test_output_checked_succeeds_without_reading_stderr() {
+        let output = sh("echo fine >&2").output_checked().await.unwrap();
+        assert!(output.status.await.unwrap().success());
+
+        // A failure doesn't wait on stderr nobody will read.
+        let output = sh("exit 1").output_checked().await.unwrap();
+        drop(output.stderr);
+        assert!(matches!(
+            output.status.await,
+            Err(CommandError::Failure { .. })
+        ));
+    }
+
+    #[test]
+    fn test_failure_recognizes_sudo_password_prompt() {
+        let error = CommandError::failure(
+            "sudo -n apt-get update".into(),
+            "sudo: a password is required\n".into(),
+        );
+        assert!(matches!(
+            error,
+            CommandError::SudoPasswordRequired { ref command } if command == "sudo -n apt-get update"
+        ));
+
+        let error = CommandError::failure("apt-get update".into(), "E: failed\n".into());
+        assert!(matches!(error, CommandError::Failure { .. }));
+    }
 }
@@ -1,9 +1,11 @@
+mod logging;
 mod paths;
 
 use lusid_http::{HttpClient, HttpError};
 use thiserror::Error;
 
-pub use crate::paths::{Paths, PathsError};
+pub use crate::logging::{env_filter, install_tracing, LogFormat};
+pub use crate::paths::{Paths, PathsError, CACHE_DIR_ENV};
 
 #[derive(Error, Debug)]
 pub enum ContextError {
@@ -22,7 +24,11 @@ pub struct Context {
 
 impl Context {
     pub fn create() -> Result<Self, ContextError> {
-        let paths = Paths::create()?;
+        Self::create_with_paths(Paths::create()?)
+    }
+
+    /// A context using `paths` rather than the platform's directories.
+    pub fn create_with_paths(paths: Paths) -> Result<Self, ContextError> {
         let http = HttpClient::new()?;
         Ok(Self { paths, http })
     }
@@ -31,7 +37,70 @@ impl Context {
         &self.paths
     }
 
-    pub fn http_client(&mut self) -> &mut HttpClient {
-        &mut self.http
+    /// The shared HTTP client. Clones share one connection pool, so any
+    /// number of downloads can use it at once.
+    pub fn http_client(&self) -> HttpClient {
+        self.http.clone()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use tokio::io::{AsyncReadExt, AsyncWriteExt};
+    use tokio::net::TcpListener;
+
+    use super::*;
+
+    #[test]
+    fn create_with_paths_uses_given_cache_dir() {
+        let dir = tempfile::tempdir().unwrap();
+        let cache_dir = dir.path().join("cache");
+        let ctx = Context::create_with_paths(Paths::new(
+            dir.path().join("data"),
+            cache_dir.clone(),
+            dir.path().join("run"),
+        ))
+        .unwrap();
+        assert_eq!(ctx.paths().cache_dir(), cache_dir);
+    }
+
+    #[tokio::test]
+    async fn concurrent_downloads_share_client() {
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let url = format!("http://{}/file", listener.local_addr().unwrap());
+        tokio::spawn(async move {
+            loop {
+                let (mut stream, _) = listener.accept().await.unwrap();
+                tokio::spawn(async move {
+                    let mut buf = vec![0; 4096];
+                    let _ = stream.read(&mut buf).await;
+                    let _ = stream
+                        .write_all(
+                            b"HTTP/1.1 200 OK\r\nContent-Length: 5\r\nConnection: close\r\n\r\nhello",
+                        )
+                        .await;
+                    let _ = stream.shutdown().await;
+                });
+            }
+        });
+
+        let dir = tempfile::tempdir().unwrap();
+        let ctx = Context::create_with_paths(Paths::new(
+            dir.path().join("data"),
+            dir.path().join("cache"),
+            dir.path().join("run"),
+        ))
+        .unwrap();
+
+        let downloads = ["a", "b"].map(|name| {
+            let http_client = ctx.http_client();
+            let url = url.clone();
+            let path = dir.path().join(name);
+            tokio::spawn(async move { http_client.download_file(&url, &path).await.map(|()| path) })
+        });
+        for download in downloads {
+            let path = download.await.unwrap().unwrap();
+            assert_eq!(tokio::fs::read(path).await.unwrap(), b"hello");
+        }
     }
 }
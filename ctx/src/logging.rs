@@ -0,0 +1,47 @@
+use tracing_subscriber::{fmt, EnvFilter};
+
+/// How log lines are written to stderr.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, clap::ValueEnum)]
+pub enum LogFormat {
+    /// Plain lines for people.
+    #[default]
+    Human,
+    /// One JSON object per line, for machines.
+    Json,
+}
+
+/// Filter logs by `level` (e.g. "debug", or "info,lusid_vm=trace"), falling
+/// back to "info" if `level` doesn't parse.
+pub fn env_filter(level: &str) -> EnvFilter {
+    EnvFilter::try_new(level).unwrap_or_else(|_| EnvFilter::new("info"))
+}
+
+/// Install the global tracing subscriber, logging to stderr.
+///
+/// `color` only applies to the human format; JSON is never colored.
+pub fn install_tracing(level: &str, format: LogFormat, color: bool) {
+    let builder = fmt()
+        .with_env_filter(env_filter(level))
+        .with_target(true)
+        .with_level(true)
+        .with_writer(std::io::stderr);
+    match format {
+        LogFormat::Human => builder.with_ansi(color).init(),
+        LogFormat::Json => builder.json().with_ansi(false).init(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn env_filter_falls_back_to_info() {
+        assert_eq!(env_filter("debug").to_string(), "debug");
+        assert_eq!(
+            env_filter("info,lusid_vm=trace").to_string(),
+            "lusid_vm=trace,info"
+        );
+        assert_eq!(env_filter("lusid=loud").to_string(), "info");
+    }
+}
@@ -1,13 +1,18 @@
-// Inspiration: https://github.com/cubic-vm/cubic/blob/68566f79d72e2037bce1b75246d92e6da7b999e5/src/env/environment_factory.rs
-
 use std::{
-    env::{self, VarError},
+    env,
+    ffi::OsString,
     path::{Path, PathBuf},
 };
+
+use directories::ProjectDirs;
 use thiserror::Error;
 
 const PROJECT_NAME: &str = "lusid";
 
+/// Environment variable to use a cache directory other than the platform's,
+/// e.g. a temporary directory in tests or CI.
+pub const CACHE_DIR_ENV: &str = "LUSID_CACHE_DIR";
+
 #[derive(Debug, Clone)]
 pub struct Paths {
     data_dir: PathBuf,
@@ -17,8 +22,8 @@ pub struct Paths {
 
 #[derive(Error, Debug, Clone)]
 pub enum PathsError {
-    #[error(transparent)]
-    Var(#[from] VarError),
+    #[error("failed to find home directory")]
+    HomeDirNotFound,
 }
 
 impl Paths {
@@ -30,44 +35,27 @@ impl Paths {
         }
     }
 
-    #[cfg(target_os = "linux")]
+    /// Platform-standard directories: XDG on Linux, `~/Library` on macOS, `%APPDATA%` on Windows.
+    ///
+    /// The cache directory is [`CACHE_DIR_ENV`] instead, if that is set.
     pub fn create() -> Result<Paths, PathsError> {
-        let data_dirs: PathBuf = Self::var("XDG_DATA_HOME")
-            .or_else(|_| Self::var("HOME").map(|home| format!("{home}/.local/share")))
-            .map(From::from)?;
-        let cache_dirs: PathBuf = Self::var("XDG_CACHE_HOME")
-            .or_else(|_| Self::var("HOME").map(|home| format!("{home}/.cache")))
-            .map(From::from)?;
-        let runtime_dirs: PathBuf = Self::var("XDG_RUNTIME_DIR")
-            .or_else(|_| Self::var("UID").map(|uid| format!("/run/user/{uid}")))
-            .map(From::from)?;
-
-        Ok(Paths::new(
-            data_dirs.join(PROJECT_NAME),
-            cache_dirs.join(PROJECT_NAME),
-            runtime_dirs.join(PROJECT_NAME),
-        ))
+        Self::create_with_cache_dir(cache_dir_override(env::var_os(CACHE_DIR_ENV)))
     }
 
-    #[cfg(target_os = "macos")]
-    pub fn create() -> Result<Paths, PathsError> {
-        let home_dir: PathBuf = Self::var("HOME").map(From::from)?;
-        Ok(Paths::new(
-            home_dir.join("Library").join(PROJECT_NAME),
-            home_dir.join("Library").join("Caches").join(PROJECT_NAME),
-            home_dir.join("Library").join("Caches").join(PROJECT_NAME),
-        ))
-    }
+    /// Like [`Paths::create`], but the cache directory is `cache_dir`, if given.
+    fn create_with_cache_dir(cache_dir: Option<PathBuf>) -> Result<Paths, PathsError> {
+        let dirs = ProjectDirs::from_path(PathBuf::from(PROJECT_NAME))
+            .ok_or(PathsError::HomeDirNotFound)?;
 
-    #[cfg(target_os = "windows")]
-    pub fn create() -> Result<Paths, PathsError> {
-        let local_app_data_dir: PathBuf = Self::var("LOCALAPPDATA").map(From::from)?;
-        let temp_dir: PathBuf = Self::var("TEMP").map(From::from)?;
-        Ok(Paths::new(
-            local_app_data_dir.join(PROJECT_NAME),
-            temp_dir.join(PROJECT_NAME),
-            temp_dir.join(PROJECT_NAME),
-        ))
+        let data_dir = dirs.data_dir().to_path_buf();
+        let cache_dir = cache_dir.unwrap_or_else(|| dirs.cache_dir().to_path_buf());
+        // Only Linux has a dedicated runtime dir, and only when XDG_RUNTIME_DIR is set.
+        let runtime_dir = dirs
+            .runtime_dir()
+            .map(Path::to_path_buf)
+            .unwrap_or_else(|| cache_dir.clone());
+
+        Ok(Paths::new(data_dir, cache_dir, runtime_dir))
     }
 
     pub fn data_dir(&self) -> &Path {
@@ -79,8 +67,62 @@ impl Paths {
     pub fn runtime_dir(&self) -> &Path {
         &self.runtime_dir
     }
+}
+
+// An empty variable counts as unset.
+fn cache_dir_override(value: Option<OsString>) -> Option<PathBuf> {
+    value.filter(|value| !value.is_empty()).map(PathBuf::from)
+}
+
+#[cfg(test)]
+mod tests {
+    use lusid_store::{Store, StoreItemId};
 
-    fn var(var: &str) -> Result<String, PathsError> {
-        env::var(var).map_err(From::from)
+    use super::*;
+
+    #[cfg(any(target_os = "linux", target_os = "macos"))]
+    #[test]
+    fn dirs_end_with_project_name() {
+        // Not `Paths::create`, which would follow LUSID_CACHE_DIR if it's set.
+        let paths = Paths::create_with_cache_dir(None).unwrap();
+        assert!(paths.data_dir().ends_with(PROJECT_NAME));
+        assert!(paths.cache_dir().ends_with(PROJECT_NAME));
+        assert!(paths.runtime_dir().ends_with(PROJECT_NAME));
+    }
+
+    #[test]
+    fn cache_dir_override_ignores_empty() {
+        assert_eq!(cache_dir_override(None), None);
+        assert_eq!(cache_dir_override(Some(OsString::new())), None);
+        assert_eq!(
+            cache_dir_override(Some(OsString::from("/tmp/lusid-cache"))),
+            Some(PathBuf::from("/tmp/lusid-cache"))
+        );
+    }
+
+    #[tokio::test]
+    async fn store_reads_from_cache_dir_override() {
+        let dir = tempfile::tempdir().unwrap();
+        let override_dir = cache_dir_override(Some(dir.path().as_os_str().to_owned()));
+        let paths = Paths::create_with_cache_dir(override_dir).unwrap();
+        assert_eq!(paths.cache_dir(), dir.path());
+
+        std::fs::create_dir_all(dir.path().join("files/plans")).unwrap();
+        std::fs::write(dir.path().join("files/plans/web.lusid"), b"name: web").unwrap();
+
+        let mut store = Store::new(paths.cache_dir());
+        let id = StoreItemId::Cache(PathBuf::from("plans/web.lusid"));
+        assert_eq!(store.read(&id).await.unwrap(), b"name: web");
+    }
+
+    // Windows nests `data` and `cache` under the project dir.
+    #[cfg(target_os = "windows")]
+    #[test]
+    fn dirs_contain_project_name() {
+        // Not `Paths::create`, which would follow LUSID_CACHE_DIR if it's set.
+        let paths = Paths::create_with_cache_dir(None).unwrap();
+        for dir in [paths.data_dir(), paths.cache_dir(), paths.runtime_dir()] {
+            assert!(dir.iter().any(|part| part == PROJECT_NAME));
+        }
     }
 }
@@ -32,6 +32,13 @@ pub enum FsError {
     #[error("Copy command returned non-zero status from '{from}' to '{to}'")]
     CopyDirStatus { from: PathBuf, to: PathBuf },
 
+    #[error("Cannot copy file from '{from}' to '{to}': {source}")]
+    CopyFile {
+        from: PathBuf,
+        to: PathBuf,
+        source: std::io::Error,
+    },
+
     #[error("Cannot read directory '{path}': {source}")]
     ReadDir {
         path: PathBuf,
@@ -154,6 +161,19 @@ pub async fn copy_dir<F: AsRef<Path>, T: AsRef<Path>>(from: F, to: T) -> Result<
     }
 }
 
+pub async fn copy_file<F: AsRef<Path>, T: AsRef<Path>>(from: F, to: T) -> Result<(), FsError> {
+    let from_path = from.as_ref();
+    let to_path = to.as_ref();
+    fs::copy(from_path, to_path)
+        .await
+        .map(|_| ())
+        .map_err(|source| FsError::CopyFile {
+            from: from_path.to_path_buf(),
+            to: to_path.to_path_buf(),
+            source,
+        })
+}
+
 pub async fn read_dir<P: AsRef<Path>>(path: P) -> Result<Vec<PathBuf>, FsError> {
     let p = path.as_ref();
     let mut dir = fs::read_dir(p).await.map_err(|source| FsError::ReadDir {
@@ -174,6 +194,14 @@ pub async fn read_dir<P: AsRef<Path>>(path: P) -> Result<Vec<PathBuf>, FsError>
     Ok(entries)
 }
 
+pub async fn metadata<P: AsRef<Path>>(path: P) -> Result<std::fs::Metadata, FsError> {
+    let p = path.as_ref();
+    fs::metadata(p).await.map_err(|source| FsError::Metadata {
+        path: p.to_path_buf(),
+        source,
+    })
+}
+
 pub async fn remove_dir<P: AsRef<Path>>(path: P) -> Result<(), FsError> {
     let p = path.as_ref();
     fs::remove_dir_all(p)
@@ -264,6 +292,14 @@ pub async fn write_file<P: AsRef<Path>>(path: P, data: &[u8]) -> Result<(), FsEr
         })
 }
 
+pub async fn read_file<P: AsRef<Path>>(path: P) -> Result<Vec<u8>, FsError> {
+    let p = path.as_ref();
+    fs::read(p).await.map_err(|source| FsError::ReadFile {
+        path: p.to_path_buf(),
+        source,
+    })
+}
+
 pub async fn read_file_to_string<P: AsRef<Path>>(path: P) -> Result<String, FsError> {
     let p = path.as_ref();
     fs::read_to_string(p)
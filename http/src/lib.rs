@@ -1,11 +1,13 @@
 use std::ffi::OsString;
 use std::path::{Path, PathBuf};
+use std::time::Duration;
 
 use lusid_fs::{self as fs, FsError};
-use reqwest::Client;
+use reqwest::{header, Client, StatusCode};
 use thiserror::Error;
 use tokio::io::AsyncWriteExt;
 use tokio_stream::StreamExt;
+use tracing::warn;
 
 const REQUEST_TIMEOUT_SEC: u64 = 10;
 
@@ -28,11 +30,81 @@ pub enum HttpError {
 
     #[error(transparent)]
     Fs(#[from] FsError),
+
+    #[error("Download of '{url}' failed after {attempts} attempts: {source}")]
+    RetriesExhausted {
+        url: String,
+        attempts: u32,
+        #[source]
+        source: Box<HttpError>,
+    },
+}
+
+impl HttpError {
+    // Network failures and server errors may be transient; client errors and
+    // local file errors won't go away by retrying.
+    fn is_retryable(&self) -> bool {
+        match self {
+            HttpError::Request(error) => error.status().is_none_or(|status| {
+                status.is_server_error()
+                    || status == StatusCode::REQUEST_TIMEOUT
+                    || status == StatusCode::TOO_MANY_REQUESTS
+            }),
+            HttpError::Stream(_) => true,
+            _ => false,
+        }
+    }
+}
+
+/// How many times to attempt a download, and how long to wait in between.
+///
+/// The wait doubles after each failed attempt, up to `max_backoff`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct RetryPolicy {
+    pub max_attempts: u32,
+    pub initial_backoff: Duration,
+    pub max_backoff: Duration,
+}
+
+impl Default for RetryPolicy {
+    fn default() -> Self {
+        Self {
+            max_attempts: 5,
+            initial_backoff: Duration::from_millis(500),
+            max_backoff: Duration::from_secs(10),
+        }
+    }
+}
+
+impl RetryPolicy {
+    /// Wait before the attempt after `attempt` (1-based) failed.
+    pub fn backoff(&self, attempt: u32) -> Duration {
+        let factor = 2u32.saturating_pow(attempt.saturating_sub(1));
+        self.initial_backoff
+            .saturating_mul(factor)
+            .min(self.max_backoff)
+    }
+}
+
+/// Receives each chunk of a download as it's written, e.g. to hash it on the fly.
+pub trait DownloadSink {
+    fn update(&mut self, bytes: &[u8]);
+
+    /// The download restarted from the beginning, so forget everything seen so far.
+    fn reset(&mut self);
+}
+
+impl DownloadSink for () {
+    fn update(&mut self, _bytes: &[u8]) {}
+
+    fn reset(&mut self) {}
 }
 
+/// Cheap to clone: clones share one connection pool.
 #[derive(Debug, Clone)]
 pub struct HttpClient {
     client: Client,
+    retry: RetryPolicy,
 }
 
 impl HttpClient {
@@ -43,7 +115,15 @@ impl HttpClient {
             .brotli(true)
             .build()
             .map_err(HttpError::BuildClient)?;
-        Ok(HttpClient { client })
+        Ok(HttpClient {
+            client,
+            retry: RetryPolicy::default(),
+        })
+    }
+
+    pub fn with_retry(mut self, retry: RetryPolicy) -> Self {
+        self.retry = retry;
+        self
     }
 
     #[allow(dead_code)]
@@ -66,6 +146,18 @@ impl HttpClient {
         &self,
         url: &str,
         file_path: P,
+    ) -> Result<(), HttpError> {
+        self.download_file_to(url, file_path, &mut ()).await
+    }
+
+    /// Download to `file_path`, passing every byte written through `sink`.
+    ///
+    /// Does nothing, and so passes nothing to `sink`, if the file already exists.
+    pub async fn download_file_to<P: AsRef<Path>>(
+        &self,
+        url: &str,
+        file_path: P,
+        sink: &mut impl DownloadSink,
     ) -> Result<(), HttpError> {
         let file_path = file_path.as_ref();
         if fs::path_exists(file_path).await? {
@@ -77,32 +169,76 @@ impl HttpClient {
             fs::remove_file(&temp_file).await?;
         }
 
-        let resp = self
-            .client
-            .get(url)
-            .send()
+        let mut attempt = 1;
+        loop {
+            match self.download_attempt(url, &temp_file, sink).await {
+                Ok(()) => break,
+                Err(error) if error.is_retryable() && attempt < self.retry.max_attempts => {
+                    let backoff = self.retry.backoff(attempt);
+                    warn!(url, attempt, ?backoff, "download failed, retrying: {error}");
+                    tokio::time::sleep(backoff).await;
+                    attempt += 1;
+                }
+                Err(error) if error.is_retryable() => {
+                    return Err(HttpError::RetriesExhausted {
+                        url: url.to_string(),
+                        attempts: attempt,
+                        source: Box::new(error),
+                    });
+                }
+                Err(error) => return Err(error),
+            }
+        }
+
+        fs::rename_file(&temp_file, file_path).await?;
+        Ok(())
+    }
+
+    // Download into `temp_file`, resuming from whatever a previous attempt left behind.
+    async fn download_attempt(
+        &self,
+        url: &str,
+        temp_file: &Path,
+        sink: &mut impl DownloadSink,
+    ) -> Result<(), HttpError> {
+        let write_error = |source| HttpError::Write {
+            path: temp_file.to_path_buf(),
+            source,
+        };
+
+        let mut file = tokio::fs::OpenOptions::new()
+            .create(true)
+            .append(true)
+            .open(temp_file)
             .await
-            .map_err(HttpError::Request)?;
+            .map_err(write_error)?;
+        let offset = file.metadata().await.map_err(write_error)?.len();
 
-        let mut file = fs::create_file(&temp_file).await?;
-        let mut stream = resp.bytes_stream();
+        let mut request = self.client.get(url);
+        if offset > 0 {
+            request = request.header(header::RANGE, format!("bytes={offset}-"));
+        }
+        let resp = request.send().await.map_err(HttpError::Request)?;
+
+        if resp.status() == StatusCode::RANGE_NOT_SATISFIABLE && offset > 0 {
+            // Everything was already downloaded.
+            return Ok(());
+        }
+        let resp = resp.error_for_status().map_err(HttpError::Request)?;
+        if offset > 0 && resp.status() != StatusCode::PARTIAL_CONTENT {
+            // The server ignored the range, so start over.
+            file.set_len(0).await.map_err(write_error)?;
+            sink.reset();
+        }
 
+        let mut stream = resp.bytes_stream();
         while let Some(chunk) = stream.next().await {
             let bytes = chunk.map_err(HttpError::Stream)?;
-            file.write_all(&bytes)
-                .await
-                .map_err(|source| HttpError::Write {
-                    path: temp_file.clone(),
-                    source,
-                })?;
+            file.write_all(&bytes).await.map_err(write_error)?;
+            sink.update(&bytes);
         }
 
-        file.flush().await.map_err(|source| HttpError::Write {
-            path: temp_file.clone(),
-            source,
-        })?;
-
-        fs::rename_file(&temp_file, file_path).await?;
+        file.flush().await.map_err(write_error)?;
         Ok(())
     }
 
@@ -129,3 +265,113 @@ fn with_added_extension(path: &Path, added: &str) -> PathBuf {
     new_ext.push(added);
     path.with_extension(new_ext)
 }
+
+#[cfg(test)]
+mod tests {
+    use std::sync::{Arc, Mutex};
+
+    use tokio::io::AsyncReadExt;
+    use tokio::net::TcpListener;
+
+    use super::*;
+
+    const BODY: &[u8] = b"0123456789";
+
+    impl DownloadSink for Vec<u8> {
+        fn update(&mut self, bytes: &[u8]) {
+            self.extend_from_slice(bytes);
+        }
+
+        fn reset(&mut self) {
+            self.clear();
+        }
+    }
+
+    fn client(max_attempts: u32) -> HttpClient {
+        HttpClient::new().unwrap().with_retry(RetryPolicy {
+            max_attempts,
+            initial_backoff: Duration::from_millis(1),
+            max_backoff: Duration::from_millis(1),
+        })
+    }
+
+    /// Serve one canned response per connection, recording each request's head.
+    async fn serve(responses: Vec<Vec<u8>>) -> (String, Arc<Mutex<Vec<String>>>) {
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let url = format!("http://{}/image", listener.local_addr().unwrap());
+        let requests = Arc::new(Mutex::new(Vec::new()));
+        let recorded = requests.clone();
+        tokio::spawn(async move {
+            let mut responses = responses.into_iter().cycle();
+            loop {
+                let (mut stream, _) = listener.accept().await.unwrap();
+                let mut buf = vec![0; 4096];
+                let len = stream.read(&mut buf).await.unwrap();
+                recorded
+                    .lock()
+                    .unwrap()
+                    .push(String::from_utf8_lossy(&buf[..len]).to_lowercase());
+                let response = responses.next().unwrap();
+                let _ = stream.write_all(&response).await;
+                let _ = stream.shutdown().await;
+            }
+        });
+        (url, requests)
+    }
+
+    fn response(status: &str, headers: &str, body: &[u8], content_length: usize) -> Vec<u8> {
+        let mut response = format!(
+            "HTTP/1.1 {status}\r\nContent-Length: {content_length}\r\nConnection: close\r\n{headers}\r\n"
+        )
+        .into_bytes();
+        response.extend_from_slice(body);
+        response
+    }
+
+    #[tokio::test]
+    async fn download_resumes_after_failures() {
+        let (url, requests) = serve(vec![
+            // Drop the connection partway through the body.
+            response("200 OK", "", &BODY[..4], BODY.len()),
+            response("503 Service Unavailable", "", b"", 0),
+            response(
+                "206 Partial Content",
+                "Content-Range: bytes 4-9/10\r\n",
+                &BODY[4..],
+                6,
+            ),
+        ])
+        .await;
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("image.qcow2");
+
+        let mut seen = Vec::new();
+        client(3)
+            .download_file_to(&url, &path, &mut seen)
+            .await
+            .unwrap();
+
+        assert_eq!(tokio::fs::read(&path).await.unwrap(), BODY);
+        assert_eq!(seen, BODY);
+        let requests = requests.lock().unwrap();
+        assert_eq!(requests.len(), 3);
+        assert!(!requests[0].contains("range:"));
+        assert!(requests[2].contains("range: bytes=4-"));
+    }
+
+    #[tokio::test]
+    async fn download_gives_up_after_max_attempts() {
+        let (url, requests) = serve(vec![response("500 Internal Server Error", "", b"", 0)]).await;
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("image.qcow2");
+
+        let error = client(3).download_file(&url, &path).await.unwrap_err();
+
+        assert!(matches!(
+            error,
+            HttpError::RetriesExhausted { attempts: 3, .. }
+        ));
+        assert_eq!(requests.lock().unwrap().len(), 3);
+        assert!(!fs::path_exists(&path).await.unwrap());
+    }
+}
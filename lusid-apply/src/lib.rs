@@ -1,21 +1,70 @@
+mod lock;
+mod parallel;
+mod params;
+mod stage;
+
+use std::{
+    cell::Cell,
+    collections::HashSet,
+    future::Future,
+    path::{Path, PathBuf},
+    time::{Duration, Instant},
+};
+
 use lusid_apply_stdio::AppUpdate;
-use lusid_causality::{compute_epochs, CausalityTree, EpochError};
+use lusid_causality::{compute_epochs, compute_target_epochs, CausalityTree, EpochError};
 use lusid_ctx::{Context, ContextError};
-use lusid_operation::{Operation, OperationApplyError};
-use lusid_params::{ParamValues, ParamValuesFromTypeError};
-use lusid_plan::{self, map_plan_subitems, plan, render_plan_tree, PlanError, PlanId, PlanNodeId};
-use lusid_resource::{Resource, ResourceState, ResourceStateError};
+use lusid_operation::{ApplyContext, Operation, OperationApplyError, Privilege};
+use lusid_params::{Diagnostic, ParamValues, SourceRegistry};
+use lusid_plan::{
+    self, map_plan_subitems, plan_within, render_plan_tree, PlanError, PlanFlatTree, PlanId,
+    PlanNodeId,
+};
+use lusid_resource::{Resource, ResourceChange, ResourceParams, ResourceState, ResourceStateError};
 use lusid_store::Store;
-use lusid_tree::FlatTree;
+use lusid_tree::{FlatTree, FlatTreeNode, Tree};
 use lusid_view::Render;
-use rimu::SourceId;
+use rimu::Spanned;
 use thiserror::Error;
 use tokio::io::{AsyncBufReadExt, AsyncWriteExt, BufReader};
+use tokio_util::sync::CancellationToken;
 use tracing::{debug, error, info};
 
+use crate::lock::ApplyLock;
+use crate::parallel::for_each_bounded;
+use crate::stage::{record_nodes, Stage, StageTimings};
+
+pub use crate::lock::ApplyLockError;
+pub use crate::params::{ParamsFormat, ParamsInput, ParamsInputError, ParamsOverride};
+
 pub struct ApplyOptions {
     pub plan_id: PlanId,
-    pub params_json: Option<String>,
+    pub params: Option<ParamsInput>,
+    /// Overrides from `--set`, merged on top of `params`.
+    pub overrides: Vec<ParamsOverride>,
+    /// Most operations to apply at once within an epoch.
+    pub max_parallel: usize,
+    /// Only apply the plan item with this id, and whatever it depends on.
+    pub target: Option<String>,
+    /// Version of lusid the plan must be compatible with.
+    pub tool_version: semver::Version,
+    /// Directory the plan's includes must stay within. Default: the working
+    /// directory if the plan is within it, otherwise the plan's own directory.
+    pub include_root: Option<PathBuf>,
+    /// How long to wait for another apply of the same plan to finish.
+    pub lock_timeout: Duration,
+    /// How operations that need root escalate.
+    pub privilege: Privilege,
+    /// Environment variables to carry over when escalating.
+    pub preserve_env: Vec<String>,
+    /// Log why each resource change is needed, at info level.
+    pub explain: bool,
+    /// Also append every update to this file, so it can be followed after
+    /// whoever is reading stdout goes away.
+    pub update_log: Option<PathBuf>,
+    /// Once cancelled, no more operations start. Those running finish, and
+    /// apply returns [`ApplyError::Cancelled`].
+    pub cancel: CancellationToken,
 }
 
 #[derive(Error, Debug)]
@@ -23,9 +72,6 @@ pub enum ApplyError {
     #[error(transparent)]
     Context(#[from] ContextError),
 
-    #[error("failed to parse JSON parameters: {0}")]
-    JsonParameters(#[source] serde_json::Error),
-
     #[error("failed to output JSON: {0}")]
     JsonOutput(#[source] serde_json::Error),
 
@@ -38,8 +84,17 @@ pub enum ApplyError {
     #[error("failed to flush stdout: {0}")]
     FlushStdout(#[source] tokio::io::Error),
 
-    #[error("failed to convert parameters for Lusid: {0}")]
-    ParamValuesFromType(#[from] ParamValuesFromTypeError),
+    #[error("failed to write update log '{path}': {source}")]
+    UpdateLog {
+        path: PathBuf,
+        source: tokio::io::Error,
+    },
+
+    #[error(transparent)]
+    Lock(#[from] ApplyLockError),
+
+    #[error(transparent)]
+    Params(#[from] ParamsInputError),
 
     #[error(transparent)]
     Plan(#[from] PlanError),
@@ -47,66 +102,388 @@ pub enum ApplyError {
     #[error(transparent)]
     Epoch(#[from] EpochError<PlanNodeId>),
 
+    #[error("no plan item with id: {target}")]
+    UnknownTarget { target: String },
+
     #[error(transparent)]
     ResourceState(#[from] ResourceStateError),
 
     #[error(transparent)]
     OperationApply(#[from] OperationApplyError),
+
+    #[error("{} operations failed, first: {}", .0.len(), .0[0])]
+    Operations(Vec<ApplyError>),
+
+    #[error("apply cancelled")]
+    Cancelled,
 }
 
-pub async fn apply(options: ApplyOptions) -> Result<(), ApplyError> {
+impl ApplyError {
+    /// Located diagnostics, for errors that point into plan or params source.
+    pub fn diagnostics(&self) -> Vec<Diagnostic> {
+        match self {
+            ApplyError::Plan(error) => error.diagnostics(),
+            ApplyError::Params(ParamsInputError::ParamValuesFromType(error)) => error.diagnostics(),
+            _ => Vec::new(),
+        }
+    }
+}
+
+/// Apply a plan. Plan and params text is registered in `sources`, for
+/// rendering the diagnostics of any error.
+pub async fn apply(options: ApplyOptions, sources: &mut SourceRegistry) -> Result<(), ApplyError> {
     info!("starting");
+    let started_at = Instant::now();
     let ApplyOptions {
         plan_id,
-        params_json,
+        params,
+        overrides,
+        max_parallel,
+        target,
+        tool_version,
+        include_root,
+        lock_timeout,
+        privilege,
+        preserve_env,
+        explain,
+        update_log,
+        cancel,
     } = options;
+    let apply_ctx = ApplyContext {
+        privilege,
+        preserve_env,
+    };
 
     let ctx = Context::create()?;
+    let lock_path = ApplyLock::path(ctx.paths().runtime_dir(), &plan_id.to_string());
+    let _lock = ApplyLock::acquire(lock_path, lock_timeout).await?;
+    if let Some(path) = update_log {
+        open_update_log(path).await?;
+    }
     let mut store = Store::new(ctx.paths().cache_dir());
 
     info!(plan = %plan_id, "using plan");
 
-    let param_values = match params_json {
-        None => {
-            info!("no parameters provided");
-            None
-        }
-        Some(json) => {
-            let value: serde_json::Value =
-                serde_json::from_str(&json).map_err(ApplyError::JsonParameters)?;
-            let source_id = SourceId::from("<cli:params>".to_string());
-            let params = ParamValues::from_type(value, source_id)?;
-            Some(params)
-        }
+    let param_values = match params {
+        None => None,
+        Some(params) => Some(params.load(sources).await?),
+    };
+    let param_values = if overrides.is_empty() {
+        param_values
+    } else {
+        let overlay = ParamsOverride::to_param_values(&overrides)?;
+        Some(match param_values {
+            None => overlay,
+            Some(base) => {
+                let (base, span) = base.take();
+                Spanned::new(base.merge(overlay.into_inner()), span)
+            }
+        })
     };
+    if param_values.is_none() {
+        info!("no parameters provided");
+    }
+
+    let mut timings = StageTimings::default();
+    let include_root = include_root.unwrap_or_else(|| plan_id.default_root());
+    let resource_params = timings
+        .run(
+            Stage::Plan,
+            plan_stage(
+                plan_id,
+                &include_root,
+                param_values,
+                &tool_version,
+                &mut store,
+                sources,
+                &emit,
+            ),
+        )
+        .await?;
+    let PlannedChanges {
+        items,
+        resources: resources_count,
+        changes: resource_changes,
+    } = plan_changes(resource_params, explain, &mut timings, &emit).await?;
+    if let Some(target) = target.as_ref().filter(|target| !items.contains(*target)) {
+        return Err(ApplyError::UnknownTarget {
+            target: target.clone(),
+        });
+    }
+    let changed = count_leaves(&resource_changes);
+    let unchanged = resources_count.saturating_sub(changed);
+
+    if resource_changes.is_empty() {
+        return report_no_changes(resources_count, started_at, &timings).await;
+    };
+
+    let operations = timings
+        .run(Stage::Operations, operations_stage(resource_changes, &emit))
+        .await?;
+
+    let operation_epochs = timings
+        .run(Stage::Epochs, async {
+            let operations = CausalityTree::from(operations);
+            let operation_epochs = match target.as_deref() {
+                None => compute_epochs(operations)?,
+                // The target is known to be in the plan, so without operations
+                // it and what it depends on are up to date.
+                Some(target) => {
+                    match compute_target_epochs(operations, |id| id.plan_item_id() == Some(target))
+                    {
+                        Err(EpochError::NoTarget) => Vec::new(),
+                        epochs => epochs?,
+                    }
+                }
+            };
+            let operation_epochs = merge_epochs(operation_epochs);
+            debug!("Operation epochs: {operation_epochs:?}");
+            record_nodes(operation_epochs.len());
+            Ok::<_, ApplyError>(operation_epochs)
+        })
+        .await?;
+
+    if operation_epochs.is_empty() {
+        // Operations were already reported, so close out the apply phase
+        // before the summary, as a view would otherwise still be waiting on it.
+        emit(AppUpdate::OperationsApplyStart {
+            operations: Vec::new(),
+        })
+        .await?;
+        emit(AppUpdate::OperationsApplyComplete).await?;
+        return report_no_changes(resources_count, started_at, &timings).await;
+    }
+
+    if cancel.is_cancelled() {
+        info!("cancelled before applying operations");
+        return Err(ApplyError::Cancelled);
+    }
+
+    let failures = timings
+        .run(
+            Stage::ApplyOperations,
+            apply_epochs(operation_epochs, max_parallel, &cancel, &apply_ctx, &emit),
+        )
+        .await?;
+
+    emit(AppUpdate::Summary {
+        changed,
+        unchanged,
+        failed: failures.len(),
+        duration_ms: elapsed_ms(started_at),
+    })
+    .await?;
+    debug!(%timings, "stage timings");
+
+    let mut failures = failures.into_iter();
+    match (failures.next(), failures.next()) {
+        (None, _) if cancel.is_cancelled() => Err(ApplyError::Cancelled),
+        (None, _) => {
+            info!("Apply completed");
+            Ok(())
+        }
+        (Some(apply_error), None) => Err(apply_error),
+        (Some(first), Some(second)) => Err(ApplyError::Operations(
+            [first, second].into_iter().chain(failures).collect(),
+        )),
+    }
+}
+
+/// Plan as far as operations, returning them without applying any.
+///
+/// Resource states are read to work out what needs to change, but nothing is
+/// changed and no updates are emitted. The library counterpart of planning
+/// from the command line.
+pub async fn plan_operations(
+    plan_id: PlanId,
+    param_values: Option<Spanned<ParamValues>>,
+    tool_version: &semver::Version,
+    store: &mut Store,
+    sources: &mut SourceRegistry,
+) -> Result<CausalityTree<Operation, PlanNodeId>, ApplyError> {
+    let root = plan_id.default_root();
+    let resource_params = plan_stage(
+        plan_id,
+        &root,
+        param_values,
+        tool_version,
+        store,
+        sources,
+        &skip_update,
+    )
+    .await?;
+    let PlannedChanges { changes, .. } = plan_changes(
+        resource_params,
+        false,
+        &mut StageTimings::default(),
+        &skip_update,
+    )
+    .await?;
+    let operations = operations_stage(changes, &skip_update).await?;
+    Ok(CausalityTree::from(operations))
+}
+
+/// Report that there's nothing to apply, as every resource is up to date.
+async fn report_no_changes(
+    resources: usize,
+    started_at: Instant,
+    timings: &StageTimings,
+) -> Result<(), ApplyError> {
+    info!("No changes to apply!");
+    emit(AppUpdate::Summary {
+        changed: 0,
+        unchanged: resources,
+        failed: 0,
+        duration_ms: elapsed_ms(started_at),
+    })
+    .await?;
+    debug!(%timings, "stage timings");
+    Ok(())
+}
+
+/// Emit nothing, for planning without an apply to report on.
+async fn skip_update(_update: AppUpdate) -> Result<(), ApplyError> {
+    Ok(())
+}
+
+/// Resource changes planned from a plan's resource params, with the ids of
+/// its items and how many resources there were.
+struct PlannedChanges {
+    items: HashSet<String>,
+    resources: usize,
+    changes: PlanFlatTree<ResourceChange>,
+}
 
-    // Parse/evaluate to tree of resource params.
-    let resource_params = plan(plan_id, param_values, &mut store).await?;
+/// Plan from resource params as far as resource changes, timing each stage
+/// and reporting its progress through `emit`.
+async fn plan_changes<E, F>(
+    resource_params: PlanFlatTree<ResourceParams>,
+    explain: bool,
+    timings: &mut StageTimings,
+    emit: &E,
+) -> Result<PlannedChanges, ApplyError>
+where
+    E: Fn(AppUpdate) -> F,
+    F: Future<Output = Result<(), ApplyError>>,
+{
+    let items = plan_item_ids(&resource_params);
+    let resources = timings
+        .run(Stage::Resources, resources_stage(resource_params, emit))
+        .await?;
+    let resource_states = timings
+        .run(
+            Stage::ResourceStates,
+            resource_states_stage(resources, emit),
+        )
+        .await?;
+    let resources = count_leaves(&resource_states);
+    let changes = timings
+        .run(
+            Stage::ResourceChanges,
+            resource_changes_stage(resource_states, explain, emit),
+        )
+        .await?;
+    Ok(PlannedChanges {
+        items,
+        resources,
+        changes,
+    })
+}
+
+/// Parse and evaluate the plan to a tree of resource params, with includes
+/// from anywhere within `root`.
+async fn plan_stage<E, F>(
+    plan_id: PlanId,
+    root: &Path,
+    param_values: Option<Spanned<ParamValues>>,
+    tool_version: &semver::Version,
+    store: &mut Store,
+    sources: &mut SourceRegistry,
+    emit: &E,
+) -> Result<PlanFlatTree<ResourceParams>, ApplyError>
+where
+    E: Fn(AppUpdate) -> F,
+    F: Future<Output = Result<(), ApplyError>>,
+{
+    let resource_params =
+        plan_within(plan_id, root, param_values, tool_version, store, sources).await?;
     debug!("Resource params: {resource_params:?}");
     emit(AppUpdate::ResourceParams {
         resource_params: render_plan_tree(resource_params.clone()),
     })
     .await?;
     let resource_params = FlatTree::from(resource_params);
+    record_nodes(count_leaves(&resource_params));
+    Ok(resource_params)
+}
 
-    // Get tree of atomic resources.
+/// Expand resource params to a tree of atomic resources.
+async fn resources_stage<E, F>(
+    resource_params: PlanFlatTree<ResourceParams>,
+    emit: &E,
+) -> Result<PlanFlatTree<Resource>, ApplyError>
+where
+    E: Fn(AppUpdate) -> F,
+    F: Future<Output = Result<(), ApplyError>>,
+{
     emit(AppUpdate::ResourcesStart).await?;
     let resources = resource_params
-        .map_tree(
-            |node, meta| map_plan_subitems(node, meta, |node| node.resources()),
-            |index, tree| {
-                emit(AppUpdate::ResourcesNode {
-                    index,
-                    tree: render_plan_tree(tree),
-                })
+        .map_tree_result_async(
+            |node, meta| async move {
+                Ok::<_, ApplyError>(map_plan_subitems(node, meta, |node| node.resources()))
+            },
+            |index| emit(AppUpdate::ResourcesNodeStart { index }),
+            // Stream each resource on its own, after the (empty) branch holding them.
+            |index, tree| async move {
+                match tree {
+                    Tree::Branch { meta, children } => {
+                        emit(AppUpdate::ResourcesNode {
+                            index,
+                            tree: render_plan_tree::<Resource>(Tree::Branch {
+                                meta,
+                                children: Vec::new(),
+                            }),
+                        })
+                        .await?;
+                        for child in children {
+                            emit(AppUpdate::ResourcesNodeChild {
+                                index,
+                                tree: render_plan_tree(child),
+                            })
+                            .await?;
+                        }
+                        Ok(())
+                    }
+                    tree => {
+                        emit(AppUpdate::ResourcesNode {
+                            index,
+                            tree: render_plan_tree(tree),
+                        })
+                        .await
+                    }
+                }
             },
         )
         .await?;
     debug!("Resources: {:?}", CausalityTree::from(resources.clone()));
     emit(AppUpdate::ResourcesComplete).await?;
+    record_nodes(count_leaves(&resources));
+    Ok(resources)
+}
 
-    // Get tree of (resource, resource state)
+/// Read the current state of each resource.
+async fn resource_states_stage<E, F>(
+    resources: PlanFlatTree<Resource>,
+    emit: &E,
+) -> Result<PlanFlatTree<(Resource, ResourceState)>, ApplyError>
+where
+    E: Fn(AppUpdate) -> F,
+    F: Future<Output = Result<(), ApplyError>>,
+{
     emit(AppUpdate::ResourceStatesStart).await?;
+    let total = count_leaves(&resources);
+    let done = Cell::new(0);
+    emit(AppUpdate::ResourceStatesProgress { done: 0, total }).await?;
     let resource_states = resources
         .map_result_async(
             |resource| async move {
@@ -115,10 +492,16 @@ pub async fn apply(options: ApplyOptions) -> Result<(), ApplyError> {
             },
             |index| emit(AppUpdate::ResourceStatesNodeStart { index }),
             |index, (_resource, resource_state)| {
-                emit(AppUpdate::ResourceStatesNodeComplete {
-                    index,
-                    node: resource_state.render(),
-                })
+                done.set(done.get() + 1);
+                let progress = AppUpdate::ResourceStatesProgress {
+                    done: done.get(),
+                    total,
+                };
+                let node = resource_state.render();
+                async move {
+                    emit(AppUpdate::ResourceStatesNodeComplete { index, node }).await?;
+                    emit(progress).await
+                }
             },
         )
         .await?;
@@ -127,12 +510,30 @@ pub async fn apply(options: ApplyOptions) -> Result<(), ApplyError> {
         CausalityTree::from(resource_states.clone()).map(|(_resource, state)| state)
     );
     emit(AppUpdate::ResourceStatesComplete).await?;
+    record_nodes(count_leaves(&resource_states));
+    Ok(resource_states)
+}
 
-    // Get tree of resource changes
+/// Work out the change each resource needs to reach its params, if any.
+async fn resource_changes_stage<E, F>(
+    resource_states: PlanFlatTree<(Resource, ResourceState)>,
+    explain: bool,
+    emit: &E,
+) -> Result<PlanFlatTree<ResourceChange>, ApplyError>
+where
+    E: Fn(AppUpdate) -> F,
+    F: Future<Output = Result<(), ApplyError>>,
+{
     emit(AppUpdate::ResourceChangesStart).await?;
     let resource_changes = resource_states
         .map_option(
-            |(resource, state)| resource.change(&state),
+            |(resource, state)| {
+                let change = resource.change(&state);
+                if let Some(change) = change.as_ref().filter(|_| explain) {
+                    info!("{resource}: {}", resource.explain(&state, change));
+                }
+                change
+            },
             |index, node| {
                 emit(AppUpdate::ResourceChangesNode {
                     index,
@@ -149,13 +550,19 @@ pub async fn apply(options: ApplyOptions) -> Result<(), ApplyError> {
         has_changes: !resource_changes.is_empty(),
     })
     .await?;
+    record_nodes(count_leaves(&resource_changes));
+    Ok(resource_changes)
+}
 
-    if resource_changes.is_empty() {
-        info!("No changes to apply!");
-        return Ok(());
-    };
-
-    // Get CausalityTree<Operations>
+/// Turn each resource change into the operations that make it.
+async fn operations_stage<E, F>(
+    resource_changes: PlanFlatTree<ResourceChange>,
+    emit: &E,
+) -> Result<PlanFlatTree<Operation>, ApplyError>
+where
+    E: Fn(AppUpdate) -> F,
+    F: Future<Output = Result<(), ApplyError>>,
+{
     emit(AppUpdate::OperationsStart).await?;
     let operations = resource_changes
         .map_tree(
@@ -173,9 +580,36 @@ pub async fn apply(options: ApplyOptions) -> Result<(), ApplyError> {
         CausalityTree::from(operations.clone())
     );
     emit(AppUpdate::OperationsComplete).await?;
+    record_nodes(count_leaves(&operations));
+    Ok(operations)
+}
 
-    let operation_epochs = compute_epochs(CausalityTree::from(operations))?;
-    debug!("Operation epochs: {operation_epochs:?}");
+/// Split each epoch into the groups merging its operations gives.
+///
+/// Merging orders some operations within an epoch, so each group becomes an
+/// epoch of its own.
+fn merge_epochs(operation_epochs: Vec<Vec<Operation>>) -> Vec<Vec<Operation>> {
+    operation_epochs
+        .into_iter()
+        .flat_map(Operation::merge)
+        .collect()
+}
+
+/// Apply each epoch in turn, with the operations within one side by side.
+///
+/// Returns the operations that failed. Later epochs depend on earlier ones,
+/// so none start after one with a failure, or once `cancel` is cancelled.
+async fn apply_epochs<E, F>(
+    operation_epochs: Vec<Vec<Operation>>,
+    max_parallel: usize,
+    cancel: &CancellationToken,
+    apply_ctx: &ApplyContext,
+    emit: &E,
+) -> Result<Vec<ApplyError>, ApplyError>
+where
+    E: Fn(AppUpdate) -> F,
+    F: Future<Output = Result<(), ApplyError>>,
+{
     emit(AppUpdate::OperationsApplyStart {
         operations: operation_epochs
             .iter()
@@ -185,6 +619,8 @@ pub async fn apply(options: ApplyOptions) -> Result<(), ApplyError> {
     .await?;
 
     let epochs_count = operation_epochs.len();
+    let mut operations_count = 0;
+    let mut failures: Vec<ApplyError> = Vec::new();
     for (epoch_index, operations) in operation_epochs.into_iter().enumerate() {
         info!(
             epoch = epoch_index,
@@ -192,85 +628,416 @@ pub async fn apply(options: ApplyOptions) -> Result<(), ApplyError> {
             "processing epoch"
         );
         debug!("Operations: {operations:?}");
+        operations_count += operations.len();
 
-        let operations = Operation::merge(operations);
-        debug!("Merged operations: {operations:?}");
+        failures = for_each_bounded(
+            operations.iter().enumerate(),
+            max_parallel,
+            cancel,
+            |(operation_index, operation)| async move {
+                let index = (epoch_index, operation_index);
 
-        for (operation_index, operation) in operations.iter().enumerate() {
-            let index = (epoch_index, operation_index);
+                emit(AppUpdate::OperationApplyStart { index }).await?;
 
-            emit(AppUpdate::OperationApplyStart { index }).await?;
+                let (applied, duration_ms) =
+                    timed(apply_operation(index, operation, apply_ctx, emit)).await;
+                if let Err(apply_error) = applied {
+                    error!(
+                        epoch = epoch_index,
+                        operation = operation_index,
+                        "{apply_error}"
+                    );
+                    emit(AppUpdate::OperationApplyFailed {
+                        index,
+                        error: apply_error.to_string(),
+                    })
+                    .await?;
+                    return Err(apply_error);
+                }
 
-            let (output, stdout, stderr) = operation.apply().await?;
+                emit(AppUpdate::OperationApplyComplete { index, duration_ms }).await
+            },
+        )
+        .await;
 
-            let output_task = async {
-                output.await?;
-                Ok::<(), ApplyError>(())
-            };
+        if !failures.is_empty() {
+            break;
+        }
+        if cancel.is_cancelled() {
+            info!(epoch = epoch_index, "cancelled, not starting later epochs");
+            break;
+        }
+    }
 
-            let stdout_task = {
-                let mut lines = BufReader::new(stdout).lines();
-                async move {
-                    while let Some(line) = lines
-                        .next_line()
-                        .await
-                        .map_err(ApplyError::ReadOperationStdio)?
-                    {
-                        emit(AppUpdate::OperationApplyStdout {
-                            index,
-                            stdout: line,
-                        })
-                        .await?;
-                    }
-                    Ok::<(), ApplyError>(())
-                }
-            };
+    emit(AppUpdate::OperationsApplyComplete).await?;
+    record_nodes(operations_count);
+    Ok(failures)
+}
 
-            let stderr_task = {
-                let mut lines = BufReader::new(stderr).lines();
-                async move {
-                    while let Some(line) = lines
-                        .next_line()
-                        .await
-                        .map_err(ApplyError::ReadOperationStdio)?
-                    {
-                        emit(AppUpdate::OperationApplyStderr {
-                            index,
-                            stderr: line,
-                        })
-                        .await?;
-                    }
-                    Ok::<(), ApplyError>(())
-                }
-            };
+async fn apply_operation<E, F>(
+    index: (usize, usize),
+    operation: &Operation,
+    ctx: &ApplyContext,
+    emit: &E,
+) -> Result<(), ApplyError>
+where
+    E: Fn(AppUpdate) -> F,
+    F: Future<Output = Result<(), ApplyError>>,
+{
+    let (output, stdout, stderr) = operation.apply(ctx).await?;
 
-            tokio::try_join!(output_task, stdout_task, stderr_task)?;
+    let output_task = async {
+        output.await?;
+        Ok::<(), ApplyError>(())
+    };
 
-            emit(AppUpdate::OperationApplyComplete {
-                index: (epoch_index, operation_index),
-            })
-            .await?;
+    let stdout_task = {
+        let mut lines = BufReader::new(stdout).lines();
+        async move {
+            while let Some(line) = lines
+                .next_line()
+                .await
+                .map_err(ApplyError::ReadOperationStdio)?
+            {
+                emit(AppUpdate::OperationApplyStdout {
+                    index,
+                    stdout: line,
+                })
+                .await?;
+            }
+            Ok::<(), ApplyError>(())
         }
-    }
+    };
+
+    let stderr_task = {
+        let mut lines = BufReader::new(stderr).lines();
+        async move {
+            while let Some(line) = lines
+                .next_line()
+                .await
+                .map_err(ApplyError::ReadOperationStdio)?
+            {
+                emit(AppUpdate::OperationApplyStderr {
+                    index,
+                    stderr: line,
+                })
+                .await?;
+            }
+            Ok::<(), ApplyError>(())
+        }
+    };
+
+    tokio::try_join!(output_task, stdout_task, stderr_task)?;
 
-    info!("Apply completed");
+    Ok(())
+}
+
+fn plan_item_ids<Node: Clone>(tree: &PlanFlatTree<Node>) -> HashSet<String> {
+    tree.depth_first_search()
+        .into_iter()
+        .filter_map(|index| match tree.get(index) {
+            Ok(FlatTreeNode::Branch { meta, .. } | FlatTreeNode::Leaf { meta, .. }) => {
+                meta.id.as_ref()?.plan_item_id().map(str::to_string)
+            }
+            Err(_) => None,
+        })
+        .collect()
+}
+
+fn count_leaves<Node, Meta>(tree: &FlatTree<Node, Meta>) -> usize
+where
+    Node: Clone,
+    Meta: Clone,
+{
+    tree.depth_first_search()
+        .into_iter()
+        .filter(|index| matches!(tree.get(*index), Ok(FlatTreeNode::Leaf { .. })))
+        .count()
+}
+
+fn elapsed_ms(started_at: Instant) -> u64 {
+    u64::try_from(started_at.elapsed().as_millis()).unwrap_or(u64::MAX)
+}
+
+/// Run `future`, returning its output and how long it took in milliseconds.
+async fn timed<F: std::future::Future>(future: F) -> (F::Output, u64) {
+    let started_at = Instant::now();
+    let output = future.await;
+    (output, elapsed_ms(started_at))
+}
+
+struct UpdateLog {
+    path: PathBuf,
+    file: tokio::fs::File,
+}
+
+// Operations run concurrently, so hold this while writing to keep lines whole.
+static OUTPUT: tokio::sync::Mutex<Option<UpdateLog>> = tokio::sync::Mutex::const_new(None);
+
+/// Start a fresh update log at `path`, which every later update is appended to.
+async fn open_update_log(path: PathBuf) -> Result<(), ApplyError> {
+    let update_log_error = |source| ApplyError::UpdateLog {
+        path: path.clone(),
+        source,
+    };
+    if let Some(parent) = path.parent() {
+        tokio::fs::create_dir_all(parent)
+            .await
+            .map_err(update_log_error)?;
+    }
+    let file = tokio::fs::File::create(&path)
+        .await
+        .map_err(update_log_error)?;
+    *OUTPUT.lock().await = Some(UpdateLog { path, file });
     Ok(())
 }
 
 async fn emit(update: AppUpdate) -> Result<(), ApplyError> {
+    let mut line = serde_json::to_vec(&update).map_err(ApplyError::JsonOutput)?;
+    line.push(b'\n');
+
+    let mut update_log = OUTPUT.lock().await;
+    if let Some(UpdateLog { path, file }) = update_log.as_mut() {
+        file.write_all(&line)
+            .await
+            .map_err(|source| ApplyError::UpdateLog {
+                path: path.clone(),
+                source,
+            })?;
+    }
+
     let mut stdout = tokio::io::stdout();
+    let written = match stdout.write_all(&line).await {
+        Ok(()) => stdout.flush().await.map_err(ApplyError::FlushStdout),
+        Err(error) => Err(ApplyError::WriteStdout(error)),
+    };
+    match written {
+        // Whoever was reading stdout went away, but can follow the log instead.
+        Err(error) if update_log.is_some() => {
+            debug!("updates only written to log: {error}");
+            Ok(())
+        }
+        written => written,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{os::unix::fs::PermissionsExt, sync::Mutex};
+
+    use lusid_causality::CausalityMeta;
+    use lusid_operation::operations::{command::CommandOperation, file::FileOperation};
+    use lusid_plan::PlanTree;
+
+    use super::*;
+
+    fn tool_version() -> semver::Version {
+        semver::Version::new(0, 1, 0)
+    }
 
-    stdout
-        .write_all(&serde_json::to_vec(&update).map_err(ApplyError::JsonOutput)?)
+    fn leaves(tree: &PlanTree<Operation>) -> Vec<String> {
+        match tree {
+            PlanTree::Branch { children, .. } => children.iter().flat_map(leaves).collect(),
+            PlanTree::Leaf { node, .. } => vec![node.to_string()],
+        }
+    }
+
+    #[tokio::test]
+    async fn plans_operations_without_applying() {
+        let dir = tempfile::tempdir().unwrap();
+        let motd = dir.path().join("motd");
+        let plan_id = PlanId::Inline {
+            id: "motd.lusid".to_string(),
+            source: format!(
+                "\
+name: \"motd\"
+setup: () =>
+  - module: \"@core/file\"
+    id: \"motd\"
+    params:
+      path: \"{}\"
+      content: \"hello\"
+",
+                motd.display()
+            ),
+        };
+        let mut store = Store::new(&dir.path().join("store"));
+
+        let operations = plan_operations(
+            plan_id,
+            None,
+            &tool_version(),
+            &mut store,
+            &mut SourceRegistry::new(),
+        )
         .await
-        .map_err(ApplyError::WriteStdout)?;
+        .unwrap();
 
-    stdout
-        .write_all(b"\n")
+        assert_eq!(
+            leaves(&operations),
+            vec![format!("File::WriteFile({}, 5 bytes)", motd.display())]
+        );
+        assert!(!motd.exists());
+    }
+
+    #[tokio::test]
+    async fn knows_items_without_changes() {
+        let dir = tempfile::tempdir().unwrap();
+        let motd = dir.path().join("motd");
+        // Already up to date, so the target has nothing to do but is still known.
+        std::fs::write(&motd, "hello").unwrap();
+        let plan_id = PlanId::Inline {
+            id: "motd.lusid".to_string(),
+            source: format!(
+                "\
+name: \"motd\"
+setup: () =>
+  - module: \"@core/file\"
+    id: \"motd\"
+    params:
+      path: \"{}\"
+      content: \"hello\"
+",
+                motd.display()
+            ),
+        };
+        let mut store = Store::new(&dir.path().join("store"));
+        let resource_params = plan_stage(
+            plan_id,
+            dir.path(),
+            None,
+            &tool_version(),
+            &mut store,
+            &mut SourceRegistry::new(),
+            &skip_update,
+        )
         .await
-        .map_err(ApplyError::WriteStdout)?;
+        .unwrap();
 
-    stdout.flush().await.map_err(ApplyError::FlushStdout)?;
+        let planned = plan_changes(
+            resource_params,
+            false,
+            &mut StageTimings::default(),
+            &skip_update,
+        )
+        .await
+        .unwrap();
 
-    Ok(())
+        assert!(planned.items.contains("motd"));
+        assert!(planned.changes.is_empty());
+    }
+
+    #[tokio::test]
+    async fn reports_operations_that_exit_unsuccessfully() {
+        let operations: CausalityTree<Operation, PlanNodeId> = CausalityTree::leaf(
+            CausalityMeta::default(),
+            Operation::Command(CommandOperation::Run {
+                command: "echo broken >&2; exit 3".to_string(),
+            }),
+        );
+
+        let epochs = merge_epochs(compute_epochs(operations).unwrap());
+        let updates = Mutex::new(Vec::new());
+        let failures = apply_epochs(
+            epochs,
+            4,
+            &CancellationToken::new(),
+            &ApplyContext {
+                privilege: Privilege::None,
+                preserve_env: Vec::new(),
+            },
+            &|update| {
+                updates.lock().unwrap().push(update);
+                std::future::ready(Ok::<(), ApplyError>(()))
+            },
+        )
+        .await
+        .unwrap();
+
+        assert_eq!(failures.len(), 1);
+        let updates = updates.lock().unwrap();
+        assert!(updates.iter().any(|update| matches!(
+            update,
+            AppUpdate::OperationApplyFailed { index: (0, 0), .. }
+        )));
+        assert!(!updates
+            .iter()
+            .any(|update| matches!(update, AppUpdate::OperationApplyComplete { .. })));
+    }
+
+    #[tokio::test]
+    async fn applies_merged_file_operations_in_order() {
+        let dir = tempfile::tempdir().unwrap();
+        let conf_dir = dir.path().join("app/conf.d");
+        let conf = conf_dir.join("app.conf");
+        // One epoch, where each operation needs the one after it applied first.
+        let operations: CausalityTree<Operation, PlanNodeId> = CausalityTree::branch(
+            CausalityMeta::default(),
+            vec![
+                FileOperation::ChangeMode {
+                    path: conf.clone(),
+                    mode: 0o600,
+                },
+                FileOperation::WriteFile {
+                    path: conf.clone(),
+                    content: b"a = 1".to_vec(),
+                },
+                FileOperation::CreateDirectory {
+                    path: conf_dir.clone(),
+                },
+                FileOperation::CreateDirectory {
+                    path: dir.path().join("app"),
+                },
+            ]
+            .into_iter()
+            .map(|operation| {
+                CausalityTree::leaf(CausalityMeta::default(), Operation::File(operation))
+            })
+            .collect(),
+        );
+
+        let epochs = merge_epochs(compute_epochs(operations).unwrap());
+        let updates = Mutex::new(Vec::new());
+        let failures = apply_epochs(
+            epochs,
+            4,
+            &CancellationToken::new(),
+            &ApplyContext {
+                privilege: Privilege::None,
+                preserve_env: Vec::new(),
+            },
+            &|update| {
+                updates.lock().unwrap().push(update);
+                std::future::ready(Ok::<(), ApplyError>(()))
+            },
+        )
+        .await
+        .unwrap();
+
+        assert!(failures.is_empty(), "{failures:?}");
+        assert_eq!(std::fs::read(&conf).unwrap(), b"a = 1");
+        let mode = std::fs::metadata(&conf).unwrap().permissions().mode();
+        assert_eq!(mode & 0o777, 0o600);
+        let completed = updates
+            .lock()
+            .unwrap()
+            .iter()
+            .filter(|update| matches!(update, AppUpdate::OperationApplyComplete { .. }))
+            .count();
+        assert_eq!(completed, 4);
+    }
+
+    #[tokio::test]
+    async fn times_operations() {
+        // Stands in for an operation that takes a while to apply.
+        let operation = async {
+            tokio::time::sleep(Duration::from_millis(20)).await;
+            Ok::<(), ApplyError>(())
+        };
+
+        let (applied, duration_ms) = timed(operation).await;
+
+        assert!(applied.is_ok());
+        assert!(duration_ms >= 20, "took {duration_ms}ms");
+    }
 }
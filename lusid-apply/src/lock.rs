@@ -0,0 +1,191 @@
+use std::{
+    io::ErrorKind,
+    path::{Path, PathBuf},
+    time::{Duration, Instant},
+};
+
+use nix::{sys::signal::kill, unistd::Pid};
+use thiserror::Error;
+use tracing::{debug, warn};
+
+const POLL_INTERVAL: Duration = Duration::from_millis(100);
+
+#[derive(Error, Debug)]
+pub enum ApplyLockError {
+    #[error("another apply is in progress (lock held at '{path}')")]
+    Held { path: PathBuf },
+
+    #[error("failed to create lock '{path}': {source}")]
+    Create {
+        path: PathBuf,
+        source: std::io::Error,
+    },
+}
+
+/// Held for the length of an apply, so only one runs per plan at a time.
+///
+/// The lock is a directory: creating one is atomic, so whoever creates it
+/// holds the lock, and dropping the lock removes it. It records the pid of
+/// its holder, so a lock left behind by an apply that died is taken over.
+#[derive(Debug)]
+pub(crate) struct ApplyLock {
+    path: PathBuf,
+}
+
+impl ApplyLock {
+    /// Lock file for `key` in `dir`, with anything unsafe in a file name replaced.
+    pub(crate) fn path(dir: &Path, key: &str) -> PathBuf {
+        let key: String = key
+            .chars()
+            .map(|c| if c.is_ascii_alphanumeric() { c } else { '_' })
+            .collect();
+        dir.join(format!("apply-{key}.lock"))
+    }
+
+    /// Take the lock, waiting up to `timeout` for another apply to release it.
+    pub(crate) async fn acquire(path: PathBuf, timeout: Duration) -> Result<Self, ApplyLockError> {
+        if let Some(parent) = path.parent() {
+            tokio::fs::create_dir_all(parent)
+                .await
+                .map_err(|source| ApplyLockError::Create {
+                    path: path.clone(),
+                    source,
+                })?;
+        }
+
+        let started_at = Instant::now();
+        loop {
+            match tokio::fs::create_dir(&path).await {
+                Ok(()) => {
+                    // Dropped on error, which removes the half-made lock.
+                    let lock = ApplyLock { path };
+                    tokio::fs::write(lock.pid_path(), std::process::id().to_string())
+                        .await
+                        .map_err(|source| ApplyLockError::Create {
+                            path: lock.path.clone(),
+                            source,
+                        })?;
+                    debug!(path = %lock.path.display(), "acquired apply lock");
+                    return Ok(lock);
+                }
+                Err(error) if error.kind() == ErrorKind::AlreadyExists => {
+                    if reclaim_if_stale(&path).await {
+                        continue;
+                    }
+                    let remaining = timeout.saturating_sub(started_at.elapsed());
+                    if remaining.is_zero() {
+                        return Err(ApplyLockError::Held { path });
+                    }
+                    tokio::time::sleep(POLL_INTERVAL.min(remaining)).await;
+                }
+                Err(source) => return Err(ApplyLockError::Create { path, source }),
+            }
+        }
+    }
+}
+
+impl ApplyLock {
+    fn pid_path(&self) -> PathBuf {
+        pid_path(&self.path)
+    }
+}
+
+fn pid_path(lock_path: &Path) -> PathBuf {
+    lock_path.join("pid")
+}
+
+/// Remove the lock at `path` if the apply holding it has died.
+///
+/// A lock without a readable pid is still being made, so it's left alone.
+async fn reclaim_if_stale(path: &Path) -> bool {
+    let Ok(pid) = tokio::fs::read_to_string(pid_path(path)).await else {
+        return false;
+    };
+    let Ok(pid) = pid.trim().parse::<i32>() else {
+        return false;
+    };
+    if is_pid_running(Pid::from_raw(pid)) {
+        return false;
+    }
+    warn!(path = %path.display(), pid, "reclaiming apply lock left by a dead process");
+    match tokio::fs::remove_dir_all(path).await {
+        Ok(()) => true,
+        // Another apply reclaimed it first.
+        Err(error) if error.kind() == ErrorKind::NotFound => true,
+        Err(error) => {
+            debug!(path = %path.display(), "failed to reclaim apply lock: {error}");
+            false
+        }
+    }
+}
+
+/// Signal 0 checks whether the process exists without affecting it.
+fn is_pid_running(pid: Pid) -> bool {
+    kill(pid, None).is_ok()
+}
+
+impl Drop for ApplyLock {
+    fn drop(&mut self) {
+        if let Err(error) = std::fs::remove_dir_all(&self.path) {
+            debug!(path = %self.path.display(), "failed to release apply lock: {error}");
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[tokio::test]
+    async fn second_acquire_fails_while_held() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = ApplyLock::path(dir.path(), "/plans/web.lusid");
+
+        let lock = ApplyLock::acquire(path.clone(), Duration::ZERO)
+            .await
+            .unwrap();
+        let error = ApplyLock::acquire(path.clone(), Duration::from_millis(150))
+            .await
+            .unwrap_err();
+        assert!(matches!(error, ApplyLockError::Held { .. }));
+
+        drop(lock);
+        ApplyLock::acquire(path, Duration::ZERO).await.unwrap();
+    }
+
+    #[tokio::test]
+    async fn records_holder_pid() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = ApplyLock::path(dir.path(), "web");
+
+        let lock = ApplyLock::acquire(path, Duration::ZERO).await.unwrap();
+        let pid = std::fs::read_to_string(lock.pid_path()).unwrap();
+        assert_eq!(pid, std::process::id().to_string());
+    }
+
+    #[tokio::test]
+    async fn reclaims_lock_of_dead_process() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = ApplyLock::path(dir.path(), "web");
+
+        let mut child = std::process::Command::new("true").spawn().unwrap();
+        let dead_pid = child.id();
+        child.wait().unwrap();
+        std::fs::create_dir(&path).unwrap();
+        std::fs::write(pid_path(&path), dead_pid.to_string()).unwrap();
+
+        let lock = ApplyLock::acquire(path, Duration::ZERO).await.unwrap();
+        let pid = std::fs::read_to_string(lock.pid_path()).unwrap();
+        assert_eq!(pid, std::process::id().to_string());
+    }
+
+    #[tokio::test]
+    async fn keeps_lock_being_made() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = ApplyLock::path(dir.path(), "web");
+        std::fs::create_dir(&path).unwrap();
+
+        let error = ApplyLock::acquire(path, Duration::ZERO).await.unwrap_err();
+        assert!(matches!(error, ApplyLockError::Held { .. }));
+    }
+}
@@ -1,10 +1,14 @@
 use clap::Parser;
+use lusid_ctx::{install_tracing, LogFormat};
+use lusid_params::SourceRegistry;
 use lusid_plan::PlanId;
-use std::path::PathBuf;
-use tracing::{debug, error};
-use tracing_subscriber::{fmt, EnvFilter};
+use lusid_view::detect_color;
+use std::{path::PathBuf, time::Duration};
+use tokio_util::sync::CancellationToken;
+use tracing::{debug, error, warn};
 
-use lusid_apply::{apply, ApplyOptions};
+use lusid_apply::{apply, ApplyOptions, ParamsFormat, ParamsInput, ParamsOverride};
+use lusid_operation::Privilege;
 
 #[derive(Parser, Debug)]
 #[command(name = "lusid-apply", about = "Apply a Lusid plan.", version)]
@@ -13,19 +17,82 @@ struct Cli {
     #[arg(long = "plan")]
     plan_path: PathBuf,
 
-    /// Parameters as a JSON string (top-level object).
+    /// Parameters as a JSON, TOML, or YAML string (top-level object).
     #[arg(long = "params")]
-    params_json: Option<String>,
+    params: Option<String>,
+
+    /// Format of `--params`. Default: detected from the input.
+    #[arg(long = "params-format", value_enum, requires = "params")]
+    params_format: Option<ParamsFormat>,
+
+    /// Path to a parameters file (.json, .toml, .yaml, or .yml).
+    #[arg(long = "params-file", conflicts_with = "params")]
+    params_file: Option<PathBuf>,
+
+    /// Override a parameter, as key=value. Dotted keys set nested fields.
+    /// May be repeated; applied on top of `--params` or `--params-file`.
+    #[arg(long = "set", value_name = "KEY=VALUE")]
+    set: Vec<ParamsOverride>,
+
+    /// Most operations to apply at once within an epoch.
+    #[arg(long = "max-parallel", default_value_t = 4)]
+    max_parallel: usize,
+
+    /// Only apply the plan item with this id, and whatever it depends on.
+    #[arg(long = "target", value_name = "ID")]
+    target: Option<String>,
+
+    /// Version of lusid the plan must be compatible with, as lusid runs this. Default: this
+    /// binary's version.
+    #[arg(long = "tool-version", value_name = "VERSION")]
+    tool_version: Option<semver::Version>,
+
+    /// Directory the plan's includes must stay within. Default: the working directory if the
+    /// plan is within it, otherwise the plan's directory.
+    #[arg(long = "include-root", value_name = "DIR")]
+    include_root: Option<PathBuf>,
+
+    /// Seconds to wait for another apply of the same plan to finish. Default: fail at once.
+    #[arg(long = "lock-timeout", value_name = "SECONDS", default_value_t = 0)]
+    lock_timeout: u64,
+
+    /// How operations that need root escalate: none, sudo, or doas.
+    #[arg(long = "privilege", default_value_t)]
+    privilege: Privilege,
+
+    /// Carry this environment variable over when escalating. May be repeated.
+    #[arg(long = "preserve-env", value_name = "NAME")]
+    preserve_env: Vec<String>,
+
+    /// Log why each change is needed: current state, desired state, and the difference.
+    #[arg(long = "explain")]
+    explain: bool,
+
+    /// Also append every update to this file, to follow after stdout goes away.
+    #[arg(long = "update-log", value_name = "PATH")]
+    update_log: Option<PathBuf>,
 
     /// Log level (e.g., trace, debug, info, warn, error). Default: info.
     #[arg(long = "log", default_value = "info")]
     log: String,
+
+    /// Format of log lines on stderr.
+    #[arg(long = "log-format", value_enum, default_value_t)]
+    log_format: LogFormat,
+
+    /// Disable colored logs (also disabled by NO_COLOR, or when stderr is not a terminal).
+    #[arg(long = "no-color")]
+    no_color: bool,
 }
 
 #[tokio::main]
 async fn main() {
     let cli = Cli::parse();
-    install_tracing(&cli.log);
+    install_tracing(
+        &cli.log,
+        cli.log_format,
+        detect_color(cli.no_color, &std::io::stderr()),
+    );
     debug!(cli = ?cli, "parsed cli");
 
     let plan_path = cli
@@ -33,24 +100,67 @@ async fn main() {
         .canonicalize()
         .unwrap_or(cli.plan_path.clone());
     let plan_id = PlanId::Path(plan_path.clone());
+    // Canonical like the plan path, so includes compare against it.
+    let include_root = cli
+        .include_root
+        .map(|root| root.canonicalize().unwrap_or(root));
+    let tool_version = cli.tool_version.unwrap_or_else(|| {
+        semver::Version::parse(env!("CARGO_PKG_VERSION")).expect("crate version is valid semver")
+    });
+    let params = match (cli.params, cli.params_file) {
+        (Some(input), _) => Some(ParamsInput::Inline {
+            input,
+            format: cli.params_format,
+        }),
+        (None, Some(path)) => Some(ParamsInput::File(path)),
+        (None, None) => None,
+    };
     let options = ApplyOptions {
         plan_id,
-        params_json: cli.params_json,
+        params,
+        overrides: cli.set,
+        max_parallel: cli.max_parallel,
+        target: cli.target,
+        tool_version,
+        include_root,
+        lock_timeout: Duration::from_secs(cli.lock_timeout),
+        privilege: cli.privilege,
+        preserve_env: cli.preserve_env,
+        explain: cli.explain,
+        update_log: cli.update_log,
+        cancel: cancel_on_interrupt(),
     };
 
-    if let Err(err) = apply(options).await {
+    let mut sources = SourceRegistry::new();
+    if let Err(err) = apply(options, &mut sources).await {
         error!("{err}");
+        for diagnostic in err.diagnostics() {
+            if let Some(rendered) = sources.render(&diagnostic) {
+                eprintln!("{rendered}");
+            }
+        }
         std::process::exit(1);
     }
 }
 
-fn install_tracing(level: &str) {
-    let filter = EnvFilter::try_new(level).unwrap_or_else(|_| EnvFilter::new("info"));
-    fmt()
-        .with_env_filter(filter)
-        .with_target(true)
-        .with_level(true)
-        .with_ansi(true)
-        .with_writer(std::io::stderr)
-        .init();
+/// Cancel on the first Ctrl-C, letting running operations finish. Exit at once
+/// on the second.
+fn cancel_on_interrupt() -> CancellationToken {
+    let cancel = CancellationToken::new();
+    tokio::spawn({
+        let cancel = cancel.clone();
+        async move {
+            if tokio::signal::ctrl_c().await.is_err() {
+                return;
+            }
+            warn!(
+                "cancelling: waiting for running operations to finish, interrupt again to stop now"
+            );
+            cancel.cancel();
+            if tokio::signal::ctrl_c().await.is_ok() {
+                std::process::exit(130);
+            }
+        }
+    });
+    cancel
 }
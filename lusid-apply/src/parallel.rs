@@ -0,0 +1,98 @@
+use std::future::Future;
+
+use futures_util::future::join_all;
+use tokio::sync::Semaphore;
+use tokio_util::sync::CancellationToken;
+
+/// Run `f` for every item with at most `max_parallel` running at once.
+///
+/// Every item runs to completion even if others fail, and all the errors are
+/// returned, in item order. Once `cancel` is cancelled, items not yet started
+/// are skipped, while those running finish.
+pub(crate) async fn for_each_bounded<T, E, F, Fut>(
+    items: impl IntoIterator<Item = T>,
+    max_parallel: usize,
+    cancel: &CancellationToken,
+    f: F,
+) -> Vec<E>
+where
+    F: Fn(T) -> Fut,
+    Fut: Future<Output = Result<(), E>>,
+{
+    let semaphore = Semaphore::new(max_parallel.max(1));
+    let tasks = items.into_iter().map(|item| {
+        let semaphore = &semaphore;
+        let task = f(item);
+        async move {
+            let _permit = semaphore
+                .acquire()
+                .await
+                .expect("semaphore is never closed");
+            if cancel.is_cancelled() {
+                return Ok(());
+            }
+            task.await
+        }
+    });
+    join_all(tasks)
+        .await
+        .into_iter()
+        .filter_map(Result::err)
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::atomic::{AtomicUsize, Ordering};
+    use std::time::Duration;
+
+    use super::*;
+
+    #[tokio::test]
+    async fn overlaps_up_to_limit_and_collects_errors() {
+        let running = AtomicUsize::new(0);
+        let max_running = AtomicUsize::new(0);
+
+        let errors = for_each_bounded(0..6, 3, &CancellationToken::new(), |item| {
+            let (running, max_running) = (&running, &max_running);
+            async move {
+                let now = running.fetch_add(1, Ordering::SeqCst) + 1;
+                max_running.fetch_max(now, Ordering::SeqCst);
+                tokio::time::sleep(Duration::from_millis(20)).await;
+                running.fetch_sub(1, Ordering::SeqCst);
+                if item % 2 == 0 {
+                    Err(item)
+                } else {
+                    Ok(())
+                }
+            }
+        })
+        .await;
+
+        assert_eq!(max_running.load(Ordering::SeqCst), 3);
+        assert_eq!(errors, vec![0, 2, 4]);
+    }
+
+    #[tokio::test]
+    async fn cancelling_stops_items_starting() {
+        let cancel = CancellationToken::new();
+        let started = std::sync::Mutex::new(Vec::new());
+
+        let errors = for_each_bounded(0..5, 2, &cancel, |item| {
+            let (cancel, started) = (&cancel, &started);
+            async move {
+                started.lock().unwrap().push(item);
+                if item == 1 {
+                    cancel.cancel();
+                }
+                // Still running when cancelled, so finishes.
+                tokio::time::sleep(Duration::from_millis(20)).await;
+                Err::<(), _>(item)
+            }
+        })
+        .await;
+
+        assert_eq!(*started.lock().unwrap(), vec![0, 1]);
+        assert_eq!(errors, vec![0, 1]);
+    }
+}
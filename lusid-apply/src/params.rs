@@ -0,0 +1,255 @@
+use std::{
+    path::{Path, PathBuf},
+    str::FromStr,
+};
+
+use lusid_params::{ParamValues, ParamValuesFromTypeError, SourceRegistry};
+use rimu::{SourceId, Spanned};
+use serde_json::{Map, Value};
+use thiserror::Error;
+
+/// Where parameter values come from on the command line.
+#[derive(Debug, Clone)]
+pub enum ParamsInput {
+    /// Inline JSON, TOML, or YAML, from `--params`.
+    Inline {
+        input: String,
+        format: Option<ParamsFormat>,
+    },
+    /// A JSON, TOML, or YAML file, from `--params-file`.
+    File(PathBuf),
+}
+
+#[derive(Error, Debug)]
+pub enum ParamsInputError {
+    #[error("failed to read params file {path}: {source}")]
+    ReadFile {
+        path: PathBuf,
+        #[source]
+        source: std::io::Error,
+    },
+
+    #[error("unknown params file extension for {path}: expected .json, .toml, .yaml, or .yml")]
+    UnknownExtension { path: PathBuf },
+
+    #[error("failed to parse JSON parameters: {0}")]
+    Json(#[from] serde_json::Error),
+
+    #[error("failed to parse TOML parameters: {0}")]
+    Toml(#[from] toml::de::Error),
+
+    #[error("failed to parse YAML parameters: {0}")]
+    Yaml(#[from] serde_saphyr::Error),
+
+    #[error("invalid --set {0:?}: expected key=value")]
+    InvalidSet(String),
+
+    #[error("failed to convert parameters for Lusid: {0}")]
+    ParamValuesFromType(#[from] ParamValuesFromTypeError),
+}
+
+/// Formats parameter values can be written in.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, clap::ValueEnum)]
+pub enum ParamsFormat {
+    Json,
+    Toml,
+    Yaml,
+}
+
+impl ParamsFormat {
+    pub fn from_path(path: &Path) -> Option<Self> {
+        match path.extension()?.to_str()? {
+            "json" => Some(ParamsFormat::Json),
+            "toml" => Some(ParamsFormat::Toml),
+            "yaml" | "yml" => Some(ParamsFormat::Yaml),
+            _ => None,
+        }
+    }
+
+    /// Guess the format of inline params: JSON objects start with `{`, and
+    /// anything that isn't valid TOML is treated as YAML.
+    pub fn detect(input: &str) -> Self {
+        if input.trim_start().starts_with('{') {
+            ParamsFormat::Json
+        } else if toml::from_str::<toml::Table>(input).is_ok() {
+            ParamsFormat::Toml
+        } else {
+            ParamsFormat::Yaml
+        }
+    }
+
+    pub fn parse(self, input: &str) -> Result<Value, ParamsInputError> {
+        Ok(match self {
+            ParamsFormat::Json => serde_json::from_str(input)?,
+            ParamsFormat::Toml => toml::from_str(input)?,
+            ParamsFormat::Yaml => serde_saphyr::from_str(input)?,
+        })
+    }
+}
+
+impl ParamsInput {
+    /// Load the params, registering their text in `sources` for diagnostics.
+    pub async fn load(
+        &self,
+        sources: &mut SourceRegistry,
+    ) -> Result<Spanned<ParamValues>, ParamsInputError> {
+        let (value, source_id) = match self {
+            ParamsInput::Inline { input, format } => {
+                let format = format.unwrap_or_else(|| ParamsFormat::detect(input));
+                let source_id = SourceId::from("<cli:params>".to_string());
+                sources.insert(&source_id, input.as_str());
+                (format.parse(input)?, source_id)
+            }
+            ParamsInput::File(path) => {
+                let format = ParamsFormat::from_path(path)
+                    .ok_or_else(|| ParamsInputError::UnknownExtension { path: path.clone() })?;
+                let contents = tokio::fs::read_to_string(path).await.map_err(|source| {
+                    ParamsInputError::ReadFile {
+                        path: path.clone(),
+                        source,
+                    }
+                })?;
+                let source_id = SourceId::from(path.display().to_string());
+                sources.insert(&source_id, contents.as_str());
+                (format.parse(&contents)?, source_id)
+            }
+        };
+        Ok(ParamValues::from_type(value, source_id)?)
+    }
+}
+
+/// A single `--set key=value` override.
+///
+/// Dotted keys (`ssh.port=2222`) set nested object fields. Values are parsed
+/// as YAML scalars, so `true`, `22`, and `[a, b]` keep their types; anything
+/// else is a string.
+#[derive(Debug, Clone, PartialEq)]
+pub struct ParamsOverride {
+    path: Vec<String>,
+    value: Value,
+}
+
+impl FromStr for ParamsOverride {
+    type Err = ParamsInputError;
+
+    fn from_str(input: &str) -> Result<Self, Self::Err> {
+        let invalid = || ParamsInputError::InvalidSet(input.to_string());
+        let (key, value) = input.split_once('=').ok_or_else(invalid)?;
+        let path: Vec<String> = key.split('.').map(str::to_string).collect();
+        if path.iter().any(String::is_empty) {
+            return Err(invalid());
+        }
+        let value = if value.is_empty() {
+            Value::String(String::new())
+        } else {
+            serde_saphyr::from_str(value).unwrap_or_else(|_| Value::String(value.to_string()))
+        };
+        Ok(ParamsOverride { path, value })
+    }
+}
+
+impl ParamsOverride {
+    /// Collect overrides into a single overlay, later overrides winning.
+    pub fn to_param_values(
+        overrides: &[ParamsOverride],
+    ) -> Result<Spanned<ParamValues>, ParamsInputError> {
+        let mut root = Map::new();
+        for ParamsOverride { path, value } in overrides {
+            let (last, parents) = path.split_last().expect("path is never empty");
+            let mut object = &mut root;
+            for key in parents {
+                let entry = object
+                    .entry(key.clone())
+                    .or_insert_with(|| Value::Object(Map::new()));
+                if !entry.is_object() {
+                    *entry = Value::Object(Map::new());
+                }
+                object = entry.as_object_mut().expect("entry is an object");
+            }
+            object.insert(last.clone(), value.clone());
+        }
+        Ok(ParamValues::from_type(
+            Value::Object(root),
+            SourceId::from("<cli:set>".to_string()),
+        )?)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    async fn load_file(name: &str, contents: &str) -> Value {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join(name);
+        tokio::fs::write(&path, contents).await.unwrap();
+        let params = ParamsInput::File(path)
+            .load(&mut SourceRegistry::new())
+            .await
+            .unwrap();
+        params.into_inner().into_type().unwrap()
+    }
+
+    #[tokio::test]
+    async fn json_and_yaml_files_are_equivalent() {
+        let json = load_file(
+            "params.json",
+            r#"{ "user": "lusid", "packages": ["git", "curl"] }"#,
+        )
+        .await;
+        let yaml = load_file("params.yaml", "user: lusid\npackages:\n  - git\n  - curl\n").await;
+
+        assert_eq!(json, yaml);
+        assert_eq!(json["user"], "lusid");
+    }
+
+    const JSON: &str = r#"{ "user": "lusid", "port": 22, "packages": ["git"] }"#;
+    const TOML: &str = "user = \"lusid\"\nport = 22\npackages = [\"git\"]\n";
+    const YAML: &str = "user: lusid\nport: 22\npackages:\n  - git\n";
+
+    #[test]
+    fn formats_parse_identically() {
+        let json = ParamsFormat::Json.parse(JSON).unwrap();
+        assert_eq!(ParamsFormat::Toml.parse(TOML).unwrap(), json);
+        assert_eq!(ParamsFormat::Yaml.parse(YAML).unwrap(), json);
+    }
+
+    #[test]
+    fn detect_inline_format() {
+        assert_eq!(ParamsFormat::detect(JSON), ParamsFormat::Json);
+        assert_eq!(ParamsFormat::detect(TOML), ParamsFormat::Toml);
+        assert_eq!(ParamsFormat::detect(YAML), ParamsFormat::Yaml);
+    }
+
+    #[tokio::test]
+    async fn unknown_extension() {
+        let result = ParamsInput::File(PathBuf::from("params.ini"))
+            .load(&mut SourceRegistry::new())
+            .await;
+        assert!(matches!(
+            result,
+            Err(ParamsInputError::UnknownExtension { .. })
+        ));
+    }
+
+    #[test]
+    fn set_overrides_nest_and_keep_types() {
+        let overrides: Vec<ParamsOverride> = ["user=lusid", "ssh.port=2222", "ssh.enabled=true"]
+            .into_iter()
+            .map(|set| set.parse().unwrap())
+            .collect();
+        let params = ParamsOverride::to_param_values(&overrides).unwrap();
+        let value: Value = params.into_inner().into_type().unwrap();
+        assert_eq!(value["user"], "lusid");
+        assert_eq!(value["ssh"]["enabled"], true);
+        // Rimu numbers are decimals, which may come back out as floats.
+        assert_eq!(value["ssh"]["port"].as_f64(), Some(2222.0));
+    }
+
+    #[test]
+    fn set_requires_key_and_value() {
+        assert!("user".parse::<ParamsOverride>().is_err());
+        assert!("=lusid".parse::<ParamsOverride>().is_err());
+        assert!("ssh..port=22".parse::<ParamsOverride>().is_err());
+    }
+}
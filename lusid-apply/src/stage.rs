@@ -0,0 +1,135 @@
+use std::{
+    fmt::{self, Display},
+    future::Future,
+    time::{Duration, Instant},
+};
+
+use tracing::{field::Empty, info_span, Instrument, Span};
+
+/// Stages of the apply pipeline, each traced in its own span.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub(crate) enum Stage {
+    Plan,
+    Resources,
+    ResourceStates,
+    ResourceChanges,
+    Operations,
+    Epochs,
+    ApplyOperations,
+}
+
+impl Stage {
+    fn span(self) -> Span {
+        // Span names must be literals, hence one arm per stage.
+        match self {
+            Stage::Plan => info_span!("plan", nodes = Empty, duration_ms = Empty),
+            Stage::Resources => info_span!("resources", nodes = Empty, duration_ms = Empty),
+            Stage::ResourceStates => {
+                info_span!("resource_states", nodes = Empty, duration_ms = Empty)
+            }
+            Stage::ResourceChanges => {
+                info_span!("resource_changes", nodes = Empty, duration_ms = Empty)
+            }
+            Stage::Operations => info_span!("operations", nodes = Empty, duration_ms = Empty),
+            Stage::Epochs => info_span!("epochs", nodes = Empty, duration_ms = Empty),
+            Stage::ApplyOperations => {
+                info_span!("apply_operations", nodes = Empty, duration_ms = Empty)
+            }
+        }
+    }
+
+    fn name(self) -> &'static str {
+        match self {
+            Stage::Plan => "plan",
+            Stage::Resources => "resources",
+            Stage::ResourceStates => "resource_states",
+            Stage::ResourceChanges => "resource_changes",
+            Stage::Operations => "operations",
+            Stage::Epochs => "epochs",
+            Stage::ApplyOperations => "apply_operations",
+        }
+    }
+}
+
+/// Record the node count on the current stage span.
+pub(crate) fn record_nodes(nodes: usize) {
+    Span::current().record("nodes", nodes);
+}
+
+/// Elapsed time per stage, in the order the stages ran.
+#[derive(Debug, Default)]
+pub(crate) struct StageTimings(Vec<(Stage, Duration)>);
+
+impl StageTimings {
+    /// Run `future` inside the span for `stage`, recording how long it took.
+    pub(crate) async fn run<T, F>(&mut self, stage: Stage, future: F) -> T
+    where
+        F: Future<Output = T>,
+    {
+        let span = stage.span();
+        let started_at = Instant::now();
+        let output = future.instrument(span.clone()).await;
+        let duration = started_at.elapsed();
+        span.record(
+            "duration_ms",
+            u64::try_from(duration.as_millis()).unwrap_or(u64::MAX),
+        );
+        self.0.push((stage, duration));
+        output
+    }
+}
+
+impl Display for StageTimings {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        for (index, (stage, duration)) in self.0.iter().enumerate() {
+            if index > 0 {
+                write!(f, ", ")?;
+            }
+            write!(f, "{}={}ms", stage.name(), duration.as_millis())?;
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::{Arc, Mutex};
+
+    use tracing::{span, Subscriber};
+    use tracing_subscriber::{layer::Context, prelude::*, registry::LookupSpan, Layer};
+
+    use super::*;
+
+    #[derive(Clone, Default)]
+    struct EnteredSpans(Arc<Mutex<Vec<&'static str>>>);
+
+    impl<S> Layer<S> for EnteredSpans
+    where
+        S: Subscriber + for<'a> LookupSpan<'a>,
+    {
+        fn on_enter(&self, id: &span::Id, ctx: Context<'_, S>) {
+            if let Some(span) = ctx.span(id) {
+                self.0.lock().unwrap().push(span.name());
+            }
+        }
+    }
+
+    #[tokio::test]
+    async fn stages_enter_named_spans() {
+        let entered = EnteredSpans::default();
+        let _guard = tracing_subscriber::registry()
+            .with(entered.clone())
+            .set_default();
+
+        let mut timings = StageTimings::default();
+        for stage in [Stage::Plan, Stage::Resources, Stage::ApplyOperations] {
+            timings.run(stage, async { record_nodes(3) }).await;
+        }
+
+        let mut names = entered.0.lock().unwrap().clone();
+        names.dedup();
+        assert_eq!(names, vec!["plan", "resources", "apply_operations"]);
+        assert_eq!(timings.0.len(), 3);
+        assert!(timings.to_string().starts_with("plan="));
+    }
+}
@@ -3,6 +3,7 @@ use lusid::{
     operation::apply,
     plan::{plan, PlanId},
 };
+use lusid_params::SourceRegistry;
 use std::env;
 
 #[tokio::main]
@@ -12,7 +13,7 @@ async fn main() {
     let path = env::current_dir().expect("Failed to get env::current_dir()");
     let plan_id = PlanId::Path(path.join("examples/multi.lusid"));
 
-    let operation = plan(plan_id, None, &mut store)
+    let operation = plan(plan_id, None, &mut store, &mut SourceRegistry::new())
         .await
         .expect("Failed to plan");
 
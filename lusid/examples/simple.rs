@@ -3,7 +3,7 @@ use lusid::{
     operation::apply,
     plan::{plan, PlanId},
 };
-use lusid_params::ParamValues;
+use lusid_params::{ParamValues, SourceRegistry};
 use rimu::SourceId;
 use serde::Serialize;
 use std::env;
@@ -23,9 +23,14 @@ async fn main() {
     let params = ParamValues::from_type(ExampleParams { whatever: true }, SourceId::empty())
         .expect("Failed to create params");
 
-    let operation = plan(plan_id, Some(params), &mut store)
-        .await
-        .expect("Failed to plan");
+    let operation = plan(
+        plan_id,
+        Some(params),
+        &mut store,
+        &mut SourceRegistry::new(),
+    )
+    .await
+    .expect("Failed to plan");
 
     apply(operation).await.expect("Failed to apply");
 }
@@ -1,15 +1,19 @@
 use comfy_table::Table;
+use indexmap::IndexMap;
 use lusid_machine::Machine;
 use lusid_system::Hostname;
+use lusid_vm::OvmfOverrides;
 use serde::Deserialize;
 use std::collections::BTreeMap;
+use std::fmt::{self, Debug};
 use std::io;
 use std::path::{Path, PathBuf};
 use thiserror::Error;
 use tokio::fs::read_to_string;
-use toml::Value;
+use toml::{Table as TomlTable, Value};
 
-use crate::Cli;
+use crate::selector::MachineSelector;
+use crate::{Cli, OutputFormat};
 
 #[derive(Error, Debug)]
 pub enum ConfigError {
@@ -39,6 +43,26 @@ pub enum ConfigError {
         source: toml::de::Error,
     },
 
+    #[error("machine {machine_id}: unknown arch \"{arch}\", expected one of: {valid}")]
+    UnknownArch {
+        machine_id: String,
+        arch: String,
+        valid: String,
+    },
+
+    #[error("machine {machine_id}: unknown os {os}, expected one of: {valid}")]
+    UnknownOs {
+        machine_id: String,
+        os: String,
+        valid: String,
+    },
+
+    #[error("no machines match: {selector}")]
+    NoMachinesMatch { selector: MachineSelector },
+
+    #[error("machine {machine_id}: plan not found: {path}")]
+    PlanNotFound { machine_id: String, path: PathBuf },
+
     #[error("failed to resolve plan path: {base_path} + {plan_path}")]
     ResolvingPlanPath {
         base_path: PathBuf,
@@ -46,6 +70,15 @@ pub enum ConfigError {
     },
 }
 
+/// Values of `arch` that [`lusid_system::Arch`] accepts.
+const VALID_ARCHES: &[&str] = &["x86-64", "aarch64"];
+
+/// Values of `os.type` that [`lusid_system::Os`] accepts.
+const VALID_OS_TYPES: &[&str] = &["linux"];
+
+/// Values of `os.linux` that [`lusid_system::Linux`] accepts.
+const VALID_LINUX_DISTROS: &[&str] = &["ubuntu", "debian", "arch"];
+
 #[derive(Debug, Clone, Deserialize)]
 struct ConfigToml {
     #[serde(default)]
@@ -53,6 +86,8 @@ struct ConfigToml {
     pub log: Option<String>,
     pub lusid_apply_linux_x86_64_path: Option<String>,
     pub lusid_apply_linux_aarch64_path: Option<String>,
+    pub ovmf_code_path: Option<PathBuf>,
+    pub ovmf_vars_path: Option<PathBuf>,
 }
 
 #[derive(Debug, Clone)]
@@ -60,8 +95,15 @@ pub struct Config {
     pub path: PathBuf,
     pub machines: BTreeMap<String, MachineConfig>,
     pub log: String,
+    pub output: OutputFormat,
+    pub no_color: bool,
+    /// Whether apply output may take over a terminal with the TUI. Off when
+    /// applying to several machines at once.
+    pub interactive: bool,
     pub lusid_apply_linux_x86_64_path: String,
     pub lusid_apply_linux_aarch64_path: String,
+    pub ovmf_code_path: Option<PathBuf>,
+    pub ovmf_vars_path: Option<PathBuf>,
 }
 
 #[derive(Debug, Clone, Deserialize)]
@@ -70,13 +112,44 @@ struct MachineConfigToml {
     pub machine: Machine,
     pub plan: PathBuf,
     pub params: Option<Value>,
+    #[serde(default)]
+    pub env: IndexMap<String, String>,
+    #[serde(default)]
+    pub tags: Vec<String>,
 }
 
-#[derive(Debug, Clone)]
+#[derive(Clone)]
 pub struct MachineConfig {
     pub machine: Machine,
     pub plan: PathBuf,
     pub params: Option<Value>,
+    /// Environment variables to apply with, such as proxy settings.
+    pub env: IndexMap<String, String>,
+    /// Labels to select machines by, as in `--machine tag:web`.
+    pub tags: Vec<String>,
+}
+
+impl Debug for MachineConfig {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("MachineConfig")
+            .field("machine", &self.machine)
+            .field("plan", &self.plan)
+            .field("params", &self.params)
+            .field("env", &RedactedEnv(&self.env))
+            .field("tags", &self.tags)
+            .finish()
+    }
+}
+
+/// Environment variable names, with values hidden, as they may be secrets.
+pub struct RedactedEnv<'a>(pub &'a IndexMap<String, String>);
+
+impl Debug for RedactedEnv<'_> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_map()
+            .entries(self.0.keys().map(|key| (key, "<redacted>")))
+            .finish()
+    }
 }
 
 impl Config {
@@ -87,6 +160,8 @@ impl Config {
             log,
             lusid_apply_linux_x86_64_path,
             lusid_apply_linux_aarch64_path,
+            ovmf_code_path,
+            ovmf_vars_path,
         } = config;
 
         let machines = Self::resolve_machines(machines, path)?;
@@ -104,15 +179,30 @@ impl Config {
             .or(lusid_apply_linux_aarch64_path.clone())
             .unwrap_or("lusid-apply-linux-aarch64".into());
 
+        let ovmf_code_path = cli.ovmf_code_path.clone().or(ovmf_code_path);
+        let ovmf_vars_path = cli.ovmf_vars_path.clone().or(ovmf_vars_path);
+
         Ok(Config {
             path: path.to_owned(),
             machines,
             log,
+            output: cli.output,
+            no_color: cli.no_color,
+            interactive: true,
             lusid_apply_linux_x86_64_path,
             lusid_apply_linux_aarch64_path,
+            ovmf_code_path,
+            ovmf_vars_path,
         })
     }
 
+    pub fn ovmf_overrides(&self) -> OvmfOverrides {
+        OvmfOverrides {
+            code: self.ovmf_code_path.clone(),
+            vars: self.ovmf_vars_path.clone(),
+        }
+    }
+
     pub fn get_machine(&self, machine_id: &str) -> Result<MachineConfig, ConfigError> {
         self.machines
             .get(machine_id)
@@ -122,6 +212,25 @@ impl Config {
             })
     }
 
+    /// Machines the selector matches, by id.
+    pub fn machines_matching(
+        &self,
+        selector: &MachineSelector,
+    ) -> Result<Vec<(String, MachineConfig)>, ConfigError> {
+        let machines: Vec<_> = self
+            .machines
+            .iter()
+            .filter(|(machine_id, config)| selector.matches(machine_id, config))
+            .map(|(machine_id, config)| (machine_id.clone(), config.clone()))
+            .collect();
+        if machines.is_empty() {
+            return Err(ConfigError::NoMachinesMatch {
+                selector: selector.clone(),
+            });
+        }
+        Ok(machines)
+    }
+
     pub fn local_machine(&self) -> Result<MachineConfig, ConfigError> {
         let hostname = Hostname::get().map_err(ConfigError::GetHostname)?;
         self.machines
@@ -131,25 +240,54 @@ impl Config {
             .cloned()
     }
 
+    /// Machine ids by tag, for machines with any tags.
+    pub fn machines_by_tag(&self) -> BTreeMap<&str, Vec<&str>> {
+        let mut machines_by_tag: BTreeMap<&str, Vec<&str>> = BTreeMap::new();
+        for (machine_id, config) in self.machines.iter() {
+            for tag in &config.tags {
+                machines_by_tag.entry(tag).or_default().push(machine_id);
+            }
+        }
+        machines_by_tag
+    }
+
+    pub fn print_machines_by_tag(&self) {
+        let mut table = Table::new();
+        table
+            .load_preset(comfy_table::presets::UTF8_FULL)
+            .apply_modifier(comfy_table::modifiers::UTF8_ROUND_CORNERS)
+            .set_content_arrangement(comfy_table::ContentArrangement::Dynamic)
+            .set_header(vec!["tag", "machines"]);
+
+        for (tag, machine_ids) in self.machines_by_tag() {
+            table.add_row(vec![tag.to_string(), machine_ids.join(", ")]);
+        }
+
+        println!("{table}")
+    }
+
     pub fn print_machines(&self) {
         let mut table = Table::new();
         table
             .load_preset(comfy_table::presets::UTF8_FULL)
             .apply_modifier(comfy_table::modifiers::UTF8_ROUND_CORNERS)
             .set_content_arrangement(comfy_table::ContentArrangement::Dynamic)
-            .set_header(vec!["id", "plan", "hostname", "arch", "os"]);
+            .set_header(vec!["id", "plan", "hostname", "arch", "os", "tags"]);
 
         for (machine_id, config) in self.machines.iter() {
             let MachineConfig {
                 machine,
                 plan,
                 params: _,
+                env: _,
+                tags,
             } = config;
             let Machine {
                 hostname,
                 arch,
                 os,
                 vm: _,
+                privilege: _,
             } = machine;
             table.add_row(vec![
                 machine_id,
@@ -157,6 +295,7 @@ impl Config {
                 &hostname.to_string(),
                 &arch.to_string(),
                 &os.to_string(),
+                &tags.join(", "),
             ]);
         }
 
@@ -175,13 +314,66 @@ impl Config {
                 path: path.to_owned(),
                 source,
             })?;
-        let config = toml::from_str(&string).map_err(|source| ConfigError::Parse {
+        let parse_error = |source| ConfigError::Parse {
             path: path.to_owned(),
             source,
-        })?;
+        };
+        let table: TomlTable = toml::from_str(&string).map_err(parse_error)?;
+        // Catch the usual mistakes before serde does, with friendlier errors.
+        Self::validate_machines(&table)?;
+        let config = table.try_into().map_err(parse_error)?;
         Ok(config)
     }
 
+    /// Check each machine's `arch` and `os` name something we support.
+    fn validate_machines(table: &TomlTable) -> Result<(), ConfigError> {
+        let Some(Value::Table(machines)) = table.get("machines") else {
+            return Ok(());
+        };
+        for (machine_id, machine) in machines {
+            let Value::Table(machine) = machine else {
+                continue;
+            };
+            match machine.get("arch") {
+                Some(Value::String(arch)) if !VALID_ARCHES.contains(&arch.as_str()) => {
+                    return Err(ConfigError::UnknownArch {
+                        machine_id: machine_id.clone(),
+                        arch: arch.clone(),
+                        valid: VALID_ARCHES.join(", "),
+                    });
+                }
+                _ => {}
+            }
+            if let Some(os) = machine.get("os") {
+                Self::validate_os(machine_id, os)?;
+            }
+        }
+        Ok(())
+    }
+
+    fn validate_os(machine_id: &str, os: &Value) -> Result<(), ConfigError> {
+        let unknown = |os: String, valid: &[&str]| ConfigError::UnknownOs {
+            machine_id: machine_id.to_string(),
+            os,
+            valid: valid.join(", "),
+        };
+        let Value::Table(os) = os else {
+            return Err(unknown(os.to_string(), VALID_OS_TYPES));
+        };
+        match os.get("type") {
+            Some(Value::String(os_type)) if VALID_OS_TYPES.contains(&os_type.as_str()) => {}
+            Some(os_type) => {
+                return Err(unknown(format!("type = {os_type}"), VALID_OS_TYPES));
+            }
+            None => return Ok(()),
+        }
+        match os.get("linux") {
+            Some(Value::String(linux)) if VALID_LINUX_DISTROS.contains(&linux.as_str()) => Ok(()),
+            Some(linux) => Err(unknown(format!("linux = {linux}"), VALID_LINUX_DISTROS)),
+            None => Ok(()),
+        }
+    }
+
     fn resolve_machines(
         machines: BTreeMap<String, MachineConfigToml>,
         plan_path: &Path,
@@ -193,13 +385,24 @@ impl Config {
                     machine,
                     plan,
                     params,
+                    env,
+                    tags,
                 } = config;
+                let plan = Self::resolve_plan_path(plan_path, &plan)?;
+                if !plan.exists() {
+                    return Err(ConfigError::PlanNotFound {
+                        machine_id: name,
+                        path: plan,
+                    });
+                }
                 Ok((
                     name,
                     MachineConfig {
                         machine,
-                        plan: Self::resolve_plan_path(plan_path, &plan)?,
+                        plan,
                         params,
+                        env,
+                        tags,
                     },
                 ))
             })
@@ -220,3 +423,171 @@ impl Config {
         }
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const MACHINE: &str = r#"
+        [machines.a]
+        hostname = "amber-aura"
+        os = { type = "linux", linux = "debian", debian = 13 }
+        arch = "x86-64"
+        plan = "./simple.lusid"
+    "#;
+
+    fn machines(toml: &str) -> Result<BTreeMap<String, MachineConfigToml>, ConfigError> {
+        let table: TomlTable = toml::from_str(toml).unwrap();
+        Config::validate_machines(&table)?;
+        let config: ConfigToml = table.try_into().unwrap();
+        Ok(config.machines)
+    }
+
+    #[test]
+    fn accepts_known_arch_and_os() {
+        assert!(machines(MACHINE).is_ok());
+    }
+
+    #[test]
+    fn unknown_arch() {
+        let error = machines(&MACHINE.replace("x86-64", "x86_64")).unwrap_err();
+        let ConfigError::UnknownArch {
+            machine_id,
+            arch,
+            valid,
+        } = &error
+        else {
+            panic!("expected unknown arch, got: {error}");
+        };
+        assert_eq!(machine_id, "a");
+        assert_eq!(arch, "x86_64");
+        assert_eq!(valid, "x86-64, aarch64");
+    }
+
+    #[test]
+    fn unknown_os() {
+        let error = machines(&MACHINE.replace("\"debian\"", "\"fedora\"")).unwrap_err();
+        assert_eq!(
+            error.to_string(),
+            "machine a: unknown os linux = \"fedora\", expected one of: ubuntu, debian, arch"
+        );
+    }
+
+    #[test]
+    fn missing_plan_path() {
+        let base_path = Path::new(env!("CARGO_MANIFEST_DIR")).join("lusid.toml");
+
+        let error = Config::resolve_machines(machines(MACHINE).unwrap(), &base_path).unwrap_err();
+        let ConfigError::PlanNotFound { machine_id, path } = &error else {
+            panic!("expected plan not found, got: {error}");
+        };
+        assert_eq!(machine_id, "a");
+        assert_eq!(
+            path,
+            &Path::new(env!("CARGO_MANIFEST_DIR")).join("./simple.lusid")
+        );
+
+        let existing = MACHINE.replace("./simple.lusid", "Cargo.toml");
+        assert!(Config::resolve_machines(machines(&existing).unwrap(), &base_path).is_ok());
+    }
+
+    const TAGGED_MACHINES: &str = r#"
+        [machines.web-1]
+        hostname = "web-1"
+        os = { type = "linux", linux = "debian", debian = 13 }
+        arch = "x86-64"
+        plan = "Cargo.toml"
+        tags = ["web"]
+
+        [machines.web-2]
+        hostname = "web-2"
+        os = { type = "linux", linux = "debian", debian = 13 }
+        arch = "x86-64"
+        plan = "Cargo.toml"
+        tags = ["web", "edge"]
+
+        [machines.db-1]
+        hostname = "db-1"
+        os = { type = "linux", linux = "debian", debian = 13 }
+        arch = "aarch64"
+        plan = "Cargo.toml"
+        tags = ["db"]
+    "#;
+
+    fn config(toml: &str) -> Config {
+        let base_path = Path::new(env!("CARGO_MANIFEST_DIR")).join("lusid.toml");
+        Config {
+            machines: Config::resolve_machines(machines(toml).unwrap(), &base_path).unwrap(),
+            path: base_path,
+            log: "info".into(),
+            output: OutputFormat::Text,
+            no_color: true,
+            interactive: true,
+            lusid_apply_linux_x86_64_path: "lusid-apply".into(),
+            lusid_apply_linux_aarch64_path: "lusid-apply-aarch64".into(),
+            ovmf_code_path: None,
+            ovmf_vars_path: None,
+        }
+    }
+
+    fn matching(config: &Config, selector: &str) -> Result<Vec<String>, ConfigError> {
+        let machines = config.machines_matching(&selector.parse().unwrap())?;
+        Ok(machines
+            .into_iter()
+            .map(|(machine_id, _)| machine_id)
+            .collect())
+    }
+
+    #[test]
+    fn selects_machines() {
+        let config = config(TAGGED_MACHINES);
+        assert_eq!(matching(&config, "web-*").unwrap(), vec!["web-1", "web-2"]);
+        assert_eq!(matching(&config, "db-1").unwrap(), vec!["db-1"]);
+        assert_eq!(matching(&config, "*-1").unwrap(), vec!["db-1", "web-1"]);
+    }
+
+    #[test]
+    fn selects_machines_by_tag() {
+        let config = config(TAGGED_MACHINES);
+        assert_eq!(
+            matching(&config, "tag:web").unwrap(),
+            vec!["web-1", "web-2"]
+        );
+        assert_eq!(matching(&config, "tag:edge").unwrap(), vec!["web-2"]);
+    }
+
+    #[test]
+    fn selects_machines_with_all_tags() {
+        let config = config(TAGGED_MACHINES);
+        assert_eq!(matching(&config, "tag:web,edge").unwrap(), vec!["web-2"]);
+        assert_eq!(matching(&config, "tag:edge,web").unwrap(), vec!["web-2"]);
+        assert!(matches!(
+            matching(&config, "tag:web,db"),
+            Err(ConfigError::NoMachinesMatch { .. })
+        ));
+    }
+
+    #[test]
+    fn selects_no_machines() {
+        let config = config(TAGGED_MACHINES);
+        let error = matching(&config, "tag:cache").unwrap_err();
+        assert_eq!(error.to_string(), "no machines match: tag:cache");
+        assert!(matches!(
+            matching(&config, "cache-*"),
+            Err(ConfigError::NoMachinesMatch { .. })
+        ));
+    }
+
+    #[test]
+    fn groups_machines_by_tag() {
+        let config = config(TAGGED_MACHINES);
+        assert_eq!(
+            config.machines_by_tag(),
+            BTreeMap::from([
+                ("db", vec!["db-1"]),
+                ("edge", vec!["web-2"]),
+                ("web", vec!["web-1", "web-2"]),
+            ])
+        );
+    }
+}
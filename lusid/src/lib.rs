@@ -1,20 +1,49 @@
 mod config;
+mod remote_log;
+mod selector;
+mod stdio;
 mod tui;
+mod update_log;
 
-use std::{env, net::Ipv4Addr, path::PathBuf, sync::Arc, time::Duration};
+use std::{
+    env,
+    future::Future,
+    io::{self, IsTerminal},
+    net::Ipv4Addr,
+    num::NonZeroUsize,
+    path::PathBuf,
+    pin::Pin,
+    sync::Arc,
+    time::{Duration, SystemTime},
+};
 
-use clap::{Parser, Subcommand};
-use lusid_apply_stdio::AppViewError;
+use clap::{Args, Parser, Subcommand, ValueEnum};
+use comfy_table::Table;
+use futures_util::{stream, StreamExt};
+use indexmap::IndexMap;
+use lusid_apply::{ParamsInput, ParamsInputError};
+use lusid_apply_stdio::{AppViewError, ApplyResult};
 use lusid_cmd::{Command, CommandError};
-use lusid_ctx::Context;
-use lusid_ssh::{Ssh, SshConnectOptions, SshError, SshVolume};
-use lusid_vm::{Vm, VmError, VmOptions};
+use lusid_ctx::{Context, LogFormat};
+use lusid_params::{ParamTypes, SourceRegistry};
+use lusid_plan::{plan_info, PlanError, PlanId};
+use lusid_ssh::{shell_command, Ssh, SshConnectOptions, SshError, SshSyncOptions, SshVolume};
+use lusid_store::Store;
+use lusid_view::detect_color;
+use lusid_vm::{
+    backing_image_files, list_cached_images, referenced_image_files, remove_cached_images,
+    select_for_removal, Accel, Vm, VmError, VmImageError, VmOptions, VmStatus,
+};
 use thiserror::Error;
-use tracing::error;
+use tokio::io::AsyncRead;
+use tracing::{debug, warn};
 use which::which;
 
-use crate::config::{Config, ConfigError, MachineConfig};
+use crate::config::{Config, ConfigError, MachineConfig, RedactedEnv};
+use crate::selector::MachineSelector;
+use crate::stdio::{stdio, StdioError, StdioOptions};
 use crate::tui::{tui, TuiError};
+use crate::update_log::{follow_update_log, remote_update_log_path};
 
 #[derive(Parser, Debug)]
 #[command(name = "lusid", version, about = "Lusid CLI")]
@@ -28,11 +57,37 @@ pub struct Cli {
     #[arg(long = "log", env = "LUSID_LOG", global = true)]
     pub log: Option<String>,
 
+    #[doc = " Format of log lines on stderr"]
+    #[arg(
+        long = "log-format",
+        env = "LUSID_LOG_FORMAT",
+        value_enum,
+        default_value_t,
+        global = true
+    )]
+    pub log_format: LogFormat,
+
+    #[doc = " Disable colored output (also disabled by NO_COLOR, or when not a terminal)"]
+    #[arg(long = "no-color", global = true)]
+    pub no_color: bool,
+
+    #[doc = " Output format for apply results"]
+    #[arg(long = "output", value_enum, default_value_t, global = true)]
+    pub output: OutputFormat,
+
     #[arg(env = "LUSID_APPLY_LINUX_X86_64", global = true)]
     pub lusid_apply_linux_x86_64_path: Option<String>,
 
     #[arg(env = "LUSID_APPLY_LINUX_AARCH64", global = true)]
     pub lusid_apply_linux_aarch64_path: Option<String>,
+
+    #[doc = " OVMF UEFI firmware code image for dev virtual machines"]
+    #[arg(long = "ovmf-code", env = "LUSID_OVMF_CODE", global = true)]
+    pub ovmf_code_path: Option<PathBuf>,
+
+    #[doc = " OVMF UEFI firmware vars image for dev virtual machines"]
+    #[arg(long = "ovmf-vars", env = "LUSID_OVMF_VARS", global = true)]
+    pub ovmf_vars_path: Option<PathBuf>,
 }
 
 #[derive(Subcommand, Debug)]
@@ -57,25 +112,70 @@ pub enum Cmd {
         #[command(subcommand)]
         command: DevCmd,
     },
+    #[doc = " Work with parameter schemas"]
+    Schema {
+        #[command(subcommand)]
+        command: SchemaCmd,
+    },
+    #[doc = " Inspect plans"]
+    Plan {
+        #[command(subcommand)]
+        command: PlanCmd,
+    },
+}
+
+#[derive(ValueEnum, Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum OutputFormat {
+    /// Interactive TUI on a terminal, otherwise plain lines.
+    #[default]
+    Text,
+    /// A single JSON result document on stdout, once apply finishes.
+    Json,
 }
 
 #[derive(Subcommand, Debug)]
 pub enum MachinesCmd {
     #[doc = " List machines from machines.toml"]
-    List,
+    List {
+        #[doc = " List machines under each tag instead"]
+        #[arg(long)]
+        by_tag: bool,
+    },
 }
 
 #[derive(Subcommand, Debug)]
 pub enum LocalCmd {
-    Apply,
+    Apply {
+        #[command(flatten)]
+        apply: ApplyArgs,
+    },
+}
+
+/// Options passed through to lusid-apply.
+#[derive(Args, Debug, Clone, Default)]
+pub struct ApplyArgs {
+    #[doc = " Log why each resource change is needed"]
+    #[arg(long)]
+    pub explain: bool,
+}
+
+impl ApplyArgs {
+    /// The lusid-apply arguments for these options.
+    fn to_args(&self) -> Vec<String> {
+        let mut args = Vec::new();
+        if self.explain {
+            args.push("--explain".to_string());
+        }
+        args
+    }
 }
 
 #[derive(Subcommand, Debug)]
 pub enum RemoteCmd {
     Apply {
-        #[doc = " Machine identifier"]
+        #[doc = " Machine identifier, glob over identifiers (web-*), or tag (tag:web)"]
         #[arg(long = "machine")]
-        machine_id: String,
+        machines: MachineSelector,
     },
     Ssh {
         #[arg(long = "machine")]
@@ -83,19 +183,123 @@ pub enum RemoteCmd {
     },
 }
 
+#[derive(Subcommand, Debug)]
+pub enum SchemaCmd {
+    #[doc = " Print parameter types inferred from sample parameters"]
+    Infer {
+        #[doc = " Sample parameters file (.json, .toml, .yaml, or .yml)"]
+        #[arg(long = "params-file")]
+        params_file: PathBuf,
+    },
+}
+
+#[derive(Subcommand, Debug)]
+pub enum PlanCmd {
+    #[doc = " Print a plan's name, version, and parameter schema, without running it"]
+    Info {
+        #[doc = " Path to the .lusid plan file"]
+        #[arg(long = "plan")]
+        plan_path: PathBuf,
+    },
+}
+
 #[derive(Subcommand, Debug)]
 pub enum DevCmd {
+    #[doc = " List dev virtual machines"]
+    List,
     Apply {
-        #[doc = " Machine identifier"]
+        #[doc = " Machine identifier, glob over identifiers (web-*), or tag (tag:web)"]
         #[arg(long = "machine")]
-        machine_id: String,
+        machines: MachineSelector,
+
+        #[doc = " How many machines to apply to at once"]
+        #[arg(long, default_value = "1")]
+        jobs: NonZeroUsize,
+
+        #[doc = " Remove any existing virtual machine and start fresh"]
+        #[arg(long)]
+        recreate: bool,
+
+        #[doc = " Print the QEMU command that starts the virtual machine, without starting it"]
+        #[arg(long, conflicts_with = "recreate")]
+        print_qemu_command: bool,
+
+        #[doc = " QEMU accelerator: kvm or tcg. Default: kvm if available, otherwise tcg"]
+        #[arg(long)]
+        accel: Option<Accel>,
+
+        #[command(flatten)]
+        apply: ApplyArgs,
     },
     Ssh {
         #[arg(long = "machine")]
         machine_id: String,
     },
+    #[doc = " Re-attach to the output of the latest apply in a dev virtual machine"]
+    Logs {
+        #[arg(long = "machine")]
+        machine_id: String,
+    },
+    #[doc = " Run a command in a running dev virtual machine"]
+    Exec {
+        #[arg(long = "machine")]
+        machine_id: String,
+
+        #[doc = " Command and its arguments, after --"]
+        #[arg(trailing_var_arg = true, required = true)]
+        args: Vec<String>,
+    },
+    #[doc = " Shut down a dev virtual machine and save its disk as a snapshot"]
+    Snapshot {
+        #[arg(long = "machine")]
+        machine_id: String,
+
+        #[doc = " Snapshot name"]
+        #[arg(long)]
+        name: String,
+    },
+    #[doc = " Shut down a dev virtual machine and restore its disk from a snapshot"]
+    Restore {
+        #[arg(long = "machine")]
+        machine_id: String,
+
+        #[doc = " Snapshot name"]
+        #[arg(long)]
+        name: String,
+    },
+    #[doc = " List snapshots of a dev virtual machine"]
+    Snapshots {
+        #[arg(long = "machine")]
+        machine_id: String,
+    },
+    #[doc = " Gracefully shut down a dev virtual machine"]
+    Stop {
+        #[arg(long = "machine")]
+        machine_id: String,
+    },
+    #[doc = " Shut down and delete a dev virtual machine"]
+    Rm {
+        #[arg(long = "machine")]
+        machine_id: String,
+    },
+    #[doc = " Remove cached images no configured machine or instance uses"]
+    Gc {
+        #[doc = " Only remove images older than this many days"]
+        #[arg(long = "max-age", value_name = "DAYS")]
+        max_age_days: Option<u64>,
+
+        #[doc = " Show what would be removed without removing anything"]
+        #[arg(long)]
+        dry_run: bool,
+    },
 }
 
+/// How long to wait for a dev vm to power down before killing it.
+const DEV_SHUTDOWN_TIMEOUT: Duration = Duration::from_secs(30);
+
+/// How long to wait for a dev vm to finish booting before applying.
+const DEV_BOOT_TIMEOUT: Duration = Duration::from_secs(300);
+
 #[derive(Error, Debug)]
 pub enum AppError {
     #[error(transparent)]
@@ -110,6 +314,9 @@ pub enum AppError {
     #[error(transparent)]
     Vm(#[from] VmError),
 
+    #[error(transparent)]
+    VmImage(#[from] VmImageError),
+
     #[error(transparent)]
     Ssh(#[from] SshError),
 
@@ -119,6 +326,9 @@ pub enum AppError {
     #[error("failed to convert params toml to json: {0}")]
     ParamsTomlToJson(#[from] serde_json::Error),
 
+    #[error("failed to output apply result as json")]
+    OutputJson(#[source] serde_json::Error),
+
     #[error("failed to read stdout from apply")]
     ReadApplyStdout(#[source] tokio::io::Error),
 
@@ -128,14 +338,45 @@ pub enum AppError {
     #[error("failed to forward stderr from lusid-apply")]
     ForwardApplyStderr(#[source] tokio::io::Error),
 
+    #[error(transparent)]
+    Params(#[from] ParamsInputError),
+
+    #[error(transparent)]
+    Plan(#[from] PlanError),
+
     #[error(transparent)]
     Which(#[from] which::Error),
 
     #[error("unexpected view state")]
     UnexpectedViewState,
 
+    #[error("one or more operations failed")]
+    OperationsFailed,
+
+    #[error("apply failed on: {}", machine_ids.join(", "))]
+    MachinesFailed { machine_ids: Vec<String> },
+
     #[error(transparent)]
     Tui(#[from] TuiError),
+
+    #[error(transparent)]
+    Stdio(#[from] StdioError),
+
+    #[error("failed to forward output from remote command")]
+    ForwardCommandOutput(#[source] tokio::io::Error),
+
+    #[error("remote command exited with status {0}")]
+    RemoteCommandFailed(u32),
+}
+
+impl AppError {
+    /// Status for the process to exit with: a failed remote command's own, otherwise 1.
+    pub fn exit_code(&self) -> i32 {
+        match self {
+            AppError::RemoteCommandFailed(code) => i32::try_from(*code).unwrap_or(1),
+            _ => 1,
+        }
+    }
 }
 
 pub async fn get_config(cli: &Cli) -> Result<Config, AppError> {
@@ -152,57 +393,129 @@ pub async fn get_config(cli: &Cli) -> Result<Config, AppError> {
 pub async fn run(cli: Cli, config: Config) -> Result<(), AppError> {
     match cli.command {
         Cmd::Machines { command } => match command {
-            MachinesCmd::List => cmd_machines_list(config).await,
+            MachinesCmd::List { by_tag } => cmd_machines_list(config, by_tag).await,
         },
         Cmd::Local { command } => match command {
-            LocalCmd::Apply => cmd_local_apply(config).await,
+            LocalCmd::Apply { apply } => cmd_local_apply(config, apply).await,
         },
         Cmd::Remote { command } => match command {
-            RemoteCmd::Apply { machine_id } => cmd_remote_apply(config, machine_id).await,
+            RemoteCmd::Apply { machines } => cmd_remote_apply(config, machines).await,
             RemoteCmd::Ssh { machine_id } => cmd_remote_ssh(config, machine_id).await,
         },
         Cmd::Dev { command } => match command {
-            DevCmd::Apply { machine_id } => cmd_dev_apply(config, machine_id).await,
+            DevCmd::List => cmd_dev_list().await,
+            DevCmd::Apply {
+                machines,
+                print_qemu_command: true,
+                accel,
+                ..
+            } => cmd_dev_print_qemu_command(config, machines, accel).await,
+            DevCmd::Apply {
+                machines,
+                jobs,
+                recreate,
+                accel,
+                apply,
+                ..
+            } => cmd_dev_apply_many(config, machines, jobs, recreate, accel, apply).await,
             DevCmd::Ssh { machine_id } => cmd_dev_ssh(config, machine_id).await,
+            DevCmd::Logs { machine_id } => cmd_dev_logs(config, machine_id).await,
+            DevCmd::Exec { machine_id, args } => cmd_dev_exec(machine_id, args).await,
+            DevCmd::Snapshot { machine_id, name } => cmd_dev_snapshot(machine_id, name).await,
+            DevCmd::Restore { machine_id, name } => cmd_dev_restore(machine_id, name).await,
+            DevCmd::Snapshots { machine_id } => cmd_dev_snapshots(machine_id).await,
+            DevCmd::Stop { machine_id } => cmd_dev_stop(machine_id).await,
+            DevCmd::Rm { machine_id } => cmd_dev_rm(machine_id).await,
+            DevCmd::Gc {
+                max_age_days,
+                dry_run,
+            } => cmd_dev_gc(config, max_age_days, dry_run).await,
+        },
+        Cmd::Schema { command } => match command {
+            SchemaCmd::Infer { params_file } => cmd_schema_infer(params_file).await,
+        },
+        Cmd::Plan { command } => match command {
+            PlanCmd::Info { plan_path } => cmd_plan_info(plan_path).await,
         },
     }
 }
 
-async fn cmd_machines_list(config: Config) -> Result<(), AppError> {
-    config.print_machines();
+async fn cmd_machines_list(config: Config, by_tag: bool) -> Result<(), AppError> {
+    if by_tag {
+        config.print_machines_by_tag();
+    } else {
+        config.print_machines();
+    }
     Ok(())
 }
 
 // Rewritten to use TUI
-async fn cmd_local_apply(config: Config) -> Result<(), AppError> {
-    let Config {
-        ref lusid_apply_linux_x86_64_path,
-        ..
-    } = config;
-    let MachineConfig { plan, params, .. } = config.local_machine()?;
-
-    let mut command = Command::new(lusid_apply_linux_x86_64_path);
+async fn cmd_local_apply(config: Config, apply: ApplyArgs) -> Result<(), AppError> {
+    let machine_config = config.local_machine()?;
+    let mut command = local_apply_command(&config, &machine_config, &apply)?;
+    let output = command.output().await?;
+
+    let wait = Box::pin(async move {
+        output.status.await?;
+        Ok::<_, CommandError>(())
+    });
+    let displayed = display(output.stdout, output.stderr, wait, &config);
+    // lusid-apply shares our terminal, so gets the interrupt itself.
+    display_through_interrupt(displayed, async {}).await
+}
+
+fn local_apply_command(
+    config: &Config,
+    machine_config: &MachineConfig,
+    apply: &ApplyArgs,
+) -> Result<Command, AppError> {
+    let MachineConfig {
+        plan,
+        params,
+        machine,
+        env,
+        tags: _,
+    } = machine_config;
+
+    let mut command = Command::new(&config.lusid_apply_linux_x86_64_path);
     command
         .args(["--plan", &plan.to_string_lossy()])
-        .args(["--log", &config.log]);
+        .args(["--log", &config.log])
+        .args(["--log-format", "json"])
+        .args(["--privilege", &machine.privilege.to_string()])
+        .args(["--tool-version", env!("CARGO_PKG_VERSION")])
+        .args(apply.to_args());
 
     if let Some(params) = params {
         let params_json = serde_json::to_string(&params)?;
         command.args(["--params", &params_json]);
     }
 
-    let output = command.output().await?;
+    if !env.is_empty() {
+        debug!(env = ?RedactedEnv(env), "applying with machine env");
+        command.envs(env).args(preserve_env_args(env));
+    }
 
-    let wait = Box::pin(async move {
-        output.status.await?;
-        Ok::<_, CommandError>(())
-    });
-    tui(output.stdout, output.stderr, wait).await?;
+    Ok(command)
+}
 
-    Ok(())
+/// Arguments asking lusid-apply to keep `env` when escalating, as sudo and doas
+/// reset the environment. Names only, so values stay out of the command line.
+fn preserve_env_args(env: &IndexMap<String, String>) -> Vec<String> {
+    env.keys()
+        .flat_map(|key| ["--preserve-env".to_string(), key.clone()])
+        .collect()
 }
 
-async fn cmd_remote_apply(_config: Config, _machine_id: String) -> Result<(), AppError> {
+/// A shell script exporting `env`, sourced before a remote apply. Kept out of
+/// the command line, which is logged, as values may be secrets.
+fn remote_env_script(env: &IndexMap<String, String>) -> String {
+    env.iter()
+        .map(|(key, value)| format!("export {}\n", shell_command(&[format!("{key}={value}")])))
+        .collect()
+}
+
+async fn cmd_remote_apply(_config: Config, _machines: MachineSelector) -> Result<(), AppError> {
     todo!()
 }
 
@@ -210,22 +523,134 @@ async fn cmd_remote_ssh(_config: Config, _machine_id: String) -> Result<(), AppE
     todo!()
 }
 
-async fn cmd_dev_apply(config: Config, machine_id: String) -> Result<(), AppError> {
+async fn cmd_dev_list() -> Result<(), AppError> {
+    let ctx = Context::create().unwrap();
+    let statuses = Vm::list(&ctx).await?;
+
+    let mut table = Table::new();
+    table
+        .load_preset(comfy_table::presets::UTF8_FULL)
+        .apply_modifier(comfy_table::modifiers::UTF8_ROUND_CORNERS)
+        .set_content_arrangement(comfy_table::ContentArrangement::Dynamic)
+        .set_header(vec!["id", "ssh port", "state", "ssh"]);
+
+    for status in statuses {
+        let VmStatus {
+            id,
+            ssh_port,
+            is_running,
+            is_ssh_open,
+        } = status;
+        table.add_row(vec![
+            id,
+            ssh_port.to_string(),
+            if is_running { "running" } else { "stopped" }.to_string(),
+            if is_ssh_open { "open" } else { "closed" }.to_string(),
+        ]);
+    }
+
+    println!("{table}");
+    Ok(())
+}
+
+/// Apply to each machine the selector matches, `jobs` at a time, then print
+/// which passed and which failed. A single machine applies as usual.
+async fn cmd_dev_apply_many(
+    config: Config,
+    selector: MachineSelector,
+    jobs: NonZeroUsize,
+    recreate: bool,
+    accel: Option<Accel>,
+    apply: ApplyArgs,
+) -> Result<(), AppError> {
+    let machines = config.machines_matching(&selector)?;
+    if let [(machine_id, _)] = machines.as_slice() {
+        let machine_id = machine_id.clone();
+        return cmd_dev_apply(config, machine_id, recreate, accel, &apply).await;
+    }
+
+    // One TUI can't show several applies.
+    let config = Config {
+        interactive: false,
+        ..config
+    };
+    let mut results: Vec<(String, Result<(), AppError>)> = stream::iter(machines)
+        .map(|(machine_id, _)| {
+            let config = config.clone();
+            let apply = &apply;
+            async move {
+                let result =
+                    cmd_dev_apply(config, machine_id.clone(), recreate, accel, apply).await;
+                (machine_id, result)
+            }
+        })
+        .buffer_unordered(jobs.get())
+        .collect()
+        .await;
+    results.sort_by(|(a, _), (b, _)| a.cmp(b));
+
+    let mut table = Table::new();
+    table
+        .load_preset(comfy_table::presets::UTF8_FULL)
+        .apply_modifier(comfy_table::modifiers::UTF8_ROUND_CORNERS)
+        .set_content_arrangement(comfy_table::ContentArrangement::Dynamic)
+        .set_header(vec!["machine", "result"]);
+    for (machine_id, result) in &results {
+        let result = match result {
+            Ok(()) => "ok".to_string(),
+            Err(error) => format!("failed: {error}"),
+        };
+        table.add_row(vec![machine_id.clone(), result]);
+    }
+    // Keep stdout to the JSON results.
+    match config.output {
+        OutputFormat::Text => println!("{table}"),
+        OutputFormat::Json => eprintln!("{table}"),
+    }
+
+    let machine_ids: Vec<String> = results
+        .into_iter()
+        .filter(|(_, result)| result.is_err())
+        .map(|(machine_id, _)| machine_id)
+        .collect();
+    if !machine_ids.is_empty() {
+        return Err(AppError::MachinesFailed { machine_ids });
+    }
+    Ok(())
+}
+
+async fn cmd_dev_apply(
+    config: Config,
+    machine_id: String,
+    recreate: bool,
+    accel: Option<Accel>,
+    apply: &ApplyArgs,
+) -> Result<(), AppError> {
     let MachineConfig {
         plan,
         machine,
         params,
+        env,
+        tags: _,
     } = config.get_machine(&machine_id)?;
 
     let instance_id = &machine_id;
-    let ports = vec![];
+    let ports = machine
+        .vm
+        .as_ref()
+        .map(|vm| vm.ports.clone())
+        .unwrap_or_default();
     let mut ctx = Context::create().unwrap();
     let options = VmOptions {
         instance_id,
         machine: &machine,
         ports,
+        recreate,
+        ovmf: config.ovmf_overrides(),
+        accel,
     };
     let vm = Vm::run(&mut ctx, options).await?;
+    vm.wait_ready(DEV_BOOT_TIMEOUT).await?;
 
     let mut ssh = Ssh::connect(SshConnectOptions {
         private_key: vm.ssh_keypair().await?.private_key,
@@ -239,7 +664,7 @@ async fn cmd_dev_apply(config: Config, machine_id: String) -> Result<(), AppErro
     let dev_dir = format!("/home/{}", vm.user);
     let plan_dir = plan.parent().unwrap();
     let plan_filename = plan.file_name().unwrap().to_string_lossy();
-    let apply_bin = which(config.lusid_apply_linux_x86_64_path)?;
+    let apply_bin = which(&config.lusid_apply_linux_x86_64_path)?;
 
     let volumes = vec![
         SshVolume::FilePath {
@@ -250,18 +675,39 @@ async fn cmd_dev_apply(config: Config, machine_id: String) -> Result<(), AppErro
             local: plan_dir.to_path_buf(),
             remote: format!("{dev_dir}/plan"),
         },
+        // Always written, so env removed from the config is gone on the next apply.
+        SshVolume::FileBytes {
+            local: remote_env_script(&env).into_bytes(),
+            permissions: Some(0o600),
+            remote: format!("{dev_dir}/apply.env"),
+        },
     ];
 
-    let log = config.log;
-    let mut command =
-        format!("{dev_dir}/lusid-apply --plan {dev_dir}/plan/{plan_filename} --log {log}");
+    let log = &config.log;
+    let privilege = machine.privilege;
+    let update_log = remote_update_log_path(&dev_dir);
+    if !env.is_empty() {
+        debug!(env = ?RedactedEnv(&env), "applying with machine env");
+    }
+    // Keep applying if the connection drops; `dev logs` re-attaches.
+    let mut command = format!(
+        ". {dev_dir}/apply.env && nohup {dev_dir}/lusid-apply --plan {dev_dir}/plan/{plan_filename} --log {log} --log-format json --privilege {privilege} --update-log {update_log} --tool-version {tool_version}",
+        tool_version = env!("CARGO_PKG_VERSION"),
+    );
     if let Some(params) = params {
         let params_json = serde_json::to_string(&params)?;
         command.push_str(&format!(" --params '{params_json}'"));
     }
+    for arg in preserve_env_args(&env).into_iter().chain(apply.to_args()) {
+        command.push_str(&format!(" {}", shell_command(&[arg])));
+    }
 
+    // The lusid-apply binary is tens of megabytes; plan files are small.
+    let sync_options = SshSyncOptions {
+        compress_above: Some(1024 * 1024),
+    };
     for volume in volumes {
-        ssh.sync(volume).await?;
+        ssh.sync(volume, &sync_options).await?;
     }
 
     let mut handle = ssh.command(&command).await?;
@@ -270,10 +716,86 @@ async fn cmd_dev_apply(config: Config, machine_id: String) -> Result<(), AppErro
         Ok::<_, SshError>(())
     });
 
-    tui(&mut handle.stdout, &mut handle.stderr, wait).await?;
+    let displayed = display(&mut handle.stdout, &mut handle.stderr, wait, &config);
+    let result = display_through_interrupt(displayed, cancel_remote_apply(&mut ssh)).await;
 
     ssh.disconnect().await?;
 
+    result
+}
+
+/// Ask a remote lusid-apply to stop starting operations, as Ctrl-C would locally.
+async fn cancel_remote_apply(ssh: &mut Ssh) {
+    let cancelled = async {
+        let mut handle = ssh.command("pkill -INT -x lusid-apply").await?;
+        handle.channel.wait().await?;
+        Ok::<_, SshError>(())
+    };
+    if let Err(error) = cancelled.await {
+        warn!("failed to cancel remote apply: {error}");
+    }
+}
+
+/// Wait for `displayed`, the output of an apply. On Ctrl-C, run `on_interrupt`
+/// to cancel the apply, then keep showing output while it winds down.
+async fn display_through_interrupt<Displayed, OnInterrupt>(
+    displayed: Displayed,
+    on_interrupt: OnInterrupt,
+) -> Result<(), AppError>
+where
+    Displayed: Future<Output = Result<(), AppError>>,
+    OnInterrupt: Future<Output = ()>,
+{
+    tokio::pin!(displayed);
+    tokio::select! {
+        result = &mut displayed => result,
+        Ok(()) = tokio::signal::ctrl_c() => {
+            warn!("cancelling apply: waiting for running operations to finish");
+            on_interrupt.await;
+            displayed.await
+        }
+    }
+}
+
+/// Show apply output in the TUI when attached to a terminal, otherwise print plain lines.
+/// With JSON output, only print an [`ApplyResult`] document once apply finishes.
+///
+/// Returns an error if any operation failed, so the process exits non-zero.
+async fn display<Stdout, Stderr, Wait, WaitError>(
+    stdout: Stdout,
+    stderr: Stderr,
+    wait: Pin<Box<Wait>>,
+    config: &Config,
+) -> Result<(), AppError>
+where
+    Stdout: AsyncRead + Unpin,
+    Stderr: AsyncRead + Unpin,
+    Wait: Future<Output = Result<(), WaitError>>,
+    WaitError: Into<TuiError> + Into<StdioError>,
+{
+    let color = detect_color(config.no_color, &io::stdout());
+    let app_view = match config.output {
+        OutputFormat::Text if config.interactive && io::stdout().is_terminal() => {
+            tui(stdout, stderr, wait, color).await?
+        }
+        OutputFormat::Text => stdio(stdout, stderr, wait, StdioOptions::detect(color)).await?,
+        OutputFormat::Json => {
+            let options = StdioOptions {
+                print_updates: false,
+                ..StdioOptions::detect(color)
+            };
+            let app_view = stdio(stdout, stderr, wait, options).await?;
+            let result = ApplyResult::from(&app_view);
+            let json = serde_json::to_string_pretty(&result).map_err(AppError::OutputJson)?;
+            println!("{json}");
+            app_view
+        }
+    };
+
+    if app_view.had_failures() {
+        return Err(AppError::OperationsFailed);
+    }
+
     Ok(())
 }
 
@@ -282,15 +804,24 @@ async fn cmd_dev_ssh(config: Config, machine_id: String) -> Result<(), AppError>
         plan: _,
         machine,
         params: _,
+        env: _,
+        tags: _,
     } = config.get_machine(&machine_id)?;
 
     let instance_id = &machine_id;
-    let ports = vec![];
+    let ports = machine
+        .vm
+        .as_ref()
+        .map(|vm| vm.ports.clone())
+        .unwrap_or_default();
     let mut ctx = Context::create().unwrap();
     let options = VmOptions {
         instance_id,
         machine: &machine,
         ports,
+        recreate: false,
+        ovmf: config.ovmf_overrides(),
+        accel: None,
     };
     let vm = Vm::run(&mut ctx, options).await?;
 
@@ -309,3 +840,320 @@ async fn cmd_dev_ssh(config: Config, machine_id: String) -> Result<(), AppError>
 
     Ok(())
 }
+
+async fn cmd_dev_print_qemu_command(
+    config: Config,
+    selector: MachineSelector,
+    accel: Option<Accel>,
+) -> Result<(), AppError> {
+    let ctx = Context::create().unwrap();
+    for (machine_id, _) in config.machines_matching(&selector)? {
+        let vm = Vm::find(&ctx, &machine_id).await?;
+        println!(
+            "{}",
+            vm.qemu_command(&ctx, &config.ovmf_overrides(), accel)?
+        );
+    }
+    Ok(())
+}
+
+async fn cmd_dev_logs(config: Config, machine_id: String) -> Result<(), AppError> {
+    let ctx = Context::create().unwrap();
+    let vm = Vm::find(&ctx, &machine_id).await?;
+
+    let options = SshConnectOptions {
+        private_key: vm.ssh_keypair().await?.private_key,
+        addrs: (Ipv4Addr::LOCALHOST, vm.ssh_port),
+        username: vm.user.clone(),
+        config: Arc::new(Default::default()),
+        timeout: Duration::from_secs(10),
+    };
+    let path = remote_update_log_path(&format!("/home/{}", vm.user));
+
+    // Updates are replayed from the start of the log, so the view is whole.
+    let (writer, reader) = tokio::io::duplex(64 * 1024);
+    let wait = Box::pin(follow_update_log(options, path, writer));
+    display(reader, tokio::io::empty(), wait, &config).await
+}
+
+async fn cmd_dev_exec(machine_id: String, args: Vec<String>) -> Result<(), AppError> {
+    let ctx = Context::create().unwrap();
+    let vm = Vm::find(&ctx, &machine_id).await?;
+
+    let mut ssh = Ssh::connect(SshConnectOptions {
+        private_key: vm.ssh_keypair().await?.private_key,
+        addrs: (Ipv4Addr::LOCALHOST, vm.ssh_port),
+        username: vm.user.clone(),
+        config: Arc::new(Default::default()),
+        timeout: Duration::from_secs(10),
+    })
+    .await?;
+
+    let mut handle = ssh.command(&shell_command(&args)).await?;
+    let (mut stdout, mut stderr) = (tokio::io::stdout(), tokio::io::stderr());
+    let (stdout, stderr) = tokio::join!(
+        tokio::io::copy(&mut handle.stdout, &mut stdout),
+        tokio::io::copy(&mut handle.stderr, &mut stderr),
+    );
+    stdout.map_err(AppError::ForwardCommandOutput)?;
+    stderr.map_err(AppError::ForwardCommandOutput)?;
+    let exit_code = handle.channel.wait().await?;
+
+    ssh.disconnect().await?;
+
+    match exit_code {
+        Some(0) | None => Ok(()),
+        Some(code) => Err(AppError::RemoteCommandFailed(code)),
+    }
+}
+
+async fn cmd_dev_snapshot(machine_id: String, name: String) -> Result<(), AppError> {
+    let ctx = Context::create().unwrap();
+    let vm = Vm::find(&ctx, &machine_id).await?;
+    vm.shutdown(DEV_SHUTDOWN_TIMEOUT).await?;
+    vm.snapshot(&name).await?;
+    Ok(())
+}
+
+async fn cmd_dev_restore(machine_id: String, name: String) -> Result<(), AppError> {
+    let ctx = Context::create().unwrap();
+    let vm = Vm::find(&ctx, &machine_id).await?;
+    vm.shutdown(DEV_SHUTDOWN_TIMEOUT).await?;
+    vm.restore(&name).await?;
+    Ok(())
+}
+
+async fn cmd_dev_snapshots(machine_id: String) -> Result<(), AppError> {
+    let ctx = Context::create().unwrap();
+    let vm = Vm::find(&ctx, &machine_id).await?;
+    for snapshot in vm.snapshots().await? {
+        println!("{}", snapshot.name);
+    }
+    Ok(())
+}
+
+async fn cmd_dev_stop(machine_id: String) -> Result<(), AppError> {
+    let ctx = Context::create().unwrap();
+    let vm = Vm::find(&ctx, &machine_id).await?;
+    vm.shutdown(DEV_SHUTDOWN_TIMEOUT).await?;
+    Ok(())
+}
+
+async fn cmd_dev_rm(machine_id: String) -> Result<(), AppError> {
+    let ctx = Context::create().unwrap();
+    let vm = Vm::find(&ctx, &machine_id).await?;
+    vm.shutdown(DEV_SHUTDOWN_TIMEOUT).await?;
+    vm.remove().await?;
+    Ok(())
+}
+
+async fn cmd_dev_gc(
+    config: Config,
+    max_age_days: Option<u64>,
+    dry_run: bool,
+) -> Result<(), AppError> {
+    let ctx = Context::create().unwrap();
+    let images = list_cached_images(&ctx).await?;
+    let mut referenced =
+        referenced_image_files(config.machines.values().map(|config| &config.machine)).await?;
+    referenced.extend(backing_image_files(&ctx).await?);
+    let max_age = max_age_days.map(|days| Duration::from_secs(days * 24 * 60 * 60));
+    let now = SystemTime::now();
+    let removals = select_for_removal(images.clone(), &referenced, max_age, now);
+
+    let mut table = Table::new();
+    table
+        .load_preset(comfy_table::presets::UTF8_FULL)
+        .apply_modifier(comfy_table::modifiers::UTF8_ROUND_CORNERS)
+        .set_content_arrangement(comfy_table::ContentArrangement::Dynamic)
+        .set_header(vec!["image", "size", "age", "action"]);
+
+    for image in &images {
+        let action = match (removals.contains(image), dry_run) {
+            (false, _) => "keep",
+            (true, false) => "remove",
+            (true, true) => "would remove",
+        };
+        table.add_row(vec![
+            image.file_name(),
+            format_size(image.size),
+            format!("{}d", image.age(now).as_secs() / (24 * 60 * 60)),
+            action.to_string(),
+        ]);
+    }
+    println!("{table}");
+
+    let freed: u64 = removals.iter().map(|image| image.size).sum();
+    if !dry_run {
+        remove_cached_images(&removals).await?;
+    }
+    println!(
+        "{} {} image files, {}",
+        if dry_run { "Would remove" } else { "Removed" },
+        removals.len(),
+        format_size(freed)
+    );
+    Ok(())
+}
+
+fn format_size(bytes: u64) -> String {
+    const UNITS: [&str; 4] = ["B", "KiB", "MiB", "GiB"];
+    let mut size = bytes as f64;
+    let mut unit = 0;
+    while size >= 1024.0 && unit < UNITS.len() - 1 {
+        size /= 1024.0;
+        unit += 1;
+    }
+    format!("{size:.1} {}", UNITS[unit])
+}
+
+async fn cmd_schema_infer(params_file: PathBuf) -> Result<(), AppError> {
+    let params = ParamsInput::File(params_file)
+        .load(&mut SourceRegistry::new())
+        .await?;
+    print!("{}", ParamTypes::infer(params.inner()).to_rimu_source());
+    Ok(())
+}
+
+async fn cmd_plan_info(plan_path: PathBuf) -> Result<(), AppError> {
+    let ctx = Context::create().unwrap();
+    let mut store = Store::new(ctx.paths().cache_dir());
+    let mut sources = SourceRegistry::new();
+    let plan_path = plan_path.canonicalize().unwrap_or(plan_path);
+
+    match plan_info(PlanId::Path(plan_path), &mut store, &mut sources).await {
+        Ok(info) => {
+            print!("{info}");
+            Ok(())
+        }
+        Err(error) => {
+            for diagnostic in error.diagnostics() {
+                if let Some(rendered) = sources.render(&diagnostic) {
+                    eprintln!("{rendered}");
+                }
+            }
+            Err(error.into())
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::ffi::OsStr;
+
+    use lusid_machine::Machine;
+
+    use super::*;
+
+    fn machine_config(env: IndexMap<String, String>) -> (Config, MachineConfig) {
+        let config = Config {
+            path: PathBuf::from("lusid.toml"),
+            machines: Default::default(),
+            log: "info".into(),
+            output: OutputFormat::Text,
+            no_color: true,
+            interactive: true,
+            lusid_apply_linux_x86_64_path: "lusid-apply".into(),
+            lusid_apply_linux_aarch64_path: "lusid-apply-aarch64".into(),
+            ovmf_code_path: None,
+            ovmf_vars_path: None,
+        };
+        let machine: Machine = toml::from_str(
+            r#"
+                hostname = "amber-aura"
+                os = { type = "linux", linux = "debian", debian = 13 }
+                arch = "x86-64"
+            "#,
+        )
+        .unwrap();
+        let machine_config = MachineConfig {
+            machine,
+            plan: PathBuf::from("simple.lusid"),
+            params: None,
+            env,
+            tags: Vec::new(),
+        };
+        (config, machine_config)
+    }
+
+    fn proxy_env() -> IndexMap<String, String> {
+        IndexMap::from([
+            ("HTTP_PROXY".to_string(), "http://proxy:3128".to_string()),
+            ("REGISTRY_TOKEN".to_string(), "it's a secret".to_string()),
+        ])
+    }
+
+    #[test]
+    fn local_apply_forwards_env() {
+        let (config, machine_config) = machine_config(proxy_env());
+        let command = local_apply_command(&config, &machine_config, &ApplyArgs::default()).unwrap();
+        let envs: Vec<_> = command.get_envs().collect();
+        assert_eq!(
+            envs,
+            vec![
+                (
+                    OsStr::new("HTTP_PROXY"),
+                    Some(OsStr::new("http://proxy:3128"))
+                ),
+                (
+                    OsStr::new("REGISTRY_TOKEN"),
+                    Some(OsStr::new("it's a secret"))
+                ),
+            ]
+        );
+    }
+
+    #[test]
+    fn local_apply_preserves_env_when_escalating() {
+        let (config, machine_config) = machine_config(proxy_env());
+        let command = local_apply_command(&config, &machine_config, &ApplyArgs::default()).unwrap();
+        let command = command.to_string();
+        assert!(
+            command.contains(" --preserve-env HTTP_PROXY --preserve-env REGISTRY_TOKEN"),
+            "{command}"
+        );
+        assert!(!command.contains("secret"), "{command}");
+    }
+
+    #[test]
+    fn local_apply_passes_explain() {
+        let (config, machine_config) = machine_config(IndexMap::new());
+        let apply = ApplyArgs { explain: true };
+        let command = local_apply_command(&config, &machine_config, &apply).unwrap();
+        assert!(command.to_string().contains(" --explain"), "{command}");
+
+        let command = local_apply_command(&config, &machine_config, &ApplyArgs::default()).unwrap();
+        assert!(!command.to_string().contains("--explain"), "{command}");
+    }
+
+    #[test]
+    fn local_apply_passes_tool_version() {
+        let (config, machine_config) = machine_config(IndexMap::new());
+        let command = local_apply_command(&config, &machine_config, &ApplyArgs::default()).unwrap();
+        assert!(
+            command
+                .to_string()
+                .contains(concat!(" --tool-version ", env!("CARGO_PKG_VERSION"))),
+            "{command}"
+        );
+    }
+
+    #[test]
+    fn remote_env_script_exports_env() {
+        assert_eq!(
+            remote_env_script(&proxy_env()),
+            "export HTTP_PROXY=http://proxy:3128\nexport 'REGISTRY_TOKEN=it'\\''s a secret'\n"
+        );
+        assert_eq!(remote_env_script(&IndexMap::new()), "");
+    }
+
+    #[test]
+    fn redacts_env_values() {
+        let (_, machine_config) = machine_config(proxy_env());
+        let debug = format!("{machine_config:?}");
+        assert!(
+            debug.contains(r#"env: {"HTTP_PROXY": "<redacted>", "REGISTRY_TOKEN": "<redacted>"}"#)
+        );
+        assert!(!debug.contains("secret"));
+    }
+}
@@ -1,5 +1,6 @@
 use clap::Parser;
-use tracing_subscriber::{fmt, EnvFilter};
+use lusid_ctx::install_tracing;
+use lusid_view::detect_color;
 
 use lusid::{get_config, run, Cli};
 
@@ -14,20 +15,14 @@ async fn main() {
         }
     };
 
-    install_tracing(&config.log);
+    install_tracing(
+        &config.log,
+        cli.log_format,
+        detect_color(config.no_color, &std::io::stderr()),
+    );
 
     if let Err(error) = run(cli, config).await {
         tracing::error!("{error}");
-        std::process::exit(1);
+        std::process::exit(error.exit_code());
     }
 }
-
-pub fn install_tracing(level: &str) {
-    let filter = EnvFilter::try_new(level).unwrap_or_else(|_| EnvFilter::new("info"));
-    fmt()
-        .with_env_filter(filter)
-        .with_target(true)
-        .with_level(true)
-        .with_ansi(true)
-        .init();
-}
@@ -0,0 +1,130 @@
+use std::fmt::Display;
+
+use serde::Deserialize;
+use serde_json::{Map, Value};
+use tracing::{debug, error, info, trace, warn, Level};
+
+/// A log record from `lusid-apply --log-format json`.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub(crate) struct RemoteLog {
+    pub level: Level,
+    pub target: String,
+    pub message: String,
+    /// Other fields of the event, as `key=value`, sorted by key.
+    pub fields: Vec<(String, String)>,
+    /// Names of the spans the event was in, outermost first.
+    pub spans: Vec<String>,
+}
+
+#[derive(Deserialize)]
+struct JsonRecord {
+    level: String,
+    #[serde(default)]
+    target: String,
+    #[serde(default)]
+    fields: Map<String, Value>,
+    #[serde(default)]
+    spans: Vec<JsonSpan>,
+}
+
+#[derive(Deserialize)]
+struct JsonSpan {
+    name: String,
+}
+
+impl RemoteLog {
+    /// Parse a JSON log line, or `None` if it isn't one (so pass it through as is).
+    pub fn parse(line: &str) -> Option<Self> {
+        let record: JsonRecord = serde_json::from_str(line).ok()?;
+        let level = record.level.parse().ok()?;
+        let mut message = String::new();
+        let mut fields = Vec::new();
+        for (key, value) in record.fields {
+            let value = match value {
+                Value::String(value) => value,
+                value => value.to_string(),
+            };
+            if key == "message" {
+                message = value;
+            } else {
+                fields.push((key, value));
+            }
+        }
+        fields.sort();
+        Some(Self {
+            level,
+            target: record.target,
+            message,
+            fields,
+            spans: record.spans.into_iter().map(|span| span.name).collect(),
+        })
+    }
+
+    /// Log through the local subscriber, at the remote level.
+    pub fn emit(&self) {
+        let target = &self.target;
+        let spans = self.spans.join(":");
+        let body = self.body();
+        match self.level {
+            Level::ERROR => error!(remote_target = %target, spans = %spans, "{body}"),
+            Level::WARN => warn!(remote_target = %target, spans = %spans, "{body}"),
+            Level::INFO => info!(remote_target = %target, spans = %spans, "{body}"),
+            Level::DEBUG => debug!(remote_target = %target, spans = %spans, "{body}"),
+            _ => trace!(remote_target = %target, spans = %spans, "{body}"),
+        }
+    }
+
+    /// The message followed by any other fields.
+    fn body(&self) -> String {
+        let mut body = self.message.clone();
+        for (key, value) in &self.fields {
+            body.push_str(&format!(" {key}={value}"));
+        }
+        body
+    }
+}
+
+impl Display for RemoteLog {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{:>5} ", self.level.as_str())?;
+        for span in &self.spans {
+            write!(f, "{span}:")?;
+        }
+        if !self.spans.is_empty() {
+            write!(f, " ")?;
+        }
+        write!(f, "{}: {}", self.target, self.body())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parses_json_log_line() {
+        let line = r#"{"timestamp":"2025-11-02T10:15:00.123456Z","level":"WARN","fields":{"message":"mirror failed","url":"https://example.com/a.qcow2","attempt":2},"target":"lusid_apply","spans":[{"name":"apply"}]}"#;
+        let log = RemoteLog::parse(line).unwrap();
+        assert_eq!(log.level, Level::WARN);
+        assert_eq!(log.target, "lusid_apply");
+        assert_eq!(log.message, "mirror failed");
+        assert_eq!(
+            log.fields,
+            vec![
+                ("attempt".to_string(), "2".to_string()),
+                ("url".to_string(), "https://example.com/a.qcow2".to_string()),
+            ]
+        );
+        assert_eq!(log.spans, vec!["apply".to_string()]);
+        assert_eq!(
+            log.to_string(),
+            " WARN apply: lusid_apply: mirror failed attempt=2 url=https://example.com/a.qcow2"
+        );
+    }
+
+    #[test]
+    fn passes_through_other_lines() {
+        assert!(RemoteLog::parse("sudo: unable to resolve host").is_none());
+        assert!(RemoteLog::parse(r#"{"level":"LOUD","fields":{}}"#).is_none());
+    }
+}
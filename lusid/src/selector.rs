@@ -0,0 +1,146 @@
+use std::{fmt::Display, str::FromStr};
+
+use thiserror::Error;
+
+use crate::config::MachineConfig;
+
+/// Which machines a command applies to.
+///
+/// - `tag:web`: machines tagged `web`. `tag:web,edge`: machines tagged both
+///   `web` and `edge`.
+/// - Otherwise a glob over machine ids, where `*` matches any run of
+///   characters and `?` any one: `web-*`. A plain id matches just that machine.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum MachineSelector {
+    Glob(String),
+    Tags(Vec<String>),
+}
+
+#[derive(Error, Debug, Clone, PartialEq, Eq)]
+pub enum MachineSelectorError {
+    #[error("machine selector is empty")]
+    Empty,
+
+    #[error("machine selector has an empty tag: {selector}")]
+    EmptyTag { selector: String },
+}
+
+impl FromStr for MachineSelector {
+    type Err = MachineSelectorError;
+
+    fn from_str(selector: &str) -> Result<Self, Self::Err> {
+        if selector.is_empty() {
+            return Err(MachineSelectorError::Empty);
+        }
+        let Some(tags) = selector.strip_prefix("tag:") else {
+            return Ok(MachineSelector::Glob(selector.to_string()));
+        };
+        let tags: Vec<String> = tags.split(',').map(str::to_string).collect();
+        if tags.iter().any(String::is_empty) {
+            return Err(MachineSelectorError::EmptyTag {
+                selector: selector.to_string(),
+            });
+        }
+        Ok(MachineSelector::Tags(tags))
+    }
+}
+
+impl Display for MachineSelector {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            MachineSelector::Glob(pattern) => write!(f, "{pattern}"),
+            MachineSelector::Tags(tags) => write!(f, "tag:{}", tags.join(",")),
+        }
+    }
+}
+
+impl MachineSelector {
+    pub fn matches(&self, machine_id: &str, config: &MachineConfig) -> bool {
+        match self {
+            MachineSelector::Glob(pattern) => glob_matches(pattern, machine_id),
+            MachineSelector::Tags(tags) => tags.iter().all(|tag| config.tags.contains(tag)),
+        }
+    }
+}
+
+/// Match `text` against a glob of `*` and `?` wildcards.
+fn glob_matches(pattern: &str, text: &str) -> bool {
+    let pattern: Vec<char> = pattern.chars().collect();
+    let text: Vec<char> = text.chars().collect();
+
+    let (mut p, mut t) = (0, 0);
+    // Where to resume after the last `*`: its pattern index, and the text
+    // index it has swallowed up to.
+    let mut star: Option<(usize, usize)> = None;
+    while t < text.len() {
+        match pattern.get(p) {
+            Some('*') => {
+                star = Some((p, t));
+                p += 1;
+            }
+            Some(&c) if c == '?' || c == text[t] => {
+                p += 1;
+                t += 1;
+            }
+            _ => match star {
+                Some((star_p, star_t)) => {
+                    star = Some((star_p, star_t + 1));
+                    p = star_p + 1;
+                    t = star_t + 1;
+                }
+                None => return false,
+            },
+        }
+    }
+    pattern[p..].iter().all(|&c| c == '*')
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parses_selectors() {
+        assert_eq!(
+            "web-*".parse(),
+            Ok(MachineSelector::Glob("web-*".to_string()))
+        );
+        assert_eq!(
+            "tag:db".parse(),
+            Ok(MachineSelector::Tags(vec!["db".to_string()]))
+        );
+        assert_eq!(
+            "tag:web,edge".parse(),
+            Ok(MachineSelector::Tags(vec![
+                "web".to_string(),
+                "edge".to_string()
+            ]))
+        );
+        assert_eq!(
+            "".parse::<MachineSelector>(),
+            Err(MachineSelectorError::Empty)
+        );
+        assert!(matches!(
+            "tag:".parse::<MachineSelector>(),
+            Err(MachineSelectorError::EmptyTag { .. })
+        ));
+        assert!(matches!(
+            "tag:web,".parse::<MachineSelector>(),
+            Err(MachineSelectorError::EmptyTag { .. })
+        ));
+    }
+
+    #[test]
+    fn globs() {
+        assert!(glob_matches("web-*", "web-1"));
+        assert!(glob_matches("web-*", "web-"));
+        assert!(!glob_matches("web-*", "db-1"));
+        assert!(glob_matches("*-1", "web-1"));
+        assert!(glob_matches("w?b-*", "wob-a"));
+        assert!(glob_matches("*a*b", "xaxxab"));
+        assert!(!glob_matches("*a*b", "xaxxa"));
+        assert!(glob_matches("web-1", "web-1"));
+        assert!(!glob_matches("web-1", "web-10"));
+        assert!(glob_matches("*", ""));
+    }
+}
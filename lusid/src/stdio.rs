@@ -0,0 +1,231 @@
+use std::future::Future;
+use std::io::{self, Write};
+use std::pin::Pin;
+
+use lusid_apply_stdio::{
+    consume_updates, strip_ansi, AppUpdate, AppView, AppViewError, UpdateSink, UpdateStreamError,
+};
+use lusid_cmd::CommandError;
+use lusid_ssh::SshError;
+use thiserror::Error;
+use tokio::io::{AsyncBufReadExt, AsyncRead, BufReader};
+use tracing::warn;
+
+use crate::remote_log::RemoteLog;
+use crate::update_log::UpdateLogError;
+
+#[derive(Error, Debug)]
+pub enum StdioError {
+    #[error(transparent)]
+    ApplyStdout(#[from] UpdateStreamError),
+
+    #[error("failed to read stderr from apply")]
+    ReadApplyStderr(#[source] tokio::io::Error),
+
+    #[error("failed to write apply output")]
+    WriteOutput(#[source] io::Error),
+
+    #[error(transparent)]
+    AppView(#[from] AppViewError),
+
+    #[error("apply command failed: {0}")]
+    Command(#[from] CommandError),
+
+    #[error("ssh failed: {0}")]
+    Ssh(#[from] SshError),
+
+    #[error(transparent)]
+    UpdateLog(#[from] UpdateLogError),
+}
+
+#[derive(Debug, Clone, Copy)]
+pub struct StdioOptions {
+    /// Remove ANSI escape sequences from forwarded output.
+    pub strip_ansi: bool,
+    /// Print operation output and the summary to stdout. Disable to keep
+    /// stdout free for machine-readable output.
+    pub print_updates: bool,
+}
+
+impl StdioOptions {
+    /// Strip ANSI sequences unless color output is enabled.
+    pub fn detect(color: bool) -> Self {
+        Self {
+            strip_ansi: !color,
+            print_updates: true,
+        }
+    }
+}
+
+/// Plain line-based consumer of apply updates, for when no terminal is attached.
+pub async fn stdio<Stdout, Stderr, Wait, WaitError>(
+    stdout: Stdout,
+    stderr: Stderr,
+    wait: Pin<Box<Wait>>,
+    options: StdioOptions,
+) -> Result<AppView, StdioError>
+where
+    Stdout: AsyncRead + Unpin,
+    Stderr: AsyncRead + Unpin,
+    Wait: Future<Output = Result<(), WaitError>>,
+    WaitError: Into<StdioError>,
+{
+    let mut sink = StdioSink::new(options, io::stdout(), io::stderr());
+
+    let mut stderr_lines = BufReader::new(stderr).lines();
+    let mut stdout_done = false;
+    let mut stderr_done = false;
+
+    let mut outcome: Option<Result<(), StdioError>> = None;
+
+    {
+        let updates = consume_updates(stdout, &mut sink);
+        tokio::pin!(wait);
+        tokio::pin!(updates);
+
+        while outcome.is_none() || !stdout_done || !stderr_done {
+            tokio::select! {
+                result = &mut wait, if outcome.is_none() => {
+                    outcome = Some(result.map_err(Into::into));
+                }
+
+                result = &mut updates, if !stdout_done => {
+                    result?;
+                    stdout_done = true;
+                }
+
+                line = stderr_lines.next_line(), if !stderr_done => {
+                    match line.map_err(StdioError::ReadApplyStderr)? {
+                        Some(line) => match RemoteLog::parse(&line) {
+                            Some(log) => log.emit(),
+                            None => eprintln!("{}", clean(&line, options)),
+                        },
+                        None => stderr_done = true,
+                    }
+                }
+            }
+        }
+    }
+
+    let StdioSink { app_view, .. } = sink;
+    if let Some(summary) = app_view.summary().filter(|_| options.print_updates) {
+        println!("Summary: {summary}.");
+    }
+
+    match outcome {
+        Some(Err(error)) => Err(error),
+        _ => Ok(app_view),
+    }
+}
+
+/// Prints the user-facing parts of each update as it arrives and folds it
+/// into the view.
+struct StdioSink<Out, Err> {
+    app_view: AppView,
+    options: StdioOptions,
+    out: Out,
+    err: Err,
+}
+
+impl<Out: Write, Err: Write> StdioSink<Out, Err> {
+    fn new(options: StdioOptions, out: Out, err: Err) -> Self {
+        Self {
+            app_view: AppView::default(),
+            options,
+            out,
+            err,
+        }
+    }
+}
+
+impl<Out: Write, Err: Write> UpdateSink for StdioSink<Out, Err> {
+    type Error = StdioError;
+
+    fn update(&mut self, update: AppUpdate) -> Result<(), Self::Error> {
+        if self.options.print_updates {
+            print_update(&update, self.options, &mut self.out, &mut self.err)
+                .map_err(StdioError::WriteOutput)?;
+        }
+        let current = std::mem::take(&mut self.app_view);
+        self.app_view = match current.update(update) {
+            Ok(app_view) => app_view,
+            Err(AppViewError::UnexpectedUpdate {
+                phase,
+                update,
+                view,
+            }) => {
+                warn!("ignoring unexpected update in {phase} phase: {update:?}");
+                *view
+            }
+            Err(error) => return Err(error.into()),
+        };
+        Ok(())
+    }
+}
+
+fn print_update(
+    update: &AppUpdate,
+    options: StdioOptions,
+    out: &mut impl Write,
+    err: &mut impl Write,
+) -> io::Result<()> {
+    match update {
+        AppUpdate::ResourceChangesComplete { has_changes: false } => writeln!(out, "No changes."),
+        AppUpdate::OperationApplyStdout { stdout, .. } => {
+            writeln!(out, "{}", clean(stdout, options))
+        }
+        AppUpdate::OperationApplyStderr { stderr, .. } => {
+            writeln!(err, "{}", clean(stderr, options))
+        }
+        _ => Ok(()),
+    }
+}
+
+fn clean(line: &str, options: StdioOptions) -> String {
+    if options.strip_ansi {
+        strip_ansi(line)
+    } else {
+        line.to_string()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use lusid_view::{View, ViewTree};
+
+    use super::*;
+
+    fn leaf(label: &str) -> ViewTree {
+        ViewTree::Leaf {
+            view: View::Span(label.into()),
+        }
+    }
+
+    #[test]
+    fn only_operation_output_reaches_stdout() {
+        let mut sink = StdioSink::new(StdioOptions::detect(false), Vec::new(), Vec::new());
+        let updates = [
+            AppUpdate::ResourceParams {
+                resource_params: leaf("params"),
+            },
+            AppUpdate::ResourcesStart,
+            AppUpdate::ResourcesNode {
+                index: 0,
+                tree: leaf("resource"),
+            },
+            AppUpdate::ResourcesComplete,
+        ];
+        for update in updates {
+            sink.update(update).unwrap();
+        }
+        assert!(sink.out.is_empty());
+        assert!(sink.err.is_empty());
+
+        let update = AppUpdate::OperationApplyStdout {
+            index: (0, 0),
+            stdout: "\u{1b}[32mhello\u{1b}[0m".into(),
+        };
+        print_update(&update, sink.options, &mut sink.out, &mut sink.err).unwrap();
+        assert_eq!(sink.out, b"hello\n");
+    }
+}
@@ -1,41 +1,42 @@
 #![allow(clippy::collapsible_if)]
 
-use std::collections::HashSet;
+use std::collections::{hash_map::Entry, HashMap, HashSet, VecDeque};
 use std::future::Future;
 use std::io;
 use std::pin::Pin;
 
 use crossterm::event::{Event, KeyCode, KeyEvent, KeyModifiers};
 use lusid_apply_stdio::{
-    AppUpdate, AppView, AppViewError, FlatViewTree, FlatViewTreeError, FlatViewTreeNode,
-    OperationView, ViewNode,
+    consume_updates, strip_ansi, AppUpdate, AppView, AppViewError, FlatViewTree, FlatViewTreeError,
+    FlatViewTreeNode, OperationStatus, OperationView, UpdateSink, UpdateStreamError, ViewNode,
 };
 use lusid_cmd::CommandError;
 use lusid_ssh::SshError;
+use lusid_view::Render;
 use ratatui::{
+    buffer::Buffer,
     layout::{Alignment, Constraint, Direction, Layout, Rect},
     style::{Color, Modifier, Style},
     text::{Line, Span, Text},
     widgets::{Block, Borders, List, ListItem, ListState, Paragraph, Wrap},
     CompletedFrame, DefaultTerminal, Frame,
 };
-use serde_json::Error as SerdeJsonError;
 use thiserror::Error;
 use tokio::{
     io::{AsyncBufReadExt, AsyncRead, BufReader},
-    sync::mpsc::{unbounded_channel, UnboundedReceiver},
+    sync::mpsc::{unbounded_channel, UnboundedReceiver, UnboundedSender},
 };
 
+use crate::remote_log::RemoteLog;
+use crate::update_log::UpdateLogError;
+
 #[derive(Error, Debug)]
 pub enum TuiError {
     #[error(transparent)]
     Io(#[from] io::Error),
 
-    #[error("failed to parse apply stdout as json: {0}")]
-    ParseApplyStdout(#[from] SerdeJsonError),
-
-    #[error("failed to read stdout from apply")]
-    ReadApplyStdout(#[source] tokio::io::Error),
+    #[error(transparent)]
+    ApplyStdout(#[from] UpdateStreamError),
 
     #[error("failed to read stderr from apply")]
     ReadApplyStderr(#[source] tokio::io::Error),
@@ -52,6 +53,9 @@ pub enum TuiError {
     #[error("ssh failed: {0}")]
     Ssh(#[from] SshError),
 
+    #[error(transparent)]
+    UpdateLog(#[from] UpdateLogError),
+
     #[error("failed to join task: {0}")]
     TaskJoin(#[from] tokio::task::JoinError),
 }
@@ -60,7 +64,8 @@ pub async fn tui<Stdout, Stderr, Wait, WaitError>(
     stdout: Stdout,
     stderr: Stderr,
     wait: Pin<Box<Wait>>,
-) -> Result<(), TuiError>
+    color: bool,
+) -> Result<AppView, TuiError>
 where
     Stdout: AsyncRead + Unpin,
     Stderr: AsyncRead + Unpin,
@@ -70,7 +75,9 @@ where
     let mut terminal = TerminalSession::init();
     let mut app = TuiApp::new();
 
-    let mut stdout_lines = BufReader::new(stdout).lines();
+    // Updates are read in the background and applied here, between draws.
+    let (update_tx, mut update_rx) = unbounded_channel();
+    let updates = consume_updates(stdout, ChannelSink(update_tx));
     let mut stderr_lines = BufReader::new(stderr).lines();
     let mut stdout_done = false;
     let mut stderr_done = false;
@@ -81,9 +88,15 @@ where
     let mut should_quit = false;
 
     tokio::pin!(wait);
+    tokio::pin!(updates);
 
     loop {
-        terminal.draw(|frame| draw_ui(frame, &mut app, outcome.as_ref()))?;
+        terminal.draw(|frame| {
+            draw_ui(frame, &mut app, outcome.as_ref());
+            if !color {
+                strip_colors(frame.buffer_mut());
+            }
+        })?;
 
         tokio::select! {
             result = &mut wait, if outcome.is_none() => {
@@ -91,23 +104,20 @@ where
                 outcome = Some(result.map_err(Into::into));
             }
 
-            line = stdout_lines.next_line(), if !stdout_done => {
-                match line {
-                    Ok(Some(line)) => {
-                        if !line.trim().is_empty() {
-                            let update: AppUpdate = serde_json::from_str(&line)?;
-                            app.apply_update(update)?;
-                        }
-                    }
-                    Ok(None) => stdout_done = true,
-                    Err(err) => return Err(err.into()),
-                }
+            result = &mut updates, if !stdout_done => {
+                result?;
+                stdout_done = true;
+            }
+
+            Some(update) = update_rx.recv() => {
+                app.apply_update(update)?;
             }
 
             line = stderr_lines.next_line(), if !stderr_done => {
                 match line {
-                    Ok(Some(_line)) => {
-                        // TODO
+                    Ok(Some(line)) => match RemoteLog::parse(&line) {
+                        Some(log) => app.logs.push(log.to_string()),
+                        None => app.logs.push(strip_ansi(&line)),
                     },
                     Ok(None) => stderr_done = true,
                     Err(err) => return Err(err.into()),
@@ -125,8 +135,21 @@ where
     }
 
     match outcome {
-        None => Ok(()),
-        Some(result) => result,
+        Some(Err(error)) => Err(error),
+        _ => Ok(app.app_view),
+    }
+}
+
+/// Hands updates to the draw loop, which owns the app.
+struct ChannelSink(UnboundedSender<AppUpdate>);
+
+impl UpdateSink for ChannelSink {
+    type Error = TuiError;
+
+    fn update(&mut self, update: AppUpdate) -> Result<(), Self::Error> {
+        // The receiver only goes away once the TUI has quit.
+        let _ = self.0.send(update);
+        Ok(())
     }
 }
 
@@ -240,6 +263,11 @@ struct TreeState {
     collapsed: HashSet<usize>,
     selected_node: Option<usize>,
     list_offset: usize,
+    list_height: usize,
+    filter: String,
+    /// Paths of the selected and collapsed nodes, to find them again if an
+    /// update replaces them.
+    paths: HashMap<usize, TreePath>,
 }
 
 impl TreeState {
@@ -255,6 +283,76 @@ impl TreeState {
         !self.collapsed.contains(&node_index)
     }
 
+    fn collapse_all(&mut self, tree: &FlatViewTree) {
+        self.collapsed = tree
+            .nodes()
+            .enumerate()
+            .filter_map(|(index, node)| match node {
+                Some(FlatViewTreeNode::Branch { .. }) => Some(index),
+                _ => None,
+            })
+            .collect();
+    }
+
+    fn expand_all(&mut self) {
+        self.collapsed.clear();
+    }
+
+    /// Record the path of each selected or collapsed node, since an update
+    /// that replaces a subtree gives the nodes in it new indices.
+    ///
+    /// Paths are kept until their node is deselected or expanded, so only
+    /// nodes not seen before cost a walk over the tree.
+    fn remember_paths(&mut self, tree: &FlatViewTree) {
+        let wanted: HashSet<usize> = self
+            .selected_node
+            .iter()
+            .chain(&self.collapsed)
+            .copied()
+            .collect();
+        self.paths.retain(|index, _| wanted.contains(index));
+        if wanted.len() == self.paths.len() {
+            return;
+        }
+
+        let parents = tree_parents(tree);
+        for index in wanted {
+            if let Entry::Vacant(entry) = self.paths.entry(index) {
+                if let Some(path) = tree_path(tree, &parents, index) {
+                    entry.insert(path);
+                }
+            }
+        }
+    }
+
+    /// Find the selected and collapsed nodes again after an update. Nodes
+    /// still in the tree keep their index, and replaced ones are found by
+    /// path. The selection is left as-is if it can't be found.
+    fn restore(&mut self, tree: &FlatViewTree) {
+        if let Some(index) = self
+            .selected_node
+            .and_then(|index| self.resolve(tree, index))
+        {
+            self.selected_node = Some(index);
+        }
+
+        let collapsed = std::mem::take(&mut self.collapsed);
+        self.collapsed = collapsed
+            .into_iter()
+            .filter_map(|index| self.resolve(tree, index))
+            .collect();
+    }
+
+    fn resolve(&mut self, tree: &FlatViewTree, index: usize) -> Option<usize> {
+        if tree.get(index).is_ok() {
+            return Some(index);
+        }
+        let path = self.paths.remove(&index)?;
+        let resolved = tree_resolve_path(tree, &path)?;
+        self.paths.insert(resolved, path);
+        Some(resolved)
+    }
+
     fn ensure_visible_row(&mut self, selected_row: usize, height: usize) {
         if height == 0 {
             return;
@@ -270,11 +368,19 @@ impl TreeState {
     }
 }
 
+/// A node's location as (position among siblings, label) steps from the root.
+type TreePath = Vec<(usize, String)>;
+
 #[derive(Debug, Default, Clone)]
 struct OperationsApplyState {
     flat_index_to_epoch_operation: Vec<(usize, usize)>,
     selected_flat: Option<usize>,
     list_offset: usize,
+
+    is_logs_focused: bool,
+    log_scroll: usize,
+    log_lines: usize,
+    log_height: usize,
 }
 
 impl OperationsApplyState {
@@ -317,6 +423,81 @@ impl OperationsApplyState {
             self.list_offset = selected_row.saturating_sub(height.saturating_sub(1));
         }
     }
+
+    fn select_flat(&mut self, selected: usize) {
+        if self.selected_flat != Some(selected) {
+            self.selected_flat = Some(selected);
+            self.log_scroll = 0;
+        }
+    }
+
+    fn scroll_logs(&mut self, scroll: LogScroll) {
+        let offset = match scroll {
+            LogScroll::Up(lines) => self.log_scroll.saturating_sub(lines),
+            LogScroll::Down(lines) => self.log_scroll.saturating_add(lines),
+            LogScroll::Top => 0,
+            LogScroll::Bottom => usize::MAX,
+        };
+        self.log_scroll = clamp_log_scroll(offset, self.log_lines, self.log_height);
+    }
+}
+
+#[derive(Debug, Clone, Copy)]
+enum LogScroll {
+    Up(usize),
+    Down(usize),
+    Top,
+    Bottom,
+}
+
+/// Clamp a scroll offset so the last page of content stays filled.
+fn clamp_log_scroll(offset: usize, content_lines: usize, viewport_height: usize) -> usize {
+    offset.min(content_lines.saturating_sub(viewport_height))
+}
+
+/// Lines `text` takes up in a log pane `width` columns wide, once wrapped.
+fn wrapped_line_count(text: &str, width: u16) -> usize {
+    Paragraph::new(text)
+        .wrap(Wrap { trim: false })
+        .line_count(width)
+}
+
+/// Number of apply log lines (stderr) kept in memory.
+const LOGS_CAPACITY: usize = 1000;
+
+/// A fixed-capacity buffer which drops the oldest item when full.
+#[derive(Debug, Clone)]
+struct CircularBuffer<T> {
+    items: VecDeque<T>,
+    capacity: usize,
+}
+
+impl<T> CircularBuffer<T> {
+    fn new(capacity: usize) -> Self {
+        Self {
+            items: VecDeque::with_capacity(capacity),
+            capacity,
+        }
+    }
+
+    fn push(&mut self, item: T) {
+        if self.capacity == 0 {
+            return;
+        }
+        if self.items.len() == self.capacity {
+            self.items.pop_front();
+        }
+        self.items.push_back(item);
+    }
+
+    fn len(&self) -> usize {
+        self.items.len()
+    }
+
+    /// Iterate from oldest to newest.
+    fn iter(&self) -> impl Iterator<Item = &T> {
+        self.items.iter()
+    }
 }
 
 #[derive(Debug, Clone)]
@@ -333,6 +514,9 @@ struct TuiApp {
 
     operations_apply_state: OperationsApplyState,
 
+    logs: CircularBuffer<String>,
+
+    is_editing_filter: bool,
     child_exited: bool,
 }
 
@@ -350,14 +534,41 @@ impl TuiApp {
             operations_state: TreeState::default(),
 
             operations_apply_state: OperationsApplyState::default(),
+            logs: CircularBuffer::new(LOGS_CAPACITY),
+            is_editing_filter: false,
             child_exited: false,
         }
     }
 
     fn apply_update(&mut self, update: AppUpdate) -> Result<(), TuiError> {
+        for stage in PipelineStage::ALL {
+            if let Some((tree, state)) = self.tree_and_state_mut(stage) {
+                state.remember_paths(tree);
+            }
+        }
+
         let current = std::mem::take(&mut self.app_view);
 
-        self.app_view = current.update(update)?;
+        self.app_view = match current.update(update) {
+            Ok(app_view) => app_view,
+            Err(AppViewError::UnexpectedUpdate {
+                phase,
+                update,
+                view,
+            }) => {
+                self.logs.push(format!(
+                    "ignoring unexpected update in {phase} phase: {update:?}"
+                ));
+                *view
+            }
+            Err(error) => return Err(error.into()),
+        };
+
+        for stage in PipelineStage::ALL {
+            if let Some((tree, state)) = self.tree_and_state_mut(stage) {
+                state.restore(tree);
+            }
+        }
 
         if self.follow_pipeline {
             let next = PipelineStage::from_app_view(&self.app_view);
@@ -378,6 +589,19 @@ impl TuiApp {
             code, modifiers, ..
         }) = event
         {
+            if self.is_editing_filter {
+                self.handle_filter_key(code);
+                return Ok(false);
+            }
+
+            if modifiers == KeyModifiers::SHIFT {
+                match code {
+                    KeyCode::Char('N') => self.jump_to_match(-1),
+                    KeyCode::Char('Z') => self.set_all_collapsed(false),
+                    _ => {}
+                }
+            }
+
             if modifiers == KeyModifiers::NONE {
                 match code {
                     KeyCode::Char('q') | KeyCode::Esc => return Ok(true),
@@ -417,8 +641,35 @@ impl TuiApp {
                     KeyCode::Down | KeyCode::Char('j') => self.move_down(),
                     KeyCode::Up | KeyCode::Char('k') => self.move_up(),
 
+                    KeyCode::Char('l') if self.stage == PipelineStage::OperationsEpochs => {
+                        let state = &mut self.operations_apply_state;
+                        state.is_logs_focused = !state.is_logs_focused;
+                    }
+                    KeyCode::PageUp | KeyCode::PageDown | KeyCode::Home | KeyCode::End
+                        if self.is_logs_focused() =>
+                    {
+                        let state = &mut self.operations_apply_state;
+                        let page = state.log_height.max(1);
+                        state.scroll_logs(match code {
+                            KeyCode::PageUp => LogScroll::Up(page),
+                            KeyCode::PageDown => LogScroll::Down(page),
+                            KeyCode::Home => LogScroll::Top,
+                            _ => LogScroll::Bottom,
+                        });
+                    }
+
                     KeyCode::Enter | KeyCode::Char(' ') => self.toggle_selected(),
 
+                    KeyCode::Char('/') => {
+                        if let Some((_tree, state)) = self.tree_for_stage_mut() {
+                            state.filter.clear();
+                            self.is_editing_filter = true;
+                        }
+                    }
+                    KeyCode::Char('n') => self.jump_to_match(1),
+
+                    KeyCode::Char('z') => self.set_all_collapsed(true),
+
                     _ => {}
                 }
             }
@@ -427,6 +678,41 @@ impl TuiApp {
         Ok(false)
     }
 
+    fn handle_filter_key(&mut self, code: KeyCode) {
+        let Some((tree, state)) = self.tree_for_stage_mut() else {
+            self.is_editing_filter = false;
+            return;
+        };
+
+        match code {
+            KeyCode::Enter => self.is_editing_filter = false,
+            KeyCode::Esc => {
+                state.filter.clear();
+                self.is_editing_filter = false;
+            }
+            KeyCode::Backspace => {
+                state.filter.pop();
+            }
+            KeyCode::Char(c) => {
+                state.filter.push(c);
+                tree_jump_to_match(tree, state, 0);
+            }
+            _ => {}
+        }
+    }
+
+    fn set_all_collapsed(&mut self, collapsed: bool) {
+        if let Some((tree, state)) = self.tree_for_stage_mut() {
+            tree_set_all_collapsed(tree, state, collapsed);
+        }
+    }
+
+    fn jump_to_match(&mut self, direction: i32) {
+        if let Some((tree, state)) = self.tree_for_stage_mut() {
+            tree_jump_to_match(tree, state, direction);
+        }
+    }
+
     fn navigate_stage_relative(&mut self, direction: i32) {
         if direction == 0 {
             return;
@@ -453,16 +739,23 @@ impl TuiApp {
         }
     }
 
+    fn is_logs_focused(&self) -> bool {
+        self.stage == PipelineStage::OperationsEpochs && self.operations_apply_state.is_logs_focused
+    }
+
     fn move_down(&mut self) {
         match self.stage {
+            PipelineStage::OperationsEpochs if self.is_logs_focused() => {
+                self.operations_apply_state.scroll_logs(LogScroll::Down(1));
+            }
             PipelineStage::OperationsEpochs => {
                 let len = self.operations_apply_state.visible_len();
                 if len == 0 {
                     return;
                 }
                 let selected = self.operations_apply_state.selected_flat.unwrap_or(0);
-                self.operations_apply_state.selected_flat =
-                    Some((selected + 1).min(len.saturating_sub(1)));
+                self.operations_apply_state
+                    .select_flat((selected + 1).min(len.saturating_sub(1)));
             }
             _ => {
                 if let Some((tree, state)) = self.tree_for_stage_mut() {
@@ -474,9 +767,13 @@ impl TuiApp {
 
     fn move_up(&mut self) {
         match self.stage {
+            PipelineStage::OperationsEpochs if self.is_logs_focused() => {
+                self.operations_apply_state.scroll_logs(LogScroll::Up(1));
+            }
             PipelineStage::OperationsEpochs => {
                 let selected = self.operations_apply_state.selected_flat.unwrap_or(0);
-                self.operations_apply_state.selected_flat = Some(selected.saturating_sub(1));
+                self.operations_apply_state
+                    .select_flat(selected.saturating_sub(1));
             }
             _ => {
                 if let Some((tree, state)) = self.tree_for_stage_mut() {
@@ -503,7 +800,14 @@ impl TuiApp {
     }
 
     fn tree_for_stage_mut(&mut self) -> Option<(&FlatViewTree, &mut TreeState)> {
-        match self.stage {
+        self.tree_and_state_mut(self.stage)
+    }
+
+    fn tree_and_state_mut(
+        &mut self,
+        stage: PipelineStage,
+    ) -> Option<(&FlatViewTree, &mut TreeState)> {
+        match stage {
             PipelineStage::ResourceParams => self
                 .app_view
                 .resource_params()
@@ -537,6 +841,7 @@ fn draw_ui(frame: &mut ratatui::Frame, app: &mut TuiApp, outcome: Option<&Result
             [
                 Constraint::Length(4),
                 Constraint::Min(5),
+                Constraint::Length(LOGS_HEIGHT),
                 Constraint::Length(1),
             ]
             .as_ref(),
@@ -546,7 +851,37 @@ fn draw_ui(frame: &mut ratatui::Frame, app: &mut TuiApp, outcome: Option<&Result
     frame.render_widget(outer, frame.area());
     draw_pipeline(frame, layout[0], app, outcome);
     draw_main(frame, layout[1], app);
-    draw_help(frame, layout[2], app);
+    draw_logs(frame, layout[2], app);
+    draw_help(frame, layout[3], app);
+}
+
+/// Reset every cell to the terminal's own colors, keeping modifiers like
+/// bold and reversed so selections still show.
+fn strip_colors(buffer: &mut Buffer) {
+    for cell in buffer.content.iter_mut() {
+        cell.set_fg(Color::Reset).set_bg(Color::Reset);
+    }
+}
+
+/// Height of the apply logs pane, including borders.
+const LOGS_HEIGHT: u16 = 6;
+
+fn draw_logs(frame: &mut ratatui::Frame, area: Rect, app: &TuiApp) {
+    let visible = area.height.saturating_sub(2) as usize;
+    let skip = app.logs.len().saturating_sub(visible);
+
+    let lines: Vec<Line> = app
+        .logs
+        .iter()
+        .skip(skip)
+        .map(|line| Line::from(Span::raw(line.as_str())))
+        .collect();
+
+    let widget = Paragraph::new(Text::from(lines))
+        .block(Block::default().borders(Borders::ALL).title("apply logs"))
+        .style(Style::default().fg(Color::DarkGray));
+
+    frame.render_widget(widget, area);
 }
 
 fn draw_pipeline(
@@ -613,6 +948,11 @@ fn pipeline_feedback_line(app: &TuiApp, outcome: Option<&Result<(), TuiError>>)
 
         AppView::Resources { .. } => "Resources planned.".to_string(),
 
+        AppView::ResourceStates {
+            progress: Some(progress),
+            ..
+        } => format!("{progress} states fetched."),
+
         AppView::ResourceStates { .. } => "Resource states are being fetched.".to_string(),
 
         AppView::ResourceChanges { has_changes, .. } => match has_changes {
@@ -625,11 +965,15 @@ fn pipeline_feedback_line(app: &TuiApp, outcome: Option<&Result<(), TuiError>>)
 
         AppView::OperationsApply { .. } => "Applying operations epochs.".to_string(),
 
-        AppView::Done { .. } => {
+        AppView::Done { summary, .. } => {
+            let complete = match summary {
+                Some(summary) => format!("Complete: {summary}."),
+                None => "Complete.".to_string(),
+            };
             if app.child_exited {
-                "Complete.".to_string()
+                complete
             } else {
-                "Complete (waiting for process to exit)...".to_string()
+                format!("{complete} (waiting for process to exit)...")
             }
         }
     }
@@ -681,14 +1025,35 @@ fn draw_main(frame: &mut ratatui::Frame<'_>, area: Rect, app: &mut TuiApp) {
     }
 }
 
-fn draw_help(frame: &mut ratatui::Frame, area: Rect, _app: &TuiApp) {
+fn draw_help(frame: &mut ratatui::Frame, area: Rect, app: &TuiApp) {
     let hints =
-        "Left and Right navigate stages  Up and Down move  Enter toggles tree  f follow  q quit";
+        "Left and Right navigate stages  Up and Down move  Enter toggles tree  l focus logs  PgUp/PgDn scroll logs  z/Z collapse/expand all  / filter  n/N next/prev match  f follow  q quit";
+
+    let filter = match app.stage {
+        PipelineStage::ResourceParams => Some(&app.params_state.filter),
+        PipelineStage::Resources => Some(&app.resources_state.filter),
+        PipelineStage::ResourceStates => Some(&app.states_state.filter),
+        PipelineStage::ResourceChanges => Some(&app.changes_state.filter),
+        PipelineStage::OperationsTree => Some(&app.operations_state.filter),
+        PipelineStage::OperationsEpochs => None,
+    };
 
-    let lines = vec![Line::from(Span::styled(
-        hints,
-        Style::default().fg(Color::DarkGray),
-    ))];
+    let line = match filter {
+        Some(filter) if app.is_editing_filter || !filter.is_empty() => Line::from(vec![
+            Span::styled(format!("/{filter}"), Style::default().fg(Color::Yellow)),
+            Span::styled(
+                if app.is_editing_filter {
+                    "  Enter accept  Esc clear"
+                } else {
+                    "  n/N next/prev match  / new filter"
+                },
+                Style::default().fg(Color::DarkGray),
+            ),
+        ]),
+        _ => Line::from(Span::styled(hints, Style::default().fg(Color::DarkGray))),
+    };
+
+    let lines = vec![line];
 
     let widget = Paragraph::new(Text::from(lines))
         .block(Block::default())
@@ -723,12 +1088,22 @@ fn draw_apply(
     let mut items: Vec<ListItem<'_>> = Vec::new();
     for (epoch_index, operations) in epochs.iter().enumerate() {
         for (operation_index, operation) in operations.iter().enumerate() {
-            let status = if operation.is_complete { "✅" } else { "…" };
-            let label = format!(
-                "[{status}] (epoch {epoch_index}, operation {operation_index}) {}",
+            let mut label = format!(
+                "(epoch {epoch_index}, operation {operation_index}) {}",
                 operation.label
             );
-            items.push(ListItem::new(Line::from(Span::raw(label))));
+            if let Some(duration_ms) = operation.duration_ms {
+                let seconds = duration_ms as f64 / 1000.0;
+                label.push_str(&format!(" ({seconds:.1}s)"));
+            }
+            let line = match &operation.status {
+                OperationStatus::Failed(error) => Line::from(vec![
+                    Span::raw(format!("[❌] {label}: ")),
+                    Span::styled(error.clone(), Style::default().fg(Color::Red)),
+                ]),
+                status => Line::from(Span::raw(format!("[{}] {label}", status.render()))),
+            };
+            items.push(ListItem::new(line));
         }
     }
 
@@ -775,14 +1150,41 @@ fn draw_apply(
         .constraints([Constraint::Percentage(60), Constraint::Percentage(40)].as_ref())
         .split(layout[1]);
 
+    state.log_height = logs_layout[0].height.saturating_sub(2) as usize;
+    // Both panes scroll together, so the longer one, as wrapped to its own
+    // width, sets how far they can go.
+    state.log_lines = wrapped_line_count(&stdout, logs_layout[0].width.saturating_sub(2)).max(
+        wrapped_line_count(&stderr, logs_layout[1].width.saturating_sub(2)),
+    );
+    state.log_scroll = clamp_log_scroll(state.log_scroll, state.log_lines, state.log_height);
+    let scroll = (u16::try_from(state.log_scroll).unwrap_or(u16::MAX), 0);
+
+    let logs_border_style = if state.is_logs_focused {
+        Style::default().fg(Color::Cyan)
+    } else {
+        Style::default()
+    };
+
     let stdout_widget = Paragraph::new(stdout)
-        .block(Block::default().borders(Borders::ALL).title("stdout"))
+        .block(
+            Block::default()
+                .borders(Borders::ALL)
+                .border_style(logs_border_style)
+                .title("stdout"),
+        )
         .wrap(Wrap { trim: false })
+        .scroll(scroll)
         .style(Style::default().fg(Color::White));
 
     let stderr_widget = Paragraph::new(stderr)
-        .block(Block::default().borders(Borders::ALL).title("stderr"))
+        .block(
+            Block::default()
+                .borders(Borders::ALL)
+                .border_style(logs_border_style)
+                .title("stderr"),
+        )
         .wrap(Wrap { trim: false })
+        .scroll(scroll)
         .style(Style::default().fg(Color::Red));
 
     frame.render_widget(stdout_widget, logs_layout[0]);
@@ -819,7 +1221,14 @@ fn draw_tree(
                 spans.push(Span::styled("• ", Style::default().fg(Color::DarkGray)));
             }
 
-            spans.push(Span::raw(&row.label));
+            if row.is_match {
+                spans.push(Span::styled(
+                    &row.label,
+                    Style::default().fg(Color::Black).bg(Color::Yellow),
+                ));
+            } else {
+                spans.extend(row.spans.iter().cloned());
+            }
 
             ListItem::new(Line::from(spans))
         })
@@ -830,6 +1239,7 @@ fn draw_tree(
     *list_state.offset_mut() = state.list_offset;
 
     let inner_height = area.height.saturating_sub(2) as usize;
+    state.list_height = inner_height;
     if let Some(selected_row) = selected_row {
         state.ensure_visible_row(selected_row, inner_height);
         *list_state.offset_mut() = state.list_offset;
@@ -852,18 +1262,105 @@ struct TreeRow {
     depth: usize,
     is_branch: bool,
     is_expanded: bool,
+    is_match: bool,
     label: String,
+    /// Styled label, from the node's view.
+    spans: Vec<Span<'static>>,
+}
+
+/// Nodes matching a filter query, plus their ancestors (which are
+/// auto-expanded so matches are always visible).
+#[derive(Debug, Default, Clone)]
+struct TreeFilter {
+    matches: HashSet<usize>,
+    ancestors: HashSet<usize>,
+}
+
+impl TreeFilter {
+    fn new(tree: &FlatViewTree, query: &str) -> Self {
+        let mut filter = TreeFilter::default();
+        if !query.is_empty() {
+            let query = query.to_lowercase();
+            let mut visited = HashSet::new();
+            filter.visit(tree, FlatViewTree::root_index(), &query, &mut visited);
+        }
+        filter
+    }
+
+    /// Returns true if the subtree at `index` contains a match.
+    fn visit(
+        &mut self,
+        tree: &FlatViewTree,
+        index: usize,
+        query: &str,
+        visited: &mut HashSet<usize>,
+    ) -> bool {
+        if !visited.insert(index) {
+            return false;
+        }
+
+        let Ok(node) = tree.get(index) else {
+            return false;
+        };
+
+        let mut found = node_label(node).to_lowercase().contains(query);
+        if found {
+            self.matches.insert(index);
+        }
+
+        if let FlatViewTreeNode::Branch { children, .. } = node {
+            let mut child_found = false;
+            for child in children.iter().copied() {
+                child_found |= self.visit(tree, child, query, visited);
+            }
+            if child_found {
+                self.ancestors.insert(index);
+                found = true;
+            }
+        }
+
+        found
+    }
+}
+
+fn node_label(node: &FlatViewTreeNode) -> String {
+    match node {
+        FlatViewTreeNode::Leaf { view } => match view {
+            ViewNode::NotStarted => "not started".to_string(),
+            ViewNode::Started => "in progress".to_string(),
+            ViewNode::Complete(v) => v.to_string(),
+        },
+        FlatViewTreeNode::Branch { view, .. } => view.to_string(),
+    }
+}
+
+/// Styled spans for a node's label, flattened onto a single row.
+fn node_spans(node: &FlatViewTreeNode) -> Vec<Span<'static>> {
+    let view = match node {
+        FlatViewTreeNode::Leaf {
+            view: ViewNode::Complete(view),
+        }
+        | FlatViewTreeNode::Branch { view, .. } => view,
+        FlatViewTreeNode::Leaf { .. } => return vec![Span::raw(node_label(node))],
+    };
+    Text::from(view.clone())
+        .lines
+        .into_iter()
+        .flat_map(|line| line.spans)
+        .collect()
 }
 
 fn build_visible_rows(tree: &FlatViewTree, state: &TreeState) -> Vec<TreeRow> {
     let mut out = Vec::new();
     let mut visited = HashSet::new();
+    let filter = TreeFilter::new(tree, &state.filter);
 
     build_visible_rows_rec(
         tree,
         FlatViewTree::root_index(),
         0,
         state,
+        &filter,
         &mut out,
         &mut visited,
     );
@@ -876,6 +1373,7 @@ fn build_visible_rows_rec(
     index: usize,
     depth: usize,
     state: &TreeState,
+    filter: &TreeFilter,
     out: &mut Vec<TreeRow>,
     visited: &mut HashSet<usize>,
 ) {
@@ -888,37 +1386,39 @@ fn build_visible_rows_rec(
         Err(_) => return,
     };
 
-    match node {
-        FlatViewTreeNode::Leaf { view } => {
-            let label = match view {
-                ViewNode::NotStarted => "not started".to_string(),
-                ViewNode::Started => "in progress".to_string(),
-                ViewNode::Complete(v) => v.to_string(),
-            };
+    let label = node_label(node);
+    let spans = node_spans(node);
+    let is_match = filter.matches.contains(&index);
 
+    match node {
+        FlatViewTreeNode::Leaf { .. } => {
             out.push(TreeRow {
                 index,
                 depth,
                 is_branch: false,
                 is_expanded: false,
+                is_match,
                 label,
+                spans,
             });
         }
 
-        FlatViewTreeNode::Branch { view, children } => {
-            let is_expanded = state.is_expanded(index);
+        FlatViewTreeNode::Branch { children, .. } => {
+            let is_expanded = state.is_expanded(index) || filter.ancestors.contains(&index);
 
             out.push(TreeRow {
                 index,
                 depth,
                 is_branch: true,
                 is_expanded,
-                label: view.to_string(),
+                is_match,
+                label,
+                spans,
             });
 
             if is_expanded {
                 for child in children.iter().copied() {
-                    build_visible_rows_rec(tree, child, depth + 1, state, out, visited);
+                    build_visible_rows_rec(tree, child, depth + 1, state, filter, out, visited);
                 }
             }
         }
@@ -949,3 +1449,364 @@ fn tree_move_selection(tree: &FlatViewTree, state: &mut TreeState, delta: i32) {
 
     state.selected_node = Some(rows[next_row].index);
 }
+
+/// Collapse (or expand) every branch, then move the selection to its nearest
+/// visible ancestor and keep it in view.
+fn tree_set_all_collapsed(tree: &FlatViewTree, state: &mut TreeState, collapsed: bool) {
+    if collapsed {
+        state.collapse_all(tree);
+    } else {
+        state.expand_all();
+    }
+
+    let rows = build_visible_rows(tree, state);
+
+    let mut selected = state.selected_node;
+    while let Some(index) = selected {
+        if rows.iter().any(|row| row.index == index) {
+            break;
+        }
+        selected = tree_parent(tree, index);
+    }
+    state.selected_node = selected.or_else(|| rows.first().map(|row| row.index));
+
+    if let Some(selected_row) = selected_row_index(&rows, state) {
+        state.ensure_visible_row(selected_row, state.list_height);
+    }
+}
+
+fn tree_parent(tree: &FlatViewTree, index: usize) -> Option<usize> {
+    tree.nodes()
+        .enumerate()
+        .find_map(|(parent, node)| match node {
+            Some(FlatViewTreeNode::Branch { children, .. }) if children.contains(&index) => {
+                Some(parent)
+            }
+            _ => None,
+        })
+}
+
+fn tree_parents(tree: &FlatViewTree) -> HashMap<usize, usize> {
+    let mut parents = HashMap::new();
+    for (parent, node) in tree.nodes().enumerate() {
+        if let Some(FlatViewTreeNode::Branch { children, .. }) = node {
+            for child in children.iter().copied() {
+                parents.insert(child, parent);
+            }
+        }
+    }
+    parents
+}
+
+fn tree_path(
+    tree: &FlatViewTree,
+    parents: &HashMap<usize, usize>,
+    index: usize,
+) -> Option<TreePath> {
+    let mut path = TreePath::new();
+    let mut current = index;
+    let mut visited = HashSet::new();
+
+    while current != FlatViewTree::root_index() {
+        if !visited.insert(current) {
+            return None;
+        }
+        let parent = *parents.get(&current)?;
+        let FlatViewTreeNode::Branch { children, .. } = tree.get(parent).ok()? else {
+            return None;
+        };
+        let position = children.iter().position(|child| *child == current)?;
+        let label = node_label(tree.get(current).ok()?);
+        path.push((position, label));
+        current = parent;
+    }
+
+    path.reverse();
+    Some(path)
+}
+
+/// Find the node at `path`, by position first. Only if the label there
+/// differs is a sibling with the same label preferred, and failing that the
+/// position is kept anyway (labels of leaves change as they progress, e.g.
+/// from "not started" to a completed view).
+fn tree_resolve_path(tree: &FlatViewTree, path: &TreePath) -> Option<usize> {
+    let mut current = FlatViewTree::root_index();
+    tree.get(current).ok()?;
+
+    for (position, label) in path {
+        let FlatViewTreeNode::Branch { children, .. } = tree.get(current).ok()? else {
+            return None;
+        };
+        let has_label = |child: &usize| {
+            tree.get(*child)
+                .is_ok_and(|node| node_label(node) == *label)
+        };
+        let at_position = children.get(*position).copied();
+        current = at_position
+            .filter(has_label)
+            .or_else(|| children.iter().copied().find(has_label))
+            .or(at_position)?;
+        tree.get(current).ok()?;
+    }
+
+    Some(current)
+}
+
+/// Move selection to the next (or previous) visible row matching the filter,
+/// wrapping around. A direction of 0 selects the first match at or after the
+/// current row.
+fn tree_jump_to_match(tree: &FlatViewTree, state: &mut TreeState, direction: i32) {
+    let rows = build_visible_rows(tree, state);
+    if rows.is_empty() {
+        return;
+    }
+
+    let len = rows.len();
+    let current_row = selected_row_index(&rows, state).unwrap_or(0);
+
+    let found = (0..len)
+        .map(|step| match direction {
+            0 => (current_row + step) % len,
+            d if d > 0 => (current_row + 1 + step) % len,
+            _ => (current_row + len - 1 - step) % len,
+        })
+        .find(|row| rows[*row].is_match);
+
+    if let Some(row) = found {
+        state.selected_node = Some(rows[row].index);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use lusid_view::{View, ViewTree};
+
+    use super::*;
+
+    fn leaf(label: &str) -> ViewTree {
+        ViewTree::Leaf {
+            view: View::Span(label.into()),
+        }
+    }
+
+    fn branch(label: &str, children: Vec<ViewTree>) -> ViewTree {
+        ViewTree::Branch {
+            view: View::Span(label.into()),
+            children,
+        }
+    }
+
+    /// 0: root
+    /// ├── 1: apt
+    /// │   ├── 2: curl
+    /// │   └── 3: git
+    /// └── 4: file
+    fn sample_view_tree() -> ViewTree {
+        branch(
+            "root",
+            vec![branch("apt", vec![leaf("curl"), leaf("git")]), leaf("file")],
+        )
+    }
+
+    fn sample_tree() -> FlatViewTree {
+        FlatViewTree::from_view_tree_completed(sample_view_tree())
+    }
+
+    #[test]
+    fn test_tree_filter_matches_and_expands_ancestors() {
+        let tree = sample_tree();
+        let state = TreeState {
+            collapsed: HashSet::from([0, 1]),
+            filter: "GIT".to_string(),
+            ..TreeState::default()
+        };
+
+        let filter = TreeFilter::new(&tree, &state.filter);
+        assert_eq!(filter.matches, HashSet::from([3]));
+        assert_eq!(filter.ancestors, HashSet::from([0, 1]));
+
+        let rows = build_visible_rows(&tree, &state);
+        let indices: Vec<usize> = rows.iter().map(|row| row.index).collect();
+        assert_eq!(indices, vec![0, 1, 2, 3, 4]);
+        assert!(rows.iter().all(|row| !row.is_branch || row.is_expanded));
+        assert!(rows[3].is_match);
+    }
+
+    #[test]
+    fn test_tree_jump_to_match_wraps() {
+        let tree = sample_tree();
+        let mut state = TreeState {
+            filter: "i".to_string(),
+            selected_node: Some(3),
+            ..TreeState::default()
+        };
+
+        // "git" (3) and "file" (4) match.
+        tree_jump_to_match(&tree, &mut state, 1);
+        assert_eq!(state.selected_node, Some(4));
+        tree_jump_to_match(&tree, &mut state, 1);
+        assert_eq!(state.selected_node, Some(3));
+        tree_jump_to_match(&tree, &mut state, -1);
+        assert_eq!(state.selected_node, Some(4));
+    }
+
+    #[test]
+    fn test_tree_collapse_all_and_expand_all() {
+        let tree = sample_tree();
+        let mut state = TreeState {
+            selected_node: Some(3),
+            list_height: 10,
+            ..TreeState::default()
+        };
+
+        tree_set_all_collapsed(&tree, &mut state, true);
+        assert_eq!(state.collapsed, HashSet::from([0, 1]));
+        // "git" is hidden, so selection moves up to the root.
+        assert_eq!(state.selected_node, Some(0));
+
+        tree_set_all_collapsed(&tree, &mut state, false);
+        assert!(state.collapsed.is_empty());
+        assert_eq!(build_visible_rows(&tree, &state).len(), 5);
+    }
+
+    #[test]
+    fn test_selection_preserved_across_updates() -> Result<(), TuiError> {
+        let mut app = TuiApp::new();
+        app.apply_update(AppUpdate::ResourceParams {
+            resource_params: sample_view_tree(),
+        })?;
+        app.apply_update(AppUpdate::ResourcesStart)?;
+        app.resources_state.selected_node = Some(3);
+
+        // Grow "apt": its children are re-appended, so "git" moves index.
+        app.apply_update(AppUpdate::ResourcesNode {
+            index: 1,
+            tree: branch("apt", vec![leaf("curl"), leaf("git"), leaf("htop")]),
+        })?;
+
+        let resources = app.app_view.resources().expect("resources");
+        let selected = app.resources_state.selected_node.expect("selection");
+        assert_ne!(selected, 3);
+        assert_eq!(node_label(resources.get(selected)?), "git");
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_selection_keeps_position_among_same_labels() -> Result<(), TuiError> {
+        let mut app = TuiApp::new();
+        app.apply_update(AppUpdate::ResourceParams {
+            resource_params: sample_view_tree(),
+        })?;
+        app.apply_update(AppUpdate::ResourcesStart)?;
+        let twins = || branch("apt", vec![leaf("git"), leaf("git")]);
+        app.apply_update(AppUpdate::ResourcesNode {
+            index: 1,
+            tree: twins(),
+        })?;
+        let second = |app: &TuiApp| -> Result<usize, TuiError> {
+            let resources = app.app_view.resources().expect("resources");
+            let FlatViewTreeNode::Branch { children, .. } = resources.get(1)? else {
+                panic!("expected a branch");
+            };
+            Ok(children[1])
+        };
+        app.resources_state.selected_node = Some(second(&app)?);
+
+        app.apply_update(AppUpdate::ResourcesNode {
+            index: 1,
+            tree: twins(),
+        })?;
+
+        assert_eq!(app.resources_state.selected_node, Some(second(&app)?));
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_unexpected_update_is_logged() -> Result<(), TuiError> {
+        let mut app = TuiApp::new();
+        app.apply_update(AppUpdate::ResourceParams {
+            resource_params: sample_view_tree(),
+        })?;
+        app.apply_update(AppUpdate::ResourcesNode {
+            index: 1,
+            tree: leaf("apt"),
+        })?;
+
+        assert!(matches!(app.app_view, AppView::ResourceParams { .. }));
+        assert_eq!(app.logs.len(), 1);
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_strip_colors_keeps_modifiers() {
+        let mut buffer = Buffer::empty(Rect::new(0, 0, 2, 1));
+        buffer.set_string(
+            0,
+            0,
+            "ok",
+            Style::default()
+                .fg(Color::Green)
+                .bg(Color::Black)
+                .add_modifier(Modifier::REVERSED),
+        );
+
+        strip_colors(&mut buffer);
+
+        let cell = &buffer.content[0];
+        assert_eq!(cell.fg, Color::Reset);
+        assert_eq!(cell.bg, Color::Reset);
+        assert!(cell.modifier.contains(Modifier::REVERSED));
+    }
+
+    #[test]
+    fn test_circular_buffer_wraps_around() {
+        let mut buffer = CircularBuffer::new(3);
+        for item in 0..5 {
+            buffer.push(item);
+        }
+
+        assert_eq!(buffer.len(), 3);
+        assert_eq!(buffer.iter().copied().collect::<Vec<_>>(), vec![2, 3, 4]);
+
+        buffer.push(5);
+        assert_eq!(buffer.iter().copied().collect::<Vec<_>>(), vec![3, 4, 5]);
+    }
+
+    #[test]
+    fn test_wrapped_line_count() {
+        assert_eq!(wrapped_line_count("one\ntwo", 10), 2);
+        // A line longer than the pane wraps onto more rows.
+        assert_eq!(wrapped_line_count("abcdefghij\nxy", 4), 4);
+        // So a long line can scroll further than its line count suggests.
+        let lines = wrapped_line_count(&"x".repeat(100), 10);
+        assert_eq!(clamp_log_scroll(usize::MAX, lines, 5), 5);
+    }
+
+    #[test]
+    fn test_clamp_log_scroll() {
+        // 30 lines in a 10 line viewport can scroll to line 20 at most.
+        assert_eq!(clamp_log_scroll(0, 30, 10), 0);
+        assert_eq!(clamp_log_scroll(15, 30, 10), 15);
+        assert_eq!(clamp_log_scroll(25, 30, 10), 20);
+        assert_eq!(clamp_log_scroll(usize::MAX, 30, 10), 20);
+        // Content shorter than the viewport never scrolls.
+        assert_eq!(clamp_log_scroll(5, 4, 10), 0);
+
+        let mut state = OperationsApplyState {
+            log_lines: 30,
+            log_height: 10,
+            ..OperationsApplyState::default()
+        };
+        state.scroll_logs(LogScroll::Bottom);
+        assert_eq!(state.log_scroll, 20);
+        state.scroll_logs(LogScroll::Down(10));
+        assert_eq!(state.log_scroll, 20);
+        state.scroll_logs(LogScroll::Up(10));
+        assert_eq!(state.log_scroll, 10);
+        state.scroll_logs(LogScroll::Top);
+        assert_eq!(state.log_scroll, 0);
+    }
+}
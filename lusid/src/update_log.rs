@@ -0,0 +1,127 @@
+use std::{io, net::Ipv4Addr, time::Duration};
+
+use lusid_ssh::{Ssh, SshConnectOptions, SshError};
+use thiserror::Error;
+use tokio::io::{AsyncBufReadExt, AsyncWrite, AsyncWriteExt, BufReader};
+use tracing::warn;
+
+/// How many times to reconnect after losing the connection mid-follow.
+const MAX_RECONNECTS: u32 = 5;
+
+/// How long to wait before reconnecting.
+const RECONNECT_DELAY: Duration = Duration::from_secs(2);
+
+/// Where a dev apply appends its updates on the remote, one JSON line each,
+/// as well as streaming them on stdout.
+pub(crate) fn remote_update_log_path(home_dir: &str) -> String {
+    format!("{home_dir}/.cache/lusid/apply.log")
+}
+
+#[derive(Error, Debug)]
+pub enum UpdateLogError {
+    #[error(transparent)]
+    Ssh(#[from] SshError),
+
+    #[error("failed to read update log: {0}")]
+    Read(#[source] io::Error),
+
+    #[error("failed to forward update log: {0}")]
+    Forward(#[source] io::Error),
+}
+
+/// How far into the update log we've read, so a reconnect picks up after
+/// the last whole line rather than from the start.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub(crate) struct UpdateLogCursor {
+    lines: u64,
+}
+
+impl UpdateLogCursor {
+    pub fn record_line(&mut self) {
+        self.lines += 1;
+    }
+
+    /// Shell command printing the log from the next unread line, following
+    /// it for as long as an apply is still running.
+    pub fn tail_command(&self, path: &str) -> String {
+        let from = self.lines + 1;
+        format!(
+            "pid=$(pgrep -x lusid-apply | head -n 1); \
+             if [ -n \"$pid\" ]; then tail -n +{from} -f --pid=\"$pid\" {path}; \
+             else tail -n +{from} {path}; fi"
+        )
+    }
+}
+
+/// Copy the remote update log to `output`, reconnecting if the connection drops.
+pub(crate) async fn follow_update_log<Output>(
+    options: SshConnectOptions<(Ipv4Addr, u16)>,
+    path: String,
+    mut output: Output,
+) -> Result<(), UpdateLogError>
+where
+    Output: AsyncWrite + Unpin,
+{
+    let mut cursor = UpdateLogCursor::default();
+    let mut reconnects = 0;
+    loop {
+        match follow_once(options.clone(), &path, &mut cursor, &mut output).await {
+            Ok(()) => return Ok(()),
+            Err(error @ UpdateLogError::Forward(_)) => return Err(error),
+            Err(error) if reconnects < MAX_RECONNECTS => {
+                reconnects += 1;
+                warn!("lost update log, reconnecting: {error}");
+                tokio::time::sleep(RECONNECT_DELAY).await;
+            }
+            Err(error) => return Err(error),
+        }
+    }
+}
+
+async fn follow_once<Output>(
+    options: SshConnectOptions<(Ipv4Addr, u16)>,
+    path: &str,
+    cursor: &mut UpdateLogCursor,
+    output: &mut Output,
+) -> Result<(), UpdateLogError>
+where
+    Output: AsyncWrite + Unpin,
+{
+    let mut ssh = Ssh::connect(options).await?;
+    let mut handle = ssh.command(&cursor.tail_command(path)).await?;
+
+    // Only whole lines count, so a line cut off by a disconnect is read again.
+    let mut lines = BufReader::new(&mut handle.stdout).lines();
+    while let Some(line) = lines.next_line().await.map_err(UpdateLogError::Read)? {
+        output
+            .write_all(format!("{line}\n").as_bytes())
+            .await
+            .map_err(UpdateLogError::Forward)?;
+        cursor.record_line();
+    }
+    handle.channel.wait().await?;
+
+    ssh.disconnect().await?;
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn resumes_after_last_whole_line() {
+        let path = remote_update_log_path("/home/debian");
+        let mut cursor = UpdateLogCursor::default();
+        assert!(cursor
+            .tail_command(&path)
+            .contains("tail -n +1 -f --pid=\"$pid\" /home/debian/.cache/lusid/apply.log"));
+
+        for _ in 0..3 {
+            cursor.record_line();
+        }
+        let command = cursor.tail_command(&path);
+        assert!(command.contains("tail -n +4 -f --pid="));
+        assert!(command.contains("else tail -n +4 /home/debian/.cache/lusid/apply.log"));
+    }
+}
@@ -1,3 +1,8 @@
+mod port;
+
+pub use crate::port::*;
+
+use lusid_cmd::Privilege;
 use lusid_system::{Arch, CpuCount, Hostname, MemorySize, Os};
 use serde::{Deserialize, Serialize};
 
@@ -7,6 +12,9 @@ pub struct Machine {
     pub arch: Arch,
     pub os: Os,
     pub vm: Option<MachineVmOptions>,
+    /// How to escalate for operations that need root. Default: sudo.
+    #[serde(default)]
+    pub privilege: Privilege,
 }
 
 #[derive(Debug, Clone, Default, PartialEq, Eq, PartialOrd, Ord, Hash, Serialize, Deserialize)]
@@ -14,4 +22,10 @@ pub struct MachineVmOptions {
     pub memory_size: Option<MemorySize>,
     pub cpu_count: Option<CpuCount>,
     pub graphics: Option<bool>,
+    #[serde(default)]
+    pub ports: Vec<VmPort>,
+    /// Extra cloud-init `user-data` (YAML), merged into the generated config at first boot.
+    pub extra_user_data: Option<String>,
+    /// QEMU machine type, such as "q35" or "virt". Default: per architecture.
+    pub machine_type: Option<String>,
 }
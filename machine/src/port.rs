@@ -0,0 +1,188 @@
+use std::{
+    fmt::Display,
+    net::{AddrParseError, Ipv4Addr},
+    num::ParseIntError,
+    str::FromStr,
+};
+
+use serde::{Deserialize, Serialize};
+use thiserror::Error;
+
+/// A TCP port forwarded from the host to a virtual machine.
+#[derive(Debug, Clone, PartialEq, Eq, PartialOrd, Ord, Hash, Serialize)]
+pub struct VmPort {
+    pub host_ip: Option<Ipv4Addr>,
+    pub host_port: Option<u16>,
+    pub vm_port: u16,
+}
+
+impl Display for VmPort {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let mut wrote_left = false;
+        if let Some(ip) = self.host_ip {
+            write!(f, "{}", ip)?;
+            wrote_left = true;
+        }
+        if let Some(port) = self.host_port {
+            if wrote_left {
+                write!(f, ":")?;
+            }
+            write!(f, "{}", port)?;
+            wrote_left = true;
+        }
+        if wrote_left {
+            write!(f, "->")?;
+        }
+        write!(f, "{}/tcp", self.vm_port)
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Error)]
+pub enum ParseVmPortError {
+    #[error("invalid port \"{value}\": {source}")]
+    Port {
+        value: String,
+        #[source]
+        source: ParseIntError,
+    },
+
+    #[error("invalid host ip \"{value}\": {source}")]
+    HostIp {
+        value: String,
+        #[source]
+        source: AddrParseError,
+    },
+
+    #[error("unsupported protocol \"{0}\": only tcp is supported")]
+    Protocol(String),
+
+    #[error("invalid port mapping \"{0}\": expected [host_ip:][host_port:]vm_port")]
+    Format(String),
+}
+
+/// Parses either `[host_ip:][host_port:]vm_port` (as in `docker -p`) or the
+/// `[host_ip:][host_port]->vm_port[/tcp]` form produced by `Display`.
+impl FromStr for VmPort {
+    type Err = ParseVmPortError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let s = s.trim();
+        let s = match s.split_once('/') {
+            Some((rest, "tcp")) => rest,
+            Some((_, protocol)) => return Err(ParseVmPortError::Protocol(protocol.to_string())),
+            None => s,
+        };
+
+        let (host, vm_port) = match s.split_once("->") {
+            Some((host, vm_port)) => (host.split(':').collect::<Vec<_>>(), vm_port),
+            None => {
+                let mut parts = s.split(':').collect::<Vec<_>>();
+                let vm_port = parts.pop().unwrap_or_default();
+                (parts, vm_port)
+            }
+        };
+
+        let (host_ip, host_port) = match host.as_slice() {
+            [] | [""] => (None, None),
+            [host_port] => (None, Some(*host_port)),
+            [host_ip, host_port] => (Some(*host_ip), Some(*host_port)),
+            _ => return Err(ParseVmPortError::Format(s.to_string())),
+        };
+
+        Ok(VmPort {
+            host_ip: host_ip.map(parse_host_ip).transpose()?,
+            host_port: host_port.map(parse_port).transpose()?,
+            vm_port: parse_port(vm_port)?,
+        })
+    }
+}
+
+fn parse_port(value: &str) -> Result<u16, ParseVmPortError> {
+    value.parse().map_err(|source| ParseVmPortError::Port {
+        value: value.to_string(),
+        source,
+    })
+}
+
+fn parse_host_ip(value: &str) -> Result<Ipv4Addr, ParseVmPortError> {
+    value.parse().map_err(|source| ParseVmPortError::HostIp {
+        value: value.to_string(),
+        source,
+    })
+}
+
+impl<'de> Deserialize<'de> for VmPort {
+    fn deserialize<D>(deserializer: D) -> Result<Self, D::Error>
+    where
+        D: serde::Deserializer<'de>,
+    {
+        #[derive(Deserialize)]
+        struct VmPortFields {
+            host_ip: Option<Ipv4Addr>,
+            host_port: Option<u16>,
+            vm_port: u16,
+        }
+
+        #[derive(Deserialize)]
+        #[serde(untagged)]
+        enum VmPortRepr {
+            String(String),
+            Fields(VmPortFields),
+        }
+
+        match VmPortRepr::deserialize(deserializer)? {
+            VmPortRepr::String(value) => value.parse().map_err(serde::de::Error::custom),
+            VmPortRepr::Fields(VmPortFields {
+                host_ip,
+                host_port,
+                vm_port,
+            }) => Ok(VmPort {
+                host_ip,
+                host_port,
+                vm_port,
+            }),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parse_host_ip_and_ports() {
+        let port: VmPort = "127.0.0.1:8080:80".parse().unwrap();
+        assert_eq!(
+            port,
+            VmPort {
+                host_ip: Some(Ipv4Addr::LOCALHOST),
+                host_port: Some(8080),
+                vm_port: 80,
+            }
+        );
+        assert_eq!(port.to_string(), "127.0.0.1:8080->80/tcp");
+        assert_eq!(port.to_string().parse::<VmPort>().unwrap(), port);
+    }
+
+    #[test]
+    fn parse_host_port_and_vm_port() {
+        let port: VmPort = "8080:80".parse().unwrap();
+        assert_eq!(
+            port,
+            VmPort {
+                host_ip: None,
+                host_port: Some(8080),
+                vm_port: 80,
+            }
+        );
+        assert_eq!(port.to_string(), "8080->80/tcp");
+        assert_eq!(port.to_string().parse::<VmPort>().unwrap(), port);
+    }
+
+    #[test]
+    fn parse_invalid() {
+        assert!("http:80".parse::<VmPort>().is_err());
+        assert!("80/udp".parse::<VmPort>().is_err());
+        assert!("1.2.3.4:1:2:3".parse::<VmPort>().is_err());
+    }
+}
@@ -12,7 +12,20 @@ use tokio::io::AsyncRead;
 
 pub mod operations;
 
+pub use lusid_cmd::Privilege;
+
 use crate::operations::apt::{Apt, AptOperation};
+use crate::operations::command::{Command, CommandOperation};
+use crate::operations::file::{File, FileOperation};
+
+/// What operations need to know about the machine they are applied on.
+#[derive(Debug, Clone, Default)]
+pub struct ApplyContext {
+    /// How operations that need root escalate.
+    pub privilege: Privilege,
+    /// Variables to carry over when escalating, such as proxy settings.
+    pub preserve_env: Vec<String>,
+}
 
 /// OperationType specifies how to merge and apply a concrete Operation type.
 ///
@@ -24,7 +37,11 @@ pub trait OperationType {
 
     /// Merge a set of operations of this type within the same epoch.
     /// Implementations should coalesce operations to a minimal set.
-    fn merge(operations: Vec<Self::Operation>) -> Vec<Self::Operation>;
+    ///
+    /// Returns groups to apply one after another: operations within a group
+    /// may be applied at once, so any that must run in order go in separate
+    /// groups.
+    fn merge(operations: Vec<Self::Operation>) -> Vec<Vec<Self::Operation>>;
 
     type ApplyError;
     type ApplyStdout: AsyncRead;
@@ -34,24 +51,21 @@ pub trait OperationType {
     /// Apply an operation of this type.
     async fn apply(
         operation: &Self::Operation,
+        ctx: &ApplyContext,
     ) -> Result<(Self::ApplyOutput, Self::ApplyStdout, Self::ApplyStderr), Self::ApplyError>;
 }
 
 #[derive(Debug, Clone)]
 pub enum Operation {
     Apt(AptOperation),
+    Command(CommandOperation),
+    File(FileOperation),
 }
 
 impl Operation {
-    /// Merge a set of operations by type.
-    pub fn merge(operations: Vec<Operation>) -> Vec<Operation> {
-        let OperationsByType { apt } = partition_by_type(operations);
-
-        let mut result = Vec::new();
-
-        result.extend(Apt::merge(apt).into_iter().map(Operation::Apt));
-
-        result
+    /// Merge a set of operations by type, into groups to apply in order.
+    pub fn merge(operations: Vec<Operation>) -> Vec<Vec<Operation>> {
+        OperationsByType::partition(operations).merge()
     }
 }
 
@@ -59,11 +73,19 @@ impl Operation {
 pub enum OperationApplyError {
     #[error("apt operation failed: {0:?}")]
     Apt(<Apt as OperationType>::ApplyError),
+
+    #[error("command operation failed: {0:?}")]
+    Command(<Command as OperationType>::ApplyError),
+
+    #[error("file operation failed: {0:?}")]
+    File(<File as OperationType>::ApplyError),
 }
 
 #[pin_project(project = OperationApplyOutputProject)]
 pub enum OperationApplyOutput {
     Apt(#[pin] <Apt as OperationType>::ApplyOutput),
+    Command(#[pin] <Command as OperationType>::ApplyOutput),
+    File(#[pin] <File as OperationType>::ApplyOutput),
 }
 
 impl Future for OperationApplyOutput {
@@ -73,6 +95,8 @@ impl Future for OperationApplyOutput {
         use OperationApplyOutputProject::*;
         match self.project() {
             Apt(fut) => fut.poll(cx).map_err(OperationApplyError::Apt),
+            Command(fut) => fut.poll(cx).map_err(OperationApplyError::Command),
+            File(fut) => fut.poll(cx).map_err(OperationApplyError::File),
         }
     }
 }
@@ -81,6 +105,8 @@ impl Future for OperationApplyOutput {
 #[pin_project(project = OperationApplyStdoutProject)]
 pub enum OperationApplyStdout {
     Apt(#[pin] <Apt as OperationType>::ApplyStdout),
+    Command(#[pin] <Command as OperationType>::ApplyStdout),
+    File(#[pin] <File as OperationType>::ApplyStdout),
 }
 
 impl AsyncRead for OperationApplyStdout {
@@ -92,6 +118,8 @@ impl AsyncRead for OperationApplyStdout {
         use OperationApplyStdoutProject::*;
         match self.project() {
             Apt(stream) => stream.poll_read(cx, buf),
+            Command(stream) => stream.poll_read(cx, buf),
+            File(stream) => stream.poll_read(cx, buf),
         }
     }
 }
@@ -100,6 +128,8 @@ impl AsyncRead for OperationApplyStdout {
 #[pin_project(project = OperationApplyStderrProject)]
 pub enum OperationApplyStderr {
     Apt(#[pin] <Apt as OperationType>::ApplyStderr),
+    Command(#[pin] <Command as OperationType>::ApplyStderr),
+    File(#[pin] <File as OperationType>::ApplyStderr),
 }
 
 impl AsyncRead for OperationApplyStderr {
@@ -111,6 +141,8 @@ impl AsyncRead for OperationApplyStderr {
         use OperationApplyStderrProject::*;
         match self.project() {
             Apt(stream) => stream.poll_read(cx, buf),
+            Command(stream) => stream.poll_read(cx, buf),
+            File(stream) => stream.poll_read(cx, buf),
         }
     }
 }
@@ -119,6 +151,7 @@ impl Operation {
     /// Apply a set of operations by type
     pub async fn apply(
         &self,
+        ctx: &ApplyContext,
     ) -> Result<
         (
             OperationApplyOutput,
@@ -129,14 +162,35 @@ impl Operation {
     > {
         match self {
             Operation::Apt(op) => {
-                let (output, stdout, stderr) =
-                    Apt::apply(op).await.map_err(OperationApplyError::Apt)?;
+                let (output, stdout, stderr) = Apt::apply(op, ctx)
+                    .await
+                    .map_err(OperationApplyError::Apt)?;
                 Ok((
                     OperationApplyOutput::Apt(output),
                     OperationApplyStdout::Apt(stdout),
                     OperationApplyStderr::Apt(stderr),
                 ))
             }
+            Operation::Command(op) => {
+                let (output, stdout, stderr) = Command::apply(op, ctx)
+                    .await
+                    .map_err(OperationApplyError::Command)?;
+                Ok((
+                    OperationApplyOutput::Command(output),
+                    OperationApplyStdout::Command(stdout),
+                    OperationApplyStderr::Command(stderr),
+                ))
+            }
+            Operation::File(op) => {
+                let (output, stdout, stderr) = File::apply(op, ctx)
+                    .await
+                    .map_err(OperationApplyError::File)?;
+                Ok((
+                    OperationApplyOutput::File(output),
+                    OperationApplyStdout::File(stdout),
+                    OperationApplyStderr::File(stderr),
+                ))
+            }
         }
     }
 }
@@ -146,22 +200,133 @@ impl Display for Operation {
         use Operation::*;
         match self {
             Apt(apt) => Display::fmt(apt, f),
+            Command(command) => Display::fmt(command, f),
+            File(file) => Display::fmt(file, f),
         }
     }
 }
 
-#[derive(Debug, Clone)]
+/// Operations split into one list per operation type, each in its original order.
+#[derive(Debug, Clone, Default)]
 pub struct OperationsByType {
-    apt: Vec<AptOperation>,
+    pub apt: Vec<AptOperation>,
+    pub command: Vec<CommandOperation>,
+    pub file: Vec<FileOperation>,
 }
 
-/// Merge a set of operations by type.
-fn partition_by_type(operations: Vec<Operation>) -> OperationsByType {
-    let mut apt: Vec<AptOperation> = Vec::new();
-    for operation in operations {
+impl OperationsByType {
+    pub fn partition(operations: Vec<Operation>) -> Self {
+        let mut by_type = Self::default();
+        for operation in operations {
+            by_type.push(operation);
+        }
+        by_type
+    }
+
+    pub fn push(&mut self, operation: Operation) {
         match operation {
-            Operation::Apt(op) => apt.push(op),
+            Operation::Apt(op) => self.apt.push(op),
+            Operation::Command(op) => self.command.push(op),
+            Operation::File(op) => self.file.push(op),
         }
     }
-    OperationsByType { apt }
+
+    /// Merge each type's operations with that type's [`OperationType::merge`].
+    ///
+    /// Types don't depend on each other, so the first group of every type is
+    /// applied together, then the second, and so on.
+    pub fn merge(self) -> Vec<Vec<Operation>> {
+        let Self { apt, command, file } = self;
+        let mut groups = Vec::new();
+        zip_groups(&mut groups, Apt::merge(apt), Operation::Apt);
+        zip_groups(&mut groups, Command::merge(command), Operation::Command);
+        zip_groups(&mut groups, File::merge(file), Operation::File);
+        groups
+    }
+}
+
+/// Add each of one type's groups to the group at the same position.
+fn zip_groups<T>(
+    groups: &mut Vec<Vec<Operation>>,
+    type_groups: Vec<Vec<T>>,
+    into_operation: fn(T) -> Operation,
+) {
+    for (index, type_group) in type_groups.into_iter().enumerate() {
+        if index == groups.len() {
+            groups.push(Vec::new());
+        }
+        groups[index].extend(type_group.into_iter().map(into_operation));
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::path::PathBuf;
+
+    use super::*;
+
+    fn install(package: &str) -> Operation {
+        Operation::Apt(AptOperation::Install {
+            packages: vec![package.to_string()],
+        })
+    }
+
+    fn run(command: &str) -> Operation {
+        Operation::Command(CommandOperation::Run {
+            command: command.to_string(),
+        })
+    }
+
+    fn create_dir(path: &str) -> Operation {
+        Operation::File(FileOperation::CreateDirectory {
+            path: PathBuf::from(path),
+        })
+    }
+
+    #[test]
+    fn partitions_every_type() {
+        let by_type = OperationsByType::partition(vec![
+            install("git"),
+            create_dir("/etc/a"),
+            run("true"),
+            install("curl"),
+            create_dir("/etc/b"),
+        ]);
+        assert_eq!(by_type.apt.len(), 2);
+        assert_eq!(by_type.command.len(), 1);
+        assert_eq!(by_type.file.len(), 2);
+    }
+
+    #[test]
+    fn merges_each_type() {
+        let merged = Operation::merge(vec![
+            create_dir("/etc/a"),
+            install("git"),
+            run("echo one"),
+            Operation::Apt(AptOperation::UpdateCache),
+            run("echo two"),
+            install("curl"),
+            create_dir("/etc/b"),
+        ]);
+        let merged: Vec<Vec<String>> = merged
+            .iter()
+            .map(|group| group.iter().map(ToString::to_string).collect())
+            .collect();
+        let expected: Vec<Vec<String>> = [
+            vec![
+                Operation::Apt(AptOperation::UpdateCache),
+                run("echo one"),
+                run("echo two"),
+                create_dir("/etc/a"),
+                create_dir("/etc/b"),
+            ],
+            vec![Operation::Apt(AptOperation::Install {
+                packages: vec!["curl".to_string(), "git".to_string()],
+            })],
+        ]
+        .iter()
+        .map(|group| group.iter().map(ToString::to_string).collect())
+        .collect();
+        assert_eq!(merged, expected);
+    }
 }
@@ -1,22 +1,25 @@
 use async_trait::async_trait;
-use lusid_cmd::{Command, CommandError};
+use lusid_cmd::{CapturedStderr, Command, CommandError};
 use std::{collections::BTreeSet, fmt::Display, pin::Pin};
 use thiserror::Error;
-use tokio::process::{ChildStderr, ChildStdout};
+use tokio::process::ChildStdout;
 use tracing::info;
 
-use crate::OperationType;
+use crate::{ApplyContext, OperationType};
 
 #[derive(Debug, Clone)]
 pub enum AptOperation {
-    Update,
-    Install { packages: Vec<String> },
+    /// Refresh the package index, so installs see current versions.
+    UpdateCache,
+    Install {
+        packages: Vec<String>,
+    },
 }
 
 impl Display for AptOperation {
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
         match self {
-            AptOperation::Update => write!(f, "Apt::Update"),
+            AptOperation::UpdateCache => write!(f, "Apt::UpdateCache"),
             AptOperation::Install { packages } => {
                 write!(f, "Apt::Install(packages = [{}])", packages.join(", "))
             }
@@ -37,14 +40,14 @@ pub struct Apt;
 impl OperationType for Apt {
     type Operation = AptOperation;
 
-    fn merge(operations: Vec<Self::Operation>) -> Vec<Self::Operation> {
-        let mut update = false;
+    fn merge(operations: Vec<Self::Operation>) -> Vec<Vec<Self::Operation>> {
+        let mut update_cache = false;
         let mut install: BTreeSet<String> = BTreeSet::new();
 
         for operation in operations {
             match operation {
-                AptOperation::Update => {
-                    update = true;
+                AptOperation::UpdateCache => {
+                    update_cache = true;
                 }
                 AptOperation::Install { packages } => {
                     for package in packages {
@@ -54,58 +57,163 @@ impl OperationType for Apt {
             }
         }
 
-        let mut operations = Vec::new();
-        if update {
-            operations.push(AptOperation::Update);
+        // Update the cache first, so installs see the latest packages.
+        let mut groups = Vec::new();
+        if update_cache {
+            groups.push(vec![AptOperation::UpdateCache]);
         }
         if !install.is_empty() {
-            operations.push(AptOperation::Install {
+            groups.push(vec![AptOperation::Install {
                 packages: install.into_iter().collect(),
-            })
+            }])
         }
-        operations
+        groups
     }
 
     type ApplyOutput = Pin<Box<dyn Future<Output = Result<(), Self::ApplyError>> + Send + 'static>>;
     type ApplyError = AptApplyError;
     type ApplyStdout = ChildStdout;
-    type ApplyStderr = ChildStderr;
+    type ApplyStderr = CapturedStderr;
 
     async fn apply(
         operation: &Self::Operation,
+        ctx: &ApplyContext,
     ) -> Result<(Self::ApplyOutput, Self::ApplyStdout, Self::ApplyStderr), Self::ApplyError> {
         match operation {
-            AptOperation::Update => {
-                info!("[apt] update");
-                let mut cmd = Command::new("apt-get");
-                cmd.env("DEBIAN_FRONTEND", "noninteractive").arg("update");
-                let output = cmd.sudo().output().await?;
-                Ok((
-                    Box::pin(async move {
-                        output.status.await?;
-                        Ok(())
-                    }),
-                    output.stdout,
-                    output.stderr,
-                ))
-            }
+            AptOperation::UpdateCache => info!("[apt] update cache"),
             AptOperation::Install { packages } => {
-                info!("[apt] install: {}", packages.join(", "));
-                let mut cmd = Command::new("apt-get");
-                cmd.env("DEBIAN_FRONTEND", "noninteractive")
-                    .arg("install")
-                    .arg("-y")
-                    .args(packages);
-                let output = cmd.sudo().output().await?;
-                Ok((
-                    Box::pin(async move {
-                        output.status.await?;
-                        Ok(())
-                    }),
-                    output.stdout,
-                    output.stderr,
-                ))
+                info!("[apt] install: {}", packages.join(", "))
             }
         }
+        apply_command(apt_command(operation, ctx)).await
+    }
+}
+
+/// Run `cmd`, failing the operation if it exits unsuccessfully.
+async fn apply_command(
+    mut cmd: Command,
+) -> Result<
+    (
+        <Apt as OperationType>::ApplyOutput,
+        ChildStdout,
+        CapturedStderr,
+    ),
+    AptApplyError,
+> {
+    let output = cmd.output_checked().await?;
+    Ok((
+        Box::pin(async move {
+            output.status.await?;
+            Ok(())
+        }),
+        output.stdout,
+        output.stderr,
+    ))
+}
+
+/// The apt-get command for an operation, escalated as `ctx` says.
+fn apt_command(operation: &AptOperation, ctx: &ApplyContext) -> Command {
+    let mut cmd = Command::new("apt-get");
+    cmd.env("DEBIAN_FRONTEND", "noninteractive");
+    match operation {
+        AptOperation::UpdateCache => {
+            cmd.arg("update");
+        }
+        AptOperation::Install { packages } => {
+            cmd.arg("install").arg("-y").args(packages);
+        }
+    }
+    cmd.privileged_with_env(ctx.privilege, &ctx.preserve_env)
+}
+
+#[cfg(test)]
+mod tests {
+    use tokio::io::AsyncReadExt;
+
+    use super::*;
+    use crate::Privilege;
+
+    fn install(package: &str) -> AptOperation {
+        AptOperation::Install {
+            packages: vec![package.to_string()],
+        }
+    }
+
+    #[test]
+    fn merge_updates_cache_once_before_installing() {
+        let merged = Apt::merge(vec![
+            AptOperation::UpdateCache,
+            install("git"),
+            AptOperation::UpdateCache,
+            install("curl"),
+            AptOperation::UpdateCache,
+            install("git"),
+        ]);
+        assert!(matches!(
+            merged.as_slice(),
+            [update, install]
+                if matches!(update.as_slice(), [AptOperation::UpdateCache])
+                    && matches!(
+                        install.as_slice(),
+                        [AptOperation::Install { packages }] if packages == &["curl", "git"]
+                    )
+        ));
+    }
+
+    fn sh(script: &str) -> Command {
+        let mut cmd = Command::new("sh");
+        cmd.args(["-c", script]);
+        cmd
+    }
+
+    /// Apply `cmd`, streaming its stderr, as lusid-apply does.
+    async fn apply(cmd: Command) -> (Result<(), AptApplyError>, String) {
+        let (output, _stdout, mut stderr) = apply_command(cmd).await.unwrap();
+        let mut streamed = String::new();
+        let (result, read) = tokio::join!(output, stderr.read_to_string(&mut streamed));
+        read.unwrap();
+        (result, streamed)
+    }
+
+    #[tokio::test]
+    async fn fails_when_apt_get_fails() {
+        let (result, streamed) =
+            apply(sh("echo 'E: Unable to locate package nope' >&2; exit 100")).await;
+        assert_eq!(streamed, "E: Unable to locate package nope\n");
+        assert!(matches!(
+            result,
+            Err(AptApplyError::Command(CommandError::Failure { ref stderr, .. }))
+                if stderr == "E: Unable to locate package nope\n"
+        ));
+
+        let (result, _) = apply(sh("true")).await;
+        assert!(result.is_ok());
+    }
+
+    #[tokio::test]
+    async fn reports_sudo_password_prompt() {
+        let (result, _) = apply(sh("echo 'sudo: a password is required' >&2; exit 1")).await;
+        assert!(matches!(
+            result,
+            Err(AptApplyError::Command(
+                CommandError::SudoPasswordRequired { .. }
+            ))
+        ));
+    }
+
+    #[test]
+    fn escalates_with_preserved_env() {
+        let ctx = ApplyContext {
+            privilege: Privilege::Sudo,
+            preserve_env: vec!["PATH".to_string()],
+        };
+        assert_eq!(
+            apt_command(&install("git"), &ctx).to_string(),
+            "sudo -n --preserve-env=DEBIAN_FRONTEND,PATH apt-get install -y git"
+        );
+        assert_eq!(
+            apt_command(&AptOperation::UpdateCache, &ApplyContext::default()).to_string(),
+            "sudo -n --preserve-env=DEBIAN_FRONTEND apt-get update"
+        );
     }
 }
@@ -0,0 +1,80 @@
+use async_trait::async_trait;
+use lusid_cmd::{self as cmd, CommandError};
+use std::{fmt::Display, pin::Pin, process::ExitStatus};
+use thiserror::Error;
+use tokio::process::{ChildStderr, ChildStdout};
+use tracing::info;
+
+use crate::{ApplyContext, OperationType};
+
+#[derive(Debug, Clone)]
+pub enum CommandOperation {
+    /// Run a shell command line with `sh -c`.
+    Run { command: String },
+}
+
+impl Display for CommandOperation {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            CommandOperation::Run { command } => write!(f, "Command::Run({command})"),
+        }
+    }
+}
+
+#[derive(Error, Debug)]
+pub enum CommandApplyError {
+    #[error(transparent)]
+    Command(#[from] CommandError),
+
+    #[error("command exited with {status}: {command}")]
+    Failed { command: String, status: ExitStatus },
+}
+
+#[derive(Debug, Clone)]
+pub struct Command;
+
+#[async_trait]
+impl OperationType for Command {
+    type Operation = CommandOperation;
+
+    fn merge(operations: Vec<Self::Operation>) -> Vec<Vec<Self::Operation>> {
+        // Commands are opaque, so each one runs as given.
+        if operations.is_empty() {
+            Vec::new()
+        } else {
+            vec![operations]
+        }
+    }
+
+    type ApplyOutput = Pin<Box<dyn Future<Output = Result<(), Self::ApplyError>> + Send + 'static>>;
+    type ApplyError = CommandApplyError;
+    type ApplyStdout = ChildStdout;
+    type ApplyStderr = ChildStderr;
+
+    async fn apply(
+        operation: &Self::Operation,
+        _ctx: &ApplyContext,
+    ) -> Result<(Self::ApplyOutput, Self::ApplyStdout, Self::ApplyStderr), Self::ApplyError> {
+        match operation {
+            CommandOperation::Run { command } => {
+                info!("[command] run: {command}");
+                let output = cmd::Command::new("sh")
+                    .args(["-c", command])
+                    .output()
+                    .await?;
+                let command = command.clone();
+                Ok((
+                    Box::pin(async move {
+                        let status = output.status.await?;
+                        if !status.success() {
+                            return Err(CommandApplyError::Failed { command, status });
+                        }
+                        Ok(())
+                    }),
+                    output.stdout,
+                    output.stderr,
+                ))
+            }
+        }
+    }
+}
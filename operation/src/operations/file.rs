@@ -0,0 +1,159 @@
+use async_trait::async_trait;
+use lusid_fs::{self as fs, FsError};
+use std::{fmt::Display, path::PathBuf, pin::Pin};
+use thiserror::Error;
+use tokio::io::{empty, Empty};
+use tracing::info;
+
+use crate::{ApplyContext, OperationType};
+
+#[derive(Debug, Clone)]
+pub enum FileOperation {
+    CreateDirectory { path: PathBuf },
+    WriteFile { path: PathBuf, content: Vec<u8> },
+    ChangeMode { path: PathBuf, mode: u32 },
+}
+
+impl Display for FileOperation {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            FileOperation::CreateDirectory { path } => {
+                write!(f, "File::CreateDirectory({})", path.display())
+            }
+            FileOperation::WriteFile { path, content } => {
+                write!(
+                    f,
+                    "File::WriteFile({}, {} bytes)",
+                    path.display(),
+                    content.len()
+                )
+            }
+            FileOperation::ChangeMode { path, mode } => {
+                write!(f, "File::ChangeMode({}, {mode:o})", path.display())
+            }
+        }
+    }
+}
+
+impl FileOperation {
+    /// Sort key for [`File::merge`]: phase, then path depth for directories.
+    fn merge_order(&self) -> (u8, usize) {
+        match self {
+            FileOperation::CreateDirectory { path } => (0, path.components().count()),
+            FileOperation::WriteFile { .. } => (1, 0),
+            FileOperation::ChangeMode { .. } => (2, 0),
+        }
+    }
+}
+
+#[derive(Error, Debug)]
+pub enum FileApplyError {
+    #[error(transparent)]
+    Fs(#[from] FsError),
+}
+
+#[derive(Debug, Clone)]
+pub struct File;
+
+#[async_trait]
+impl OperationType for File {
+    type Operation = FileOperation;
+
+    /// Each file operation targets its own path, so there is nothing to
+    /// coalesce, but operations are grouped so each one's prerequisites are
+    /// applied in an earlier group:
+    ///
+    /// 1. `CreateDirectory`, one group per depth, parents before children.
+    /// 2. `WriteFile`, so every directory a file is written into exists.
+    /// 3. `ChangeMode`, so a mode applies to the file as written.
+    ///
+    /// Within a group, operations keep their original order.
+    fn merge(mut operations: Vec<Self::Operation>) -> Vec<Vec<Self::Operation>> {
+        operations.sort_by_key(FileOperation::merge_order);
+        let mut groups: Vec<Vec<Self::Operation>> = Vec::new();
+        for operation in operations {
+            match groups.last_mut() {
+                Some(group) if group[0].merge_order() == operation.merge_order() => {
+                    group.push(operation)
+                }
+                _ => groups.push(vec![operation]),
+            }
+        }
+        groups
+    }
+
+    type ApplyOutput = Pin<Box<dyn Future<Output = Result<(), Self::ApplyError>> + Send + 'static>>;
+    type ApplyError = FileApplyError;
+    type ApplyStdout = Empty;
+    type ApplyStderr = Empty;
+
+    async fn apply(
+        operation: &Self::Operation,
+        _ctx: &ApplyContext,
+    ) -> Result<(Self::ApplyOutput, Self::ApplyStdout, Self::ApplyStderr), Self::ApplyError> {
+        let operation = operation.clone();
+        let output: Self::ApplyOutput = Box::pin(async move {
+            match operation {
+                FileOperation::CreateDirectory { path } => {
+                    info!("[file] create directory: {}", path.display());
+                    fs::create_dir(&path).await?;
+                }
+                FileOperation::WriteFile { path, content } => {
+                    info!("[file] write: {}", path.display());
+                    fs::write_file(&path, &content).await?;
+                }
+                FileOperation::ChangeMode { path, mode } => {
+                    info!("[file] change mode: {} to {mode:o}", path.display());
+                    fs::set_file_mode(&path, mode).await?;
+                }
+            }
+            Ok(())
+        });
+        Ok((output, empty(), empty()))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn merge_orders_directories_writes_then_modes() {
+        let merged = File::merge(vec![
+            FileOperation::ChangeMode {
+                path: "/etc/app/conf.d/app.conf".into(),
+                mode: 0o600,
+            },
+            FileOperation::WriteFile {
+                path: "/etc/app/conf.d/app.conf".into(),
+                content: b"a = 1".to_vec(),
+            },
+            FileOperation::CreateDirectory {
+                path: "/etc/app/conf.d".into(),
+            },
+            FileOperation::WriteFile {
+                path: "/etc/app/main.conf".into(),
+                content: b"b = 2".to_vec(),
+            },
+            FileOperation::CreateDirectory {
+                path: "/etc/app".into(),
+            },
+        ]);
+        let merged: Vec<Vec<String>> = merged
+            .iter()
+            .map(|group| group.iter().map(ToString::to_string).collect())
+            .collect();
+        assert_eq!(
+            merged,
+            vec![
+                vec!["File::CreateDirectory(/etc/app)"],
+                vec!["File::CreateDirectory(/etc/app/conf.d)"],
+                vec![
+                    "File::WriteFile(/etc/app/conf.d/app.conf, 5 bytes)",
+                    "File::WriteFile(/etc/app/main.conf, 5 bytes)",
+                ],
+                vec!["File::ChangeMode(/etc/app/conf.d/app.conf, 600)"],
+            ]
+        );
+    }
+}
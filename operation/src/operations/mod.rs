@@ -1 +1,3 @@
 pub mod apt;
+pub mod command;
+pub mod file;
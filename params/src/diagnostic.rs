@@ -0,0 +1,119 @@
+use std::fmt::Write;
+
+use rimu::Span;
+
+/// An error message pointing at a location in a plan or params source.
+#[derive(Debug, Clone)]
+pub struct Diagnostic {
+    pub message: String,
+    pub span: Span,
+}
+
+impl Diagnostic {
+    pub fn new(message: impl Into<String>, span: Span) -> Self {
+        Self {
+            message: message.into(),
+            span,
+        }
+    }
+
+    /// Render as a caret diagnostic against the original source text:
+    ///
+    /// ```text
+    /// error: The "type" property must be a string
+    ///  --> plan.lusid:4:11
+    ///   |
+    /// 4 |     type: 5
+    ///   |           ^
+    /// ```
+    ///
+    /// Spans covering several lines are underlined to the end of their first
+    /// line.
+    pub fn render(&self, source: &str) -> String {
+        let start = floor_char_boundary(source, self.span.start());
+        let end = floor_char_boundary(source, self.span.end().max(start));
+
+        let line_start = source[..start].rfind('\n').map_or(0, |index| index + 1);
+        let line_end = source[start..]
+            .find('\n')
+            .map_or(source.len(), |index| start + index);
+        let line = &source[line_start..line_end];
+
+        let line_number = source[..line_start].matches('\n').count() + 1;
+        let column = source[line_start..start].chars().count() + 1;
+        let width = source[start..end.min(line_end)].chars().count().max(1);
+
+        let gutter = " ".repeat(line_number.to_string().len());
+        let mut out = String::new();
+        let _ = writeln!(out, "error: {}", self.message);
+        let _ = writeln!(
+            out,
+            "{gutter}--> {}:{line_number}:{column}",
+            self.span.source()
+        );
+        let _ = writeln!(out, "{gutter} |");
+        let _ = writeln!(out, "{line_number} | {line}");
+        let _ = write!(
+            out,
+            "{gutter} | {}{}",
+            " ".repeat(column - 1),
+            "^".repeat(width)
+        );
+        out
+    }
+}
+
+fn floor_char_boundary(source: &str, mut index: usize) -> usize {
+    index = index.min(source.len());
+    while !source.is_char_boundary(index) {
+        index -= 1;
+    }
+    index
+}
+
+#[cfg(test)]
+mod tests {
+    use rimu::SourceId;
+    use rimu_interop::PropertyError;
+
+    use super::*;
+    use crate::ParamTypeFromRimuError;
+
+    const SOURCE: &str = "name: \"example\"\nparams:\n  user:\n    type: 5\n";
+
+    fn span(start: usize, end: usize) -> Span {
+        Span::new(SourceId::from("plan.lusid".to_string()), start, end)
+    }
+
+    #[test]
+    fn caret_points_at_type_not_a_string() {
+        let start = SOURCE.find('5').unwrap();
+        let error = ParamTypeFromRimuError::Property(PropertyError::WrongType {
+            key: "type".to_string(),
+            expected: "string",
+            span: span(start, start + 1),
+        });
+        let diagnostics = error.diagnostics();
+        assert_eq!(diagnostics.len(), 1);
+
+        let rendered = diagnostics[0].render(SOURCE);
+        let lines: Vec<&str> = rendered.lines().collect();
+        assert_eq!(
+            lines,
+            vec![
+                "error: The \"type\" property must be a string",
+                " --> plan.lusid:4:11",
+                "  |",
+                "4 |     type: 5",
+                "  |           ^",
+            ]
+        );
+    }
+
+    #[test]
+    fn multiline_span_is_clipped_to_first_line() {
+        let start = SOURCE.find("user").unwrap();
+        let rendered = Diagnostic::new("bad user", span(start, SOURCE.len())).render(SOURCE);
+        assert!(rendered.ends_with("3 |   user:\n  |   ^^^^^"));
+    }
+}
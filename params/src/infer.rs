@@ -0,0 +1,139 @@
+use rimu::{SourceId, Span, Spanned, Value};
+
+use crate::{ParamField, ParamType, ParamTypes, ParamValues};
+
+impl ParamType {
+    /// Best-effort type for a sample value.
+    ///
+    /// Lists take the type of their first item and objects the type shared by
+    /// all their values. Anything without a clear type (nulls, empty lists,
+    /// objects with mixed values) infers as [`ParamType::Any`].
+    pub fn infer(value: &Value) -> ParamType {
+        match value {
+            Value::Boolean(_) => ParamType::Boolean,
+            Value::String(_) => ParamType::String,
+            Value::Number(_) => ParamType::Number,
+            Value::List(items) => ParamType::List {
+                item: Box::new(infer_spanned(items.first())),
+            },
+            Value::Object(object) => {
+                let mut values = object.values();
+                let first = infer_spanned(values.next());
+                let value = if values
+                    .all(|value| same_type(first.inner(), &ParamType::infer(value.inner())))
+                {
+                    first
+                } else {
+                    any(first.take().1)
+                };
+                ParamType::Object {
+                    value: Box::new(value),
+                }
+            }
+            _ => ParamType::Any,
+        }
+    }
+}
+
+impl ParamTypes {
+    /// Struct of required fields, one per sample parameter.
+    pub fn infer(values: &ParamValues) -> ParamTypes {
+        let fields = values
+            .0
+            .iter()
+            .map(|(key, value)| {
+                let (value, span) = value.clone().take();
+                let field = ParamField::new(ParamType::infer(&value));
+                (key.clone(), Spanned::new(field, span))
+            })
+            .collect();
+        ParamTypes::Struct(fields)
+    }
+}
+
+fn infer_spanned(value: Option<&Spanned<Value>>) -> Spanned<ParamType> {
+    match value {
+        Some(value) => {
+            let (value, span) = value.clone().take();
+            Spanned::new(ParamType::infer(&value), span)
+        }
+        None => any(Span::new(SourceId::empty(), 0, 0)),
+    }
+}
+
+fn any(span: Span) -> Spanned<ParamType> {
+    Spanned::new(ParamType::Any, span)
+}
+
+fn same_type(a: &ParamType, b: &ParamType) -> bool {
+    match (a, b) {
+        (ParamType::Any, ParamType::Any)
+        | (ParamType::Boolean, ParamType::Boolean)
+        | (ParamType::String, ParamType::String)
+        | (ParamType::Number, ParamType::Number) => true,
+        (ParamType::List { item: a }, ParamType::List { item: b })
+        | (ParamType::Object { value: a }, ParamType::Object { value: b }) => {
+            same_type(a.inner(), b.inner())
+        }
+        _ => false,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use serde_json::json;
+
+    use super::*;
+
+    fn infer(value: serde_json::Value) -> String {
+        let values = ParamValues::from_type(value, SourceId::empty())
+            .unwrap()
+            .into_inner();
+        ParamTypes::infer(&values).to_rimu_source()
+    }
+
+    #[test]
+    fn infer_nested_object() {
+        let source = infer(json!({
+            "hostname": "example",
+            "packages": ["git", "curl"],
+            "ssh": { "port": 22, "user": 1000 },
+        }));
+        assert_eq!(
+            source,
+            "\
+hostname:
+  type: string
+packages:
+  type: list
+  item:
+    type: string
+ssh:
+  type: object
+  value:
+    type: number
+"
+        );
+    }
+
+    #[test]
+    fn infer_empty_list_and_mixed_object_as_any() {
+        let source = infer(json!({
+            "env": { "DEBUG": true, "PATH": "/bin" },
+            "packages": [],
+        }));
+        assert_eq!(
+            source,
+            "\
+env:
+  type: object
+  value:
+    type: any
+packages:
+  type: list
+  item:
+    type: any
+"
+        );
+    }
+}
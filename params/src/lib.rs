@@ -1,41 +1,121 @@
 //! Parameter schemas and values.
 
+mod diagnostic;
+mod infer;
+mod render;
+mod source;
+#[cfg(test)]
+mod testing;
+
 use displaydoc::Display;
 use indexmap::IndexMap;
 use rimu::{from_serde_value, SerdeValue, SerdeValueError, SourceId, Span, Spanned, Value};
-use rimu_interop::{to_rimu, FromRimu, ToRimuError};
+use rimu_interop::{to_rimu, FromRimu, PropertyError, RimuObject, ToRimu, ToRimuError};
 use serde::{de::DeserializeOwned, Serialize};
 use thiserror::Error;
 
+pub use crate::diagnostic::*;
+pub use crate::source::*;
+
 #[derive(Debug, Clone)]
 pub enum ParamType {
+    /// Accepts any value.
+    Any,
     Boolean,
     String,
     Number,
-    List { item: Box<Spanned<ParamType>> },
-    Object { value: Box<Spanned<ParamType>> },
+    List {
+        item: Box<Spanned<ParamType>>,
+    },
+    Object {
+        value: Box<Spanned<ParamType>>,
+    },
+}
+
+impl ParamType {
+    /// A list of `item`s.
+    pub fn list(item: ParamType) -> Self {
+        ParamType::List {
+            item: Box::new(unspanned(item)),
+        }
+    }
+
+    /// An object whose values are all `value`s.
+    pub fn object(value: ParamType) -> Self {
+        ParamType::Object {
+            value: Box::new(unspanned(value)),
+        }
+    }
 }
 
 #[derive(Debug, Clone)]
 pub struct ParamField {
     typ: ParamType,
     optional: bool,
+    default: Option<Value>,
 }
 
 impl ParamField {
+    /// A required field of type `typ`.
     pub const fn new(typ: ParamType) -> Self {
         Self {
             typ,
             optional: false,
+            default: None,
         }
     }
 
+    pub const fn any() -> Self {
+        Self::new(ParamType::Any)
+    }
+
+    pub const fn boolean() -> Self {
+        Self::new(ParamType::Boolean)
+    }
+
+    pub const fn string() -> Self {
+        Self::new(ParamType::String)
+    }
+
+    pub const fn number() -> Self {
+        Self::new(ParamType::Number)
+    }
+
+    pub fn list(item: ParamType) -> Self {
+        Self::new(ParamType::list(item))
+    }
+
+    pub fn object(value: ParamType) -> Self {
+        Self::new(ParamType::object(value))
+    }
+
+    /// Allow the field to be left out.
+    pub fn optional(mut self) -> Self {
+        self.optional = true;
+        self
+    }
+
+    /// What the field means when left out, which also lets it be left out.
+    pub fn with_default(mut self, default: Value) -> Self {
+        self.default = Some(default);
+        self
+    }
+
     pub fn typ(&self) -> &ParamType {
         &self.typ
     }
 
-    pub fn optional(&self) -> &bool {
-        &self.optional
+    pub fn is_optional(&self) -> bool {
+        self.optional
+    }
+
+    pub fn default(&self) -> Option<&Value> {
+        self.default.as_ref()
+    }
+
+    /// Whether a value must be given for this field.
+    pub fn is_required(&self) -> bool {
+        !self.optional && self.default.is_none()
     }
 }
 
@@ -47,6 +127,34 @@ pub enum ParamTypes {
     Union(Vec<IndexMap<String, Spanned<ParamField>>>),
 }
 
+impl ParamTypes {
+    /// A struct of `fields`, as built in Rust rather than read from Rimu.
+    pub fn from_fields<K: Into<String>>(fields: impl IntoIterator<Item = (K, ParamField)>) -> Self {
+        ParamTypes::Struct(unspanned_fields(fields))
+    }
+
+    /// A union of structs, one per case of fields.
+    pub fn from_cases<K: Into<String>, F: IntoIterator<Item = (K, ParamField)>>(
+        cases: impl IntoIterator<Item = F>,
+    ) -> Self {
+        ParamTypes::Union(cases.into_iter().map(unspanned_fields).collect())
+    }
+}
+
+// Schemas built in Rust have no source, so their spans point nowhere.
+fn unspanned<T: Clone>(value: T) -> Spanned<T> {
+    Spanned::new(value, Span::new(SourceId::empty(), 0, 0))
+}
+
+fn unspanned_fields<K: Into<String>>(
+    fields: impl IntoIterator<Item = (K, ParamField)>,
+) -> IndexMap<String, Spanned<ParamField>> {
+    fields
+        .into_iter()
+        .map(|(key, field)| (key.into(), unspanned(field)))
+        .collect()
+}
+
 #[derive(Debug, Clone, Default)]
 pub struct ParamValues(IndexMap<String, Spanned<Value>>);
 
@@ -73,20 +181,53 @@ impl ParamValues {
     }
 }
 
+impl ParamValuesFromTypeError {
+    /// Located diagnostics for this error.
+    pub fn diagnostics(&self) -> Vec<Diagnostic> {
+        match self {
+            ParamValuesFromTypeError::ToRimu(_) => Vec::new(),
+            ParamValuesFromTypeError::FromRimu(error) => error.diagnostics(),
+        }
+    }
+}
+
 #[derive(Debug, Clone, Error, Display)]
 pub enum ParamValuesFromRimuError {
     /// Expected an object mapping parameter names to values
-    NotAnObject,
+    NotAnObject { span: Span },
+}
+
+impl ParamValuesFromRimuError {
+    /// Located diagnostics for this error.
+    pub fn diagnostics(&self) -> Vec<Diagnostic> {
+        match self {
+            ParamValuesFromRimuError::NotAnObject { span } => {
+                vec![Diagnostic::new(self.to_string(), span.clone())]
+            }
+        }
+    }
 }
 
 impl FromRimu for ParamValues {
     type Error = ParamValuesFromRimuError;
 
     fn from_rimu(value: Value) -> Result<Self, Self::Error> {
-        let Value::Object(object) = value else {
-            return Err(ParamValuesFromRimuError::NotAnObject);
-        };
-        Ok(ParamValues(object))
+        // Without a span there is nowhere to point, so point nowhere.
+        let span = Span::new(SourceId::empty(), 0, 0);
+        ParamValues::from_rimu_spanned(Spanned::new(value, span))
+            .map(Spanned::into_inner)
+            .map_err(Spanned::into_inner)
+    }
+
+    fn from_rimu_spanned(value: Spanned<Value>) -> Result<Spanned<Self>, Spanned<Self::Error>> {
+        let (value, span) = value.take();
+        match value {
+            Value::Object(object) => Ok(Spanned::new(ParamValues(object), span)),
+            _ => Err(Spanned::new(
+                ParamValuesFromRimuError::NotAnObject { span: span.clone() },
+                span,
+            )),
+        }
     }
 }
 
@@ -99,6 +240,32 @@ impl ParamValues {
         self.0.get(key)
     }
 
+    /// Look up a nested value by dotted path, e.g. `network.interfaces.0.name`.
+    ///
+    /// Numeric segments index into lists. Returns `None` if any segment is
+    /// missing, out of range, or steps into a scalar.
+    pub fn get_path(&self, path: &str) -> Option<&Spanned<Value>> {
+        let mut segments = path.split('.');
+        let mut current = self.0.get(segments.next()?)?;
+        for segment in segments {
+            current = match current.inner() {
+                Value::Object(object) => object.get(segment)?,
+                Value::List(items) => items.get(segment.parse::<usize>().ok()?)?,
+                _ => return None,
+            };
+        }
+        Some(current)
+    }
+
+    /// Deep-merge `other` on top of `self`.
+    ///
+    /// Objects present in both are merged key by key; any other value in
+    /// `other` (including lists) replaces the value in `self`. Each value keeps
+    /// the span of the source it came from.
+    pub fn merge(self, other: ParamValues) -> ParamValues {
+        ParamValues(merge_objects(self.0, other.0))
+    }
+
     pub fn into_type<T>(self) -> Result<T, SerdeValueError>
     where
         T: DeserializeOwned,
@@ -109,62 +276,98 @@ impl ParamValues {
     }
 }
 
+fn merge_objects(
+    mut base: IndexMap<String, Spanned<Value>>,
+    overlay: IndexMap<String, Spanned<Value>>,
+) -> IndexMap<String, Spanned<Value>> {
+    for (key, value) in overlay {
+        let merged = match base.get(&key) {
+            Some(existing) => merge_value(existing.clone(), value),
+            None => value,
+        };
+        base.insert(key, merged);
+    }
+    base
+}
+
+fn merge_value(base: Spanned<Value>, overlay: Spanned<Value>) -> Spanned<Value> {
+    let (base_inner, base_span) = base.take();
+    let (overlay_inner, overlay_span) = overlay.take();
+    match (base_inner, overlay_inner) {
+        (Value::Object(base_object), Value::Object(overlay_object)) => Spanned::new(
+            Value::Object(merge_objects(base_object, overlay_object)),
+            base_span,
+        ),
+        (_, overlay_inner) => Spanned::new(overlay_inner, overlay_span),
+    }
+}
+
 #[derive(Debug, Clone, Error, Display)]
 pub enum ParamTypeFromRimuError {
     /// Expected an object for parameter type
     NotAnObject,
-    /// Missing property: "type"
-    HasNoType,
-    /// The "type" property must be a string
-    TypeNotAString { span: Span },
+    /// {0}
+    Property(#[from] PropertyError),
     /// Unknown parameter type: {0}
     UnknownType(String),
-    /// List type is missing required "item" property
-    ListMissingItem,
     /// Invalid "item" type in list: {0:?}
     ListItem(Box<Spanned<ParamTypeFromRimuError>>),
-    /// Object type is missing required "value" property
-    ObjectMissingValue,
     /// Invalid "value" type in object: {0:?}
     ObjectValue(Box<Spanned<ParamTypeFromRimuError>>),
 }
 
+impl ParamTypeFromRimuError {
+    /// Located diagnostics for this error, innermost first.
+    pub fn diagnostics(&self) -> Vec<Diagnostic> {
+        match self {
+            ParamTypeFromRimuError::Property(error) => error
+                .span()
+                .map(|span| vec![Diagnostic::new(self.to_string(), span.clone())])
+                .unwrap_or_default(),
+            ParamTypeFromRimuError::ListItem(error)
+            | ParamTypeFromRimuError::ObjectValue(error) => {
+                spanned_diagnostics(error, ParamTypeFromRimuError::diagnostics)
+            }
+            _ => Vec::new(),
+        }
+    }
+}
+
+fn spanned_diagnostics<E: Clone + std::fmt::Display>(
+    error: &Spanned<E>,
+    diagnostics: impl Fn(&E) -> Vec<Diagnostic>,
+) -> Vec<Diagnostic> {
+    let (inner, span) = error.clone().take();
+    let nested = diagnostics(&inner);
+    if nested.is_empty() {
+        vec![Diagnostic::new(inner.to_string(), span)]
+    } else {
+        nested
+    }
+}
+
 impl FromRimu for ParamType {
     type Error = ParamTypeFromRimuError;
 
     fn from_rimu(value: Value) -> Result<Self, Self::Error> {
-        let Value::Object(mut object) = value else {
-            return Err(ParamTypeFromRimuError::NotAnObject);
-        };
-
-        let Some(typ) = object.get("type") else {
-            return Err(ParamTypeFromRimuError::HasNoType);
-        };
-
-        let (typ, typ_span) = typ.clone().take();
-        let Value::String(typ) = typ else {
-            return Err(ParamTypeFromRimuError::TypeNotAString { span: typ_span });
-        };
+        let mut object =
+            RimuObject::from_value(value).ok_or(ParamTypeFromRimuError::NotAnObject)?;
+        let typ = object.take_string("type")?.into_inner();
 
         match typ.as_str() {
+            "any" => Ok(ParamType::Any),
             "boolean" => Ok(ParamType::Boolean),
             "string" => Ok(ParamType::String),
             "number" => Ok(ParamType::Number),
             "list" => {
-                let item = object
-                    .swap_remove("item")
-                    .ok_or(ParamTypeFromRimuError::ListMissingItem)?;
-                let item = ParamType::from_rimu_spanned(item)
+                let item = ParamType::from_rimu_spanned(object.take_required("item")?)
                     .map_err(|error| ParamTypeFromRimuError::ListItem(Box::new(error)))?;
                 Ok(ParamType::List {
                     item: Box::new(item),
                 })
             }
             "object" => {
-                let value = object
-                    .swap_remove("value")
-                    .ok_or(ParamTypeFromRimuError::ObjectMissingValue)?;
-                let value = ParamType::from_rimu_spanned(value)
+                let value = ParamType::from_rimu_spanned(object.take_required("value")?)
                     .map_err(|error| ParamTypeFromRimuError::ObjectValue(Box::new(error)))?;
                 Ok(ParamType::Object {
                     value: Box::new(value),
@@ -179,34 +382,35 @@ impl FromRimu for ParamType {
 pub enum ParamFieldFromRimuError {
     /// Expected an object for parameter field
     NotAnObject,
-    /// The "optional" property must be a boolean
-    OptionalNotABoolean { span: Span },
+    /// {0}
+    Property(#[from] PropertyError),
     /// Invalid field type: {0:?}
     FieldType(#[from] ParamTypeFromRimuError),
+    /// Default does not match field type: {0:?}
+    InvalidDefault(Box<ValidateValueError>),
 }
 
 impl FromRimu for ParamField {
     type Error = ParamFieldFromRimuError;
 
     fn from_rimu(value: Value) -> Result<Self, Self::Error> {
-        let Value::Object(mut object) = value else {
-            return Err(ParamFieldFromRimuError::NotAnObject);
-        };
-
-        let optional = if let Some(optional_value) = object.swap_remove("optional") {
-            let (inner, span) = optional_value.take();
-            match inner {
-                Value::Boolean(b) => b,
-                _ => {
-                    return Err(ParamFieldFromRimuError::OptionalNotABoolean { span });
-                }
-            }
-        } else {
-            false
-        };
-
-        let typ = ParamType::from_rimu(Value::Object(object))?;
-        Ok(ParamField { typ, optional })
+        let mut object =
+            RimuObject::from_value(value).ok_or(ParamFieldFromRimuError::NotAnObject)?;
+        let optional = object
+            .take_optional_bool("optional")?
+            .is_some_and(Spanned::into_inner);
+        let default = object.take_optional("default");
+
+        let typ = ParamType::from_rimu(object.into_value())?;
+        if let Some(default) = &default {
+            validate_type(&Spanned::new(typ.clone(), default.span()), default)
+                .map_err(|error| ParamFieldFromRimuError::InvalidDefault(Box::new(error)))?;
+        }
+        Ok(ParamField {
+            typ,
+            optional,
+            default: default.map(Spanned::into_inner),
+        })
     }
 }
 
@@ -293,6 +497,76 @@ impl FromRimu for ParamTypes {
     }
 }
 
+impl ToRimu for ParamType {
+    fn to_rimu(&self) -> Value {
+        let name = match self {
+            ParamType::Any => "any",
+            ParamType::Boolean => "boolean",
+            ParamType::String => "string",
+            ParamType::Number => "number",
+            ParamType::List { .. } => "list",
+            ParamType::Object { .. } => "object",
+        };
+        let mut object = IndexMap::new();
+        object.insert(
+            "type".to_string(),
+            unspanned(Value::String(name.to_string())),
+        );
+        match self {
+            ParamType::List { item } => {
+                object.insert("item".to_string(), to_rimu_spanned(item));
+            }
+            ParamType::Object { value } => {
+                object.insert("value".to_string(), to_rimu_spanned(value));
+            }
+            _ => {}
+        }
+        Value::Object(object)
+    }
+}
+
+impl ToRimu for ParamField {
+    fn to_rimu(&self) -> Value {
+        let Value::Object(mut object) = self.typ.to_rimu() else {
+            unreachable!("types are written as objects");
+        };
+        if self.optional {
+            object.insert("optional".to_string(), unspanned(Value::Boolean(true)));
+        }
+        if let Some(default) = &self.default {
+            object.insert("default".to_string(), unspanned(default.clone()));
+        }
+        Value::Object(object)
+    }
+}
+
+impl ToRimu for ParamTypes {
+    fn to_rimu(&self) -> Value {
+        match self {
+            ParamTypes::Struct(fields) => struct_to_rimu(fields),
+            ParamTypes::Union(cases) => Value::List(
+                cases
+                    .iter()
+                    .map(|case| unspanned(struct_to_rimu(case)))
+                    .collect(),
+            ),
+        }
+    }
+}
+
+fn struct_to_rimu(fields: &IndexMap<String, Spanned<ParamField>>) -> Value {
+    Value::Object(
+        fields
+            .iter()
+            .map(|(key, field)| (key.clone(), to_rimu_spanned(field)))
+            .collect(),
+    )
+}
+
+fn to_rimu_spanned<T: ToRimu + Clone>(value: &Spanned<T>) -> Spanned<Value> {
+    Spanned::new(value.inner().to_rimu(), value.span())
+}
+
 #[derive(Debug, Clone, Error, Display)]
 pub enum ValidateValueError {
     /// Value does not match expected type
@@ -319,16 +593,47 @@ pub enum ParamValidationError {
         key: String,
         expected_type: Box<Spanned<ParamType>>,
     },
-    /// Unknown parameter "{key}"
+    /// Unknown parameter "{key}"{suggestion}
     UnknownParam {
         key: String,
         value: Box<Spanned<Value>>,
+        suggestion: Suggestion,
     },
     /// Invalid parameter "{key}": {error:?}
     InvalidParam {
         key: String,
         error: Box<ValidateValueError>,
     },
+    /// Invalid default for parameter "{key}": {error:?}
+    InvalidDefault {
+        key: String,
+        error: Box<ValidateValueError>,
+    },
+}
+
+/// The closest known parameter name to an unknown one, if any is close.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct Suggestion(pub Option<String>);
+
+impl std::fmt::Display for Suggestion {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match &self.0 {
+            Some(key) => write!(f, " (did you mean \"{key}\"?)"),
+            None => Ok(()),
+        }
+    }
+}
+
+// Typos up to this many edits away are worth suggesting.
+const MAX_SUGGESTION_DISTANCE: usize = 2;
+
+fn suggest<'a>(key: &str, candidates: impl Iterator<Item = &'a String>) -> Suggestion {
+    let closest = candidates
+        .map(|candidate| (strsim::levenshtein(key, candidate), candidate))
+        .filter(|(distance, _)| *distance <= MAX_SUGGESTION_DISTANCE && *distance < key.len())
+        .min_by_key(|(distance, _)| *distance)
+        .map(|(_, candidate)| candidate.clone());
+    Suggestion(closest)
 }
 
 #[derive(Debug, Clone, Error, Display)]
@@ -353,6 +658,66 @@ pub enum ParamsValidationError {
     EmptyUnion,
 }
 
+impl ValidateValueError {
+    fn diagnostic(&self, context: String) -> Diagnostic {
+        match self {
+            ValidateValueError::TypeMismatch {
+                expected_type,
+                got_value,
+            } => {
+                let (expected_type, _) = expected_type.as_ref().clone().take();
+                let (_, span) = got_value.as_ref().clone().take();
+                Diagnostic::new(format!("{context}: expected {expected_type:?}"), span)
+            }
+            ValidateValueError::ListItem { index, error } => {
+                error.diagnostic(format!("{context}[{index}]"))
+            }
+            ValidateValueError::ObjectEntry { key, error } => {
+                error.diagnostic(format!("{context}.{key}"))
+            }
+        }
+    }
+}
+
+impl ParamValidationError {
+    pub fn diagnostic(&self) -> Diagnostic {
+        match self {
+            ParamValidationError::MissingParam { expected_type, .. } => {
+                let (_, span) = expected_type.as_ref().clone().take();
+                Diagnostic::new(self.to_string(), span)
+            }
+            ParamValidationError::UnknownParam { value, .. } => {
+                let (_, span) = value.as_ref().clone().take();
+                Diagnostic::new(self.to_string(), span)
+            }
+            ParamValidationError::InvalidParam { key, error } => {
+                error.diagnostic(format!("Invalid parameter \"{key}\""))
+            }
+            ParamValidationError::InvalidDefault { key, error } => {
+                error.diagnostic(format!("Invalid default for parameter \"{key}\""))
+            }
+        }
+    }
+}
+
+impl ParamsValidationError {
+    /// Located diagnostics for each parameter that failed validation.
+    pub fn diagnostics(&self) -> Vec<Diagnostic> {
+        match self {
+            ParamsValidationError::Struct(error) => error
+                .errors
+                .iter()
+                .map(ParamValidationError::diagnostic)
+                .collect(),
+            ParamsValidationError::Union { case_errors } => case_errors
+                .iter()
+                .flat_map(|case| case.errors.iter().map(ParamValidationError::diagnostic))
+                .collect(),
+            _ => Vec::new(),
+        }
+    }
+}
+
 fn mismatch(typ: &Spanned<ParamType>, value: &Spanned<Value>) -> ValidateValueError {
     ValidateValueError::TypeMismatch {
         expected_type: Box::new(typ.clone()),
@@ -368,6 +733,8 @@ fn validate_type(
     let value_inner = value.inner();
 
     match typ_inner {
+        ParamType::Any => Ok(()),
+
         ParamType::Boolean => match value_inner {
             Value::Boolean(_) => Ok(()),
             _ => Err(mismatch(param_type, value)),
@@ -419,11 +786,17 @@ fn validate_type(
     }
 }
 
+// Returns the values with the default of each field left out filled in.
 fn validate_struct(
     fields: &IndexMap<String, Spanned<ParamField>>,
     values: &ParamValues,
-) -> Result<(), ParamsStructValidationError> {
-    let mut errors: Vec<ParamValidationError> = Vec::new();
+) -> Result<ParamValues, ParamsStructValidationError> {
+    // Grouped so output reads the same across runs: missing, then invalid,
+    // then unknown, each in the order the fields or values were written.
+    let mut missing: Vec<ParamValidationError> = Vec::new();
+    let mut invalid: Vec<ParamValidationError> = Vec::new();
+    let mut unknown: Vec<ParamValidationError> = Vec::new();
+    let mut filled = values.clone();
 
     // Requiredness and per-field validation.
     for (key, spanned_field) in fields.iter() {
@@ -433,35 +806,53 @@ fn validate_struct(
         match values.0.get(key) {
             Some(spanned_value) => {
                 if let Err(error) = validate_type(&spanned_type, spanned_value) {
-                    errors.push(ParamValidationError::InvalidParam {
+                    invalid.push(ParamValidationError::InvalidParam {
                         key: key.clone(),
                         error: Box::new(error),
                     });
                 }
             }
-            None => {
-                if !field.optional {
-                    errors.push(ParamValidationError::MissingParam {
-                        key: key.clone(),
-                        expected_type: Box::new(spanned_type),
-                    });
+            None => match field.default() {
+                // Defaults written in Rust aren't checked until used.
+                Some(default) => {
+                    let default = Spanned::new(default.clone(), spanned_type.span());
+                    match validate_type(&spanned_type, &default) {
+                        Ok(()) => {
+                            filled.0.insert(key.clone(), default);
+                        }
+                        Err(error) => invalid.push(ParamValidationError::InvalidDefault {
+                            key: key.clone(),
+                            error: Box::new(error),
+                        }),
+                    }
                 }
-            }
+                None => {
+                    if field.is_required() {
+                        missing.push(ParamValidationError::MissingParam {
+                            key: key.clone(),
+                            expected_type: Box::new(spanned_type),
+                        });
+                    }
+                }
+            },
         }
     }
 
     // Unknown keys.
     for (key, spanned_value) in values.0.iter() {
         if !fields.contains_key(key) {
-            errors.push(ParamValidationError::UnknownParam {
+            unknown.push(ParamValidationError::UnknownParam {
                 key: key.clone(),
                 value: Box::new(spanned_value.clone()),
+                suggestion: suggest(key, fields.keys()),
             });
         }
     }
 
+    let errors: Vec<ParamValidationError> =
+        missing.into_iter().chain(invalid).chain(unknown).collect();
     if errors.is_empty() {
-        Ok(())
+        Ok(filled)
     } else {
         Err(ParamsStructValidationError { errors })
     }
@@ -469,10 +860,12 @@ fn validate_struct(
 
 // For Struct: validate all fields.
 // For Union: succeed if any one case validates; otherwise return all case errors.
+//
+// Returns the values with defaults filled in from the struct or matching case.
 pub fn validate(
     param_types: Option<&Spanned<ParamTypes>>,
     param_values: Option<&Spanned<ParamValues>>,
-) -> Result<(), ParamsValidationError> {
+) -> Result<Option<Spanned<ParamValues>>, ParamsValidationError> {
     let (param_types, param_values) = match (param_types, param_values) {
         (Some(param_types), Some(param_values)) => (param_types, param_values),
         (Some(_), None) => {
@@ -482,18 +875,19 @@ pub fn validate(
             return Err(ParamsValidationError::ValuesWithoutTypes);
         }
         (None, None) => {
-            return Ok(());
+            return Ok(None);
         }
     };
 
+    let span = param_values.span();
     let param_types = param_types.inner();
     let param_values = param_values.inner();
 
     match param_types {
         ParamTypes::Struct(map) => {
-            validate_struct(map, param_values).map_err(Box::new)?;
+            let filled = validate_struct(map, param_values).map_err(Box::new)?;
 
-            Ok(())
+            Ok(Some(Spanned::new(filled, span)))
         }
         ParamTypes::Union(cases) => {
             if cases.is_empty() {
@@ -504,7 +898,7 @@ pub fn validate(
 
             for case in cases {
                 match validate_struct(case, param_values) {
-                    Ok(()) => return Ok(()),
+                    Ok(filled) => return Ok(Some(Spanned::new(filled, span))),
                     Err(error) => case_errors.push(error),
                 }
             }
@@ -513,3 +907,373 @@ pub fn validate(
         }
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::testing::assert_value_eq;
+    use serde_json::{json, Value as JsonValue};
+
+    fn values(value: JsonValue, source: &str) -> ParamValues {
+        ParamValues::from_type(value, SourceId::from(source.to_string()))
+            .unwrap()
+            .into_inner()
+    }
+
+    #[test]
+    fn top_level_array_is_not_an_object() {
+        let value = to_rimu(json!([1, 2]), SourceId::from("params".to_string())).unwrap();
+        let expected = value.span();
+
+        let error = ParamValues::from_rimu_spanned(value)
+            .unwrap_err()
+            .into_inner();
+        let ParamValuesFromRimuError::NotAnObject { span } = &error;
+        assert_eq!(span, &expected);
+
+        let diagnostics = error.diagnostics();
+        assert_eq!(diagnostics.len(), 1);
+        assert_eq!(diagnostics[0].span, expected);
+    }
+
+    fn merged(base: JsonValue, overlay: JsonValue) -> JsonValue {
+        values(base, "base")
+            .merge(values(overlay, "overlay"))
+            .into_type()
+            .unwrap()
+    }
+
+    #[test]
+    fn merge_shallow_override() {
+        assert_eq!(
+            merged(
+                json!({ "user": "lusid", "port": "22" }),
+                json!({ "port": "2222", "host": "example" })
+            ),
+            json!({ "user": "lusid", "port": "2222", "host": "example" })
+        );
+    }
+
+    #[test]
+    fn merge_nested_objects() {
+        assert_eq!(
+            merged(
+                json!({ "ssh": { "port": "22", "user": "lusid" } }),
+                json!({ "ssh": { "port": "2222" } })
+            ),
+            json!({ "ssh": { "port": "2222", "user": "lusid" } })
+        );
+    }
+
+    #[test]
+    fn merge_replaces_lists() {
+        assert_eq!(
+            merged(
+                json!({ "packages": ["git", "curl"] }),
+                json!({ "packages": ["vim"] })
+            ),
+            json!({ "packages": ["vim"] })
+        );
+    }
+
+    #[test]
+    fn merge_keeps_span_of_source() {
+        let span = |params: &ParamValues, key: &str| params.get(key).unwrap().clone().take().1;
+        let base = values(json!({ "user": "lusid", "port": 22 }), "base");
+        let overlay = values(json!({ "port": 2222 }), "overlay");
+        let (user_span, port_span) = (span(&base, "user"), span(&overlay, "port"));
+
+        let params = base.merge(overlay);
+        assert_eq!(span(&params, "user"), user_span);
+        assert_eq!(span(&params, "port"), port_span);
+    }
+
+    fn unknown_params(fields: &[&str], value: JsonValue) -> Vec<String> {
+        let fields: IndexMap<String, Spanned<ParamField>> = fields
+            .iter()
+            .map(|key| {
+                let field = ParamField::new(ParamType::String);
+                let span = Span::new(SourceId::empty(), 0, 0);
+                (key.to_string(), Spanned::new(field, span))
+            })
+            .collect();
+        let Err(error) = validate_struct(&fields, &values(value, "params")) else {
+            return Vec::new();
+        };
+        error
+            .errors
+            .iter()
+            .filter(|error| matches!(error, ParamValidationError::UnknownParam { .. }))
+            .map(ToString::to_string)
+            .collect()
+    }
+
+    #[test]
+    fn unknown_param_suggests_close_typo() {
+        assert_eq!(
+            unknown_params(
+                &["hostname", "user"],
+                json!({ "hostnam": "a", "user": "b" })
+            ),
+            vec![r#"Unknown parameter "hostnam" (did you mean "hostname"?)"#]
+        );
+    }
+
+    #[test]
+    fn unknown_param_without_close_match() {
+        assert_eq!(
+            unknown_params(&["hostname"], json!({ "hostname": "a", "packages": "b" })),
+            vec![r#"Unknown parameter "packages""#]
+        );
+    }
+
+    #[test]
+    fn exact_key_is_not_unknown() {
+        assert_eq!(
+            unknown_params(
+                &["hostname", "user"],
+                json!({ "hostname": "a", "user": "b" })
+            ),
+            Vec::<String>::new()
+        );
+    }
+
+    #[test]
+    fn struct_errors_are_grouped_by_kind() {
+        let span = Span::new(SourceId::empty(), 0, 0);
+        let fields: IndexMap<String, Spanned<ParamField>> =
+            [("port", ParamType::Number), ("user", ParamType::String)]
+                .into_iter()
+                .map(|(key, typ)| {
+                    (
+                        key.to_string(),
+                        Spanned::new(ParamField::new(typ), span.clone()),
+                    )
+                })
+                .collect();
+
+        // Written unknown first, so only grouping puts it last.
+        let error = validate_struct(
+            &fields,
+            &values(json!({ "extra": true, "port": "22" }), "params"),
+        )
+        .unwrap_err();
+
+        let kinds: Vec<(&str, &str)> = error
+            .errors
+            .iter()
+            .map(|error| match error {
+                ParamValidationError::MissingParam { key, .. } => ("missing", key.as_str()),
+                ParamValidationError::InvalidParam { key, .. }
+                | ParamValidationError::InvalidDefault { key, .. } => ("invalid", key.as_str()),
+                ParamValidationError::UnknownParam { key, .. } => ("unknown", key.as_str()),
+            })
+            .collect();
+        assert_eq!(
+            kinds,
+            vec![
+                ("missing", "user"),
+                ("invalid", "port"),
+                ("unknown", "extra")
+            ]
+        );
+    }
+
+    fn server_types() -> Spanned<ParamTypes> {
+        unspanned(ParamTypes::from_fields([
+            ("hostname", ParamField::string()),
+            (
+                "user",
+                ParamField::string().with_default(Value::String("lusid".into())),
+            ),
+            ("port", ParamField::number().optional()),
+            ("packages", ParamField::list(ParamType::String).optional()),
+            ("labels", ParamField::object(ParamType::String).optional()),
+        ]))
+    }
+
+    fn validate_server(
+        value: JsonValue,
+    ) -> Result<Option<Spanned<ParamValues>>, ParamsValidationError> {
+        let values = ParamValues::from_type(value, SourceId::from("params".to_string())).unwrap();
+        validate(Some(&server_types()), Some(&values))
+    }
+
+    #[test]
+    fn builds_fields_fluently() {
+        let ParamTypes::Struct(fields) = server_types().into_inner() else {
+            panic!("expected a struct");
+        };
+        let hostname = fields["hostname"].inner();
+        assert!(hostname.is_required());
+        let user = fields["user"].inner();
+        assert!(!user.is_required());
+        assert!(!user.is_optional());
+        assert!(matches!(user.default(), Some(Value::String(user)) if user == "lusid"));
+        let packages = fields["packages"].inner();
+        assert!(packages.is_optional());
+        assert_value_eq(packages.typ(), &ParamType::list(ParamType::String));
+    }
+
+    #[test]
+    fn validates_against_built_schema() {
+        assert!(validate_server(json!({ "hostname": "web" })).is_ok());
+        assert!(validate_server(json!({
+            "hostname": "web",
+            "user": "admin",
+            "port": 2222,
+            "packages": ["git"],
+            "labels": { "role": "web" },
+        }))
+        .is_ok());
+        assert!(validate_server(json!({ "port": 2222 })).is_err());
+        assert!(validate_server(json!({ "hostname": "web", "port": "22" })).is_err());
+        assert!(validate_server(json!({ "hostname": "web", "packages": [1] })).is_err());
+    }
+
+    #[test]
+    fn fills_in_defaults() {
+        let values = validate_server(json!({ "hostname": "web" }))
+            .unwrap()
+            .unwrap()
+            .into_inner();
+        assert!(
+            matches!(values.get("user").map(Spanned::inner), Some(Value::String(user)) if user == "lusid")
+        );
+        assert!(values.get("port").is_none());
+
+        let values = validate_server(json!({ "hostname": "web", "user": "admin" }))
+            .unwrap()
+            .unwrap()
+            .into_inner();
+        assert!(
+            matches!(values.get("user").map(Spanned::inner), Some(Value::String(user)) if user == "admin")
+        );
+    }
+
+    #[test]
+    fn fills_in_defaults_of_matching_case() {
+        let types = unspanned(ParamTypes::from_cases([
+            vec![
+                ("package", ParamField::string()),
+                (
+                    "state",
+                    ParamField::string().with_default(Value::String("installed".into())),
+                ),
+            ],
+            vec![("packages", ParamField::list(ParamType::String))],
+        ]));
+        let values = ParamValues::from_type(
+            json!({ "package": "git" }),
+            SourceId::from("params".to_string()),
+        )
+        .unwrap();
+        let values = validate(Some(&types), Some(&values))
+            .unwrap()
+            .unwrap()
+            .into_inner();
+        assert!(
+            matches!(values.get("state").map(Spanned::inner), Some(Value::String(state)) if state == "installed")
+        );
+    }
+
+    #[test]
+    fn rejects_default_of_wrong_type() {
+        let field = ParamField::number().with_default(Value::String("22".into()));
+        assert!(matches!(
+            ParamField::from_rimu(field.to_rimu()),
+            Err(ParamFieldFromRimuError::InvalidDefault(_))
+        ));
+
+        let types = unspanned(ParamTypes::from_fields([("port", field)]));
+        let values =
+            ParamValues::from_type(json!({}), SourceId::from("params".to_string())).unwrap();
+        let Err(ParamsValidationError::Struct(error)) = validate(Some(&types), Some(&values))
+        else {
+            panic!("expected the default to be rejected");
+        };
+        assert!(matches!(
+            error.errors.as_slice(),
+            [ParamValidationError::InvalidDefault { key, .. }] if key == "port"
+        ));
+    }
+
+    fn round_trip(types: ParamTypes) {
+        let back = ParamTypes::from_rimu(types.to_rimu()).unwrap();
+        assert_value_eq(&back, &types);
+    }
+
+    #[test]
+    fn param_type_round_trips() {
+        let types = [
+            ParamType::Any,
+            ParamType::Boolean,
+            ParamType::String,
+            ParamType::Number,
+            ParamType::list(ParamType::Number),
+            ParamType::object(ParamType::list(ParamType::String)),
+        ];
+        for typ in types {
+            let back = ParamType::from_rimu(typ.to_rimu()).unwrap();
+            assert_value_eq(&back, &typ);
+        }
+    }
+
+    #[test]
+    fn param_types_round_trip() {
+        round_trip(ParamTypes::from_fields([
+            ("hostname", ParamField::string()),
+            ("packages", ParamField::list(ParamType::String).optional()),
+        ]));
+        round_trip(ParamTypes::from_cases([
+            vec![("package", ParamField::string())],
+            vec![("packages", ParamField::list(ParamType::String))],
+        ]));
+    }
+
+    #[test]
+    fn param_field_default_round_trips() {
+        let field = ParamField::string().with_default(Value::String("lusid".into()));
+        let back = ParamField::from_rimu(field.to_rimu()).unwrap();
+        assert_value_eq(&back, &field);
+        assert!(!back.is_optional());
+    }
+
+    fn network() -> ParamValues {
+        values(
+            json!({
+                "network": {
+                    "interfaces": [{ "name": "eth0" }, { "name": "wlan0" }],
+                },
+            }),
+            "params",
+        )
+    }
+
+    fn get_path(params: &ParamValues, path: &str) -> Option<JsonValue> {
+        let value = params.get_path(path)?.clone().into_inner();
+        Some(from_serde_value(SerdeValue::from(value)).unwrap())
+    }
+
+    #[test]
+    fn get_path_nested() {
+        let params = network();
+        assert_eq!(
+            get_path(&params, "network.interfaces.1.name"),
+            Some(json!("wlan0"))
+        );
+    }
+
+    #[test]
+    fn get_path_out_of_range_index() {
+        let params = network();
+        assert_eq!(get_path(&params, "network.interfaces.2.name"), None);
+        assert_eq!(get_path(&params, "network.interfaces.first"), None);
+    }
+
+    #[test]
+    fn get_path_through_scalar() {
+        let params = network();
+        assert_eq!(get_path(&params, "network.interfaces.0.name.length"), None);
+    }
+}
@@ -0,0 +1,144 @@
+use std::fmt::Write;
+
+use indexmap::IndexMap;
+use rimu::{Spanned, Value};
+
+use crate::{ParamField, ParamType, ParamTypes};
+
+impl ParamTypes {
+    /// Render as Rimu source, in the same shape `from_rimu` reads.
+    pub fn to_rimu_source(&self) -> String {
+        match self {
+            ParamTypes::Struct(fields) => render_struct(fields),
+            ParamTypes::Union(cases) => {
+                let mut out = String::new();
+                for case in cases {
+                    for (index, line) in render_struct(case).lines().enumerate() {
+                        let prefix = if index == 0 { "- " } else { "  " };
+                        let _ = writeln!(out, "{prefix}{line}");
+                    }
+                }
+                out
+            }
+        }
+    }
+}
+
+fn render_struct(fields: &IndexMap<String, Spanned<ParamField>>) -> String {
+    let mut out = String::new();
+    for (key, field) in fields {
+        let field = field.inner();
+        let _ = writeln!(out, "{key}:");
+        render_type(&mut out, field.typ(), 1);
+        if field.is_optional() {
+            let _ = writeln!(out, "  optional: true");
+        }
+        if let Some(default) = field.default() {
+            let _ = writeln!(out, "  default: {}", render_value(default));
+        }
+    }
+    out
+}
+
+fn render_type(out: &mut String, typ: &ParamType, depth: usize) {
+    let indent = "  ".repeat(depth);
+    let name = match typ {
+        ParamType::Any => "any",
+        ParamType::Boolean => "boolean",
+        ParamType::String => "string",
+        ParamType::Number => "number",
+        ParamType::List { .. } => "list",
+        ParamType::Object { .. } => "object",
+    };
+    let _ = writeln!(out, "{indent}type: {name}");
+    match typ {
+        ParamType::List { item } => {
+            let _ = writeln!(out, "{indent}item:");
+            render_type(out, item.inner(), depth + 1);
+        }
+        ParamType::Object { value } => {
+            let _ = writeln!(out, "{indent}value:");
+            render_type(out, value.inner(), depth + 1);
+        }
+        _ => {}
+    }
+}
+
+// Written as an expression, so strings are quoted rather than bare.
+fn render_value(value: &Value) -> String {
+    match value {
+        Value::String(string) => {
+            let mut out = String::from("\"");
+            for c in string.chars() {
+                match c {
+                    '"' => out.push_str("\\\""),
+                    '\\' => out.push_str("\\\\"),
+                    '\n' => out.push_str("\\n"),
+                    '\r' => out.push_str("\\r"),
+                    '\t' => out.push_str("\\t"),
+                    c => out.push(c),
+                }
+            }
+            out.push('"');
+            out
+        }
+        Value::List(items) => {
+            let items: Vec<String> = items
+                .iter()
+                .map(|item| render_value(item.inner()))
+                .collect();
+            format!("[{}]", items.join(", "))
+        }
+        Value::Object(object) => {
+            let entries: Vec<String> = object
+                .iter()
+                .map(|(key, value)| {
+                    format!(
+                        "{}: {}",
+                        render_value(&Value::String(key.clone())),
+                        render_value(value.inner())
+                    )
+                })
+                .collect();
+            format!("{{ {} }}", entries.join(", "))
+        }
+        other => other.to_string(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::{ParamField, ParamType, ParamTypes};
+    use rimu::Value;
+
+    #[test]
+    fn renders_defaults_as_expressions() {
+        let types = ParamTypes::from_fields([
+            (
+                "user",
+                ParamField::string().with_default(Value::String("say \"hi\"".into())),
+            ),
+            (
+                "debug",
+                ParamField::boolean().with_default(Value::Boolean(false)),
+            ),
+            ("packages", ParamField::list(ParamType::String).optional()),
+        ]);
+        assert_eq!(
+            types.to_rimu_source(),
+            "\
+user:
+  type: string
+  default: \"say \\\"hi\\\"\"
+debug:
+  type: boolean
+  default: false
+packages:
+  type: list
+  item:
+    type: string
+  optional: true
+"
+        );
+    }
+}
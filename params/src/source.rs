@@ -0,0 +1,62 @@
+use std::collections::HashMap;
+
+use rimu::{SourceId, Span};
+
+use crate::Diagnostic;
+
+/// Original text of each plan and params source, so spans into them can be shown.
+#[derive(Debug, Clone, Default)]
+pub struct SourceRegistry {
+    // Keyed by display form, which is how sources are named in diagnostics.
+    sources: HashMap<String, String>,
+}
+
+impl SourceRegistry {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn insert(&mut self, source_id: &SourceId, text: impl Into<String>) {
+        self.sources.insert(source_id.to_string(), text.into());
+    }
+
+    pub fn get(&self, source_id: &SourceId) -> Option<&str> {
+        self.sources.get(&source_id.to_string()).map(String::as_str)
+    }
+
+    /// Text covered by `span`, if its source is known.
+    pub fn resolve(&self, span: &Span) -> Option<&str> {
+        let source = self.get(&span.source())?;
+        source.get(span.start()..span.end())
+    }
+
+    /// Render `diagnostic` against its source, if known.
+    pub fn render(&self, diagnostic: &Diagnostic) -> Option<String> {
+        let source = self.get(&diagnostic.span.source())?;
+        Some(diagnostic.render(source))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn resolve_spans_from_two_sources() {
+        let plan_id = SourceId::from("plan.lusid".to_string());
+        let params_id = SourceId::from("<cli:params>".to_string());
+        let mut sources = SourceRegistry::new();
+        sources.insert(&plan_id, "name: \"example\"\n");
+        sources.insert(&params_id, "{ \"user\": \"lusid\" }");
+
+        assert_eq!(sources.resolve(&Span::new(plan_id, 7, 14)), Some("example"));
+        assert_eq!(
+            sources.resolve(&Span::new(params_id, 10, 17)),
+            Some("\"lusid\"")
+        );
+        assert_eq!(
+            sources.resolve(&Span::new(SourceId::from("other".to_string()), 0, 1)),
+            None
+        );
+    }
+}
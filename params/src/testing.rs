@@ -0,0 +1,92 @@
+//! Test helpers for comparing values and types without their spans.
+
+use rimu::{from_serde_value, SerdeValue, Spanned, Value};
+use rimu_interop::ToRimu;
+use serde_json::Value as JsonValue;
+
+use crate::{ParamField, ParamType, ParamTypes};
+
+/// Something that can be compared by what it holds, not where it was written.
+pub(crate) trait Unspanned {
+    fn unspanned(&self) -> JsonValue;
+}
+
+impl Unspanned for Value {
+    fn unspanned(&self) -> JsonValue {
+        from_serde_value(SerdeValue::from(self.clone())).expect("value converts to JSON")
+    }
+}
+
+impl<T: Unspanned + Clone> Unspanned for Spanned<T> {
+    fn unspanned(&self) -> JsonValue {
+        self.inner().unspanned()
+    }
+}
+
+impl Unspanned for ParamType {
+    fn unspanned(&self) -> JsonValue {
+        self.to_rimu().unspanned()
+    }
+}
+
+impl Unspanned for ParamField {
+    fn unspanned(&self) -> JsonValue {
+        self.to_rimu().unspanned()
+    }
+}
+
+impl Unspanned for ParamTypes {
+    fn unspanned(&self) -> JsonValue {
+        self.to_rimu().unspanned()
+    }
+}
+
+/// Assert `got` and `expected` are equal, ignoring spans.
+#[track_caller]
+pub(crate) fn assert_value_eq<T: Unspanned>(got: &T, expected: &T) {
+    assert_eq!(got.unspanned(), expected.unspanned());
+}
+
+#[cfg(test)]
+mod tests {
+    use rimu::{SourceId, Span};
+
+    use super::*;
+
+    fn string(value: &str, source: &str, start: usize) -> Spanned<Value> {
+        let span = Span::new(
+            SourceId::from(source.to_string()),
+            start,
+            start + value.len(),
+        );
+        Spanned::new(Value::String(value.to_string()), span)
+    }
+
+    #[test]
+    fn ignores_spans() {
+        assert_value_eq(&string("lusid", "a", 0), &string("lusid", "b", 10));
+
+        let nested = |source: &str, start: usize| ParamType::List {
+            item: Box::new(Spanned::new(
+                ParamType::String,
+                Span::new(SourceId::from(source.to_string()), start, start),
+            )),
+        };
+        assert_value_eq(&nested("a", 0), &nested("b", 10));
+    }
+
+    #[test]
+    #[should_panic]
+    fn compares_values() {
+        assert_value_eq(&string("lusid", "a", 0), &string("root", "a", 0));
+    }
+
+    #[test]
+    #[should_panic]
+    fn compares_types() {
+        assert_value_eq(
+            &ParamType::list(ParamType::String),
+            &ParamType::list(ParamType::Number),
+        );
+    }
+}
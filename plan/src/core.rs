@@ -1,9 +1,12 @@
 use lusid_params::{validate, ParamValues};
-use lusid_resource::{apt::Apt, ResourceParams, ResourceType};
+use lusid_resource::{apt::Apt, command::Command, file::File, ResourceParams, ResourceType};
 use rimu::Spanned;
 
 use crate::PlanItemToResourceError;
 
+/// Ids of the built-in modules, used as `@core/<id>`.
+pub const CORE_MODULE_IDS: &[&str] = &[Apt::ID, Command::ID, File::ID];
+
 pub fn is_core_module(module: &Spanned<String>) -> Option<&str> {
     module.inner().strip_prefix("@core/")
 }
@@ -14,8 +17,13 @@ pub fn core_module(
 ) -> Result<ResourceParams, PlanItemToResourceError> {
     match core_module_id {
         Apt::ID => core_module_for_resource::<Apt>(param_values).map(ResourceParams::Apt),
+        Command::ID => {
+            core_module_for_resource::<Command>(param_values).map(ResourceParams::Command)
+        }
+        File::ID => core_module_for_resource::<File>(param_values).map(ResourceParams::File),
         other => Err(PlanItemToResourceError::UnsupportedCoreModuleId {
             id: other.to_string(),
+            supported: CORE_MODULE_IDS.join(", "),
         }),
     }
 }
@@ -25,10 +33,62 @@ fn core_module_for_resource<R: ResourceType>(
 ) -> Result<R::Params, PlanItemToResourceError> {
     let param_values = param_values.ok_or(PlanItemToResourceError::MissingParams)?;
     let param_types = R::param_types();
-    validate(param_types.as_ref(), Some(&param_values)).map_err(PlanItemToResourceError::from)?;
+    let param_values = validate(param_types.as_ref(), Some(&param_values))
+        .map_err(PlanItemToResourceError::from)?
+        .unwrap_or(param_values);
     let params: R::Params = param_values
         .into_inner()
         .into_type()
         .map_err(PlanItemToResourceError::from)?;
+    R::validate_params(&params).map_err(PlanItemToResourceError::from)?;
     Ok(params)
 }
+
+#[cfg(test)]
+mod tests {
+    use lusid_resource::file::FileParams;
+    use rimu::{SourceId, Span};
+    use serde_json::json;
+
+    use super::*;
+
+    fn module(id: &str) -> Spanned<String> {
+        Spanned::new(id.to_string(), Span::new(SourceId::empty(), 0, 0))
+    }
+
+    #[test]
+    fn file_module_writes_file() {
+        let params = ParamValues::from_type(
+            json!({ "path": "/etc/motd", "content": "hello\n", "mode": "644" }),
+            SourceId::empty(),
+        )
+        .unwrap();
+        let module = module("@core/file");
+        let id = is_core_module(&module).unwrap();
+
+        let params = core_module(id, Some(params)).unwrap();
+        let ResourceParams::File(FileParams::File {
+            path,
+            content,
+            mode,
+        }) = params
+        else {
+            panic!("expected file params");
+        };
+        assert_eq!(path.to_str(), Some("/etc/motd"));
+        assert_eq!(content, "hello\n");
+        assert_eq!(mode, Some(0o644));
+    }
+
+    #[test]
+    fn unknown_module_lists_supported() {
+        let module = module("@core/nope");
+        let id = is_core_module(&module).unwrap();
+        let error = core_module(id, None).unwrap_err().to_string();
+        assert!(error.contains("nope"), "{error}");
+        assert!(
+            error.ends_with("expected one of: apt, command, file"),
+            "{error}"
+        );
+    }
+}
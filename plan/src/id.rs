@@ -1,56 +1,149 @@
+use displaydoc::Display;
 use lusid_store::StoreItemId;
 use rimu::SourceId;
 use std::{
-    fmt::Display,
-    path::{Path, PathBuf},
+    env,
+    path::{Component, Path, PathBuf},
 };
+use thiserror::Error;
 use url::Url;
 
+use crate::PlanError;
+
 #[derive(Debug, Clone, PartialEq, Eq, PartialOrd, Ord, Hash)]
 pub enum PlanId {
     Path(PathBuf),
     Git(Url, PathBuf),
+    /// Plan source given directly, named by `id` in diagnostics.
+    Inline {
+        id: String,
+        source: String,
+    },
+}
+
+#[derive(Debug, Error, Display)]
+pub enum PlanIdJoinError {
+    /// Include {path:?} must be relative to the including plan
+    Absolute { path: PathBuf },
+
+    /// Include {path:?} is outside the plan root {root:?}
+    EscapesRoot { path: PathBuf, root: PathBuf },
 }
 
 impl PlanId {
-    pub fn join<P: AsRef<Path>>(&self, path: P) -> PlanId {
+    /// Directory of this plan. Inline plans have no directory, so their root
+    /// is empty.
+    pub fn root(&self) -> PathBuf {
         match self {
-            PlanId::Path(current_path) => PlanId::Path(relative(current_path, path)),
-            PlanId::Git(url, current_path) => {
-                PlanId::Git(url.clone(), relative(current_path, path))
-            }
+            PlanId::Path(path) | PlanId::Git(_, path) => parent(path).to_path_buf(),
+            PlanId::Inline { .. } => PathBuf::new(),
+        }
+    }
+
+    /// The root for this plan's includes when none is given: the working
+    /// directory if the plan is within it, so a plan can include from its
+    /// sibling directories (`../shared/base.lusid`), otherwise the plan's
+    /// own directory.
+    pub fn default_root(&self) -> PathBuf {
+        match env::current_dir() {
+            Ok(cwd) => self.default_root_in(&cwd),
+            Err(_) => self.root(),
+        }
+    }
+
+    fn default_root_in(&self, cwd: &Path) -> PathBuf {
+        let dir = self.root();
+        if dir.is_relative() {
+            // Already relative to the working directory.
+            PathBuf::new()
+        } else if dir.starts_with(cwd) {
+            cwd.to_path_buf()
+        } else {
+            dir
         }
     }
 
+    /// Resolve an include relative to this plan's directory.
+    ///
+    /// `.` and `..` are resolved lexically, and the result must stay within
+    /// `root`, so a plan can't include arbitrary files. Absolute includes are
+    /// rejected for the same reason. Includes from an inline plan resolve
+    /// against `root`.
+    pub fn join<P: AsRef<Path>>(&self, path: P, root: &Path) -> Result<PlanId, PlanIdJoinError> {
+        let path = path.as_ref();
+        if path.has_root() {
+            return Err(PlanIdJoinError::Absolute {
+                path: path.to_path_buf(),
+            });
+        }
+
+        let dir = match self {
+            PlanId::Path(current_path) | PlanId::Git(_, current_path) => parent(current_path),
+            PlanId::Inline { .. } => root,
+        };
+        let joined = normalize(&dir.join(path));
+        let root = normalize(root);
+        let escapes = joined.components().any(|c| c == Component::ParentDir);
+        if escapes || !joined.starts_with(&root) {
+            return Err(PlanIdJoinError::EscapesRoot { path: joined, root });
+        }
+
+        Ok(match self {
+            PlanId::Git(url, _) => PlanId::Git(url.clone(), joined),
+            PlanId::Path(_) | PlanId::Inline { .. } => PlanId::Path(joined),
+        })
+    }
+
     pub fn as_path(self) -> Option<PathBuf> {
         match self {
             PlanId::Path(path) => Some(path),
-            PlanId::Git(_, _) => None,
+            PlanId::Git(_, _) | PlanId::Inline { .. } => None,
         }
     }
 }
 
-fn relative<P: AsRef<Path>>(current_path: &Path, next_path: P) -> PathBuf {
-    current_path
-        .parent()
-        .unwrap_or(&PathBuf::default())
-        .join(next_path)
+fn parent(path: &Path) -> &Path {
+    path.parent().unwrap_or(Path::new(""))
 }
 
-impl Display for PlanId {
+/// Resolve `.` and `..` without touching the filesystem. Leading `..` that
+/// can't be resolved are kept.
+fn normalize(path: &Path) -> PathBuf {
+    let mut out = PathBuf::new();
+    for component in path.components() {
+        match component {
+            Component::CurDir => {}
+            Component::ParentDir => match out.components().next_back() {
+                Some(Component::Normal(_)) => {
+                    out.pop();
+                }
+                Some(Component::RootDir | Component::Prefix(_)) => {}
+                _ => out.push(".."),
+            },
+            component => out.push(component),
+        }
+    }
+    out
+}
+
+impl std::fmt::Display for PlanId {
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
         match self {
             PlanId::Path(path) => write!(f, "Path({})", path.display()),
             PlanId::Git(url, path) => write!(f, "Git({}, {})", url, path.display()),
+            PlanId::Inline { id, .. } => write!(f, "Inline({id})"),
         }
     }
 }
 
-impl From<PlanId> for StoreItemId {
-    fn from(value: PlanId) -> Self {
+impl TryFrom<PlanId> for StoreItemId {
+    type Error = PlanError;
+
+    fn try_from(value: PlanId) -> Result<Self, Self::Error> {
         match value {
-            PlanId::Path(path) => StoreItemId::LocalFile(path),
-            PlanId::Git(_url, _path) => todo!(),
+            PlanId::Path(path) => Ok(StoreItemId::LocalFile(path)),
+            PlanId::Git(url, _path) => Err(PlanError::UnsupportedGitPlan { url }),
+            PlanId::Inline { id, .. } => Err(PlanError::InlineNotInStore { id }),
         }
     }
 }
@@ -64,6 +157,7 @@ impl From<PlanId> for SourceId {
                     .append_pair("path", &path.to_string_lossy());
                 SourceId::from(url.to_string())
             }
+            PlanId::Inline { id, .. } => SourceId::from(id),
         }
     }
 }
@@ -75,7 +169,17 @@ pub enum PlanNodeId {
     SubItem { scope_id: String, item_id: String },
 }
 
-impl Display for PlanNodeId {
+impl PlanNodeId {
+    /// The id given to a top-level item in its plan, if any.
+    pub fn plan_item_id(&self) -> Option<&str> {
+        match self {
+            PlanNodeId::PlanItem { item_id, .. } => Some(item_id),
+            PlanNodeId::Plan(_) | PlanNodeId::SubItem { .. } => None,
+        }
+    }
+}
+
+impl std::fmt::Display for PlanNodeId {
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
         match self {
             PlanNodeId::Plan(id) => write!(f, "Plan({id})"),
@@ -88,3 +192,73 @@ impl Display for PlanNodeId {
         }
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn join(from: &str, include: &str) -> Result<PlanId, PlanIdJoinError> {
+        PlanId::Path(PathBuf::from(from)).join(include, Path::new("/plans"))
+    }
+
+    #[test]
+    fn sibling_include() {
+        assert_eq!(
+            join("/plans/hosts/web.lusid", "./db.lusid").unwrap(),
+            PlanId::Path(PathBuf::from("/plans/hosts/db.lusid"))
+        );
+    }
+
+    #[test]
+    fn parent_include_within_root() {
+        assert_eq!(
+            join("/plans/hosts/web.lusid", "../shared/base.lusid").unwrap(),
+            PlanId::Path(PathBuf::from("/plans/shared/base.lusid"))
+        );
+    }
+
+    #[test]
+    fn absolute_and_escaping_includes_are_rejected() {
+        assert!(matches!(
+            join("/plans/web.lusid", "/etc/passwd"),
+            Err(PlanIdJoinError::Absolute { .. })
+        ));
+        assert!(matches!(
+            join("/plans/hosts/web.lusid", "../../etc/passwd"),
+            Err(PlanIdJoinError::EscapesRoot { .. })
+        ));
+
+        let inline = PlanId::Inline {
+            id: "inline".to_string(),
+            source: String::new(),
+        };
+        assert!(matches!(
+            inline.join("../secret.lusid", Path::new("")),
+            Err(PlanIdJoinError::EscapesRoot { .. })
+        ));
+    }
+
+    #[test]
+    fn default_root_is_working_directory_containing_plan() {
+        let plan_id = PlanId::Path(PathBuf::from("/plans/hosts/web.lusid"));
+        let root = plan_id.default_root_in(Path::new("/plans"));
+        assert_eq!(root, Path::new("/plans"));
+        assert_eq!(
+            plan_id.join("../shared/base.lusid", &root).unwrap(),
+            PlanId::Path(PathBuf::from("/plans/shared/base.lusid"))
+        );
+
+        // Run from elsewhere, includes stay within the plan's directory.
+        assert_eq!(
+            plan_id.default_root_in(Path::new("/home/me")),
+            Path::new("/plans/hosts")
+        );
+
+        let relative = PlanId::Path(PathBuf::from("hosts/web.lusid"));
+        let root = relative.default_root_in(Path::new("/plans"));
+        assert_eq!(
+            relative.join("../shared/base.lusid", &root).unwrap(),
+            PlanId::Path(PathBuf::from("shared/base.lusid"))
+        );
+    }
+}
@@ -0,0 +1,85 @@
+use std::fmt::{self, Display};
+
+use lusid_params::{ParamTypes, SourceRegistry};
+use lusid_store::Store;
+use rimu::Spanned;
+
+use crate::{load::load, model::Plan, read_plan_source, PlanError, PlanId};
+
+/// What a plan says about itself, read without evaluating its setup.
+#[derive(Debug, Clone)]
+pub struct PlanInfo {
+    pub name: Option<String>,
+    pub version: Option<String>,
+    pub params: Option<ParamTypes>,
+}
+
+impl From<Plan> for PlanInfo {
+    fn from(plan: Plan) -> Self {
+        let Plan {
+            name,
+            version,
+            params,
+            setup: _,
+        } = plan;
+        PlanInfo {
+            name: name.map(|name| name.into_inner().0),
+            version: version.map(|version| version.into_inner().0),
+            params: params.map(Spanned::into_inner),
+        }
+    }
+}
+
+impl Display for PlanInfo {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let unset = "(none)";
+        writeln!(f, "name: {}", self.name.as_deref().unwrap_or(unset))?;
+        writeln!(f, "version: {}", self.version.as_deref().unwrap_or(unset))?;
+        match &self.params {
+            None => writeln!(f, "params: {unset}"),
+            Some(params) => {
+                writeln!(f, "params:")?;
+                for line in params.to_rimu_source().lines() {
+                    writeln!(f, "  {line}")?;
+                }
+                Ok(())
+            }
+        }
+    }
+}
+
+/// Load a plan and describe it, without validating params or running setup.
+pub async fn plan_info(
+    plan_id: PlanId,
+    store: &mut Store,
+    sources: &mut SourceRegistry,
+) -> Result<PlanInfo, PlanError> {
+    let code = read_plan_source(&plan_id, store, sources).await?;
+    let plan = load(&code, &plan_id)?;
+    Ok(PlanInfo::from(plan.into_inner()))
+}
+
+#[cfg(test)]
+mod tests {
+    use std::path::PathBuf;
+
+    use super::*;
+
+    #[test]
+    fn simple_example_info() {
+        let code = include_str!("../../examples/simple.lusid");
+        let plan_id = PlanId::Path(PathBuf::from("simple.lusid"));
+        let info = PlanInfo::from(load(code, &plan_id).unwrap().into_inner());
+
+        assert_eq!(
+            info.to_string(),
+            "\
+name: simple
+version: 0.1.0
+params:
+  whatever:
+    type: boolean
+"
+        );
+    }
+}
@@ -1,19 +1,25 @@
 use displaydoc::Display;
-use lusid_params::{validate, ParamValues, ParamsValidationError};
-use lusid_resource::ResourceParams;
+use lusid_params::{validate, Diagnostic, ParamValues, ParamsValidationError, SourceRegistry};
+use lusid_resource::{ResourceParamError, ResourceParams};
 use lusid_store::{Store, StoreError, StoreItemId};
-use rimu::Spanned;
-use std::{path::PathBuf, string::FromUtf8Error};
+use rimu::{SourceId, Spanned};
+use std::{
+    path::{Path, PathBuf},
+    string::FromUtf8Error,
+};
 use thiserror::Error;
+use url::Url;
 
 mod core;
 mod eval;
 mod id;
+mod info;
 mod load;
 mod model;
 mod tree;
 
-pub use crate::id::{PlanId, PlanNodeId};
+pub use crate::id::{PlanId, PlanIdJoinError, PlanNodeId};
+pub use crate::info::*;
 pub use crate::tree::*;
 use crate::{
     core::{core_module, is_core_module},
@@ -31,12 +37,28 @@ pub enum PlanError {
         source: StoreError,
     },
 
+    /// Plans from git are not supported yet: {url}
+    UnsupportedGitPlan { url: Url },
+
+    /// Inline plan {id} has no store item
+    InlineNotInStore { id: String },
+
     /// Failed to decode plan source as UTF-8
     InvalidUtf8(#[from] FromUtf8Error),
 
     /// Failed to load plan source
     Load(#[from] LoadError),
 
+    /// Plan version \"{version}\" is not a valid semver requirement
+    InvalidVersion {
+        version: String,
+        #[source]
+        source: semver::Error,
+    },
+
+    /// Plan requires version {required}, but this is version {current}
+    IncompatibleVersion { required: String, current: String },
+
     /// Parameter validation failed
     Validate(#[from] ParamsValidationError),
 
@@ -47,16 +69,67 @@ pub enum PlanError {
     PlanItemToResource(#[from] PlanItemToResourceError),
 }
 
+impl PlanError {
+    /// Located diagnostics, for errors that point into plan or params source.
+    pub fn diagnostics(&self) -> Vec<Diagnostic> {
+        match self {
+            PlanError::Load(LoadError::PlanFromRimu(error)) => {
+                let (error, span) = error.as_ref().clone().take();
+                vec![Diagnostic::new(error.to_string(), span)]
+            }
+            PlanError::Load(error @ LoadError::DuplicateKey { span, .. }) => {
+                vec![Diagnostic::new(error.to_string(), span.clone())]
+            }
+            PlanError::Validate(error)
+            | PlanError::PlanItemToResource(PlanItemToResourceError::ParamsValidation(error)) => {
+                error.diagnostics()
+            }
+            PlanError::PlanItemToResource(PlanItemToResourceError::PlanSubtree(error)) => {
+                error.diagnostics()
+            }
+            _ => Vec::new(),
+        }
+    }
+}
+
 /// Top-level planning routine: load plan, validate parameters, and evaluate to
 /// a CausalityTree<Resource>.
-#[tracing::instrument(skip_all)]
+///
+/// Includes must stay within the plan's [default root](PlanId::default_root).
+/// Plans that declare a version must be compatible with `tool_version`, the
+/// version of lusid running them.
 pub async fn plan(
     plan_id: PlanId,
     param_values: Option<Spanned<ParamValues>>,
+    tool_version: &semver::Version,
+    store: &mut Store,
+    sources: &mut SourceRegistry,
+) -> Result<PlanTree<ResourceParams>, PlanError> {
+    let root = plan_id.default_root();
+    plan_within(plan_id, &root, param_values, tool_version, store, sources).await
+}
+
+/// Like [`plan`], but with includes allowed anywhere within `root`, such as a
+/// directory of plans shared between hosts.
+#[tracing::instrument(skip_all)]
+pub async fn plan_within(
+    plan_id: PlanId,
+    root: &Path,
+    param_values: Option<Spanned<ParamValues>>,
+    tool_version: &semver::Version,
     store: &mut Store,
+    sources: &mut SourceRegistry,
 ) -> Result<PlanTree<ResourceParams>, PlanError> {
-    tracing::debug!("Plan {plan_id:?} with params {param_values:?}");
-    let children = plan_recursive(plan_id, param_values.as_ref(), store).await?;
+    tracing::debug!("Plan {plan_id:?} within {root:?} with params {param_values:?}");
+    let children = plan_recursive(
+        plan_id,
+        param_values.as_ref(),
+        root,
+        tool_version,
+        store,
+        sources,
+    )
+    .await?;
     let tree = PlanTree::Branch {
         children,
         meta: PlanMeta::default(),
@@ -65,36 +138,68 @@ pub async fn plan(
     Ok(tree)
 }
 
+/// Read a plan's source, registering it for diagnostics.
+async fn read_plan_source(
+    plan_id: &PlanId,
+    store: &mut Store,
+    sources: &mut SourceRegistry,
+) -> Result<String, PlanError> {
+    let code = match plan_id {
+        PlanId::Inline { source, .. } => source.clone(),
+        _ => {
+            let store_item_id = StoreItemId::try_from(plan_id.clone())?;
+            let bytes =
+                store
+                    .read(&store_item_id)
+                    .await
+                    .map_err(|source| PlanError::StoreRead {
+                        id: store_item_id.clone(),
+                        source,
+                    })?;
+            String::from_utf8(bytes)?
+        }
+    };
+    sources.insert(&SourceId::from(plan_id.clone()), code.as_str());
+    Ok(code)
+}
+
 async fn plan_recursive(
     plan_id: PlanId,
     param_values: Option<&Spanned<ParamValues>>,
+    root: &Path,
+    tool_version: &semver::Version,
     store: &mut Store,
+    sources: &mut SourceRegistry,
 ) -> Result<Vec<PlanTree<ResourceParams>>, PlanError> {
-    let store_item_id: StoreItemId = plan_id.clone().into();
-    let bytes = store
-        .read(&store_item_id)
-        .await
-        .map_err(|source| PlanError::StoreRead {
-            id: store_item_id.clone(),
-            source,
-        })?;
-    let code = String::from_utf8(bytes)?;
+    let code = read_plan_source(&plan_id, store, sources).await?;
     let plan = load(&code, &plan_id)?;
 
     let Plan {
         name: _,
-        version: _,
+        version,
         params: param_types,
         setup,
     } = plan.into_inner();
 
-    validate(param_types.as_ref(), param_values)?;
+    if let Some(version) = version {
+        version.inner().check_compatible(tool_version)?;
+    }
+
+    let param_values = validate(param_types.as_ref(), param_values)?;
 
-    let plan_items = evaluate(setup, param_values.cloned())?;
+    let plan_items = evaluate(setup, param_values)?;
 
     let mut resources = Vec::with_capacity(plan_items.len());
     for plan_item in plan_items {
-        let node = Box::pin(plan_item_to_resource(plan_item, &plan_id, store)).await?;
+        let node = Box::pin(plan_item_to_resource(
+            plan_item,
+            &plan_id,
+            root,
+            tool_version,
+            store,
+            sources,
+        ))
+        .await?;
         resources.push(node);
     }
 
@@ -112,8 +217,14 @@ pub enum PlanItemToResourceError {
     /// Failed to convert parameter values to resource params
     SerdeValue(#[from] rimu::SerdeValueError),
 
-    /// Unsupported core module id \"{id}\"
-    UnsupportedCoreModuleId { id: String },
+    /// Invalid resource parameters: {0}
+    InvalidParams(#[from] ResourceParamError),
+
+    /// Unsupported core module id \"{id}\", expected one of: {supported}
+    UnsupportedCoreModuleId { id: String, supported: String },
+
+    /// Invalid include
+    Include(#[from] PlanIdJoinError),
 
     /// Failed to compute subtree for nested plan
     PlanSubtree(#[from] Box<PlanError>),
@@ -122,7 +233,10 @@ pub enum PlanItemToResourceError {
 async fn plan_item_to_resource(
     plan_item: Spanned<crate::model::PlanItem>,
     current_plan_id: &PlanId,
+    root: &Path,
+    tool_version: &semver::Version,
     store: &mut Store,
+    sources: &mut SourceRegistry,
 ) -> Result<PlanTree<ResourceParams>, PlanItemToResourceError> {
     let (plan_item, _span) = plan_item.take();
     let crate::model::PlanItem {
@@ -162,13 +276,259 @@ async fn plan_item_to_resource(
         })
     } else {
         let path = PathBuf::from(module.inner());
-        let plan_id = current_plan_id.join(path);
-        let children = plan_recursive(plan_id, param_values.as_ref(), store)
-            .await
-            .map_err(Box::new)?;
+        let plan_id = current_plan_id.join(path, root)?;
+        let children = plan_recursive(
+            plan_id,
+            param_values.as_ref(),
+            root,
+            tool_version,
+            store,
+            sources,
+        )
+        .await
+        .map_err(Box::new)?;
         Ok(PlanTree::Branch {
             meta: PlanMeta { id, before, after },
             children,
         })
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn tool_version() -> semver::Version {
+        semver::Version::new(0, 1, 0)
+    }
+
+    async fn plan_file(contents: Option<&[u8]>) -> Result<PlanTree<ResourceParams>, PlanError> {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("plan.lusid");
+        if let Some(contents) = contents {
+            tokio::fs::write(&path, contents).await.unwrap();
+        }
+        let mut store = Store::new(dir.path());
+        plan(
+            PlanId::Path(path),
+            None,
+            &tool_version(),
+            &mut store,
+            &mut SourceRegistry::new(),
+        )
+        .await
+    }
+
+    #[tokio::test]
+    async fn missing_plan_is_an_error() {
+        let result = plan_file(None).await;
+        assert!(matches!(result, Err(PlanError::StoreRead { .. })));
+    }
+
+    #[tokio::test]
+    async fn non_utf8_plan_is_an_error() {
+        let result = plan_file(Some(&[0xff, 0xfe, 0x00])).await;
+        assert!(matches!(result, Err(PlanError::InvalidUtf8(_))));
+    }
+
+    #[tokio::test]
+    async fn plan_from_inline_source() {
+        let dir = tempfile::tempdir().unwrap();
+        let plan_id = PlanId::Inline {
+            id: "inline.lusid".to_string(),
+            source: "\
+name: \"inline\"
+setup: () =>
+  - module: \"@core/apt\"
+    id: \"git\"
+    params:
+      package: \"git\"
+"
+            .to_string(),
+        };
+        let mut sources = SourceRegistry::new();
+
+        let tree = plan(
+            plan_id,
+            None,
+            &tool_version(),
+            &mut Store::new(dir.path()),
+            &mut sources,
+        )
+        .await
+        .unwrap();
+
+        let PlanTree::Branch { children, .. } = tree else {
+            panic!("expected a branch");
+        };
+        let [PlanTree::Leaf { node, meta }] = children.as_slice() else {
+            panic!("expected one leaf");
+        };
+        assert_eq!(node.to_string(), "Apt(package = git)");
+        assert_eq!(
+            meta.id.as_ref().and_then(PlanNodeId::plan_item_id),
+            Some("git")
+        );
+        assert!(sources
+            .get(&SourceId::from("inline.lusid".to_string()))
+            .is_some());
+    }
+
+    #[tokio::test]
+    async fn omitted_params_take_their_default() {
+        let dir = tempfile::tempdir().unwrap();
+        let plan_id = PlanId::Inline {
+            id: "inline.lusid".to_string(),
+            source: "\
+name: \"inline\"
+params:
+  package:
+    type: \"string\"
+    default: \"git\"
+setup: (params) =>
+  - module: \"@core/apt\"
+    params:
+      package: params.package
+"
+            .to_string(),
+        };
+        let param_values =
+            ParamValues::from_type(serde_json::json!({}), SourceId::from("params".to_string()))
+                .unwrap();
+
+        let tree = plan(
+            plan_id,
+            Some(param_values),
+            &tool_version(),
+            &mut Store::new(dir.path()),
+            &mut SourceRegistry::new(),
+        )
+        .await
+        .unwrap();
+
+        let PlanTree::Branch { children, .. } = tree else {
+            panic!("expected a branch");
+        };
+        let [PlanTree::Leaf { node, .. }] = children.as_slice() else {
+            panic!("expected one leaf");
+        };
+        assert_eq!(node.to_string(), "Apt(package = git)");
+    }
+
+    #[tokio::test]
+    async fn includes_stay_within_root() {
+        let dir = tempfile::tempdir().unwrap();
+        for (path, source) in [
+            (
+                "shared/base.lusid",
+                "\
+name: \"base\"
+setup: () =>
+  - module: \"@core/apt\"
+    params:
+      package: \"git\"
+",
+            ),
+            (
+                "hosts/web.lusid",
+                "\
+name: \"web\"
+setup: () =>
+  - module: \"../shared/base.lusid\"
+",
+            ),
+        ] {
+            let path = dir.path().join(path);
+            std::fs::create_dir_all(path.parent().unwrap()).unwrap();
+            std::fs::write(path, source).unwrap();
+        }
+        let plan_id = PlanId::Path(dir.path().join("hosts/web.lusid"));
+        let mut store = Store::new(dir.path());
+
+        let result = plan(
+            plan_id.clone(),
+            None,
+            &tool_version(),
+            &mut store,
+            &mut SourceRegistry::new(),
+        )
+        .await;
+        assert!(
+            result.is_err(),
+            "included from outside the plan's directory"
+        );
+
+        let tree = plan_within(
+            plan_id,
+            dir.path(),
+            None,
+            &tool_version(),
+            &mut store,
+            &mut SourceRegistry::new(),
+        )
+        .await
+        .unwrap();
+        let PlanTree::Branch { children, .. } = tree else {
+            panic!("expected a branch");
+        };
+        let [PlanTree::Branch { children, .. }] = children.as_slice() else {
+            panic!("expected the included plan");
+        };
+        let [PlanTree::Leaf { node, .. }] = children.as_slice() else {
+            panic!("expected one leaf");
+        };
+        assert_eq!(node.to_string(), "Apt(package = git)");
+    }
+
+    #[tokio::test]
+    async fn checks_version_against_given_tool_version() {
+        let dir = tempfile::tempdir().unwrap();
+        let plan_id = PlanId::Inline {
+            id: "inline.lusid".to_string(),
+            source: "\
+name: \"inline\"
+version: \"2.x\"
+setup: () => []
+"
+            .to_string(),
+        };
+        let plan_with = |version: semver::Version| {
+            let plan_id = plan_id.clone();
+            let dir = dir.path().to_path_buf();
+            async move {
+                plan(
+                    plan_id,
+                    None,
+                    &version,
+                    &mut Store::new(&dir),
+                    &mut SourceRegistry::new(),
+                )
+                .await
+            }
+        };
+
+        assert!(matches!(
+            plan_with(semver::Version::new(1, 9, 0)).await,
+            Err(PlanError::IncompatibleVersion { ref current, .. }) if current == "1.9.0"
+        ));
+        assert!(plan_with(semver::Version::new(2, 1, 0)).await.is_ok());
+    }
+
+    #[tokio::test]
+    async fn git_plan_is_an_error() {
+        let dir = tempfile::tempdir().unwrap();
+        let plan_id = PlanId::Git(
+            Url::parse("https://example.com/plans.git").unwrap(),
+            PathBuf::from("plan.lusid"),
+        );
+        let result = plan(
+            plan_id,
+            None,
+            &tool_version(),
+            &mut Store::new(dir.path()),
+            &mut SourceRegistry::new(),
+        )
+        .await;
+        assert!(matches!(result, Err(PlanError::UnsupportedGitPlan { .. })));
+    }
+}
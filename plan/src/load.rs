@@ -1,9 +1,9 @@
 //! Load Rimu source into a Plan (spanned).
 
-use std::{cell::RefCell, rc::Rc};
+use std::{cell::RefCell, collections::HashSet, rc::Rc};
 
 use displaydoc::Display;
-use rimu::Spanned;
+use rimu::{Block, Expression, Span, Spanned, SpannedBlock, SpannedExpression};
 use rimu_interop::FromRimu;
 use thiserror::Error;
 
@@ -18,6 +18,8 @@ pub enum LoadError {
     RimuParse(Vec<rimu::ParseError>),
     /// No code found in source
     NoCode,
+    /// Key "{key}" is given more than once
+    DuplicateKey { key: String, span: Span },
     /// Evaluating Rimu AST failed
     RimuEval(#[from] Box<rimu::EvalError>),
     /// Failed to convert Rimu value into Plan
@@ -33,6 +35,8 @@ pub fn load(code: &str, plan_id: &PlanId) -> Result<Spanned<Plan>, LoadError> {
     let Some(ast) = ast else {
         return Err(LoadError::NoCode);
     };
+    // Evaluating keeps only the last of a repeated key, so check the source.
+    check_block_keys(&ast)?;
 
     let env = Rc::new(RefCell::new(rimu::Environment::new()));
     let value = rimu::evaluate(&ast, env).map_err(Box::new)?;
@@ -40,3 +44,150 @@ pub fn load(code: &str, plan_id: &PlanId) -> Result<Spanned<Plan>, LoadError> {
         Plan::from_rimu_spanned(value).map_err(|error| LoadError::PlanFromRimu(Box::new(error)))?;
     Ok(plan)
 }
+
+/// Fail on the first object, anywhere in `block`, that repeats a key.
+fn check_block_keys(block: &SpannedBlock) -> Result<(), LoadError> {
+    match block.inner() {
+        Block::Expression(expression) => check_expression_keys_inner(expression),
+        Block::List(items) => items.iter().try_for_each(check_block_keys),
+        Block::Object(entries) => {
+            check_unique_keys(entries.iter().map(|(key, _)| key))?;
+            entries
+                .iter()
+                .try_for_each(|(_, value)| check_block_keys(value))
+        }
+        Block::Function { args: _, body } => check_block_keys(body),
+        Block::Call { function, args } => {
+            check_expression_keys(function)?;
+            check_block_keys(args)
+        }
+        Block::Let { variables, body } => {
+            for (_, value) in variables {
+                check_block_keys(value)?;
+            }
+            check_block_keys(body)
+        }
+        Block::If {
+            condition,
+            consequent,
+            alternative,
+        } => {
+            check_block_keys(condition)?;
+            for branch in [consequent, alternative].into_iter().flatten() {
+                check_block_keys(branch)?;
+            }
+            Ok(())
+        }
+    }
+}
+
+fn check_expression_keys(expression: &SpannedExpression) -> Result<(), LoadError> {
+    check_expression_keys_inner(expression.inner())
+}
+
+fn check_expression_keys_inner(expression: &Expression) -> Result<(), LoadError> {
+    match expression {
+        Expression::Null
+        | Expression::Boolean(_)
+        | Expression::String(_)
+        | Expression::Number(_)
+        | Expression::Identifier(_)
+        | Expression::Error => Ok(()),
+        Expression::List(items) => items.iter().try_for_each(check_expression_keys),
+        Expression::Object(entries) => {
+            check_unique_keys(entries.iter().map(|(key, _)| key))?;
+            entries
+                .iter()
+                .try_for_each(|(_, value)| check_expression_keys(value))
+        }
+        Expression::Function { args: _, body } => check_expression_keys(body),
+        Expression::Unary { right, operator: _ } => check_expression_keys(right),
+        Expression::Binary {
+            left,
+            right,
+            operator: _,
+        } => {
+            check_expression_keys(left)?;
+            check_expression_keys(right)
+        }
+        Expression::Call { function, args } => {
+            check_expression_keys(function)?;
+            args.iter().try_for_each(check_expression_keys)
+        }
+        Expression::GetIndex { container, index } => {
+            check_expression_keys(container)?;
+            check_expression_keys(index)
+        }
+        Expression::GetKey { container, key: _ } => check_expression_keys(container),
+        Expression::GetSlice {
+            container,
+            start,
+            end,
+        } => {
+            check_expression_keys(container)?;
+            for bound in [start, end].into_iter().flatten() {
+                check_expression_keys(bound)?;
+            }
+            Ok(())
+        }
+    }
+}
+
+fn check_unique_keys<'a>(keys: impl Iterator<Item = &'a Spanned<String>>) -> Result<(), LoadError> {
+    let mut seen = HashSet::new();
+    for key in keys {
+        if !seen.insert(key.inner()) {
+            return Err(LoadError::DuplicateKey {
+                key: key.inner().clone(),
+                span: key.span(),
+            });
+        }
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn load_source(code: &str) -> Result<Spanned<Plan>, LoadError> {
+        load(
+            code,
+            &PlanId::Inline {
+                id: "inline.lusid".to_string(),
+                source: code.to_string(),
+            },
+        )
+    }
+
+    #[test]
+    fn rejects_repeated_key() {
+        let code = "\
+name: \"inline\"
+params:
+  package:
+    type: \"string\"
+  package:
+    type: \"number\"
+setup: (params) => []
+";
+        let Err(LoadError::DuplicateKey { key, span }) = load_source(code) else {
+            panic!("expected a duplicate key error");
+        };
+        assert_eq!(key, "package");
+        assert_eq!(&code[span.range()], "package");
+        assert_eq!(span.start(), code.rfind("package:").unwrap());
+    }
+
+    #[test]
+    fn rejects_repeated_key_within_expression() {
+        let code = "\
+name: \"inline\"
+setup: () => [{ module: \"@core/apt\", module: \"@core/file\" }]
+";
+        assert!(matches!(
+            load_source(code),
+            Err(LoadError::DuplicateKey { key, .. }) if key == "module"
+        ));
+    }
+}
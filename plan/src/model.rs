@@ -6,6 +6,8 @@ use rimu::{Function, Span, Spanned, Value};
 use rimu_interop::FromRimu;
 use thiserror::Error;
 
+use crate::PlanError;
+
 #[derive(Debug, Clone)]
 pub struct Name(pub String);
 
@@ -35,6 +37,30 @@ pub enum VersionFromRimuError {
     NotAString,
 }
 
+impl Version {
+    /// Check `current` satisfies this version, read as a semver requirement:
+    /// "0.1" or "0.1.0" accept any compatible 0.1 release, "2.x" any 2 release.
+    /// A blank version accepts anything.
+    pub fn check_compatible(&self, current: &semver::Version) -> Result<(), PlanError> {
+        let required = self.0.trim();
+        if required.is_empty() {
+            return Ok(());
+        }
+        let requirement =
+            semver::VersionReq::parse(required).map_err(|source| PlanError::InvalidVersion {
+                version: required.to_string(),
+                source,
+            })?;
+        if !requirement.matches(current) {
+            return Err(PlanError::IncompatibleVersion {
+                required: required.to_string(),
+                current: current.to_string(),
+            });
+        }
+        Ok(())
+    }
+}
+
 impl FromRimu for Version {
     type Error = VersionFromRimuError;
 
@@ -259,3 +285,38 @@ impl FromRimu for Plan {
         })
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn check(version: &str, current: &str) -> Result<(), PlanError> {
+        Version(version.to_string()).check_compatible(&semver::Version::parse(current).unwrap())
+    }
+
+    #[test]
+    fn compatible_version() {
+        assert!(check("0.1.0", "0.1.3").is_ok());
+        assert!(check("2.x", "2.4.0").is_ok());
+    }
+
+    #[test]
+    fn incompatible_version() {
+        let error = check("2.x", "1.9.0").unwrap_err();
+        assert!(matches!(
+            error,
+            PlanError::IncompatibleVersion { ref required, ref current }
+                if required == "2.x" && current == "1.9.0"
+        ));
+        assert!(matches!(
+            check("not a version", "1.0.0"),
+            Err(PlanError::InvalidVersion { .. })
+        ));
+    }
+
+    #[test]
+    fn blank_version_is_compatible() {
+        assert!(check("", "1.0.0").is_ok());
+        assert!(check("  ", "1.0.0").is_ok());
+    }
+}
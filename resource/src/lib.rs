@@ -1,5 +1,6 @@
 use std::fmt::Display;
 
+pub use crate::registry::*;
 pub use crate::resources::*;
 
 use async_trait::async_trait;
@@ -11,10 +12,15 @@ use rimu::Spanned;
 use serde::de::DeserializeOwned;
 use thiserror::Error;
 
+mod registry;
 mod resources;
 
 use crate::resources::apt::AptParams;
 use crate::resources::apt::{Apt, AptChange, AptResource, AptState};
+use crate::resources::command::{
+    Command, CommandChange, CommandParams, CommandResource, CommandState,
+};
+use crate::resources::file::{File, FileChange, FileParams, FileResource, FileState};
 
 /// ResourceType:
 /// - ParamTypes for Rimu schema
@@ -35,6 +41,12 @@ pub trait ResourceType {
     /// Resource atom (indivisible system definition).
     type Resource: Render;
 
+    /// Check params make sense together, beyond what [`ResourceType::param_types`]
+    /// can express. Called after schema validation.
+    fn validate_params(_params: &Self::Params) -> Result<(), ResourceParamError> {
+        Ok(())
+    }
+
     /// Create resource atom from params.
     fn resources(params: Self::Params) -> Vec<CausalityTree<Self::Resource>>;
 
@@ -53,13 +65,42 @@ pub trait ResourceType {
     /// Get change atomic resource from current state to intended state.
     fn change(resource: &Self::Resource, state: &Self::State) -> Option<Self::Change>;
 
+    /// Whether a change would leave the machine as it is, so needs no operations.
+    ///
+    /// Prefer returning `None` from [`ResourceType::change`] when the state
+    /// already matches; this is for changes that can only tell afterwards.
+    fn is_noop(_change: &Self::Change) -> bool {
+        false
+    }
+
+    /// Why a change is needed: what the machine has, what the resource wants,
+    /// and what will be done about it.
+    fn explain(resource: &Self::Resource, state: &Self::State, change: &Self::Change) -> String;
+
     // Convert atomic resource change into operations (mutations).
     fn operations(change: Self::Change) -> Vec<CausalityTree<Operation>>;
 }
 
+/// Params that fit the schema, but contradict themselves.
+#[derive(Error, Debug, Clone, PartialEq, Eq)]
+#[error("{message}")]
+pub struct ResourceParamError {
+    pub message: String,
+}
+
+impl ResourceParamError {
+    pub fn new(message: impl Into<String>) -> Self {
+        Self {
+            message: message.into(),
+        }
+    }
+}
+
 #[derive(Debug, Clone)]
 pub enum ResourceParams {
     Apt(AptParams),
+    Command(CommandParams),
+    File(FileParams),
 }
 
 impl Display for ResourceParams {
@@ -67,6 +108,8 @@ impl Display for ResourceParams {
         use ResourceParams::*;
         match self {
             Apt(apt) => apt.fmt(f),
+            Command(command) => command.fmt(f),
+            File(file) => file.fmt(f),
         }
     }
 }
@@ -74,6 +117,8 @@ impl Display for ResourceParams {
 #[derive(Debug, Clone)]
 pub enum Resource {
     Apt(AptResource),
+    Command(CommandResource),
+    File(FileResource),
 }
 
 impl Display for Resource {
@@ -81,6 +126,8 @@ impl Display for Resource {
         use Resource::*;
         match self {
             Apt(apt) => apt.fmt(f),
+            Command(command) => command.fmt(f),
+            File(file) => file.fmt(f),
         }
     }
 }
@@ -88,6 +135,8 @@ impl Display for Resource {
 #[derive(Debug, Clone)]
 pub enum ResourceState {
     Apt(AptState),
+    Command(CommandState),
+    File(FileState),
 }
 
 impl Display for ResourceState {
@@ -95,6 +144,8 @@ impl Display for ResourceState {
         use ResourceState::*;
         match self {
             Apt(apt) => apt.fmt(f),
+            Command(command) => command.fmt(f),
+            File(file) => file.fmt(f),
         }
     }
 }
@@ -103,11 +154,19 @@ impl Display for ResourceState {
 pub enum ResourceStateError {
     #[error("apt state error: {0}")]
     Apt(#[from] <Apt as ResourceType>::StateError),
+
+    #[error("command state error: {0}")]
+    Command(#[from] <Command as ResourceType>::StateError),
+
+    #[error("file state error: {0}")]
+    File(#[from] <File as ResourceType>::StateError),
 }
 
 #[derive(Debug, Clone)]
 pub enum ResourceChange {
     Apt(AptChange),
+    Command(CommandChange),
+    File(FileChange),
 }
 
 impl Display for ResourceChange {
@@ -115,6 +174,8 @@ impl Display for ResourceChange {
         use ResourceChange::*;
         match self {
             Apt(apt) => apt.fmt(f),
+            Command(command) => command.fmt(f),
+            File(file) => file.fmt(f),
         }
     }
 }
@@ -133,6 +194,8 @@ impl ResourceParams {
 
         match self {
             ResourceParams::Apt(params) => typed::<Apt>(params, Resource::Apt),
+            ResourceParams::Command(params) => typed::<Command>(params, Resource::Command),
+            ResourceParams::File(params) => typed::<File>(params, Resource::File),
         }
     }
 }
@@ -151,6 +214,17 @@ impl Resource {
             Resource::Apt(resource) => {
                 typed::<Apt>(resource, ResourceState::Apt, ResourceStateError::Apt).await
             }
+            Resource::Command(resource) => {
+                typed::<Command>(
+                    resource,
+                    ResourceState::Command,
+                    ResourceStateError::Command,
+                )
+                .await
+            }
+            Resource::File(resource) => {
+                typed::<File>(resource, ResourceState::File, ResourceStateError::File).await
+            }
         }
     }
 
@@ -163,12 +237,16 @@ impl Resource {
             R::change(resource, state).map(map)
         }
 
-        // TODO (mw): remove #[allow(unreachable_patterns)] once we have more resources
-        #[allow(unreachable_patterns)]
         match (self, state) {
             (Resource::Apt(resource), ResourceState::Apt(state)) => {
                 typed::<Apt>(resource, state, ResourceChange::Apt)
             }
+            (Resource::Command(resource), ResourceState::Command(state)) => {
+                typed::<Command>(resource, state, ResourceChange::Command)
+            }
+            (Resource::File(resource), ResourceState::File(state)) => {
+                typed::<File>(resource, state, ResourceChange::File)
+            }
             _ => {
                 // Programmer error, should never happen, or if it does should be immediately obvious.
                 panic!("Unmatched resource and state")
@@ -177,10 +255,37 @@ impl Resource {
     }
 }
 
+impl Resource {
+    /// Explain `change`, as made from this resource in `state`.
+    pub fn explain(&self, state: &ResourceState, change: &ResourceChange) -> String {
+        match (self, state, change) {
+            (Resource::Apt(resource), ResourceState::Apt(state), ResourceChange::Apt(change)) => {
+                Apt::explain(resource, state, change)
+            }
+            (
+                Resource::Command(resource),
+                ResourceState::Command(state),
+                ResourceChange::Command(change),
+            ) => Command::explain(resource, state, change),
+            (
+                Resource::File(resource),
+                ResourceState::File(state),
+                ResourceChange::File(change),
+            ) => File::explain(resource, state, change),
+            _ => {
+                // Programmer error, should never happen, or if it does should be immediately obvious.
+                panic!("Unmatched resource, state, and change")
+            }
+        }
+    }
+}
+
 impl ResourceChange {
     pub fn operations(self) -> Vec<CausalityTree<Operation>> {
         match self {
             ResourceChange::Apt(change) => Apt::operations(change),
+            ResourceChange::Command(change) => Command::operations(change),
+            ResourceChange::File(change) => File::operations(change),
         }
     }
 }
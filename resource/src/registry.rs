@@ -0,0 +1,474 @@
+use std::{
+    any::Any,
+    error::Error as StdError,
+    fmt::{Debug, Display},
+    marker::PhantomData,
+};
+
+use async_trait::async_trait;
+use indexmap::IndexMap;
+use lusid_causality::CausalityTree;
+use lusid_operation::Operation;
+use lusid_params::{validate, ParamTypes, ParamValues, ParamsValidationError};
+use rimu::{SerdeValueError, Spanned};
+use thiserror::Error;
+
+use crate::{
+    resources::{apt::Apt, command::Command, file::File},
+    ResourceParamError, ResourceType,
+};
+
+/// What a [`DynValue`] can hold: any params, resource, state, or change.
+trait ErasedValue: Any + Debug + Display + Send + Sync {
+    fn as_any(&self) -> &dyn Any;
+    fn into_any(self: Box<Self>) -> Box<dyn Any>;
+    fn clone_box(&self) -> Box<dyn ErasedValue>;
+}
+
+impl<T> ErasedValue for T
+where
+    T: Any + Debug + Display + Clone + Send + Sync,
+{
+    fn as_any(&self) -> &dyn Any {
+        self
+    }
+
+    fn into_any(self: Box<Self>) -> Box<dyn Any> {
+        self
+    }
+
+    fn clone_box(&self) -> Box<dyn ErasedValue> {
+        Box::new(self.clone())
+    }
+}
+
+/// A params, resource, state, or change value of some resource type, with
+/// its type erased, tagged with the id of the resource type it belongs to.
+pub struct DynValue {
+    resource_type: &'static str,
+    value: Box<dyn ErasedValue>,
+}
+
+impl DynValue {
+    pub fn new<T>(resource_type: &'static str, value: T) -> Self
+    where
+        T: Any + Debug + Display + Clone + Send + Sync,
+    {
+        Self {
+            resource_type,
+            value: Box::new(value),
+        }
+    }
+
+    /// Id of the resource type this value belongs to.
+    pub fn resource_type(&self) -> &'static str {
+        self.resource_type
+    }
+
+    pub fn downcast_ref<T: Any>(&self) -> Option<&T> {
+        self.value.as_any().downcast_ref()
+    }
+
+    pub fn downcast<T: Any>(self) -> Result<T, Self> {
+        if self.downcast_ref::<T>().is_none() {
+            return Err(self);
+        }
+        Ok(*self
+            .value
+            .into_any()
+            .downcast()
+            .expect("type was just checked"))
+    }
+}
+
+impl Clone for DynValue {
+    fn clone(&self) -> Self {
+        Self {
+            resource_type: self.resource_type,
+            value: self.value.clone_box(),
+        }
+    }
+}
+
+impl Debug for DynValue {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        Debug::fmt(&self.value, f)
+    }
+}
+
+impl Display for DynValue {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        Display::fmt(&self.value, f)
+    }
+}
+
+#[derive(Error, Debug)]
+pub enum ResourceRegistryError {
+    #[error("no resource type registered with id: {id}")]
+    UnknownType { id: String },
+
+    #[error("missing params")]
+    MissingParams,
+
+    #[error(transparent)]
+    ParamsValidation(#[from] ParamsValidationError),
+
+    #[error(transparent)]
+    SerdeValue(#[from] SerdeValueError),
+
+    #[error(transparent)]
+    InvalidParams(#[from] ResourceParamError),
+
+    #[error("{resource_type} state error: {source}")]
+    State {
+        resource_type: &'static str,
+        source: Box<dyn StdError + Send + Sync>,
+    },
+}
+
+/// A [`ResourceType`] behind a trait object, taking and giving [`DynValue`]s.
+#[async_trait]
+pub trait DynResourceType: Send + Sync {
+    fn id(&self) -> &'static str;
+
+    fn param_types(&self) -> Option<Spanned<ParamTypes>>;
+
+    /// Validate and deserialize params.
+    fn params(&self, param_values: Spanned<ParamValues>)
+        -> Result<DynValue, ResourceRegistryError>;
+
+    fn resources(&self, params: DynValue) -> Vec<CausalityTree<DynValue>>;
+
+    async fn state(&self, resource: &DynValue) -> Result<DynValue, ResourceRegistryError>;
+
+    fn change(&self, resource: &DynValue, state: &DynValue) -> Option<DynValue>;
+
+    fn is_noop(&self, change: &DynValue) -> bool;
+
+    fn explain(&self, resource: &DynValue, state: &DynValue, change: &DynValue) -> String;
+
+    fn operations(&self, change: DynValue) -> Vec<CausalityTree<Operation>>;
+}
+
+struct Registered<R>(PhantomData<fn() -> R>);
+
+fn expect_ref<'a, T: Any>(value: &'a DynValue, id: &'static str) -> &'a T {
+    // Programmer error, should never happen, or if it does should be immediately obvious.
+    value.downcast_ref().unwrap_or_else(|| {
+        panic!(
+            "{} value given to {id} resource type",
+            value.resource_type()
+        )
+    })
+}
+
+fn expect<T: Any>(value: DynValue, id: &'static str) -> T {
+    value.downcast().unwrap_or_else(|value| {
+        panic!(
+            "{} value given to {id} resource type",
+            value.resource_type()
+        )
+    })
+}
+
+#[async_trait]
+impl<R> DynResourceType for Registered<R>
+where
+    R: ResourceType + 'static,
+    R::Params: Debug + Display + Clone + Send + Sync,
+    R::Resource: Debug + Display + Clone + Send + Sync,
+    R::State: Debug + Display + Clone + Send + Sync,
+    R::Change: Debug + Display + Clone + Send + Sync,
+    R::StateError: StdError + Send + Sync + 'static,
+{
+    fn id(&self) -> &'static str {
+        R::ID
+    }
+
+    fn param_types(&self) -> Option<Spanned<ParamTypes>> {
+        R::param_types()
+    }
+
+    fn params(
+        &self,
+        param_values: Spanned<ParamValues>,
+    ) -> Result<DynValue, ResourceRegistryError> {
+        let param_values =
+            validate(R::param_types().as_ref(), Some(&param_values))?.unwrap_or(param_values);
+        let params: R::Params = param_values.into_inner().into_type()?;
+        R::validate_params(&params)?;
+        Ok(DynValue::new(R::ID, params))
+    }
+
+    fn resources(&self, params: DynValue) -> Vec<CausalityTree<DynValue>> {
+        R::resources(expect(params, R::ID))
+            .into_iter()
+            .map(|tree| tree.map(|resource| DynValue::new(R::ID, resource)))
+            .collect()
+    }
+
+    async fn state(&self, resource: &DynValue) -> Result<DynValue, ResourceRegistryError> {
+        R::state(expect_ref(resource, R::ID))
+            .await
+            .map(|state| DynValue::new(R::ID, state))
+            .map_err(|error| ResourceRegistryError::State {
+                resource_type: R::ID,
+                source: Box::new(error),
+            })
+    }
+
+    fn change(&self, resource: &DynValue, state: &DynValue) -> Option<DynValue> {
+        R::change(expect_ref(resource, R::ID), expect_ref(state, R::ID))
+            .map(|change| DynValue::new(R::ID, change))
+    }
+
+    fn is_noop(&self, change: &DynValue) -> bool {
+        R::is_noop(expect_ref(change, R::ID))
+    }
+
+    fn explain(&self, resource: &DynValue, state: &DynValue, change: &DynValue) -> String {
+        R::explain(
+            expect_ref(resource, R::ID),
+            expect_ref(state, R::ID),
+            expect_ref(change, R::ID),
+        )
+    }
+
+    fn operations(&self, change: DynValue) -> Vec<CausalityTree<Operation>> {
+        R::operations(expect(change, R::ID))
+    }
+}
+
+/// Resource types by id, so new types register in one place rather than in
+/// every match over [`Resource`](crate::Resource) and friends.
+pub struct ResourceRegistry {
+    types: IndexMap<&'static str, Box<dyn DynResourceType>>,
+}
+
+impl Default for ResourceRegistry {
+    /// A registry of the built-in resource types.
+    fn default() -> Self {
+        let mut registry = Self::empty();
+        registry.register::<Apt>();
+        registry.register::<Command>();
+        registry.register::<File>();
+        registry
+    }
+}
+
+impl ResourceRegistry {
+    pub fn empty() -> Self {
+        Self {
+            types: IndexMap::new(),
+        }
+    }
+
+    /// Add a resource type, replacing any registered with the same id.
+    pub fn register<R>(&mut self) -> &mut Self
+    where
+        R: ResourceType + 'static,
+        R::Params: Debug + Display + Clone + Send + Sync,
+        R::Resource: Debug + Display + Clone + Send + Sync,
+        R::State: Debug + Display + Clone + Send + Sync,
+        R::Change: Debug + Display + Clone + Send + Sync,
+        R::StateError: StdError + Send + Sync + 'static,
+    {
+        self.types
+            .insert(R::ID, Box::new(Registered::<R>(PhantomData)));
+        self
+    }
+
+    /// Ids of registered resource types, in registration order.
+    pub fn ids(&self) -> impl Iterator<Item = &'static str> + '_ {
+        self.types.keys().copied()
+    }
+
+    pub fn get(&self, id: &str) -> Result<&dyn DynResourceType, ResourceRegistryError> {
+        self.types
+            .get(id)
+            .map(|resource_type| resource_type.as_ref())
+            .ok_or_else(|| ResourceRegistryError::UnknownType { id: id.to_string() })
+    }
+
+    /// Validate and deserialize params for the resource type `id`.
+    pub fn params(
+        &self,
+        id: &str,
+        param_values: Option<Spanned<ParamValues>>,
+    ) -> Result<DynValue, ResourceRegistryError> {
+        let param_values = param_values.ok_or(ResourceRegistryError::MissingParams)?;
+        self.get(id)?.params(param_values)
+    }
+
+    pub fn resources(
+        &self,
+        params: DynValue,
+    ) -> Result<Vec<CausalityTree<DynValue>>, ResourceRegistryError> {
+        Ok(self.get(params.resource_type())?.resources(params))
+    }
+
+    pub async fn state(&self, resource: &DynValue) -> Result<DynValue, ResourceRegistryError> {
+        self.get(resource.resource_type())?.state(resource).await
+    }
+
+    pub fn change(
+        &self,
+        resource: &DynValue,
+        state: &DynValue,
+    ) -> Result<Option<DynValue>, ResourceRegistryError> {
+        Ok(self.get(resource.resource_type())?.change(resource, state))
+    }
+
+    pub fn is_noop(&self, change: &DynValue) -> Result<bool, ResourceRegistryError> {
+        Ok(self.get(change.resource_type())?.is_noop(change))
+    }
+
+    pub fn operations(
+        &self,
+        change: DynValue,
+    ) -> Result<Vec<CausalityTree<Operation>>, ResourceRegistryError> {
+        Ok(self.get(change.resource_type())?.operations(change))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::convert::Infallible;
+
+    use indexmap::indexmap;
+    use lusid_causality::CausalityMeta;
+    use lusid_params::{ParamField, ParamType};
+    use rimu::{SourceId, Span};
+    use serde::{Deserialize, Serialize};
+
+    use super::*;
+
+    /// Wants a greeting to be set; it never is.
+    struct Greeting;
+
+    #[derive(Debug, Clone, Serialize, Deserialize)]
+    struct GreetingParams {
+        message: String,
+    }
+
+    impl Display for GreetingParams {
+        fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+            write!(f, "Greeting(message = {})", self.message)
+        }
+    }
+
+    #[derive(Debug, Clone, PartialEq, Eq)]
+    struct Message(Option<String>);
+
+    impl Display for Message {
+        fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+            match &self.0 {
+                Some(message) => write!(f, "Message({message})"),
+                None => write!(f, "Message(none)"),
+            }
+        }
+    }
+
+    #[async_trait]
+    impl ResourceType for Greeting {
+        const ID: &'static str = "greeting";
+
+        fn param_types() -> Option<Spanned<ParamTypes>> {
+            let span = Span::new(SourceId::empty(), 0, 0);
+            Some(Spanned::new(
+                ParamTypes::Struct(indexmap! {
+                    "message".to_string() => Spanned::new(ParamField::new(ParamType::String), span.clone()),
+                }),
+                span,
+            ))
+        }
+
+        type Params = GreetingParams;
+        type Resource = Message;
+
+        fn resources(params: Self::Params) -> Vec<CausalityTree<Self::Resource>> {
+            vec![CausalityTree::leaf(
+                CausalityMeta::default(),
+                Message(Some(params.message)),
+            )]
+        }
+
+        type State = Message;
+        type StateError = Infallible;
+        async fn state(_resource: &Self::Resource) -> Result<Self::State, Self::StateError> {
+            Ok(Message(None))
+        }
+
+        type Change = Message;
+        fn change(resource: &Self::Resource, state: &Self::State) -> Option<Self::Change> {
+            (resource != state).then(|| resource.clone())
+        }
+
+        fn is_noop(change: &Self::Change) -> bool {
+            change.0.is_none()
+        }
+
+        fn explain(
+            resource: &Self::Resource,
+            state: &Self::State,
+            _change: &Self::Change,
+        ) -> String {
+            format!("greeting is {state}, want {resource}")
+        }
+
+        fn operations(_change: Self::Change) -> Vec<CausalityTree<Operation>> {
+            Vec::new()
+        }
+    }
+
+    #[tokio::test]
+    async fn drives_registered_type() {
+        let mut registry = ResourceRegistry::default();
+        registry.register::<Greeting>();
+        assert_eq!(
+            registry.ids().collect::<Vec<_>>(),
+            vec!["apt", "command", "file", "greeting"]
+        );
+
+        let param_values = ParamValues::from_type(
+            GreetingParams {
+                message: "hello".to_string(),
+            },
+            SourceId::empty(),
+        )
+        .unwrap();
+        let params = registry.params("greeting", Some(param_values)).unwrap();
+        assert_eq!(params.to_string(), "Greeting(message = hello)");
+
+        let resources = registry.resources(params).unwrap();
+        let [CausalityTree::Leaf { node: resource, .. }] = resources.as_slice() else {
+            panic!("expected one resource");
+        };
+        assert_eq!(
+            resource.downcast_ref::<Message>(),
+            Some(&Message(Some("hello".to_string())))
+        );
+
+        let state = registry.state(resource).await.unwrap();
+        let change = registry.change(resource, &state).unwrap().unwrap();
+        assert_eq!(change.resource_type(), "greeting");
+        assert!(!registry.is_noop(&change).unwrap());
+        assert_eq!(
+            registry
+                .get("greeting")
+                .unwrap()
+                .explain(resource, &state, &change),
+            "greeting is Message(none), want Message(hello)"
+        );
+        assert!(registry.operations(change).unwrap().is_empty());
+
+        assert!(matches!(
+            registry.params("greeting", None),
+            Err(ResourceRegistryError::MissingParams)
+        ));
+        assert!(matches!(
+            registry.get("missing"),
+            Err(ResourceRegistryError::UnknownType { .. })
+        ));
+    }
+}
@@ -1,16 +1,21 @@
-use std::fmt::Display;
+use std::{
+    collections::BTreeMap,
+    fmt::Display,
+    time::{Duration, SystemTime},
+};
 
 use async_trait::async_trait;
 use indexmap::indexmap;
 use lusid_causality::{CausalityMeta, CausalityTree};
 use lusid_cmd::{Command, CommandError};
+use lusid_fs::{self as fs, FsError};
 use lusid_operation::{operations::apt::AptOperation, Operation};
 use lusid_params::{ParamField, ParamType, ParamTypes};
 use rimu::{SourceId, Span, Spanned};
 use serde::Deserialize;
 use thiserror::Error;
 
-use crate::ResourceType;
+use crate::{ResourceParamError, ResourceType};
 
 #[derive(Debug, Clone, Deserialize)]
 #[serde(untagged)]
@@ -33,26 +38,174 @@ impl Display for AptParams {
 #[derive(Debug, Clone)]
 pub struct AptResource {
     pub package: String,
+    /// Exact version wanted, from `package=version`. Default: any.
+    pub version: Option<String>,
+}
+
+impl AptResource {
+    /// Parse a package as given to `apt-get install`: `name` or `name=version`.
+    fn parse(package: String) -> Self {
+        match package.split_once('=') {
+            Some((name, version)) => Self {
+                package: name.to_string(),
+                version: Some(version.to_string()),
+            },
+            None => Self {
+                package,
+                version: None,
+            },
+        }
+    }
+
+    fn install_spec(&self) -> String {
+        match &self.version {
+            Some(version) => format!("{}={version}", self.package),
+            None => self.package.clone(),
+        }
+    }
 }
 
 impl Display for AptResource {
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
-        let Self { package } = self;
-        write!(f, "Apt({package})")
+        write!(f, "Apt({})", self.install_spec())
     }
 }
 
+/// `apt-get update` rewrites the package lists, so this changes when it runs.
+const APT_LISTS_DIR: &str = "/var/lib/apt/lists";
+
+/// How long after an `apt-get update` to trust the package index.
+const APT_CACHE_MAX_AGE: Duration = Duration::from_secs(24 * 60 * 60);
+
 #[derive(Debug, Clone)]
 pub enum AptState {
-    NotInstalled,
-    Installed,
+    NotInstalled {
+        /// When the package index was last updated, if known.
+        cache_updated_at: Option<SystemTime>,
+    },
+    Installed {
+        version: String,
+    },
+    /// Installed, but not at the version asked for.
+    WrongVersion {
+        version: String,
+        cache_updated_at: Option<SystemTime>,
+    },
+}
+
+/// A package as `dpkg-query` reports it.
+#[derive(Debug, Clone, PartialEq, Eq)]
+struct DpkgPackage {
+    /// Empty if no version was ever installed.
+    version: String,
+    /// The last word of dpkg's status: "installed", "half-installed",
+    /// "config-files", and so on.
+    status: String,
+}
+
+impl DpkgPackage {
+    fn is_installed(&self) -> bool {
+        // Pending triggers don't stop a package from being usable.
+        matches!(
+            self.status.as_str(),
+            "installed" | "triggers-awaited" | "triggers-pending"
+        )
+    }
+}
+
+/// `${binary:Package}` qualifies the name with its architecture when several
+/// can be installed at once ("libc6:amd64"), so each line has its own name.
+const DPKG_QUERY_FORMAT: &str = "${binary:Package} ${Version} ${Status}\n";
+
+/// Parse `dpkg-query -W` output in [`DPKG_QUERY_FORMAT`], one package per
+/// line: "git 1:2.39.5-0+deb12u2 install ok installed".
+fn parse_dpkg_query(output: &str) -> Result<BTreeMap<String, DpkgPackage>, AptStateError> {
+    output
+        .lines()
+        .filter(|line| !line.trim().is_empty())
+        .map(|line| {
+            let parse_error = || AptStateError::ParseStatus {
+                status: line.to_string(),
+            };
+            // The version is empty for packages that were never installed,
+            // so split the status off the end rather than on every space.
+            let (package, rest) = line.split_once(' ').ok_or_else(parse_error)?;
+            let mut words = rest.rsplitn(4, ' ');
+            let status = words.next().ok_or_else(parse_error)?;
+            let (Some(_error), Some(_want), Some(version)) =
+                (words.next(), words.next(), words.next())
+            else {
+                return Err(parse_error());
+            };
+            Ok((
+                package.to_string(),
+                DpkgPackage {
+                    version: version.to_string(),
+                    status: status.to_string(),
+                },
+            ))
+        })
+        .collect()
+}
+
+/// The package without its architecture: "libc6" for "libc6:amd64".
+fn bare_name(package: &str) -> &str {
+    package
+        .split_once(':')
+        .map_or(package, |(name, _arch)| name)
+}
+
+/// Find `package` among parsed packages, with or without an architecture.
+///
+/// An exact match wins. Otherwise a qualified package matches its bare name,
+/// as dpkg only qualifies packages that can be installed for several
+/// architectures, and a bare package matches any architecture, preferring
+/// one that is installed.
+fn find_package<'a>(
+    packages: &'a BTreeMap<String, DpkgPackage>,
+    package: &str,
+) -> Option<&'a DpkgPackage> {
+    packages.get(package).or_else(|| {
+        let name = bare_name(package);
+        let mut candidates = packages
+            .iter()
+            .filter(|(key, _)| key.as_str() == name || bare_name(key) == package)
+            .map(|(_, found)| found);
+        candidates
+            .clone()
+            .find(|found| found.is_installed())
+            .or_else(|| candidates.next())
+    })
+}
+
+/// `dpkg-query` exits nonzero for a package it has never heard of.
+fn is_unknown_package(stderr: &str) -> bool {
+    stderr.contains("no packages found matching")
+}
+
+/// Whether an index updated at `updated_at` is recent enough to install from.
+fn cache_is_fresh(updated_at: Option<SystemTime>, now: SystemTime) -> bool {
+    match updated_at {
+        Some(updated_at) => now
+            .duration_since(updated_at)
+            .is_ok_and(|age| age < APT_CACHE_MAX_AGE),
+        None => false,
+    }
+}
+
+async fn cache_updated_at() -> Result<Option<SystemTime>, FsError> {
+    if !fs::path_exists(APT_LISTS_DIR).await? {
+        return Ok(None);
+    }
+    Ok(fs::metadata(APT_LISTS_DIR).await?.modified().ok())
 }
 
 impl Display for AptState {
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
         match self {
-            AptState::NotInstalled => write!(f, "Apt::NotInstalled"),
-            AptState::Installed => write!(f, "Apt::Installed"),
+            AptState::NotInstalled { .. } => write!(f, "Apt::NotInstalled"),
+            AptState::Installed { version } => write!(f, "Apt::Installed({version})"),
+            AptState::WrongVersion { version, .. } => write!(f, "Apt::WrongVersion({version})"),
         }
     }
 }
@@ -62,19 +215,26 @@ pub enum AptStateError {
     #[error(transparent)]
     Command(#[from] CommandError),
 
+    #[error(transparent)]
+    Fs(#[from] FsError),
+
     #[error("failed to parse status: {status}")]
     ParseStatus { status: String },
 }
 
 #[derive(Debug, Clone)]
 pub enum AptChange {
-    Install { package: String },
+    Install {
+        package: String,
+        /// Update the package index first, as it is missing or stale.
+        update_cache: bool,
+    },
 }
 
 impl Display for AptChange {
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
         match self {
-            AptChange::Install { package } => write!(f, "Apt::Installed({package})"),
+            AptChange::Install { package, .. } => write!(f, "Apt::Installed({package})"),
         }
     }
 }
@@ -110,18 +270,56 @@ impl ResourceType for Apt {
     type Params = AptParams;
     type Resource = AptResource;
 
+    /// Every package needs a name, a pinned version can't be empty, and a
+    /// package can't be pinned to two different versions.
+    fn validate_params(params: &Self::Params) -> Result<(), ResourceParamError> {
+        let packages = match params {
+            AptParams::Package { package } => std::slice::from_ref(package),
+            AptParams::Packages { packages } => packages.as_slice(),
+        };
+        let mut versions: BTreeMap<String, Option<String>> = BTreeMap::new();
+        for spec in packages {
+            let resource = AptResource::parse(spec.clone());
+            if resource.package.is_empty() {
+                return Err(ResourceParamError::new(format!(
+                    "apt package has no name: \"{spec}\""
+                )));
+            }
+            if resource.version.as_deref() == Some("") {
+                return Err(ResourceParamError::new(format!(
+                    "apt package has an empty version: \"{spec}\""
+                )));
+            }
+            match versions.get(&resource.package) {
+                Some(version) if version != &resource.version => {
+                    return Err(ResourceParamError::new(format!(
+                        "apt package {} is wanted at conflicting versions: {} and {}",
+                        resource.package,
+                        version.as_deref().unwrap_or("any"),
+                        resource.version.as_deref().unwrap_or("any"),
+                    )));
+                }
+                Some(_) => {}
+                None => {
+                    versions.insert(resource.package, resource.version);
+                }
+            }
+        }
+        Ok(())
+    }
+
     fn resources(params: Self::Params) -> Vec<CausalityTree<Self::Resource>> {
         match params {
             AptParams::Package { package } => vec![CausalityTree::leaf(
                 CausalityMeta::default(),
-                AptResource { package },
+                AptResource::parse(package),
             )],
             AptParams::Packages { packages } => vec![CausalityTree::branch(
                 CausalityMeta::default(),
                 packages
                     .into_iter()
                     .map(|package| {
-                        CausalityTree::leaf(CausalityMeta::default(), AptResource { package })
+                        CausalityTree::leaf(CausalityMeta::default(), AptResource::parse(package))
                     })
                     .collect(),
             )],
@@ -131,65 +329,96 @@ impl ResourceType for Apt {
     type State = AptState;
     type StateError = AptStateError;
     async fn state(resource: &Self::Resource) -> Result<Self::State, Self::StateError> {
-        Command::new("dpkg-query")
-            .args(["-W", "-f='${Status}'", &resource.package])
+        let packages = Command::new("dpkg-query")
+            .arg("-W")
+            .arg(format!("-f={DPKG_QUERY_FORMAT}"))
+            .arg(&resource.package)
             .handle(
-                |stdout| {
-                    let stdout = String::from_utf8_lossy(stdout);
-                    let status_parts: Vec<_> = stdout.trim_matches('\'').split(" ").collect();
-                    let Some(status) = status_parts.get(2) else {
-                        return Err(AptStateError::ParseStatus {
-                            status: stdout.to_string(),
-                        });
-                    };
-                    match *status {
-                        "not-installed" => Ok(AptState::NotInstalled),
-                        "unpacked" => Ok(AptState::NotInstalled),
-                        "half-installed" => Ok(AptState::NotInstalled),
-                        "installed" => Ok(AptState::Installed),
-                        "config-files" => Ok(AptState::NotInstalled),
-                        _ => Err(AptStateError::ParseStatus {
-                            status: stdout.to_string(),
-                        }),
-                    }
-                },
+                |stdout| parse_dpkg_query(&String::from_utf8_lossy(stdout)),
                 |stderr| {
-                    let stderr = String::from_utf8_lossy(stderr);
-                    if stderr.contains("no packages found matching") {
-                        Ok(Some(AptState::NotInstalled))
-                    } else {
-                        Ok(None)
-                    }
+                    Ok(is_unknown_package(&String::from_utf8_lossy(stderr)).then(BTreeMap::new))
                 },
             )
-            .await?
+            .await??;
+
+        let installed =
+            find_package(&packages, &resource.package).filter(|package| package.is_installed());
+        match (installed, &resource.version) {
+            (Some(package), Some(wanted)) if &package.version != wanted => {
+                Ok(AptState::WrongVersion {
+                    version: package.version.clone(),
+                    cache_updated_at: cache_updated_at().await?,
+                })
+            }
+            (Some(package), _) => Ok(AptState::Installed {
+                version: package.version.clone(),
+            }),
+            (None, _) => Ok(AptState::NotInstalled {
+                cache_updated_at: cache_updated_at().await?,
+            }),
+        }
     }
 
     type Change = AptChange;
     fn change(resource: &Self::Resource, state: &Self::State) -> Option<Self::Change> {
         match state {
-            AptState::Installed => None,
-            AptState::NotInstalled => Some(AptChange::Install {
-                package: resource.package.clone(),
+            AptState::Installed { .. } => None,
+            AptState::NotInstalled { cache_updated_at }
+            | AptState::WrongVersion {
+                cache_updated_at, ..
+            } => Some(AptChange::Install {
+                package: resource.install_spec(),
+                update_cache: !cache_is_fresh(*cache_updated_at, SystemTime::now()),
             }),
         }
     }
 
+    fn explain(resource: &Self::Resource, state: &Self::State, change: &Self::Change) -> String {
+        let current = match state {
+            AptState::NotInstalled { .. } => "is not installed".to_string(),
+            AptState::Installed { version } => format!("is installed at {version}"),
+            AptState::WrongVersion { version, .. } => format!("is installed at {version}"),
+        };
+        let wanted = match &resource.version {
+            Some(version) => format!("want {version}"),
+            None => "want any version".to_string(),
+        };
+        let action = match change {
+            AptChange::Install {
+                package,
+                update_cache: true,
+            } => format!("update the package index, then install {package}"),
+            AptChange::Install {
+                package,
+                update_cache: false,
+            } => format!("install {package}"),
+        };
+        format!("{} {current}, {wanted}: {action}", resource.package)
+    }
+
     fn operations(change: Self::Change) -> Vec<CausalityTree<Operation>> {
         match change {
-            AptChange::Install { package } => {
+            AptChange::Install {
+                package,
+                update_cache,
+            } => {
+                let install = Operation::Apt(AptOperation::Install {
+                    packages: vec![package],
+                });
+                if !update_cache {
+                    return vec![CausalityTree::leaf(CausalityMeta::default(), install)];
+                }
+                // Every install updates first; apt merges those into one update.
                 vec![
                     CausalityTree::Leaf {
-                        node: Operation::Apt(AptOperation::Update),
+                        node: Operation::Apt(AptOperation::UpdateCache),
                         meta: CausalityMeta {
                             id: Some("update".into()),
                             ..Default::default()
                         },
                     },
                     CausalityTree::Leaf {
-                        node: Operation::Apt(AptOperation::Install {
-                            packages: vec![package],
-                        }),
+                        node: install,
                         meta: CausalityMeta {
                             id: None,
                             before: vec!["update".into()],
@@ -201,3 +430,182 @@ impl ResourceType for Apt {
         }
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn resource() -> AptResource {
+        AptResource::parse("git".into())
+    }
+
+    /// Captured from `dpkg-query -W -f='${binary:Package} ${Version} ${Status}\n'`.
+    const DPKG_QUERY_OUTPUT: &str = "\
+git 1:2.39.5-0+deb12u2 install ok installed
+curl 7.88.1-10+deb12u12 install reinstreq half-installed
+nano 7.2-1+deb12u1 deinstall ok config-files
+vim  unknown ok not-installed
+";
+
+    fn packages(packages: &[&str]) -> AptParams {
+        AptParams::Packages {
+            packages: packages.iter().map(ToString::to_string).collect(),
+        }
+    }
+
+    #[test]
+    fn validates_params() {
+        assert!(Apt::validate_params(&packages(&["git", "curl=7.88.1-10+deb12u12"])).is_ok());
+        // The same pin twice is redundant, but not contradictory.
+        assert!(Apt::validate_params(&packages(&["git=1:2.39.5", "git=1:2.39.5"])).is_ok());
+
+        let error =
+            Apt::validate_params(&packages(&["git=1:2.39.5", "curl", "git=1:2.43.0"])).unwrap_err();
+        assert_eq!(
+            error.message,
+            "apt package git is wanted at conflicting versions: 1:2.39.5 and 1:2.43.0"
+        );
+        assert!(Apt::validate_params(&packages(&["git", "git=1:2.39.5"])).is_err());
+        assert!(Apt::validate_params(&AptParams::Package {
+            package: "git=".into()
+        })
+        .is_err());
+        assert!(Apt::validate_params(&packages(&["=1.0"])).is_err());
+    }
+
+    #[test]
+    fn parses_dpkg_query_output() {
+        let packages = parse_dpkg_query(DPKG_QUERY_OUTPUT).unwrap();
+        assert_eq!(
+            packages["git"],
+            DpkgPackage {
+                version: "1:2.39.5-0+deb12u2".into(),
+                status: "installed".into(),
+            }
+        );
+        assert!(packages["git"].is_installed());
+        assert!(!packages["curl"].is_installed());
+        assert!(!packages["nano"].is_installed());
+        assert_eq!(packages["vim"].version, "");
+        assert!(!packages["vim"].is_installed());
+        assert!(parse_dpkg_query("git installed\n").is_err());
+    }
+
+    /// As above, on a system with i386 enabled as a foreign architecture.
+    const DPKG_QUERY_MULTIARCH_OUTPUT: &str = "\
+git 1:2.39.5-0+deb12u2 install ok installed
+libc6:amd64 2.36-9+deb12u9 install ok installed
+libc6:i386 2.36-9+deb12u9 deinstall ok config-files
+libssl3:i386 3.0.15-1~deb12u1 install ok installed
+";
+
+    #[test]
+    fn finds_packages_with_or_without_architecture() {
+        let packages = parse_dpkg_query(DPKG_QUERY_MULTIARCH_OUTPUT).unwrap();
+        assert_eq!(packages.len(), 4);
+        let installed =
+            |package: &str| find_package(&packages, package).map(DpkgPackage::is_installed);
+
+        assert_eq!(installed("libc6"), Some(true));
+        assert_eq!(installed("libc6:amd64"), Some(true));
+        assert_eq!(installed("libc6:i386"), Some(false));
+        assert_eq!(installed("libssl3"), Some(true));
+        assert_eq!(installed("libssl3:amd64"), None);
+        assert_eq!(installed("git"), Some(true));
+        assert_eq!(installed("git:amd64"), Some(true));
+        assert_eq!(installed("curl"), None);
+    }
+
+    #[test]
+    fn recognizes_unknown_packages() {
+        assert!(is_unknown_package(
+            "dpkg-query: no packages found matching nonexistent\n"
+        ));
+        assert!(!is_unknown_package(
+            "dpkg-query: error: some other failure\n"
+        ));
+    }
+
+    #[test]
+    fn installed_package_needs_no_change() {
+        let state = AptState::Installed {
+            version: "1:2.39.5-0+deb12u2".into(),
+        };
+        assert!(Apt::change(&resource(), &state).is_none());
+
+        // Pinned at the version already installed.
+        let pinned = AptResource::parse("git=1:2.39.5-0+deb12u2".into());
+        assert!(Apt::change(&pinned, &state).is_none());
+    }
+
+    #[test]
+    fn wrong_version_installs_wanted_version() {
+        let resource = AptResource::parse("git=1:2.39.5-0+deb12u2".into());
+        assert_eq!(resource.package, "git");
+        let state = AptState::WrongVersion {
+            version: "1:2.39.2-1.1".into(),
+            cache_updated_at: None,
+        };
+        let Some(AptChange::Install { package, .. }) = Apt::change(&resource, &state) else {
+            panic!("expected an install");
+        };
+        assert_eq!(package, "git=1:2.39.5-0+deb12u2");
+    }
+
+    #[test]
+    fn explains_wrong_version() {
+        let resource = AptResource::parse("git=1:2.39.5-0+deb12u2".into());
+        let state = AptState::WrongVersion {
+            version: "1:2.39.2-1.1".into(),
+            cache_updated_at: None,
+        };
+        let change = Apt::change(&resource, &state).unwrap();
+        assert_eq!(
+            Apt::explain(&resource, &state, &change),
+            "git is installed at 1:2.39.2-1.1, want 1:2.39.5-0+deb12u2: \
+             update the package index, then install git=1:2.39.5-0+deb12u2"
+        );
+    }
+
+    fn update_caches(change: AptChange) -> usize {
+        Apt::operations(change)
+            .iter()
+            .filter(|tree| {
+                matches!(
+                    tree,
+                    CausalityTree::Leaf {
+                        node: Operation::Apt(AptOperation::UpdateCache),
+                        ..
+                    }
+                )
+            })
+            .count()
+    }
+
+    #[test]
+    fn missing_package_installs() {
+        let state = AptState::NotInstalled {
+            cache_updated_at: None,
+        };
+        let change = Apt::change(&resource(), &state).unwrap();
+        assert_eq!(update_caches(change.clone()), 1);
+        assert_eq!(Apt::operations(change).len(), 2);
+    }
+
+    #[test]
+    fn fresh_cache_skips_update() {
+        let state = AptState::NotInstalled {
+            cache_updated_at: Some(SystemTime::now() - Duration::from_secs(60)),
+        };
+        let change = Apt::change(&resource(), &state).unwrap();
+        assert_eq!(update_caches(change), 0);
+    }
+
+    #[test]
+    fn cache_goes_stale_after_max_age() {
+        let now = SystemTime::now();
+        assert!(cache_is_fresh(Some(now - Duration::from_secs(60)), now));
+        assert!(!cache_is_fresh(Some(now - APT_CACHE_MAX_AGE), now));
+        assert!(!cache_is_fresh(None, now));
+    }
+}
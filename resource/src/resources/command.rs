@@ -0,0 +1,248 @@
+use std::{fmt::Display, path::PathBuf};
+
+use async_trait::async_trait;
+use indexmap::indexmap;
+use lusid_causality::{CausalityMeta, CausalityTree};
+use lusid_cmd::{self as cmd, CommandError};
+use lusid_fs::{self as fs, FsError};
+use lusid_operation::{operations::command::CommandOperation, Operation};
+use lusid_params::{ParamField, ParamType, ParamTypes};
+use rimu::{SourceId, Span, Spanned};
+use serde::Deserialize;
+use thiserror::Error;
+
+use crate::ResourceType;
+
+#[derive(Debug, Clone, Deserialize)]
+pub struct CommandParams {
+    /// Shell command line to run.
+    pub run: String,
+    /// Skip running if this shell command line succeeds.
+    pub unless: Option<String>,
+    /// Skip running if this path exists.
+    pub creates: Option<PathBuf>,
+}
+
+impl Display for CommandParams {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "Command(run = {}", self.run)?;
+        if let Some(unless) = &self.unless {
+            write!(f, ", unless = {unless}")?;
+        }
+        if let Some(creates) = &self.creates {
+            write!(f, ", creates = {}", creates.display())?;
+        }
+        write!(f, ")")
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct CommandResource {
+    pub run: String,
+    pub unless: Option<String>,
+    pub creates: Option<PathBuf>,
+}
+
+impl Display for CommandResource {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "Command({})", self.run)
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum CommandState {
+    /// A guard says the command's work is already done.
+    Done,
+    NotDone,
+}
+
+impl Display for CommandState {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            CommandState::Done => write!(f, "Command::Done"),
+            CommandState::NotDone => write!(f, "Command::NotDone"),
+        }
+    }
+}
+
+#[derive(Error, Debug)]
+pub enum CommandStateError {
+    #[error(transparent)]
+    Command(#[from] CommandError),
+
+    #[error(transparent)]
+    Fs(#[from] FsError),
+}
+
+#[derive(Debug, Clone)]
+pub enum CommandChange {
+    Run { run: String },
+}
+
+impl Display for CommandChange {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            CommandChange::Run { run } => write!(f, "Command::Run({run})"),
+        }
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct Command;
+
+#[async_trait]
+impl ResourceType for Command {
+    const ID: &'static str = "command";
+
+    fn param_types() -> Option<Spanned<ParamTypes>> {
+        let span = Span::new(SourceId::empty(), 0, 0);
+        let string = || Spanned::new(ParamField::new(ParamType::String), span.clone());
+        Some(Spanned::new(
+            ParamTypes::Union(vec![
+                indexmap! {
+                    "run".to_string() => string(),
+                },
+                indexmap! {
+                    "run".to_string() => string(),
+                    "unless".to_string() => string(),
+                },
+                indexmap! {
+                    "run".to_string() => string(),
+                    "creates".to_string() => string(),
+                },
+                indexmap! {
+                    "run".to_string() => string(),
+                    "unless".to_string() => string(),
+                    "creates".to_string() => string(),
+                },
+            ]),
+            span,
+        ))
+    }
+
+    type Params = CommandParams;
+    type Resource = CommandResource;
+
+    fn resources(params: Self::Params) -> Vec<CausalityTree<Self::Resource>> {
+        let CommandParams {
+            run,
+            unless,
+            creates,
+        } = params;
+        vec![CausalityTree::leaf(
+            CausalityMeta::default(),
+            CommandResource {
+                run,
+                unless,
+                creates,
+            },
+        )]
+    }
+
+    type State = CommandState;
+    type StateError = CommandStateError;
+    async fn state(resource: &Self::Resource) -> Result<Self::State, Self::StateError> {
+        let created = match &resource.creates {
+            Some(creates) => fs::path_exists(creates).await?,
+            None => false,
+        };
+        if created {
+            return Ok(CommandState::Done);
+        }
+        if let Some(unless) = &resource.unless {
+            let output = cmd::Command::new("sh")
+                .args(["-c", unless])
+                .output()
+                .await?;
+            if output.status.await?.success() {
+                return Ok(CommandState::Done);
+            }
+        }
+        Ok(CommandState::NotDone)
+    }
+
+    type Change = CommandChange;
+    fn change(resource: &Self::Resource, state: &Self::State) -> Option<Self::Change> {
+        match state {
+            CommandState::Done => None,
+            CommandState::NotDone => Some(CommandChange::Run {
+                run: resource.run.clone(),
+            }),
+        }
+    }
+
+    fn explain(resource: &Self::Resource, _state: &Self::State, change: &Self::Change) -> String {
+        let mut reasons = Vec::new();
+        if let Some(creates) = &resource.creates {
+            reasons.push(format!("{} does not exist", creates.display()));
+        }
+        if let Some(unless) = &resource.unless {
+            reasons.push(format!("`{unless}` failed"));
+        }
+        let reason = if reasons.is_empty() {
+            "no guard given".to_string()
+        } else {
+            reasons.join(" and ")
+        };
+        match change {
+            CommandChange::Run { run } => format!("{reason}: run `{run}`"),
+        }
+    }
+
+    fn operations(change: Self::Change) -> Vec<CausalityTree<Operation>> {
+        match change {
+            CommandChange::Run { run } => vec![CausalityTree::leaf(
+                CausalityMeta::default(),
+                Operation::Command(CommandOperation::Run { command: run }),
+            )],
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn resource(unless: Option<&str>, creates: Option<PathBuf>) -> CommandResource {
+        CommandResource {
+            run: "touch /tmp/done".into(),
+            unless: unless.map(Into::into),
+            creates,
+        }
+    }
+
+    #[tokio::test]
+    async fn existing_creates_path_needs_no_change() {
+        let dir = tempfile::tempdir().unwrap();
+        let resource = resource(None, Some(dir.path().to_path_buf()));
+        let state = Command::state(&resource).await.unwrap();
+        assert_eq!(state, CommandState::Done);
+        assert!(Command::change(&resource, &state).is_none());
+    }
+
+    #[tokio::test]
+    async fn successful_unless_needs_no_change() {
+        let resource = resource(Some("true"), None);
+        let state = Command::state(&resource).await.unwrap();
+        assert_eq!(state, CommandState::Done);
+        assert!(Command::change(&resource, &state).is_none());
+    }
+
+    #[tokio::test]
+    async fn runs_otherwise() {
+        let dir = tempfile::tempdir().unwrap();
+        let resource = resource(Some("false"), Some(dir.path().join("missing")));
+        let state = Command::state(&resource).await.unwrap();
+        assert_eq!(state, CommandState::NotDone);
+
+        let change = Command::change(&resource, &state).unwrap();
+        let operations = Command::operations(change);
+        assert!(matches!(
+            operations.as_slice(),
+            [CausalityTree::Leaf {
+                node: Operation::Command(CommandOperation::Run { command }),
+                ..
+            }] if command == "touch /tmp/done"
+        ));
+    }
+}
@@ -0,0 +1,338 @@
+use std::{fmt::Display, os::unix::fs::PermissionsExt, path::PathBuf};
+
+use async_trait::async_trait;
+use indexmap::indexmap;
+use lusid_causality::{CausalityMeta, CausalityTree};
+use lusid_fs::{self as fs, FsError};
+use lusid_operation::{operations::file::FileOperation, Operation};
+use lusid_params::{ParamField, ParamType, ParamTypes};
+use rimu::{SourceId, Span, Spanned};
+use serde::{Deserialize, Deserializer};
+use thiserror::Error;
+
+use crate::ResourceType;
+
+#[derive(Debug, Clone, Deserialize)]
+#[serde(untagged)]
+pub enum FileParams {
+    File {
+        path: PathBuf,
+        content: String,
+        /// Octal permissions, as in `chmod`: "644".
+        #[serde(default, deserialize_with = "deserialize_mode")]
+        mode: Option<u32>,
+    },
+    Directory {
+        directory: PathBuf,
+    },
+}
+
+fn deserialize_mode<'de, D: Deserializer<'de>>(deserializer: D) -> Result<Option<u32>, D::Error> {
+    let mode = String::deserialize(deserializer)?;
+    u32::from_str_radix(&mode, 8)
+        .map(Some)
+        .map_err(|_| serde::de::Error::custom(format!("invalid octal file mode: {mode}")))
+}
+
+impl Display for FileParams {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            FileParams::File { path, mode, .. } => match mode {
+                Some(mode) => write!(f, "File(path = {}, mode = {mode:o})", path.display()),
+                None => write!(f, "File(path = {})", path.display()),
+            },
+            FileParams::Directory { directory } => {
+                write!(f, "File(directory = {})", directory.display())
+            }
+        }
+    }
+}
+
+#[derive(Debug, Clone)]
+pub enum FileResource {
+    File {
+        path: PathBuf,
+        content: Vec<u8>,
+        mode: Option<u32>,
+    },
+    Directory {
+        path: PathBuf,
+    },
+}
+
+impl Display for FileResource {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            FileResource::File { path, .. } => write!(f, "File({})", path.display()),
+            FileResource::Directory { path } => write!(f, "File::Directory({})", path.display()),
+        }
+    }
+}
+
+#[derive(Debug, Clone)]
+pub enum FileState {
+    Missing,
+    File { content: Vec<u8>, mode: u32 },
+    Directory,
+}
+
+impl Display for FileState {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            FileState::Missing => write!(f, "File::Missing"),
+            FileState::File { content, mode } => {
+                write!(f, "File::File({} bytes, mode = {mode:o})", content.len())
+            }
+            FileState::Directory => write!(f, "File::Directory"),
+        }
+    }
+}
+
+#[derive(Error, Debug)]
+pub enum FileStateError {
+    #[error(transparent)]
+    Fs(#[from] FsError),
+}
+
+#[derive(Debug, Clone)]
+pub enum FileChange {
+    Write {
+        path: PathBuf,
+        content: Vec<u8>,
+        mode: Option<u32>,
+    },
+    ChangeMode {
+        path: PathBuf,
+        mode: u32,
+    },
+    CreateDirectory {
+        path: PathBuf,
+    },
+}
+
+impl Display for FileChange {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            FileChange::Write { path, .. } => write!(f, "File::Write({})", path.display()),
+            FileChange::ChangeMode { path, mode } => {
+                write!(f, "File::ChangeMode({}, {mode:o})", path.display())
+            }
+            FileChange::CreateDirectory { path } => {
+                write!(f, "File::CreateDirectory({})", path.display())
+            }
+        }
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct File;
+
+#[async_trait]
+impl ResourceType for File {
+    const ID: &'static str = "file";
+
+    fn param_types() -> Option<Spanned<ParamTypes>> {
+        let span = Span::new(SourceId::empty(), 0, 0);
+        let string = || Spanned::new(ParamField::new(ParamType::String), span.clone());
+        Some(Spanned::new(
+            ParamTypes::Union(vec![
+                indexmap! {
+                    "path".to_string() => string(),
+                    "content".to_string() => string(),
+                },
+                indexmap! {
+                    "path".to_string() => string(),
+                    "content".to_string() => string(),
+                    "mode".to_string() => string(),
+                },
+                indexmap! {
+                    "directory".to_string() => string(),
+                },
+            ]),
+            span,
+        ))
+    }
+
+    type Params = FileParams;
+    type Resource = FileResource;
+
+    fn resources(params: Self::Params) -> Vec<CausalityTree<Self::Resource>> {
+        let resource = match params {
+            FileParams::File {
+                path,
+                content,
+                mode,
+            } => FileResource::File {
+                path,
+                content: content.into_bytes(),
+                mode,
+            },
+            FileParams::Directory { directory } => FileResource::Directory { path: directory },
+        };
+        vec![CausalityTree::leaf(CausalityMeta::default(), resource)]
+    }
+
+    type State = FileState;
+    type StateError = FileStateError;
+    async fn state(resource: &Self::Resource) -> Result<Self::State, Self::StateError> {
+        let path = match resource {
+            FileResource::File { path, .. } | FileResource::Directory { path } => path,
+        };
+        if !fs::path_exists(path).await? {
+            return Ok(FileState::Missing);
+        }
+        let metadata = fs::metadata(path).await?;
+        if metadata.is_dir() {
+            return Ok(FileState::Directory);
+        }
+        let content = match resource {
+            // Only compare content when we mean to write it.
+            FileResource::File { .. } => fs::read_file(path).await?,
+            FileResource::Directory { .. } => Vec::new(),
+        };
+        Ok(FileState::File {
+            content,
+            mode: metadata.permissions().mode() & 0o7777,
+        })
+    }
+
+    type Change = FileChange;
+    fn change(resource: &Self::Resource, state: &Self::State) -> Option<Self::Change> {
+        match (resource, state) {
+            (
+                FileResource::File {
+                    path,
+                    content,
+                    mode,
+                },
+                FileState::File {
+                    content: current_content,
+                    mode: current_mode,
+                },
+            ) if content == current_content => match mode {
+                Some(mode) if mode != current_mode => Some(FileChange::ChangeMode {
+                    path: path.clone(),
+                    mode: *mode,
+                }),
+                _ => None,
+            },
+            (
+                FileResource::File {
+                    path,
+                    content,
+                    mode,
+                },
+                _,
+            ) => Some(FileChange::Write {
+                path: path.clone(),
+                content: content.clone(),
+                mode: *mode,
+            }),
+            (FileResource::Directory { .. }, FileState::Directory) => None,
+            (FileResource::Directory { path }, _) => {
+                Some(FileChange::CreateDirectory { path: path.clone() })
+            }
+        }
+    }
+
+    fn explain(resource: &Self::Resource, state: &Self::State, change: &Self::Change) -> String {
+        let current = match state {
+            FileState::Missing => "is missing".to_string(),
+            FileState::Directory => "is a directory".to_string(),
+            FileState::File { content, mode } => {
+                format!("is a file ({} bytes, mode {mode:o})", content.len())
+            }
+        };
+        let wanted = match resource {
+            FileResource::File { content, mode, .. } => match mode {
+                Some(mode) => format!("want a file ({} bytes, mode {mode:o})", content.len()),
+                None => format!("want a file ({} bytes)", content.len()),
+            },
+            FileResource::Directory { .. } => "want a directory".to_string(),
+        };
+        let (path, action) = match change {
+            FileChange::Write { path, content, .. } => {
+                (path, format!("write {} bytes", content.len()))
+            }
+            FileChange::ChangeMode { path, mode } => (path, format!("change mode to {mode:o}")),
+            FileChange::CreateDirectory { path } => (path, "create directory".to_string()),
+        };
+        format!("{} {current}, {wanted}: {action}", path.display())
+    }
+
+    fn operations(change: Self::Change) -> Vec<CausalityTree<Operation>> {
+        match change {
+            FileChange::Write {
+                path,
+                content,
+                mode,
+            } => {
+                let write = CausalityTree::Leaf {
+                    node: Operation::File(FileOperation::WriteFile {
+                        path: path.clone(),
+                        content,
+                    }),
+                    meta: CausalityMeta {
+                        id: Some("write".into()),
+                        ..Default::default()
+                    },
+                };
+                let Some(mode) = mode else {
+                    return vec![write];
+                };
+                vec![
+                    write,
+                    CausalityTree::Leaf {
+                        node: Operation::File(FileOperation::ChangeMode { path, mode }),
+                        meta: CausalityMeta {
+                            id: None,
+                            before: vec!["write".into()],
+                            after: vec![],
+                        },
+                    },
+                ]
+            }
+            FileChange::ChangeMode { path, mode } => vec![CausalityTree::leaf(
+                CausalityMeta::default(),
+                Operation::File(FileOperation::ChangeMode { path, mode }),
+            )],
+            FileChange::CreateDirectory { path } => vec![CausalityTree::leaf(
+                CausalityMeta::default(),
+                Operation::File(FileOperation::CreateDirectory { path }),
+            )],
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn resource(mode: Option<u32>) -> FileResource {
+        FileResource::File {
+            path: PathBuf::from("/etc/motd"),
+            content: b"hello\n".to_vec(),
+            mode,
+        }
+    }
+
+    #[test]
+    fn matching_file_needs_no_change() {
+        let state = FileState::File {
+            content: b"hello\n".to_vec(),
+            mode: 0o644,
+        };
+        assert!(File::change(&resource(None), &state).is_none());
+        assert!(File::change(&resource(Some(0o644)), &state).is_none());
+        assert!(matches!(
+            File::change(&resource(Some(0o600)), &state),
+            Some(FileChange::ChangeMode { mode: 0o600, .. })
+        ));
+    }
+
+    #[test]
+    fn missing_file_writes_then_sets_mode() {
+        let change = File::change(&resource(Some(0o600)), &FileState::Missing).unwrap();
+        assert_eq!(File::operations(change).len(), 2);
+    }
+}
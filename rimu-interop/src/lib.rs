@@ -1,5 +1,7 @@
 mod from_rimu;
+mod object;
 mod to_rimu;
 
 pub use crate::from_rimu::*;
+pub use crate::object::*;
 pub use crate::to_rimu::*;
@@ -0,0 +1,148 @@
+use displaydoc::Display;
+use indexmap::IndexMap;
+use rimu::{Span, Spanned, Value};
+use thiserror::Error;
+
+/// An error reading a property from a [`RimuObject`].
+#[derive(Debug, Clone, Error, Display)]
+pub enum PropertyError {
+    /// Missing property: "{key}"
+    Missing { key: String },
+    /// The "{key}" property must be a {expected}
+    WrongType {
+        key: String,
+        expected: &'static str,
+        span: Span,
+    },
+}
+
+impl PropertyError {
+    /// Where the offending value is, if there is one.
+    pub fn span(&self) -> Option<&Span> {
+        match self {
+            PropertyError::Missing { .. } => None,
+            PropertyError::WrongType { span, .. } => Some(span),
+        }
+    }
+}
+
+/// An object being read by a [`FromRimu`](crate::FromRimu) impl.
+///
+/// Properties are taken out as they are read, so whatever is left can be
+/// checked for unknown keys or passed on.
+#[derive(Debug, Clone)]
+pub struct RimuObject(IndexMap<String, Spanned<Value>>);
+
+impl RimuObject {
+    /// The object, or `None` if the value isn't one.
+    pub fn from_value(value: Value) -> Option<Self> {
+        match value {
+            Value::Object(object) => Some(RimuObject(object)),
+            _ => None,
+        }
+    }
+
+    pub fn into_value(self) -> Value {
+        Value::Object(self.0)
+    }
+
+    pub fn take_optional(&mut self, key: &str) -> Option<Spanned<Value>> {
+        self.0.swap_remove(key)
+    }
+
+    pub fn take_required(&mut self, key: &str) -> Result<Spanned<Value>, PropertyError> {
+        self.take_optional(key)
+            .ok_or_else(|| PropertyError::Missing {
+                key: key.to_string(),
+            })
+    }
+
+    pub fn take_string(&mut self, key: &str) -> Result<Spanned<String>, PropertyError> {
+        let value = self.take_required(key)?;
+        expect_string(key, value)
+    }
+
+    pub fn take_optional_string(
+        &mut self,
+        key: &str,
+    ) -> Result<Option<Spanned<String>>, PropertyError> {
+        self.take_optional(key)
+            .map(|value| expect_string(key, value))
+            .transpose()
+    }
+
+    pub fn take_optional_bool(
+        &mut self,
+        key: &str,
+    ) -> Result<Option<Spanned<bool>>, PropertyError> {
+        self.take_optional(key)
+            .map(|value| {
+                let (value, span) = value.take();
+                match value {
+                    Value::Boolean(boolean) => Ok(Spanned::new(boolean, span)),
+                    _ => Err(wrong_type(key, "boolean", span)),
+                }
+            })
+            .transpose()
+    }
+}
+
+fn expect_string(key: &str, value: Spanned<Value>) -> Result<Spanned<String>, PropertyError> {
+    let (value, span) = value.take();
+    match value {
+        Value::String(string) => Ok(Spanned::new(string, span)),
+        _ => Err(wrong_type(key, "string", span)),
+    }
+}
+
+fn wrong_type(key: &str, expected: &'static str, span: Span) -> PropertyError {
+    PropertyError::WrongType {
+        key: key.to_string(),
+        expected,
+        span,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use rimu::SourceId;
+
+    use super::*;
+
+    fn spanned(value: Value, start: usize) -> Spanned<Value> {
+        Spanned::new(value, Span::new(SourceId::empty(), start, start + 1))
+    }
+
+    fn object() -> RimuObject {
+        let mut object = IndexMap::new();
+        object.insert("type".to_string(), spanned(Value::Boolean(true), 4));
+        object.insert(
+            "optional".to_string(),
+            spanned(Value::String("yes".to_string()), 8),
+        );
+        RimuObject::from_value(Value::Object(object)).unwrap()
+    }
+
+    #[test]
+    fn missing_key() {
+        let error = object().take_string("item").unwrap_err();
+        assert!(matches!(&error, PropertyError::Missing { key } if key == "item"));
+        assert_eq!(error.to_string(), "Missing property: \"item\"");
+        assert!(error.span().is_none());
+        assert!(object().take_optional_string("item").unwrap().is_none());
+    }
+
+    #[test]
+    fn wrong_type() {
+        let error = object().take_string("type").unwrap_err();
+        assert_eq!(error.to_string(), "The \"type\" property must be a string");
+        assert_eq!(error.span().map(|span| span.start()), Some(4));
+
+        let error = object().take_optional_bool("optional").unwrap_err();
+        assert_eq!(
+            error.to_string(),
+            "The \"optional\" property must be a boolean"
+        );
+        assert_eq!(error.span().map(|span| span.start()), Some(8));
+    }
+}
@@ -17,3 +17,9 @@ where
     let rimu_value = rimu_serde_value.with_span(Span::new(source_id, 0, 0));
     Ok(rimu_value)
 }
+
+/// Types that can be written back as the Rimu value their
+/// [`FromRimu`](crate::FromRimu) impl reads.
+pub trait ToRimu {
+    fn to_rimu(&self) -> Value;
+}
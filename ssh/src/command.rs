@@ -70,6 +70,24 @@ impl SshChannelHandle {
     }
 }
 
+/// Join `args` into one command line for the remote shell, quoting any
+/// argument the shell would otherwise split or expand.
+pub fn shell_command<S: AsRef<str>>(args: &[S]) -> String {
+    args.iter()
+        .map(|arg| shell_quote(arg.as_ref()))
+        .collect::<Vec<_>>()
+        .join(" ")
+}
+
+fn shell_quote(arg: &str) -> String {
+    let is_safe = |c: char| c.is_ascii_alphanumeric() || "-_./=:,@%+".contains(c);
+    if !arg.is_empty() && arg.chars().all(is_safe) {
+        return arg.to_string();
+    }
+    // Inside single quotes nothing is special, except the closing quote.
+    format!("'{}'", arg.replace('\'', r"'\''"))
+}
+
 /// A streaming handle to a running SSH command.
 ///
 /// - stdout/stderr are AsyncBufRead (and AsyncRead) via ReadStream.
@@ -115,3 +133,21 @@ pub(super) async fn ssh_command(
         command: command.to_owned(),
     })
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn shell_command_quotes_when_needed() {
+        assert_eq!(shell_command(&["ls", "-la", "/tmp"]), "ls -la /tmp");
+        assert_eq!(
+            shell_command(&["echo", "hello world", ""]),
+            "echo 'hello world' ''"
+        );
+        assert_eq!(
+            shell_command(&["sh", "-c", "echo $HOME; it's"]),
+            r"sh -c 'echo $HOME; it'\''s'"
+        );
+    }
+}
@@ -0,0 +1,218 @@
+use std::{fmt::Display, future::Future, io, net::SocketAddr, sync::Arc, time::Duration};
+
+use thiserror::Error;
+use tokio::{
+    io::{copy_bidirectional, AsyncRead, AsyncWrite},
+    net::TcpListener,
+    task::{JoinHandle, JoinSet},
+    time::sleep,
+};
+use tracing::{debug, info, warn};
+
+use crate::session::{AsyncSession, NoCheckHandler};
+
+#[derive(Error, Debug)]
+pub enum SshForwardError {
+    #[error("failed to listen on local port {port}: {source}")]
+    Bind {
+        port: u16,
+        #[source]
+        source: io::Error,
+    },
+}
+
+/// Wait after a failed accept, doubling while failures continue.
+const ACCEPT_BACKOFF_MIN: Duration = Duration::from_millis(10);
+const ACCEPT_BACKOFF_MAX: Duration = Duration::from_secs(1);
+
+/// A local port forward, open until dropped.
+///
+/// Each connection to [`SshTunnel::local_addr`] is carried over its own
+/// direct-tcpip channel to the remote host and port. Dropping the tunnel drops
+/// every channel's stream, and russh closes a channel when its stream is
+/// dropped, so the remote side sees each connection close too.
+pub struct SshTunnel {
+    local_addr: SocketAddr,
+    task: JoinHandle<()>,
+}
+
+impl SshTunnel {
+    /// Where the tunnel listens, useful when asked for local port 0.
+    pub fn local_addr(&self) -> SocketAddr {
+        self.local_addr
+    }
+}
+
+impl Drop for SshTunnel {
+    fn drop(&mut self) {
+        self.task.abort();
+    }
+}
+
+pub(super) async fn ssh_forward_local(
+    session: Arc<AsyncSession<NoCheckHandler>>,
+    local_port: u16,
+    remote_host: &str,
+    remote_port: u16,
+) -> Result<SshTunnel, SshForwardError> {
+    let listener = TcpListener::bind(("127.0.0.1", local_port))
+        .await
+        .map_err(|source| SshForwardError::Bind {
+            port: local_port,
+            source,
+        })?;
+    let local_addr = listener
+        .local_addr()
+        .map_err(|source| SshForwardError::Bind {
+            port: local_port,
+            source,
+        })?;
+    info!(%local_addr, remote_host, remote_port, "forwarding local port");
+
+    let remote_host = remote_host.to_string();
+    let task = tokio::spawn(forward_connections(listener, move |peer: SocketAddr| {
+        let session = session.clone();
+        let remote_host = remote_host.clone();
+        async move {
+            let channel = session
+                .channel_open_direct_tcpip(
+                    remote_host,
+                    remote_port.into(),
+                    peer.ip().to_string(),
+                    peer.port().into(),
+                )
+                .await?;
+            Ok::<_, russh::Error>(channel.into_stream())
+        }
+    }));
+
+    Ok(SshTunnel { local_addr, task })
+}
+
+/// Accept connections forever, piping each one to a stream from `open`.
+///
+/// Connections are held in a [`JoinSet`], so aborting this task drops their
+/// streams too. Failing accepts are retried with a growing wait, as errors like
+/// running out of file descriptors last a while.
+async fn forward_connections<Open, Fut, Stream, Error>(listener: TcpListener, open: Open)
+where
+    Open: Fn(SocketAddr) -> Fut,
+    Fut: Future<Output = Result<Stream, Error>> + Send + 'static,
+    Stream: AsyncRead + AsyncWrite + Unpin + Send + 'static,
+    Error: Display,
+{
+    let mut connections = JoinSet::new();
+    let mut backoff = ACCEPT_BACKOFF_MIN;
+    loop {
+        // Reap connections that have since closed.
+        while connections.try_join_next().is_some() {}
+
+        let (mut socket, peer) = match listener.accept().await {
+            Ok(connection) => {
+                backoff = ACCEPT_BACKOFF_MIN;
+                connection
+            }
+            Err(error) => {
+                warn!(?backoff, "failed to accept tunnel connection: {error}");
+                sleep(backoff).await;
+                backoff = (backoff * 2).min(ACCEPT_BACKOFF_MAX);
+                continue;
+            }
+        };
+        let stream = open(peer);
+        connections.spawn(async move {
+            let mut stream = match stream.await {
+                Ok(stream) => stream,
+                Err(error) => {
+                    warn!(%peer, "failed to open tunnel channel: {error}");
+                    return;
+                }
+            };
+            if let Err(error) = copy_bidirectional(&mut socket, &mut stream).await {
+                debug!(%peer, "tunnel connection closed: {error}");
+            }
+        });
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use tokio::io::{duplex, AsyncReadExt, AsyncWriteExt};
+    use tokio::net::TcpStream;
+    use tokio::sync::mpsc;
+
+    use super::*;
+
+    /// Echo back whatever each connection sends.
+    async fn echo_server() -> SocketAddr {
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let addr = listener.local_addr().unwrap();
+        tokio::spawn(async move {
+            loop {
+                let (mut stream, _) = listener.accept().await.unwrap();
+                tokio::spawn(async move {
+                    let (mut read, mut write) = stream.split();
+                    let _ = tokio::io::copy(&mut read, &mut write).await;
+                });
+            }
+        });
+        addr
+    }
+
+    // Stands in for the SSH channel with a plain TCP connection, so this
+    // covers the forwarding without needing an sshd.
+    #[tokio::test]
+    async fn round_trips_bytes_through_tunnel() {
+        let echo = echo_server().await;
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let tunnel = SshTunnel {
+            local_addr: listener.local_addr().unwrap(),
+            task: tokio::spawn(forward_connections(listener, move |_peer| {
+                TcpStream::connect(echo)
+            })),
+        };
+
+        let mut client = TcpStream::connect(tunnel.local_addr()).await.unwrap();
+        client.write_all(b"ping").await.unwrap();
+        let mut buf = [0; 4];
+        client.read_exact(&mut buf).await.unwrap();
+        assert_eq!(&buf, b"ping");
+
+        // Dropping the tunnel closes connections still open through it.
+        drop(tunnel);
+        let mut rest = Vec::new();
+        client.read_to_end(&mut rest).await.unwrap();
+        assert!(rest.is_empty());
+    }
+
+    // Stands in for the SSH channel with an in-memory pipe, holding the far
+    // end as the remote side would. A real channel stream closes the channel
+    // when dropped, as the near end does here.
+    #[tokio::test]
+    async fn dropping_tunnel_closes_remote_side() {
+        let (remote_tx, mut remote_rx) = mpsc::unbounded_channel();
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let tunnel = SshTunnel {
+            local_addr: listener.local_addr().unwrap(),
+            task: tokio::spawn(forward_connections(listener, move |_peer| {
+                let (near, far) = duplex(64);
+                remote_tx.send(far).unwrap();
+                async move { Ok::<_, io::Error>(near) }
+            })),
+        };
+
+        let mut client = TcpStream::connect(tunnel.local_addr()).await.unwrap();
+        client.write_all(b"ping").await.unwrap();
+        let mut remote = remote_rx.recv().await.unwrap();
+        let mut buf = [0; 4];
+        remote.read_exact(&mut buf).await.unwrap();
+        assert_eq!(&buf, b"ping");
+
+        drop(tunnel);
+        let mut rest = Vec::new();
+        remote.read_to_end(&mut rest).await.unwrap();
+        assert!(rest.is_empty());
+        client.read_to_end(&mut rest).await.unwrap();
+        assert!(rest.is_empty());
+    }
+}
@@ -1,17 +1,21 @@
 mod command;
 mod connect;
+mod forward;
 mod keypair;
 mod session;
 mod stream;
 mod sync;
 mod terminal;
 
-pub use crate::command::{SshCommandError, SshCommandHandle};
+pub use crate::command::{shell_command, SshCommandError, SshCommandHandle};
 pub use crate::connect::{SshConnectError, SshConnectOptions};
+pub use crate::forward::{SshForwardError, SshTunnel};
 pub use crate::keypair::{SshKeypair, SshKeypairError};
-pub use crate::sync::{SshSyncError, SshVolume};
+pub use crate::sync::{SshSyncError, SshSyncOptions, SshVolume};
 pub use crate::terminal::SshTerminalError;
 
+use std::sync::Arc;
+
 use thiserror::Error;
 use tokio::net::ToSocketAddrs;
 
@@ -34,6 +38,9 @@ pub enum SshError {
     #[error(transparent)]
     Sync(#[from] SshSyncError),
 
+    #[error(transparent)]
+    Forward(#[from] SshForwardError),
+
     #[error(transparent)]
     Keypair(#[from] SshKeypairError),
 
@@ -46,7 +53,8 @@ pub enum SshError {
 
 /// High-level SSH client built on the async channel/session abstractions.
 pub struct Ssh {
-    session: Session,
+    // Shared with port forwards, which open channels in the background.
+    session: Arc<Session>,
 }
 
 impl Ssh {
@@ -57,7 +65,9 @@ impl Ssh {
         Addrs: ToSocketAddrs + Clone + Send,
     {
         let session = connect_with_retry(options).await?;
-        Ok(Self { session })
+        Ok(Self {
+            session: Arc::new(session),
+        })
     }
 
     /// Execute a remote command and get a streaming handle.
@@ -70,8 +80,12 @@ impl Ssh {
 
     /// Synchronize a volume (directory, file, or raw bytes) via SFTP.
     #[tracing::instrument(skip(self))]
-    pub async fn sync(&mut self, volume: SshVolume) -> Result<(), SshError> {
-        sync::ssh_sync(&self.session, volume)
+    pub async fn sync(
+        &mut self,
+        volume: SshVolume,
+        options: &SshSyncOptions,
+    ) -> Result<(), SshError> {
+        sync::ssh_sync(&self.session, volume, options)
             .await
             .map_err(SshError::Sync)
     }
@@ -84,6 +98,20 @@ impl Ssh {
             .map_err(SshError::Terminal)
     }
 
+    /// Forward `local_port` on localhost to `remote_host:remote_port`, as
+    /// seen from the server, until the returned tunnel is dropped.
+    #[tracing::instrument(skip(self))]
+    pub async fn forward_local(
+        &mut self,
+        local_port: u16,
+        remote_host: &str,
+        remote_port: u16,
+    ) -> Result<SshTunnel, SshError> {
+        forward::ssh_forward_local(self.session.clone(), local_port, remote_host, remote_port)
+            .await
+            .map_err(SshError::Forward)
+    }
+
     /// Disconnect the SSH session.
     #[tracing::instrument(skip(self))]
     pub async fn disconnect(&mut self) -> Result<(), SshError> {
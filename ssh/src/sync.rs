@@ -1,21 +1,25 @@
+use flate2::{write::GzEncoder, Compression};
 use russh::client::Handler;
 use russh_sftp::{
     client::{error::Error as SftpError, SftpSession},
     protocol::{FileAttributes, OpenFlags},
 };
+use sha2::{Digest, Sha256};
 use std::{
     fmt::Debug,
+    io::Write,
     path::{Path, PathBuf},
 };
 use thiserror::Error;
 use tokio::{
     fs as tfs,
-    io::{AsyncReadExt, AsyncWriteExt},
+    io::{AsyncRead, AsyncReadExt, AsyncSeekExt, AsyncWriteExt},
 };
 use tracing::{debug, info, instrument, trace, warn};
 
 use lusid_fs::{self as fs, FsError};
 
+use crate::command::shell_command;
 use crate::session::{AsyncSession, NoCheckHandler};
 
 #[derive(Clone, PartialEq, Eq)]
@@ -51,6 +55,22 @@ impl Debug for SshVolume {
     }
 }
 
+/// How a sync uploads file content.
+#[derive(Debug, Clone, Default)]
+pub struct SshSyncOptions {
+    /// Gzip files larger than this many bytes before upload, and `gunzip`
+    /// them on the remote. Smaller files, and every file when `None`, are
+    /// uploaded as is.
+    pub compress_above: Option<u64>,
+}
+
+impl SshSyncOptions {
+    fn should_compress(&self, size: u64) -> bool {
+        self.compress_above
+            .is_some_and(|threshold| size > threshold)
+    }
+}
+
 #[derive(Error, Debug)]
 pub enum SshSyncError {
     #[error("filesystem error: {0}")]
@@ -73,16 +93,24 @@ pub enum SshSyncError {
 
     #[error("source path must be a directory")]
     SourceMustBeDirectory,
+
+    #[error("failed to decompress '{path}' on the remote (exit code {exit_code:?})")]
+    Decompress {
+        path: String,
+        exit_code: Option<u32>,
+    },
 }
 
 #[instrument(skip(session))]
 pub(super) async fn ssh_sync(
     session: &AsyncSession<NoCheckHandler>,
     volume: SshVolume,
+    options: &SshSyncOptions,
 ) -> Result<(), SshSyncError> {
     info!("Starting SSH volume sync");
-    let mut sftp = open_sftp(session).await?;
-    sftp_upload_volume(&mut sftp, &volume).await?;
+    let sftp = open_sftp(session).await?;
+    let mut remote = SftpRemote { sftp, session };
+    sftp_upload_volume(&mut remote, &volume, options).await?;
     info!("Volume sync completed");
     Ok(())
 }
@@ -97,26 +125,229 @@ async fn open_sftp<H: Handler + 'static>(
     Ok(sftp)
 }
 
+/// The remote file operations a sync needs: SFTP in practice, a fake in tests.
+trait SyncRemote {
+    /// Attributes of the remote path, or `None` if nothing is there.
+    async fn stat(&mut self, path: &str) -> Result<Option<FileAttributes>, SshSyncError>;
+
+    async fn create_dir(&mut self, path: &str) -> Result<(), SshSyncError>;
+
+    /// SHA-256 of the remote file's content.
+    async fn sha256(&mut self, path: &str) -> Result<[u8; 32], SshSyncError>;
+
+    /// Create or truncate the remote file and fill it from `content`.
+    async fn write(
+        &mut self,
+        path: &str,
+        content: &mut (dyn AsyncRead + Unpin + Send),
+    ) -> Result<(), SshSyncError>;
+
+    async fn set_metadata(
+        &mut self,
+        path: &str,
+        metadata: FileAttributes,
+    ) -> Result<(), SshSyncError>;
+
+    /// Decompress the gzipped file at `compressed` into `path`, then remove it.
+    async fn gunzip(&mut self, compressed: &str, path: &str) -> Result<(), SshSyncError>;
+}
+
+/// SFTP for file operations, plus the session to run `gunzip` over.
+struct SftpRemote<'a> {
+    sftp: SftpSession,
+    session: &'a AsyncSession<NoCheckHandler>,
+}
+
+impl SyncRemote for SftpRemote<'_> {
+    async fn stat(&mut self, path: &str) -> Result<Option<FileAttributes>, SshSyncError> {
+        if !self.sftp.try_exists(path).await? {
+            return Ok(None);
+        }
+        Ok(Some(self.sftp.metadata(path).await?))
+    }
+
+    async fn create_dir(&mut self, path: &str) -> Result<(), SshSyncError> {
+        self.sftp.create_dir(path).await?;
+        Ok(())
+    }
+
+    async fn sha256(&mut self, path: &str) -> Result<[u8; 32], SshSyncError> {
+        let mut remote_file = self.sftp.open(path).await?;
+        sha256_reader(&mut remote_file).await
+    }
+
+    async fn write(
+        &mut self,
+        path: &str,
+        content: &mut (dyn AsyncRead + Unpin + Send),
+    ) -> Result<(), SshSyncError> {
+        let flags = OpenFlags::CREATE
+            .union(OpenFlags::TRUNCATE)
+            .union(OpenFlags::WRITE);
+        let mut remote_file = self.sftp.open_with_flags(path, flags).await?;
+        trace!("Opened remote file for writing");
+
+        let mut buf = vec![0u8; CHUNK_SIZE];
+        loop {
+            let n = content.read(&mut buf).await?;
+            if n == 0 {
+                break;
+            }
+            remote_file.write_all(&buf[..n]).await?;
+        }
+
+        remote_file.flush().await?;
+        remote_file.shutdown().await?;
+        Ok(())
+    }
+
+    async fn set_metadata(
+        &mut self,
+        path: &str,
+        metadata: FileAttributes,
+    ) -> Result<(), SshSyncError> {
+        self.sftp.set_metadata(path, metadata).await?;
+        Ok(())
+    }
+
+    async fn gunzip(&mut self, compressed: &str, path: &str) -> Result<(), SshSyncError> {
+        let command = format!(
+            "{} > {} && {}",
+            shell_command(&["gunzip", "-c", compressed]),
+            shell_command(&[path]),
+            shell_command(&["rm", "-f", compressed]),
+        );
+        trace!(command, "Decompressing on the remote");
+
+        let mut channel = self.session.open_channel().await?;
+        channel.exec(true, command).await?;
+        let exit_code = channel.recv_exit_status().wait().await.copied();
+        if !channel.is_closed() {
+            channel.close().await?;
+            channel.wait_close().await;
+        }
+
+        if exit_code != Some(0) {
+            return Err(SshSyncError::Decompress {
+                path: path.to_string(),
+                exit_code,
+            });
+        }
+        Ok(())
+    }
+}
+
+/// Write `content` of `size` bytes to `path`, gzipped in transit if the
+/// options say it is large enough to be worth it.
+async fn write_content(
+    remote: &mut impl SyncRemote,
+    path: &str,
+    content: &mut (dyn AsyncRead + Unpin + Send),
+    size: u64,
+    options: &SshSyncOptions,
+) -> Result<(), SshSyncError> {
+    if !options.should_compress(size) {
+        return remote.write(path, content).await;
+    }
+
+    let mut raw = Vec::with_capacity(size as usize);
+    content.read_to_end(&mut raw).await?;
+    let compressed = gzip(&raw)?;
+    debug!(
+        size_bytes = size,
+        compressed_bytes = compressed.len(),
+        "Uploading compressed"
+    );
+
+    let compressed_path = format!("{path}.gz.upload");
+    remote.write(&compressed_path, &mut &compressed[..]).await?;
+    remote.gunzip(&compressed_path, path).await
+}
+
+fn gzip(content: &[u8]) -> Result<Vec<u8>, std::io::Error> {
+    let mut encoder = GzEncoder::new(Vec::new(), Compression::default());
+    encoder.write_all(content)?;
+    encoder.finish()
+}
+
+const CHUNK_SIZE: usize = 128 * 1024;
+
+async fn sha256_reader(
+    reader: &mut (impl AsyncRead + Unpin + ?Sized),
+) -> Result<[u8; 32], SshSyncError> {
+    let mut hasher = Sha256::new();
+    let mut buf = vec![0u8; CHUNK_SIZE];
+    loop {
+        let n = reader.read(&mut buf).await?;
+        if n == 0 {
+            break;
+        }
+        hasher.update(&buf[..n]);
+    }
+    Ok(hasher.finalize().into())
+}
+
+/// What an upload has to do, given what is already on the remote.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum RemoteFileState {
+    Missing,
+    Differs,
+    /// Same content, but the permissions need updating.
+    PermissionsDiffer,
+    Unchanged,
+}
+
+/// Compare the remote file against local content of `size` bytes hashing to
+/// `sha256`: sizes first, so only same-sized files are hashed remotely.
+async fn remote_file_state(
+    remote: &mut impl SyncRemote,
+    path: &str,
+    size: u64,
+    sha256: [u8; 32],
+    permissions: Option<u32>,
+) -> Result<RemoteFileState, SshSyncError> {
+    let Some(metadata) = remote.stat(path).await? else {
+        return Ok(RemoteFileState::Missing);
+    };
+    if metadata.is_dir() || metadata.size != Some(size) {
+        return Ok(RemoteFileState::Differs);
+    }
+    if remote.sha256(path).await? != sha256 {
+        return Ok(RemoteFileState::Differs);
+    }
+    let mode = |permissions: Option<u32>| permissions.map(|p| p & 0o7777);
+    match permissions {
+        Some(_) if mode(permissions) != mode(metadata.permissions) => {
+            Ok(RemoteFileState::PermissionsDiffer)
+        }
+        _ => Ok(RemoteFileState::Unchanged),
+    }
+}
+
 async fn sftp_upload_volume(
-    sftp: &mut SftpSession,
+    sftp: &mut impl SyncRemote,
     volume: &SshVolume,
+    options: &SshSyncOptions,
 ) -> Result<(), SshSyncError> {
     match volume {
-        SshVolume::DirPath { local, remote } => sftp_upload_dir(sftp, local, remote).await,
-        SshVolume::FilePath { local, remote } => sftp_upload_file(sftp, local, remote).await,
+        SshVolume::DirPath { local, remote } => sftp_upload_dir(sftp, local, remote, options).await,
+        SshVolume::FilePath { local, remote } => {
+            sftp_upload_file(sftp, local, remote, options).await
+        }
         SshVolume::FileBytes {
             local,
             permissions,
             remote,
-        } => sftp_upload_file_bytes(sftp, local, *permissions, remote).await,
+        } => sftp_upload_file_bytes(sftp, local, *permissions, remote, options).await,
     }
 }
 
 #[instrument(skip(sftp))]
 async fn sftp_upload_dir(
-    sftp: &mut SftpSession,
+    sftp: &mut impl SyncRemote,
     local_root: &Path,
     remote_root: &str,
+    options: &SshSyncOptions,
 ) -> Result<(), SshSyncError> {
     if !local_root.is_dir() {
         return Err(SshSyncError::SourceMustBeDirectory);
@@ -151,7 +382,7 @@ async fn sftp_upload_dir(
             } else if md.is_file() {
                 let rel = path.strip_prefix(local_root).unwrap_or(Path::new(""));
                 let remote_file = remote_join(remote_root, rel);
-                sftp_upload_file(sftp, &path, &remote_file).await?;
+                sftp_upload_file(sftp, &path, &remote_file, options).await?;
             } else {
                 warn!(
                     path = %path.display(),
@@ -168,9 +399,10 @@ async fn sftp_upload_dir(
 
 #[instrument(skip(sftp))]
 async fn sftp_upload_file(
-    sftp: &mut SftpSession,
+    sftp: &mut impl SyncRemote,
     local: &Path,
     remote: &str,
+    options: &SshSyncOptions,
 ) -> Result<(), SshSyncError> {
     #[allow(clippy::collapsible_if)]
     if let Some(parent) = remote_parent(remote) {
@@ -185,37 +417,34 @@ async fn sftp_upload_file(
     let size = local_metadata.len();
     trace!(local = %local.display(), size_bytes = size, "Opened local file");
 
-    let flags = OpenFlags::CREATE
-        .union(OpenFlags::TRUNCATE)
-        .union(OpenFlags::WRITE);
-    let mut remote_file = sftp.open_with_flags(remote, flags).await?;
-    trace!("Opened remote file for writing");
-
-    let mut buf = vec![0u8; 128 * 1024];
-    loop {
-        let n = local_file.read(&mut buf).await?;
-        if n == 0 {
-            break;
+    let remote_metadata: FileAttributes = (&local_metadata).into();
+    let sha256 = sha256_reader(&mut local_file).await?;
+    match remote_file_state(sftp, remote, size, sha256, remote_metadata.permissions).await? {
+        RemoteFileState::Unchanged => {
+            debug!("Remote file unchanged, skipping upload");
+            return Ok(());
+        }
+        RemoteFileState::PermissionsDiffer => {
+            debug!("Remote file unchanged, updating permissions");
+        }
+        RemoteFileState::Missing | RemoteFileState::Differs => {
+            local_file.rewind().await?;
+            write_content(sftp, remote, &mut local_file, size, options).await?;
+            debug!("File upload completed");
         }
-        remote_file.write_all(&buf[..n]).await?;
     }
 
-    remote_file.flush().await?;
-    remote_file.shutdown().await?;
-
-    let remote_metadata: FileAttributes = (&local_metadata).into();
     sftp.set_metadata(remote, remote_metadata).await?;
-
-    debug!("File upload completed");
     Ok(())
 }
 
 #[instrument(skip(sftp))]
 async fn sftp_upload_file_bytes(
-    sftp: &mut SftpSession,
+    sftp: &mut impl SyncRemote,
     local: &[u8],
     permissions: Option<u32>,
     remote: &str,
+    options: &SshSyncOptions,
 ) -> Result<(), SshSyncError> {
     #[allow(clippy::collapsible_if)]
     if let Some(parent) = remote_parent(remote) {
@@ -225,28 +454,36 @@ async fn sftp_upload_file_bytes(
         }
     }
 
-    let flags = OpenFlags::CREATE
-        .union(OpenFlags::TRUNCATE)
-        .union(OpenFlags::WRITE);
-    let mut remote_file = sftp.open_with_flags(remote, flags).await?;
-    trace!("Opened remote file for writing");
+    let sha256 = Sha256::digest(local).into();
+    match remote_file_state(sftp, remote, local.len() as u64, sha256, permissions).await? {
+        RemoteFileState::Unchanged => {
+            debug!("Remote file unchanged, skipping upload");
+            return Ok(());
+        }
+        RemoteFileState::PermissionsDiffer => {
+            debug!("Remote file unchanged, updating permissions");
+        }
+        RemoteFileState::Missing | RemoteFileState::Differs => {
+            write_content(sftp, remote, &mut &*local, local.len() as u64, options).await?;
+            debug!("File upload completed");
+        }
+    }
 
-    remote_file.write_all(local).await?;
-    remote_file.flush().await?;
-    remote_file
-        .set_metadata(FileAttributes {
-            permissions,
-            ..FileAttributes::empty()
-        })
+    if permissions.is_some() {
+        sftp.set_metadata(
+            remote,
+            FileAttributes {
+                permissions,
+                ..FileAttributes::empty()
+            },
+        )
         .await?;
-    remote_file.shutdown().await?;
-
-    debug!("File upload completed");
+    }
     Ok(())
 }
 
 #[instrument(skip(sftp))]
-async fn sftp_mkdirs(sftp: &mut SftpSession, remote_dir: &str) -> Result<(), SshSyncError> {
+async fn sftp_mkdirs(sftp: &mut impl SyncRemote, remote_dir: &str) -> Result<(), SshSyncError> {
     let remote_dir = remote_dir.trim();
     if remote_dir.is_empty() || remote_dir == "." {
         return Ok(());
@@ -265,8 +502,7 @@ async fn sftp_mkdirs(sftp: &mut SftpSession, remote_dir: &str) -> Result<(), Ssh
             accum.push_str(seg);
         }
 
-        if sftp.try_exists(&accum).await? {
-            let metadata = sftp.metadata(&accum).await?;
+        if let Some(metadata) = sftp.stat(&accum).await? {
             if metadata.is_dir() {
                 trace!(path = %accum, "Remote directory already exists");
             } else {
@@ -279,14 +515,14 @@ async fn sftp_mkdirs(sftp: &mut SftpSession, remote_dir: &str) -> Result<(), Ssh
         }
 
         match sftp.create_dir(&accum).await {
-            Ok(_) => trace!(path = %accum, "Created remote directory"),
+            Ok(()) => trace!(path = %accum, "Created remote directory"),
             Err(e) => {
                 tracing::error!(
                     path = %accum,
                     error = %e,
                     "Failed to create remote directory"
                 );
-                return Err(SshSyncError::from(e));
+                return Err(e);
             }
         }
     }
@@ -324,3 +560,177 @@ fn remote_parent(path: &str) -> Option<&str> {
         None => None,
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use std::collections::HashMap;
+    use std::io::Read;
+
+    use flate2::read::GzDecoder;
+
+    use super::*;
+
+    /// An in-memory remote that counts how often it is written to.
+    #[derive(Default)]
+    struct CountingRemote {
+        files: HashMap<String, (Vec<u8>, FileAttributes)>,
+        dirs: Vec<String>,
+        writes: usize,
+        gunzips: usize,
+    }
+
+    impl SyncRemote for CountingRemote {
+        async fn stat(&mut self, path: &str) -> Result<Option<FileAttributes>, SshSyncError> {
+            if self.dirs.iter().any(|dir| dir == path) {
+                let mut metadata = FileAttributes::empty();
+                metadata.set_dir(true);
+                return Ok(Some(metadata));
+            }
+            Ok(self
+                .files
+                .get(path)
+                .map(|(content, metadata)| FileAttributes {
+                    size: Some(content.len() as u64),
+                    ..metadata.clone()
+                }))
+        }
+
+        async fn create_dir(&mut self, path: &str) -> Result<(), SshSyncError> {
+            self.dirs.push(path.to_string());
+            Ok(())
+        }
+
+        async fn sha256(&mut self, path: &str) -> Result<[u8; 32], SshSyncError> {
+            Ok(Sha256::digest(&self.files[path].0).into())
+        }
+
+        async fn write(
+            &mut self,
+            path: &str,
+            content: &mut (dyn AsyncRead + Unpin + Send),
+        ) -> Result<(), SshSyncError> {
+            self.writes += 1;
+            let mut buf = Vec::new();
+            content.read_to_end(&mut buf).await?;
+            self.files
+                .insert(path.to_string(), (buf, FileAttributes::empty()));
+            Ok(())
+        }
+
+        async fn set_metadata(
+            &mut self,
+            path: &str,
+            metadata: FileAttributes,
+        ) -> Result<(), SshSyncError> {
+            self.writes += 1;
+            let (_, current) = self.files.get_mut(path).expect("file exists");
+            current.permissions = metadata.permissions;
+            Ok(())
+        }
+
+        async fn gunzip(&mut self, compressed: &str, path: &str) -> Result<(), SshSyncError> {
+            self.gunzips += 1;
+            let (content, _) = self
+                .files
+                .remove(compressed)
+                .expect("compressed file exists");
+            let mut decompressed = Vec::new();
+            GzDecoder::new(&content[..]).read_to_end(&mut decompressed)?;
+            let metadata = self
+                .files
+                .remove(path)
+                .map(|(_, metadata)| metadata)
+                .unwrap_or_else(FileAttributes::empty);
+            self.files
+                .insert(path.to_string(), (decompressed, metadata));
+            Ok(())
+        }
+    }
+
+    #[tokio::test]
+    async fn second_sync_of_same_content_writes_nothing() {
+        let dir = tempfile::tempdir().unwrap();
+        std::fs::create_dir(dir.path().join("etc")).unwrap();
+        std::fs::write(dir.path().join("etc/motd"), b"hello\n").unwrap();
+        std::fs::write(dir.path().join("README"), b"readme\n").unwrap();
+        let volumes = [
+            SshVolume::DirPath {
+                local: dir.path().to_path_buf(),
+                remote: "/srv/app".into(),
+            },
+            SshVolume::FileBytes {
+                local: b"key = value\n".to_vec(),
+                permissions: Some(0o600),
+                remote: "/srv/app.conf".into(),
+            },
+        ];
+
+        let options = SshSyncOptions::default();
+        let mut remote = CountingRemote::default();
+        for volume in &volumes {
+            sftp_upload_volume(&mut remote, volume, &options)
+                .await
+                .unwrap();
+        }
+        assert!(remote.writes > 0);
+        assert_eq!(remote.files["/srv/app/etc/motd"].0, b"hello\n");
+
+        remote.writes = 0;
+        for volume in &volumes {
+            sftp_upload_volume(&mut remote, volume, &options)
+                .await
+                .unwrap();
+        }
+        assert_eq!(remote.writes, 0);
+
+        std::fs::write(dir.path().join("etc/motd"), b"changed\n").unwrap();
+        sftp_upload_volume(&mut remote, &volumes[0], &options)
+            .await
+            .unwrap();
+        assert_eq!(remote.files["/srv/app/etc/motd"].0, b"changed\n");
+    }
+
+    #[test]
+    fn compresses_only_above_threshold() {
+        assert!(!SshSyncOptions::default().should_compress(u64::MAX));
+        let options = SshSyncOptions {
+            compress_above: Some(1024),
+        };
+        assert!(!options.should_compress(0));
+        assert!(!options.should_compress(1024));
+        assert!(options.should_compress(1025));
+    }
+
+    #[tokio::test]
+    async fn large_files_round_trip_compressed() {
+        let options = SshSyncOptions {
+            compress_above: Some(64),
+        };
+        let large = "lusid ".repeat(100).into_bytes();
+        let volumes = [
+            SshVolume::FileBytes {
+                local: large.clone(),
+                permissions: Some(0o755),
+                remote: "/srv/large".into(),
+            },
+            SshVolume::FileBytes {
+                local: b"small".to_vec(),
+                permissions: None,
+                remote: "/srv/small".into(),
+            },
+        ];
+
+        let mut remote = CountingRemote::default();
+        for volume in &volumes {
+            sftp_upload_volume(&mut remote, volume, &options)
+                .await
+                .unwrap();
+        }
+
+        assert_eq!(remote.gunzips, 1);
+        assert_eq!(remote.files["/srv/large"].0, large);
+        assert_eq!(remote.files["/srv/large"].1.permissions, Some(0o755));
+        assert_eq!(remote.files["/srv/small"].0, b"small");
+        assert!(!remote.files.contains_key("/srv/large.gz.upload"));
+    }
+}
@@ -1,6 +1,7 @@
 use async_trait::async_trait;
 use displaydoc::Display;
 use std::{
+    collections::HashMap,
     fmt::Debug,
     io,
     path::{Path, PathBuf},
@@ -15,28 +16,38 @@ pub trait SubStore {
     fn new(cache_dir: PathBuf) -> Self;
 
     async fn read(&mut self, id: &Self::ItemId) -> Result<Vec<u8>, Self::Error>;
+
+    /// Store bytes, so a later read of the same id gets them back.
+    async fn write(&mut self, id: &Self::ItemId, bytes: &[u8]) -> Result<(), Self::Error>;
 }
 
 #[derive(Debug, Clone)]
 pub struct Store {
     local_file_store: LocalFileStore,
+    memory_store: MemoryStore,
 }
 
 #[derive(Debug, Clone)]
 pub enum StoreItemId {
     LocalFile(PathBuf),
+    /// A file under the cache directory, by its path relative to it.
+    Cache(PathBuf),
+    Memory(String),
 }
 
 #[derive(Debug, Error, Display)]
 pub enum StoreError {
     /// Local file store failed
     LocalFile(#[from] io::Error),
+    /// Memory store failed
+    Memory(#[from] MemoryStoreError),
 }
 
 impl Store {
     pub fn new(cache_dir: &Path) -> Self {
         Self {
             local_file_store: LocalFileStore::new(cache_dir.join("files")),
+            memory_store: MemoryStore::new(cache_dir.join("memory")),
         }
     }
 
@@ -47,23 +58,180 @@ impl Store {
                 .read(id)
                 .await
                 .map_err(StoreError::from),
+            StoreItemId::Cache(id) => self
+                .local_file_store
+                .read(&self.local_file_store.cached(id))
+                .await
+                .map_err(StoreError::from),
+            StoreItemId::Memory(id) => self.memory_store.read(id).await.map_err(StoreError::from),
+        }
+    }
+
+    pub async fn write(&mut self, id: &StoreItemId, bytes: &[u8]) -> Result<(), StoreError> {
+        match id {
+            StoreItemId::LocalFile(id) => self
+                .local_file_store
+                .write(id, bytes)
+                .await
+                .map_err(StoreError::from),
+            StoreItemId::Cache(id) => {
+                let path = self.local_file_store.cached(id);
+                self.local_file_store
+                    .write(&path, bytes)
+                    .await
+                    .map_err(StoreError::from)
+            }
+            StoreItemId::Memory(id) => self
+                .memory_store
+                .write(id, bytes)
+                .await
+                .map_err(StoreError::from),
         }
     }
 }
 
 #[derive(Debug, Clone, Default)]
-pub struct LocalFileStore;
+pub struct LocalFileStore {
+    cache_dir: PathBuf,
+}
+
+impl LocalFileStore {
+    /// Where a cached item lives.
+    fn cached(&self, id: &Path) -> PathBuf {
+        self.cache_dir.join(id)
+    }
+}
 
 #[async_trait]
 impl SubStore for LocalFileStore {
     type ItemId = PathBuf;
     type Error = io::Error;
 
-    fn new(_cache_dir: PathBuf) -> Self {
-        Self
+    fn new(cache_dir: PathBuf) -> Self {
+        Self { cache_dir }
     }
 
     async fn read(&mut self, id: &Self::ItemId) -> Result<Vec<u8>, Self::Error> {
         tokio::fs::read(id).await
     }
+
+    /// Write to a sibling temporary file, then rename it into place, so
+    /// readers never see a partly written file.
+    async fn write(&mut self, id: &Self::ItemId, bytes: &[u8]) -> Result<(), Self::Error> {
+        if let Some(parent) = id.parent() {
+            tokio::fs::create_dir_all(parent).await?;
+        }
+        let mut partial = id.as_os_str().to_owned();
+        partial.push(".partial");
+        let partial = PathBuf::from(partial);
+        tokio::fs::write(&partial, bytes).await?;
+        tokio::fs::rename(&partial, id).await
+    }
+}
+
+/// Items held in memory, so tests can provide sources without touching disk.
+#[derive(Debug, Clone, Default)]
+pub struct MemoryStore {
+    items: HashMap<String, Vec<u8>>,
+}
+
+#[derive(Debug, Error, Display)]
+pub enum MemoryStoreError {
+    /// Memory store item not found: {id}
+    NotFound { id: String },
+}
+
+#[async_trait]
+impl SubStore for MemoryStore {
+    type ItemId = String;
+    type Error = MemoryStoreError;
+
+    fn new(_cache_dir: PathBuf) -> Self {
+        Self::default()
+    }
+
+    async fn read(&mut self, id: &Self::ItemId) -> Result<Vec<u8>, Self::Error> {
+        self.items
+            .get(id)
+            .cloned()
+            .ok_or_else(|| MemoryStoreError::NotFound { id: id.clone() })
+    }
+
+    async fn write(&mut self, id: &Self::ItemId, bytes: &[u8]) -> Result<(), Self::Error> {
+        self.items.insert(id.clone(), bytes.to_vec());
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[tokio::test]
+    async fn local_file_write_then_read() {
+        let dir = tempfile::tempdir().unwrap();
+        let mut store = Store::new(dir.path());
+        let id = StoreItemId::LocalFile(dir.path().join("plans/remote/web.lusid"));
+
+        store.write(&id, b"first").await.unwrap();
+        assert_eq!(store.read(&id).await.unwrap(), b"first");
+
+        store.write(&id, b"second").await.unwrap();
+        assert_eq!(store.read(&id).await.unwrap(), b"second");
+        assert!(!dir.path().join("plans/remote/web.lusid.partial").exists());
+    }
+
+    #[tokio::test]
+    async fn local_file_read_missing() {
+        let dir = tempfile::tempdir().unwrap();
+        let mut store = Store::new(dir.path());
+        let id = StoreItemId::LocalFile(dir.path().join("missing"));
+
+        let error = store.read(&id).await.unwrap_err();
+        assert!(matches!(
+            error,
+            StoreError::LocalFile(error) if error.kind() == io::ErrorKind::NotFound
+        ));
+    }
+
+    #[tokio::test]
+    async fn memory_store_reads_seeded_plans() {
+        let mut store = MemoryStore::default();
+        let web = "web.lusid".to_string();
+        let db = "db.lusid".to_string();
+        store.write(&web, b"name: web").await.unwrap();
+        store.write(&db, b"name: db").await.unwrap();
+
+        assert_eq!(store.read(&web).await.unwrap(), b"name: web");
+        assert_eq!(store.read(&db).await.unwrap(), b"name: db");
+        assert!(matches!(
+            store.read(&"cache.lusid".to_string()).await,
+            Err(MemoryStoreError::NotFound { .. })
+        ));
+    }
+
+    #[tokio::test]
+    async fn cache_items_live_under_cache_dir() {
+        let dir = tempfile::tempdir().unwrap();
+        let mut store = Store::new(dir.path());
+        let id = StoreItemId::Cache(PathBuf::from("plans/web.lusid"));
+
+        store.write(&id, b"name: web").await.unwrap();
+        assert_eq!(
+            std::fs::read(dir.path().join("files/plans/web.lusid")).unwrap(),
+            b"name: web"
+        );
+        assert_eq!(store.read(&id).await.unwrap(), b"name: web");
+    }
+
+    #[tokio::test]
+    async fn store_reads_memory_items() {
+        let dir = tempfile::tempdir().unwrap();
+        let mut store = Store::new(dir.path());
+        let id = StoreItemId::Memory("web.lusid".to_string());
+
+        store.write(&id, b"name: web").await.unwrap();
+        assert_eq!(store.read(&id).await.unwrap(), b"name: web");
+        assert!(!dir.path().join("memory").exists());
+    }
 }
@@ -1,8 +1,9 @@
-use std::fmt::Display;
+use std::{fmt::Display, str::FromStr};
 
-use serde::{Deserialize, Serialize};
+use serde::{de, Deserialize, Serialize};
+use thiserror::Error;
 
-#[derive(Debug, Copy, Clone, PartialEq, Eq, PartialOrd, Ord, Hash, Serialize, Deserialize)]
+#[derive(Debug, Copy, Clone, PartialEq, Eq, PartialOrd, Ord, Hash, Serialize)]
 pub struct CpuCount(u16);
 
 impl CpuCount {
@@ -17,7 +18,53 @@ impl Display for CpuCount {
     }
 }
 
-#[derive(Debug, Copy, Clone, PartialEq, Eq, PartialOrd, Ord, Hash, Serialize, Deserialize)]
+#[derive(Debug, Clone, PartialEq, Eq, Error)]
+pub enum ParseCpuCountError {
+    #[error("invalid cpu count \"{0}\": expected a positive integer")]
+    Invalid(String),
+
+    #[error("cpu count must be at least 1")]
+    Zero,
+}
+
+impl TryFrom<u64> for CpuCount {
+    type Error = ParseCpuCountError;
+
+    fn try_from(value: u64) -> Result<Self, Self::Error> {
+        match u16::try_from(value) {
+            Ok(0) => Err(ParseCpuCountError::Zero),
+            Ok(count) => Ok(Self(count)),
+            Err(_) => Err(ParseCpuCountError::Invalid(value.to_string())),
+        }
+    }
+}
+
+impl FromStr for CpuCount {
+    type Err = ParseCpuCountError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let value: u64 = s
+            .trim()
+            .parse()
+            .map_err(|_| ParseCpuCountError::Invalid(s.to_string()))?;
+        Self::try_from(value)
+    }
+}
+
+impl<'de> Deserialize<'de> for CpuCount {
+    fn deserialize<D>(deserializer: D) -> Result<Self, D::Error>
+    where
+        D: de::Deserializer<'de>,
+    {
+        match NumberOrString::deserialize(deserializer)? {
+            NumberOrString::Number(value) => CpuCount::try_from(value),
+            NumberOrString::String(value) => value.parse(),
+        }
+        .map_err(de::Error::custom)
+    }
+}
+
+#[derive(Debug, Copy, Clone, PartialEq, Eq, PartialOrd, Ord, Hash, Serialize)]
 pub struct MemorySize(u64); // In bytes
 
 impl MemorySize {
@@ -37,3 +84,110 @@ impl From<MemorySize> for u64 {
         value.0
     }
 }
+
+#[derive(Debug, Clone, PartialEq, Eq, Error)]
+pub enum ParseMemorySizeError {
+    #[error("invalid memory size \"{0}\": expected a number with optional unit (e.g. 512M, 4G)")]
+    Invalid(String),
+
+    #[error(
+        "invalid memory size unit \"{0}\": expected one of B, K, M, G, T (optionally with iB or B)"
+    )]
+    Unit(String),
+}
+
+/// Parses sizes like "4G", "512M", "2GiB" or "1073741824".
+///
+/// Units are binary (powers of 1024), as with QEMU's `-m` option.
+impl FromStr for MemorySize {
+    type Err = ParseMemorySizeError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let trimmed = s.trim();
+        let split = trimmed
+            .find(|c: char| !(c.is_ascii_digit() || c == '.'))
+            .unwrap_or(trimmed.len());
+        let (number, unit) = trimmed.split_at(split);
+
+        let number: f64 = number
+            .parse()
+            .map_err(|_| ParseMemorySizeError::Invalid(s.to_string()))?;
+
+        let multiplier: u64 = match unit.trim().to_ascii_uppercase().as_str() {
+            "" | "B" => 1,
+            "K" | "KB" | "KIB" => 1 << 10,
+            "M" | "MB" | "MIB" => 1 << 20,
+            "G" | "GB" | "GIB" => 1 << 30,
+            "T" | "TB" | "TIB" => 1 << 40,
+            _ => return Err(ParseMemorySizeError::Unit(unit.to_string())),
+        };
+
+        let bytes = number * multiplier as f64;
+        if !bytes.is_finite() || bytes > u64::MAX as f64 {
+            return Err(ParseMemorySizeError::Invalid(s.to_string()));
+        }
+
+        Ok(Self(bytes as u64))
+    }
+}
+
+impl<'de> Deserialize<'de> for MemorySize {
+    fn deserialize<D>(deserializer: D) -> Result<Self, D::Error>
+    where
+        D: de::Deserializer<'de>,
+    {
+        match NumberOrString::deserialize(deserializer)? {
+            NumberOrString::Number(bytes) => Ok(MemorySize(bytes)),
+            NumberOrString::String(value) => value.parse().map_err(de::Error::custom),
+        }
+    }
+}
+
+#[derive(Deserialize)]
+#[serde(untagged)]
+enum NumberOrString {
+    Number(u64),
+    String(String),
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn memory_size_from_str() {
+        assert_eq!("4G".parse(), Ok(MemorySize::new(4 * 1024 * 1024 * 1024)));
+        assert_eq!("512M".parse(), Ok(MemorySize::new(512 * 1024 * 1024)));
+        assert_eq!("2GiB".parse(), Ok(MemorySize::new(2 * 1024 * 1024 * 1024)));
+        assert_eq!("1024".parse(), Ok(MemorySize::new(1024)));
+    }
+
+    #[test]
+    fn memory_size_invalid() {
+        assert_eq!(
+            "lots".parse::<MemorySize>(),
+            Err(ParseMemorySizeError::Invalid("lots".to_string()))
+        );
+        assert_eq!(
+            "4X".parse::<MemorySize>(),
+            Err(ParseMemorySizeError::Unit("X".to_string()))
+        );
+    }
+
+    #[test]
+    fn memory_size_deserialize() {
+        let size: MemorySize = serde_json::from_str(r#""4G""#).unwrap();
+        assert_eq!(u64::from(size), 4 * 1024 * 1024 * 1024);
+        let size: MemorySize = serde_json::from_str("1024").unwrap();
+        assert_eq!(u64::from(size), 1024);
+    }
+
+    #[test]
+    fn cpu_count_from_str() {
+        assert_eq!("2".parse(), Ok(CpuCount::new(2)));
+        assert_eq!("0".parse::<CpuCount>(), Err(ParseCpuCountError::Zero));
+        assert!("lots".parse::<CpuCount>().is_err());
+        let count: CpuCount = serde_json::from_str(r#""4""#).unwrap();
+        assert_eq!(count, CpuCount::new(4));
+    }
+}
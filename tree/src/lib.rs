@@ -12,11 +12,18 @@
 //! - From<Tree> to FlatTree always creates the root at index 0.
 //! - From<FlatTree> to Tree is lenient: missing children are skipped; if the
 //!   root is missing, returns an empty Branch with default Meta.
+//!
+//! Serialization:
+//! - FlatTree serializes its node list as is, missing nodes included, so
+//!   indices are the same after a round trip.
+//! - Deserializing a FlatTree validates it, rejecting children that point at
+//!   missing nodes and cycles.
 
+use serde::{Deserialize, Serialize};
 use std::future::Future;
 use thiserror::Error;
 
-#[derive(Debug, Clone)]
+#[derive(Debug, Clone, Serialize, Deserialize)]
 pub enum Tree<Node, Meta> {
     Branch {
         meta: Meta,
@@ -91,23 +98,95 @@ impl<Node, Meta> Tree<Node, Meta> {
     }
 }
 
-#[derive(Debug, Clone)]
+#[derive(Debug, Clone, Serialize, Deserialize)]
 pub enum FlatTreeNode<Node, Meta> {
     Branch { meta: Meta, children: Vec<usize> },
     Leaf { meta: Meta, node: Node },
 }
 
-#[derive(Debug, Clone)]
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(try_from = "UncheckedFlatTree<Node, Meta>")]
 pub struct FlatTree<Node, Meta> {
     nodes: Vec<Option<FlatTreeNode<Node, Meta>>>,
 }
 
+/// A deserialized FlatTree, before it has been validated.
+#[derive(Deserialize)]
+struct UncheckedFlatTree<Node, Meta> {
+    nodes: Vec<Option<FlatTreeNode<Node, Meta>>>,
+}
+
+impl<Node, Meta> TryFrom<UncheckedFlatTree<Node, Meta>> for FlatTree<Node, Meta> {
+    type Error = FlatTreeError;
+
+    fn try_from(unchecked: UncheckedFlatTree<Node, Meta>) -> Result<Self, Self::Error> {
+        let flat = FlatTree {
+            nodes: unchecked.nodes,
+        };
+        flat.validate()?;
+        Ok(flat)
+    }
+}
+
 #[derive(Debug, Error)]
 pub enum FlatTreeError {
     #[error("node at index {0} is None")]
     NodeMissing(usize),
     #[error("index {0} is out of bounds")]
     IndexOutOfBounds(usize),
+    #[error("node at index {parent} has child {child}, which is missing or out of bounds")]
+    DanglingChild { parent: usize, child: usize },
+    #[error("node at index {0} is its own descendant")]
+    Cycle(usize),
+}
+
+#[derive(Clone, Copy, PartialEq, Eq)]
+enum Visit {
+    New,
+    OnPath,
+    Done,
+}
+
+impl<Node, Meta> FlatTree<Node, Meta> {
+    /// Check every child index points at a node, and no node is its own
+    /// descendant.
+    pub fn validate(&self) -> Result<(), FlatTreeError> {
+        let mut visits = vec![Visit::New; self.nodes.len()];
+        for start in 0..self.nodes.len() {
+            if visits[start] != Visit::New {
+                continue;
+            }
+            visits[start] = Visit::OnPath;
+            // Each entry is a node on the current path and its next child to visit.
+            let mut stack = vec![(start, 0)];
+            while let Some((index, position)) = stack.pop() {
+                let children: &[usize] = match &self.nodes[index] {
+                    Some(FlatTreeNode::Branch { children, .. }) => children.as_slice(),
+                    _ => &[],
+                };
+                let Some(&child) = children.get(position) else {
+                    visits[index] = Visit::Done;
+                    continue;
+                };
+                stack.push((index, position + 1));
+                if !matches!(self.nodes.get(child), Some(Some(_))) {
+                    return Err(FlatTreeError::DanglingChild {
+                        parent: index,
+                        child,
+                    });
+                }
+                match visits[child] {
+                    Visit::OnPath => return Err(FlatTreeError::Cycle(child)),
+                    Visit::Done => {}
+                    Visit::New => {
+                        visits[child] = Visit::OnPath;
+                        stack.push((child, 0));
+                    }
+                }
+            }
+        }
+        Ok(())
+    }
 }
 
 impl<Node, Meta> FlatTree<Node, Meta>
@@ -367,7 +446,8 @@ where
         Error,
         MapFn,
         Fut,
-        WriteFut,
+        WriteStartFut,
+        WriteUpdateFut,
         WriteStartFn,
         WriteUpdateFn,
     >(
@@ -380,9 +460,10 @@ where
         NextNode: Clone,
         MapFn: Fn(Node, Meta) -> Fut + Copy,
         Fut: Future<Output = Result<Tree<NextNode, Meta>, Error>>,
-        WriteFut: Future<Output = Result<(), Error>>,
-        WriteStartFn: Fn(usize) -> WriteFut,
-        WriteUpdateFn: Fn(usize, Tree<NextNode, Meta>) -> WriteFut,
+        WriteStartFut: Future<Output = Result<(), Error>>,
+        WriteUpdateFut: Future<Output = Result<(), Error>>,
+        WriteStartFn: Fn(usize) -> WriteStartFut,
+        WriteUpdateFn: Fn(usize, Tree<NextNode, Meta>) -> WriteUpdateFut,
     {
         let mut next_nodes = vec![None; self.nodes.len()];
         for (index, node) in self.nodes.into_iter().enumerate() {
@@ -529,3 +610,85 @@ where
         })
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use serde_json::json;
+
+    use super::*;
+
+    fn leaf(node: &str) -> Tree<String, ()> {
+        Tree::leaf((), node.to_string())
+    }
+
+    #[test]
+    fn flat_tree_round_trips_through_serde() {
+        let tree = Tree::branch(
+            (),
+            vec![leaf("a"), Tree::branch((), vec![leaf("b"), leaf("c")])],
+        );
+        let mut flat = FlatTree::from(tree);
+        // Leaves missing nodes behind, with the new subtree appended.
+        flat.replace_tree(Some(Tree::branch((), vec![leaf("d")])), 2);
+
+        let json = serde_json::to_value(&flat).unwrap();
+        assert_eq!(json["nodes"][3], json!(null));
+        let back: FlatTree<String, ()> = serde_json::from_value(json.clone()).unwrap();
+
+        assert_eq!(serde_json::to_value(&back).unwrap(), json);
+        assert!(back.validate().is_ok());
+        assert_eq!(
+            serde_json::to_value(Tree::from(back)).unwrap(),
+            serde_json::to_value(Tree::branch(
+                (),
+                vec![leaf("a"), Tree::branch((), vec![leaf("d")])],
+            ))
+            .unwrap()
+        );
+    }
+
+    fn branch(children: Vec<usize>) -> Option<FlatTreeNode<String, ()>> {
+        Some(FlatTreeNode::Branch { meta: (), children })
+    }
+
+    fn flat_leaf(node: &str) -> Option<FlatTreeNode<String, ()>> {
+        Some(FlatTreeNode::Leaf {
+            meta: (),
+            node: node.to_string(),
+        })
+    }
+
+    #[test]
+    fn validates_well_formed_tree() {
+        let tree = Tree::branch((), vec![leaf("a"), Tree::branch((), vec![leaf("b")])]);
+        assert!(FlatTree::from(tree).validate().is_ok());
+        assert!(FlatTree::<String, ()> { nodes: Vec::new() }
+            .validate()
+            .is_ok());
+    }
+
+    #[test]
+    fn rejects_dangling_child() {
+        let flat = FlatTree {
+            nodes: vec![branch(vec![1, 2, 3]), flat_leaf("a"), None],
+        };
+        assert!(matches!(
+            flat.validate(),
+            Err(FlatTreeError::DanglingChild {
+                parent: 0,
+                child: 2
+            })
+        ));
+
+        let json = serde_json::to_value(&flat).unwrap();
+        assert!(serde_json::from_value::<FlatTree<String, ()>>(json).is_err());
+    }
+
+    #[test]
+    fn rejects_cycle() {
+        let flat = FlatTree {
+            nodes: vec![branch(vec![1]), branch(vec![2]), branch(vec![1])],
+        };
+        assert!(matches!(flat.validate(), Err(FlatTreeError::Cycle(1))));
+    }
+}
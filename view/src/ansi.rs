@@ -0,0 +1,186 @@
+use crate::{Color, Fragment, Line, Paragraph, Span, TextStyle, View};
+
+const RESET: &str = "\x1b[0m";
+
+impl Color {
+    /// Index in the standard 16-color ANSI palette.
+    fn ansi_index(&self) -> u8 {
+        match self {
+            Color::Black => 0,
+            Color::Red => 1,
+            Color::Green => 2,
+            Color::Yellow => 3,
+            Color::Blue => 4,
+            Color::Magenta => 5,
+            Color::Cyan => 6,
+            Color::Gray => 7,
+            Color::DarkGray => 8,
+            Color::LightRed => 9,
+            Color::LightGreen => 10,
+            Color::LightYellow => 11,
+            Color::LightBlue => 12,
+            Color::LightMagenta => 13,
+            Color::LightCyan => 14,
+            Color::White => 15,
+        }
+    }
+
+    fn ansi_fg_code(&self) -> u8 {
+        match self.ansi_index() {
+            index @ 0..=7 => 30 + index,
+            index => 90 + index - 8,
+        }
+    }
+
+    fn ansi_bg_code(&self) -> u8 {
+        self.ansi_fg_code() + 10
+    }
+}
+
+impl TextStyle {
+    /// Layer `other` on top of this style, as a span's style applies within its line.
+    pub fn patch(&self, other: &TextStyle) -> TextStyle {
+        TextStyle {
+            foreground_color: other
+                .foreground_color
+                .clone()
+                .or_else(|| self.foreground_color.clone()),
+            background_color: other
+                .background_color
+                .clone()
+                .or_else(|| self.background_color.clone()),
+            is_bold: self.is_bold || other.is_bold,
+            is_italic: self.is_italic || other.is_italic,
+            is_underlined: self.is_underlined || other.is_underlined,
+            underline_color: other
+                .underline_color
+                .clone()
+                .or_else(|| self.underline_color.clone()),
+            is_crossed_out: self.is_crossed_out || other.is_crossed_out,
+        }
+    }
+
+    /// SGR parameters for this style, empty if the style is plain.
+    fn sgr_codes(&self) -> Vec<String> {
+        let mut codes = Vec::new();
+        if self.is_bold {
+            codes.push("1".to_string());
+        }
+        if self.is_italic {
+            codes.push("3".to_string());
+        }
+        if self.is_underlined {
+            codes.push("4".to_string());
+        }
+        if self.is_crossed_out {
+            codes.push("9".to_string());
+        }
+        if let Some(color) = &self.foreground_color {
+            codes.push(color.ansi_fg_code().to_string());
+        }
+        if let Some(color) = &self.background_color {
+            codes.push(color.ansi_bg_code().to_string());
+        }
+        if let Some(color) = &self.underline_color {
+            codes.push(format!("58;5;{}", color.ansi_index()));
+        }
+        codes
+    }
+
+    fn render_ansi(&self, content: &str) -> String {
+        let codes = self.sgr_codes();
+        if codes.is_empty() || content.is_empty() {
+            return content.to_string();
+        }
+        format!("\x1b[{}m{content}{RESET}", codes.join(";"))
+    }
+}
+
+impl Span {
+    /// Render with ANSI escape codes for the span's style.
+    pub fn render_ansi(&self) -> String {
+        self.render_ansi_within(&TextStyle::default())
+    }
+
+    fn render_ansi_within(&self, base: &TextStyle) -> String {
+        base.patch(&self.style).render_ansi(&self.content)
+    }
+}
+
+impl Line {
+    /// Render with ANSI escape codes, each span styled on top of the line's style.
+    pub fn render_ansi(&self) -> String {
+        self.render_ansi_within(&TextStyle::default())
+    }
+
+    fn render_ansi_within(&self, base: &TextStyle) -> String {
+        let style = base.patch(&self.style);
+        self.spans
+            .iter()
+            .map(|span| span.render_ansi_within(&style))
+            .collect()
+    }
+}
+
+impl Paragraph {
+    /// Render with ANSI escape codes, one line per line.
+    pub fn render_ansi(&self) -> String {
+        self.lines
+            .iter()
+            .map(|line| format!("{}\n", line.render_ansi_within(&self.style)))
+            .collect()
+    }
+}
+
+impl Fragment {
+    /// Render each child with ANSI escape codes.
+    pub fn render_ansi(&self) -> String {
+        self.children.iter().map(View::render_ansi).collect()
+    }
+}
+
+impl View {
+    /// Render with ANSI escape codes, for output to a color terminal.
+    ///
+    /// With no styles set, this matches the `Display` output.
+    pub fn render_ansi(&self) -> String {
+        match self {
+            View::Span(view) => view.render_ansi(),
+            View::Fragment(view) => view.render_ansi(),
+            View::Line(view) => view.render_ansi(),
+            View::Paragraph(view) => view.render_ansi(),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn bold_red_span() {
+        let span = Span::new_styled("error", TextStyle::new().bold().fg(Color::Red));
+        assert_eq!(span.render_ansi(), "\x1b[1;31merror\x1b[0m");
+    }
+
+    #[test]
+    fn plain_span() {
+        let span = Span::new("plain");
+        assert_eq!(span.render_ansi(), "plain");
+    }
+
+    #[test]
+    fn line_style_applies_to_spans() {
+        let line = Line::new_styled(
+            vec![
+                Span::new("a"),
+                Span::new_styled("b", TextStyle::new().fg(Color::LightBlue)),
+            ],
+            TextStyle::new().bg(Color::Black),
+        );
+        assert_eq!(
+            View::Line(line).render_ansi(),
+            "\x1b[40ma\x1b[0m\x1b[94;40mb\x1b[0m"
+        );
+    }
+}
@@ -0,0 +1,38 @@
+use std::{ffi::OsStr, io::IsTerminal};
+
+/// Whether output should be styled with color.
+///
+/// Color is off when `--no-color` is given, when `NO_COLOR` is set to
+/// anything but an empty string (see <https://no-color.org>), or when the
+/// output isn't a terminal.
+pub fn color_enabled(no_color_flag: bool, no_color_env: Option<&OsStr>, is_terminal: bool) -> bool {
+    !no_color_flag && no_color_env.is_none_or(OsStr::is_empty) && is_terminal
+}
+
+/// [`color_enabled`] for `output`, with `NO_COLOR` read from the environment.
+pub fn detect_color(no_color_flag: bool, output: &impl IsTerminal) -> bool {
+    color_enabled(
+        no_color_flag,
+        std::env::var_os("NO_COLOR").as_deref(),
+        output.is_terminal(),
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn color_only_on_a_terminal_without_opt_out() {
+        let set = Some(OsStr::new("1"));
+        let empty = Some(OsStr::new(""));
+
+        assert!(color_enabled(false, None, true));
+        assert!(color_enabled(false, empty, true));
+
+        assert!(!color_enabled(false, None, false));
+        assert!(!color_enabled(true, None, true));
+        assert!(!color_enabled(false, set, true));
+        assert!(!color_enabled(true, set, false));
+    }
+}
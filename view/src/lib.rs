@@ -1,7 +1,12 @@
+mod ansi;
+mod color;
 mod render;
 mod tree;
+#[cfg(feature = "ratatui")]
+mod tui;
 mod view;
 
+pub use crate::color::*;
 pub use crate::render::*;
 pub use crate::tree::*;
 pub use crate::view::*;
@@ -0,0 +1,181 @@
+use ratatui::{
+    layout::Alignment as TuiAlignment,
+    style::{Color as TuiColor, Modifier, Style as TuiStyle},
+    text::{Line as TuiLine, Span as TuiSpan, Text as TuiText},
+};
+
+use crate::{Alignment, Color, Fragment, Line, Paragraph, Span, TextStyle, View};
+
+impl From<Color> for TuiColor {
+    fn from(value: Color) -> Self {
+        match value {
+            Color::Black => TuiColor::Black,
+            Color::Red => TuiColor::Red,
+            Color::Green => TuiColor::Green,
+            Color::Yellow => TuiColor::Yellow,
+            Color::Blue => TuiColor::Blue,
+            Color::Magenta => TuiColor::Magenta,
+            Color::Cyan => TuiColor::Cyan,
+            Color::Gray => TuiColor::Gray,
+            Color::DarkGray => TuiColor::DarkGray,
+            Color::LightRed => TuiColor::LightRed,
+            Color::LightGreen => TuiColor::LightGreen,
+            Color::LightYellow => TuiColor::LightYellow,
+            Color::LightBlue => TuiColor::LightBlue,
+            Color::LightMagenta => TuiColor::LightMagenta,
+            Color::LightCyan => TuiColor::LightCyan,
+            Color::White => TuiColor::White,
+        }
+    }
+}
+
+impl From<TextStyle> for TuiStyle {
+    fn from(value: TextStyle) -> Self {
+        let TextStyle {
+            foreground_color,
+            background_color,
+            is_bold,
+            is_italic,
+            is_underlined,
+            underline_color,
+            is_crossed_out,
+        } = value;
+
+        let mut modifier = Modifier::empty();
+        modifier.set(Modifier::BOLD, is_bold);
+        modifier.set(Modifier::ITALIC, is_italic);
+        modifier.set(Modifier::UNDERLINED, is_underlined);
+        modifier.set(Modifier::CROSSED_OUT, is_crossed_out);
+
+        let mut style = TuiStyle::default().add_modifier(modifier);
+        style.fg = foreground_color.map(TuiColor::from);
+        style.bg = background_color.map(TuiColor::from);
+        style.underline_color = underline_color.map(TuiColor::from);
+        style
+    }
+}
+
+impl From<Alignment> for TuiAlignment {
+    fn from(value: Alignment) -> Self {
+        match value {
+            Alignment::Left => TuiAlignment::Left,
+            Alignment::Center => TuiAlignment::Center,
+            Alignment::Right => TuiAlignment::Right,
+        }
+    }
+}
+
+impl From<Span> for TuiSpan<'static> {
+    fn from(value: Span) -> Self {
+        TuiSpan::styled(value.content, value.style)
+    }
+}
+
+impl From<Line> for TuiLine<'static> {
+    fn from(value: Line) -> Self {
+        let Line {
+            spans,
+            style,
+            alignment,
+        } = value;
+        let mut line =
+            TuiLine::from(spans.into_iter().map(TuiSpan::from).collect::<Vec<_>>()).style(style);
+        line.alignment = alignment.map(TuiAlignment::from);
+        line
+    }
+}
+
+impl From<Paragraph> for TuiText<'static> {
+    fn from(value: Paragraph) -> Self {
+        let Paragraph {
+            lines,
+            alignment,
+            style,
+        } = value;
+        let mut text =
+            TuiText::from(lines.into_iter().map(TuiLine::from).collect::<Vec<_>>()).style(style);
+        text.alignment = alignment.map(TuiAlignment::from);
+        text
+    }
+}
+
+impl From<Fragment> for TuiText<'static> {
+    /// Children flow inline, as with `Display`: each child continues the last line.
+    fn from(value: Fragment) -> Self {
+        let mut text = TuiText::default();
+        for child in value.children {
+            let mut lines = TuiText::from(child).lines.into_iter();
+            match (text.lines.last_mut(), lines.next()) {
+                (Some(last), Some(first)) => last.spans.extend(first.spans),
+                (None, Some(first)) => text.lines.push(first),
+                (_, None) => {}
+            }
+            text.lines.extend(lines);
+        }
+        text
+    }
+}
+
+impl From<View> for TuiText<'static> {
+    fn from(value: View) -> Self {
+        match value {
+            View::Span(view) => TuiText::from(TuiLine::from(TuiSpan::from(view))),
+            View::Line(view) => TuiText::from(TuiLine::from(view)),
+            View::Paragraph(view) => TuiText::from(view),
+            View::Fragment(view) => TuiText::from(view),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn color_to_ratatui() {
+        let cases = [
+            (Color::Black, TuiColor::Black),
+            (Color::Red, TuiColor::Red),
+            (Color::Green, TuiColor::Green),
+            (Color::Yellow, TuiColor::Yellow),
+            (Color::Blue, TuiColor::Blue),
+            (Color::Magenta, TuiColor::Magenta),
+            (Color::Cyan, TuiColor::Cyan),
+            (Color::Gray, TuiColor::Gray),
+            (Color::DarkGray, TuiColor::DarkGray),
+            (Color::LightRed, TuiColor::LightRed),
+            (Color::LightGreen, TuiColor::LightGreen),
+            (Color::LightYellow, TuiColor::LightYellow),
+            (Color::LightBlue, TuiColor::LightBlue),
+            (Color::LightMagenta, TuiColor::LightMagenta),
+            (Color::LightCyan, TuiColor::LightCyan),
+            (Color::White, TuiColor::White),
+        ];
+        for (color, expected) in cases {
+            assert_eq!(TuiColor::from(color), expected);
+        }
+    }
+
+    #[test]
+    fn style_to_ratatui() {
+        let style = TuiStyle::from(TextStyle::new().bold().fg(Color::Red));
+        assert_eq!(
+            style,
+            TuiStyle::default()
+                .fg(TuiColor::Red)
+                .add_modifier(Modifier::BOLD)
+        );
+    }
+
+    #[test]
+    fn fragment_flows_inline() {
+        let view = View::from(vec![
+            View::Span("a".into()),
+            View::Span(Span::new_styled("b", TextStyle::new().fg(Color::Green))),
+        ]);
+        let text = TuiText::from(view);
+        assert_eq!(text.lines.len(), 1);
+        assert_eq!(text.lines[0].spans.len(), 2);
+        assert_eq!(text.lines[0].spans[1].style.fg, Some(TuiColor::Green));
+    }
+}
@@ -1,8 +1,9 @@
 use std::fmt::Display;
+use std::mem::take;
 
 use serde::{Deserialize, Serialize};
 
-use crate::{Alignment, Line, TextStyle, View};
+use crate::{Alignment, Line, Span, TextStyle, View};
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct Paragraph {
@@ -47,6 +48,139 @@ impl Paragraph {
         self.lines.push(line);
         self
     }
+
+    /// Reflow lines to fit within `width` columns, for plain-text output.
+    ///
+    /// Breaks at whitespace (collapsing runs of it), splitting words longer than `width`.
+    /// Span styles carry over to each piece, and center- or right-aligned lines are padded.
+    pub fn wrap(&self, width: usize) -> Paragraph {
+        let width = width.max(1);
+        let mut lines = Vec::new();
+
+        for line in self.lines.iter() {
+            let alignment = line.alignment.as_ref().or(self.alignment.as_ref());
+            for mut spans in wrap_line(line, width) {
+                pad_spans(&mut spans, width, alignment);
+                lines.push(Line {
+                    spans,
+                    style: line.style.clone(),
+                    alignment: line.alignment.clone(),
+                });
+            }
+        }
+
+        Paragraph {
+            lines,
+            alignment: self.alignment.clone(),
+            style: self.style.clone(),
+        }
+    }
+}
+
+fn spans_width(spans: &[Span]) -> usize {
+    spans.iter().map(|span| span.content.chars().count()).sum()
+}
+
+/// Split a line into words, each word made of one or more styled spans.
+fn line_words(line: &Line) -> Vec<Vec<Span>> {
+    let mut words = Vec::new();
+    let mut word = Vec::new();
+
+    for span in line.spans.iter() {
+        let mut segment = String::new();
+        for c in span.content.chars() {
+            if c.is_whitespace() {
+                if !segment.is_empty() {
+                    word.push(Span::new_styled(take(&mut segment), span.style.clone()));
+                }
+                if !word.is_empty() {
+                    words.push(take(&mut word));
+                }
+            } else {
+                segment.push(c);
+            }
+        }
+        if !segment.is_empty() {
+            word.push(Span::new_styled(segment, span.style.clone()));
+        }
+    }
+    if !word.is_empty() {
+        words.push(word);
+    }
+
+    words
+}
+
+/// Split a word into chunks of at most `width` characters.
+fn split_word(word: Vec<Span>, width: usize) -> Vec<Vec<Span>> {
+    if spans_width(&word) <= width {
+        return vec![word];
+    }
+
+    let mut chunks = Vec::new();
+    let mut chunk = Vec::new();
+    let mut chunk_width = 0;
+
+    for span in word {
+        let mut segment = String::new();
+        for c in span.content.chars() {
+            if chunk_width == width {
+                if !segment.is_empty() {
+                    chunk.push(Span::new_styled(take(&mut segment), span.style.clone()));
+                }
+                chunks.push(take(&mut chunk));
+                chunk_width = 0;
+            }
+            segment.push(c);
+            chunk_width += 1;
+        }
+        if !segment.is_empty() {
+            chunk.push(Span::new_styled(segment, span.style));
+        }
+    }
+    if !chunk.is_empty() {
+        chunks.push(chunk);
+    }
+
+    chunks
+}
+
+/// Greedily fill rows of at most `width` characters. Always returns at least one row.
+fn wrap_line(line: &Line, width: usize) -> Vec<Vec<Span>> {
+    let mut rows = Vec::new();
+    let mut row: Vec<Span> = Vec::new();
+    let mut row_width = 0;
+
+    for word in line_words(line) {
+        for chunk in split_word(word, width) {
+            let chunk_width = spans_width(&chunk);
+            if row_width > 0 && row_width + 1 + chunk_width > width {
+                rows.push(take(&mut row));
+                row_width = 0;
+            }
+            if row_width > 0 {
+                row.push(Span::new(" "));
+                row_width += 1;
+            }
+            row.extend(chunk);
+            row_width += chunk_width;
+        }
+    }
+    rows.push(row);
+
+    rows
+}
+
+fn pad_spans(spans: &mut Vec<Span>, width: usize, alignment: Option<&Alignment>) {
+    let remaining = width.saturating_sub(spans_width(spans));
+    let padding = match alignment {
+        Some(Alignment::Center) => remaining / 2,
+        Some(Alignment::Right) => remaining,
+        Some(Alignment::Left) | None => 0,
+    };
+    if padding > 0 {
+        spans.insert(0, Span::new(" ".repeat(padding)));
+    }
 }
 
 impl Display for Paragraph {
@@ -76,3 +210,40 @@ impl From<Paragraph> for View {
         View::Paragraph(value)
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn wrap_long_line() {
+        let line = Line::new(vec![
+            Span::new("the "),
+            Span::new_styled("quick brown", TextStyle::new().bold()),
+            Span::new(" fox jumps overboard"),
+        ]);
+        let paragraph = Paragraph::new(vec![line]).wrap(10);
+
+        assert_eq!(
+            paragraph.to_string(),
+            "the quick\nbrown fox\njumps\noverboard\n"
+        );
+        assert!(paragraph.lines[0].spans[2].style.is_bold);
+        assert!(paragraph.lines[1].spans[0].style.is_bold);
+        assert!(!paragraph.lines[1].spans[2].style.is_bold);
+    }
+
+    #[test]
+    fn wrap_splits_long_words() {
+        let paragraph = Paragraph::from(vec!["abcdefghijklm"]).wrap(5);
+        assert_eq!(paragraph.to_string(), "abcde\nfghij\nklm\n");
+    }
+
+    #[test]
+    fn wrap_pads_centered() {
+        let paragraph = Paragraph::from(vec!["hi", "hello"])
+            .alignment(Alignment::Center)
+            .wrap(10);
+        assert_eq!(paragraph.to_string(), "    hi\n  hello\n");
+    }
+}
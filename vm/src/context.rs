@@ -1,6 +1,7 @@
 use lusid_ctx::{Context as BaseContext, ContextError as BaseContextError};
 use thiserror::Error;
 
+use crate::ovmf::{OvmfNotFoundError, OvmfOverrides, OvmfPaths};
 use crate::paths::{ExecutablePaths, ExecutablePathsError, Paths};
 use lusid_http::{HttpClient, HttpError};
 
@@ -14,6 +15,9 @@ pub enum ContextError {
 
     #[error(transparent)]
     ExecutablePaths(#[from] ExecutablePathsError),
+
+    #[error(transparent)]
+    Ovmf(#[from] OvmfNotFoundError),
 }
 
 #[derive(Debug, Clone)]
@@ -21,22 +25,29 @@ pub struct Context {
     http_client: HttpClient,
     paths: Paths,
     executables: ExecutablePaths,
+    ovmf: OvmfPaths,
 }
 
 impl Context {
-    pub fn create(base: &mut BaseContext) -> Result<Self, ContextError> {
-        let http_client = base.http_client().clone();
+    pub fn create(
+        base: &BaseContext,
+        ovmf_overrides: &OvmfOverrides,
+    ) -> Result<Self, ContextError> {
+        let http_client = base.http_client();
         let paths = Paths::new(base.paths().clone());
         let executables = ExecutablePaths::new()?;
+        let ovmf = OvmfPaths::resolve(ovmf_overrides)?;
         Ok(Self {
             http_client,
             paths,
             executables,
+            ovmf,
         })
     }
 
-    pub fn http_client(&mut self) -> &mut HttpClient {
-        &mut self.http_client
+    /// The shared HTTP client; see [`BaseContext::http_client`].
+    pub fn http_client(&self) -> HttpClient {
+        self.http_client.clone()
     }
 
     pub fn paths(&self) -> &Paths {
@@ -46,4 +57,8 @@ impl Context {
     pub fn executables(&self) -> &ExecutablePaths {
         &self.executables
     }
+
+    pub fn ovmf(&self) -> &OvmfPaths {
+        &self.ovmf
+    }
 }
@@ -0,0 +1,299 @@
+use std::{
+    collections::HashSet,
+    path::{Path, PathBuf},
+    time::{Duration, SystemTime},
+};
+
+use lusid_ctx::Context as BaseContext;
+use lusid_fs::{self as fs, FsError};
+use lusid_machine::Machine;
+use tokio::io::{AsyncReadExt, AsyncSeekExt, SeekFrom};
+
+use crate::{
+    image::{get_images_list, VmImageError},
+    instance::VmPaths,
+    paths::Paths,
+};
+
+/// A file in the images cache: an image, its hash sums, or a partial download.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct CachedImage {
+    pub path: PathBuf,
+    pub size: u64,
+    pub modified: SystemTime,
+}
+
+impl CachedImage {
+    pub fn file_name(&self) -> String {
+        self.path
+            .file_name()
+            .map(|name| name.to_string_lossy().into_owned())
+            .unwrap_or_default()
+    }
+
+    pub fn age(&self, now: SystemTime) -> Duration {
+        now.duration_since(self.modified).unwrap_or_default()
+    }
+}
+
+pub async fn list_cached_images(ctx: &BaseContext) -> Result<Vec<CachedImage>, VmImageError> {
+    let paths = Paths::new(ctx.paths().clone());
+    list_cached_images_in(&paths.images_dir()).await
+}
+
+async fn list_cached_images_in(images_dir: &Path) -> Result<Vec<CachedImage>, VmImageError> {
+    if !fs::path_exists(images_dir).await? {
+        return Ok(Vec::new());
+    }
+
+    let mut images = Vec::new();
+    for path in fs::read_dir(images_dir).await? {
+        let metadata = fs::metadata(&path).await?;
+        if !metadata.is_file() {
+            continue;
+        }
+        images.push(CachedImage {
+            size: metadata.len(),
+            modified: metadata.modified().unwrap_or(SystemTime::UNIX_EPOCH),
+            path,
+        });
+    }
+    images.sort_by(|a, b| a.path.cmp(&b.path));
+    Ok(images)
+}
+
+/// File names of the images (and their hash sums) the given machines would use.
+pub async fn referenced_image_files<'a>(
+    machines: impl IntoIterator<Item = &'a Machine>,
+) -> Result<HashSet<String>, VmImageError> {
+    let image_indexes: Vec<_> = get_images_list().await?.into_values().collect();
+    let mut referenced = HashSet::new();
+    for machine in machines {
+        for image_index in &image_indexes {
+            if image_index.os == machine.os && image_index.arch == machine.arch {
+                referenced.insert(image_index.to_image_file_name());
+                referenced.insert(image_index.to_hash_file_name());
+            }
+        }
+    }
+    Ok(referenced)
+}
+
+/// File names of the images backing an instance overlay or a snapshot of one.
+///
+/// Removing any of these would break the instance, whatever the config says.
+pub async fn backing_image_files(ctx: &BaseContext) -> Result<HashSet<String>, VmImageError> {
+    let paths = Paths::new(ctx.paths().clone());
+    backing_image_files_in(&paths.instances_dir()).await
+}
+
+async fn backing_image_files_in(instances_dir: &Path) -> Result<HashSet<String>, VmImageError> {
+    let mut backing = HashSet::new();
+    if !fs::path_exists(instances_dir).await? {
+        return Ok(backing);
+    }
+
+    for instance_dir in fs::read_dir(instances_dir).await? {
+        let paths = VmPaths::new(&instance_dir);
+        let mut overlays = vec![paths.overlay_image_path()];
+        let snapshots_dir = paths.snapshots_dir();
+        if fs::path_exists(&snapshots_dir).await? {
+            let overlay_name = paths.overlay_image_path();
+            let overlay_name = overlay_name.file_name().expect("overlay has a name");
+            for snapshot_dir in fs::read_dir(&snapshots_dir).await? {
+                overlays.push(snapshot_dir.join(overlay_name));
+            }
+        }
+
+        for overlay in overlays {
+            if !fs::path_exists(&overlay).await? {
+                continue;
+            }
+            if let Some(name) = read_backing_file(&overlay)
+                .await?
+                .as_deref()
+                .and_then(Path::file_name)
+            {
+                backing.insert(name.to_string_lossy().into_owned());
+            }
+        }
+    }
+    Ok(backing)
+}
+
+/// Read the backing file path from a qcow2 header, if it has one.
+async fn read_backing_file(path: &Path) -> Result<Option<PathBuf>, FsError> {
+    const QCOW2_MAGIC: [u8; 4] = *b"QFI\xfb";
+    let read_error = |source| FsError::ReadFile {
+        path: path.to_path_buf(),
+        source,
+    };
+
+    let mut file = fs::open_file(path).await?;
+    // magic (4), version (4), backing_file_offset (8), backing_file_size (4)
+    let mut header = [0u8; 20];
+    match file.read_exact(&mut header).await {
+        Ok(_) => {}
+        Err(error) if error.kind() == std::io::ErrorKind::UnexpectedEof => return Ok(None),
+        Err(error) => return Err(read_error(error)),
+    }
+    if header[0..4] != QCOW2_MAGIC {
+        return Ok(None);
+    }
+    let offset = u64::from_be_bytes(header[8..16].try_into().unwrap());
+    let size = u32::from_be_bytes(header[16..20].try_into().unwrap());
+    if offset == 0 || size == 0 {
+        return Ok(None);
+    }
+
+    let mut backing_file = vec![0u8; size as usize];
+    file.seek(SeekFrom::Start(offset))
+        .await
+        .map_err(read_error)?;
+    file.read_exact(&mut backing_file)
+        .await
+        .map_err(read_error)?;
+    Ok(Some(PathBuf::from(
+        String::from_utf8_lossy(&backing_file).into_owned(),
+    )))
+}
+
+/// Pick the cached files to remove: anything not referenced, or with
+/// `max_age`, anything not referenced and older than it. Referenced files are
+/// never removed.
+pub fn select_for_removal(
+    images: Vec<CachedImage>,
+    referenced: &HashSet<String>,
+    max_age: Option<Duration>,
+    now: SystemTime,
+) -> Vec<CachedImage> {
+    images
+        .into_iter()
+        .filter(|image| {
+            !referenced.contains(&image.file_name())
+                && max_age.is_none_or(|max_age| image.age(now) > max_age)
+        })
+        .collect()
+}
+
+pub async fn remove_cached_images(images: &[CachedImage]) -> Result<(), VmImageError> {
+    for image in images {
+        fs::remove_file(&image.path).await?;
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const DAY: Duration = Duration::from_secs(24 * 60 * 60);
+
+    #[tokio::test]
+    async fn selects_unreferenced_and_old_images() {
+        let dir = tempfile::tempdir().unwrap();
+        for name in [
+            "x86_64_debian-13.qcow2",
+            "x86_64_debian-13.sha512sums",
+            "x86_64_ubuntu-22.04.qcow2",
+            "x86_64_ubuntu-24.04.qcow2.tmp",
+        ] {
+            tokio::fs::write(dir.path().join(name), name).await.unwrap();
+        }
+        tokio::fs::create_dir(dir.path().join("nested"))
+            .await
+            .unwrap();
+
+        let images = list_cached_images_in(dir.path()).await.unwrap();
+        assert_eq!(images.len(), 4);
+
+        let referenced: HashSet<String> = ["x86_64_debian-13.qcow2", "x86_64_debian-13.sha512sums"]
+            .into_iter()
+            .map(String::from)
+            .collect();
+        let names = |images: Vec<CachedImage>| {
+            images
+                .iter()
+                .map(CachedImage::file_name)
+                .collect::<Vec<_>>()
+        };
+
+        let now = SystemTime::now();
+        assert_eq!(
+            names(select_for_removal(images.clone(), &referenced, None, now)),
+            vec!["x86_64_ubuntu-22.04.qcow2", "x86_64_ubuntu-24.04.qcow2.tmp"]
+        );
+        assert!(select_for_removal(images.clone(), &referenced, Some(DAY), now).is_empty());
+        assert_eq!(
+            names(select_for_removal(
+                images,
+                &referenced,
+                Some(DAY),
+                now + 2 * DAY
+            )),
+            vec!["x86_64_ubuntu-22.04.qcow2", "x86_64_ubuntu-24.04.qcow2.tmp"]
+        );
+    }
+
+    /// A qcow2 header with just enough set for `read_backing_file`.
+    fn qcow2_overlay(backing_file: &str) -> Vec<u8> {
+        let offset: u64 = 72;
+        let mut data = Vec::new();
+        data.extend_from_slice(b"QFI\xfb");
+        data.extend_from_slice(&3u32.to_be_bytes());
+        data.extend_from_slice(&offset.to_be_bytes());
+        data.extend_from_slice(&(backing_file.len() as u32).to_be_bytes());
+        data.resize(offset as usize, 0);
+        data.extend_from_slice(backing_file.as_bytes());
+        data
+    }
+
+    #[tokio::test]
+    async fn keeps_images_backing_instances_and_snapshots() {
+        let dir = tempfile::tempdir().unwrap();
+        let images_dir = dir.path().join("images");
+        let instances_dir = dir.path().join("instances");
+        tokio::fs::create_dir(&images_dir).await.unwrap();
+        for name in [
+            "x86_64_debian-12.qcow2",
+            "x86_64_debian-13.qcow2",
+            "x86_64_ubuntu-24.04.qcow2",
+        ] {
+            tokio::fs::write(images_dir.join(name), name).await.unwrap();
+        }
+
+        let instance_dir = instances_dir.join("web");
+        let paths = VmPaths::new(&instance_dir);
+        let snapshot_dir = paths.snapshots_dir().join("provisioned");
+        tokio::fs::create_dir_all(&snapshot_dir).await.unwrap();
+        let backing = |name: &str| images_dir.join(name).display().to_string();
+        tokio::fs::write(
+            paths.overlay_image_path(),
+            qcow2_overlay(&backing("x86_64_debian-13.qcow2")),
+        )
+        .await
+        .unwrap();
+        tokio::fs::write(
+            snapshot_dir.join("overlay.qcow2"),
+            qcow2_overlay(&backing("x86_64_debian-12.qcow2")),
+        )
+        .await
+        .unwrap();
+
+        let referenced = backing_image_files_in(&instances_dir).await.unwrap();
+        let mut sorted: Vec<_> = referenced.iter().cloned().collect();
+        sorted.sort();
+        assert_eq!(
+            sorted,
+            vec!["x86_64_debian-12.qcow2", "x86_64_debian-13.qcow2"]
+        );
+
+        let images = list_cached_images_in(&images_dir).await.unwrap();
+        let now = SystemTime::now() + 30 * DAY;
+        let removals: Vec<_> = select_for_removal(images, &referenced, Some(DAY), now)
+            .iter()
+            .map(CachedImage::file_name)
+            .collect();
+        assert_eq!(removals, vec!["x86_64_ubuntu-24.04.qcow2"]);
+    }
+}
@@ -1,15 +1,24 @@
 use lusid_fs::{self as fs, FsError};
+use lusid_http::DownloadSink;
+use sha2::{Digest, Sha512};
+use std::io::Read;
 use std::path::Path;
+use std::sync::mpsc::sync_channel;
 use thiserror::Error;
-use tokio::io::AsyncReadExt;
 
 use crate::image::index::{VmImageHashRef, VmImageIndex};
 
+/// Bytes read from an image at a time while hashing it.
+pub const DEFAULT_HASH_BUFFER_SIZE: usize = 1024 * 1024;
+
 #[derive(Error, Debug)]
 pub enum VmImageHashError {
     #[error(transparent)]
     Fs(#[from] FsError),
 
+    #[error("hashing task failed: {0}")]
+    HashTask(#[from] tokio::task::JoinError),
+
     #[error("malformed file name from '{url}'")]
     MalformedFileName { url: String },
 
@@ -35,143 +44,300 @@ pub enum VmImageHash<'a> {
 impl<'a> VmImageHash<'a> {
     pub fn new(hash_ref: &VmImageHashRef, path: &'a Path) -> Self {
         match hash_ref {
-            VmImageHashRef::Sha512Sums { url: _ } => VmImageHash::Sha512Sums { path },
+            VmImageHashRef::Sha512Sums { .. } => VmImageHash::Sha512Sums { path },
         }
     }
 
+    /// Hash an image already on disk and check it against the sums.
     pub async fn validate(
         &self,
         image_index: &VmImageIndex,
         image_path: &Path,
     ) -> Result<(), VmImageHashError> {
-        async fn sha512_file_hex<P: AsRef<Path>>(path: P) -> Result<String, FsError> {
-            use sha2::{Digest, Sha512};
-
-            let p = path.as_ref();
-            let mut file = fs::open_file(p).await?;
-            let mut hasher = Sha512::new();
-            let mut buf = [0u8; 8192];
-
-            loop {
-                let n = file
-                    .read(&mut buf)
-                    .await
-                    .map_err(|source| FsError::ReadFile {
-                        path: p.to_path_buf(),
-                        source,
-                    })?;
-                if n == 0 {
-                    break;
-                }
-                hasher.update(&buf[..n]);
-            }
-
-            let digest = hasher.finalize();
-            let mut hex = String::with_capacity(digest.len() * 2);
-            for b in digest {
-                hex.push_str(&format!("{:02x}", b));
-            }
-            Ok(hex)
-        }
-
-        /// Parse the contents of a Debian-style sha512sums file and return the hash that
-        /// corresponds to `image_name`.
-        ///
-        /// Accepts lines like:
-        /// <128-hex> [space][space or more][optional '*']<filename>
-        /// Ignores empty lines and lines starting with '#'.
-        fn lookup_sha512_for(sums: &str, image_name: &str) -> Result<String, VmImageHashError> {
-            for (idx, raw_line) in sums.lines().enumerate() {
-                let line = raw_line.trim_end_matches('\r').trim();
-                if line.is_empty() || line.starts_with('#') {
-                    continue;
-                }
-
-                // Split into two parts: hash and the rest (file name). Using splitn to
-                // avoid splitting file names that might (rarely) contain spaces.
-                let (hash, name_part) = if let Some((h, rest)) = split_once_whitespace(line) {
-                    (h, rest)
-                } else {
-                    return Err(VmImageHashError::MalformedLine {
-                        line_index: idx + 1,
-                        line: raw_line.to_string(),
-                    });
-                };
-
-                // Normalize filename token: handle optional leading '*' (binary mode).
-                let listed_name = name_part.trim_start_matches('*');
-
-                // Some sums may include paths. Compare only the file name component.
-                let listed_basename = std::path::Path::new(listed_name)
-                    .file_name()
-                    .and_then(|s| s.to_str())
-                    .unwrap_or(listed_name);
-
-                // Validate hash shape: 128 hex chars
-                if hash.len() != 128 || !hash.chars().all(|c| c.is_ascii_hexdigit()) {
-                    return Err(VmImageHashError::MalformedLine {
-                        line_index: idx + 1,
-                        line: raw_line.to_string(),
-                    });
-                }
-
-                if listed_basename == image_name {
-                    return Ok(hash.to_ascii_lowercase());
-                }
-            }
+        self.validate_with_buffer_size(image_index, image_path, DEFAULT_HASH_BUFFER_SIZE)
+            .await
+    }
 
-            Err(VmImageHashError::HashNotFound {
-                name: image_name.to_string(),
-            })
-        }
+    /// [`VmImageHash::validate`], reading `buffer_size` bytes at a time.
+    pub async fn validate_with_buffer_size(
+        &self,
+        image_index: &VmImageIndex,
+        image_path: &Path,
+        buffer_size: usize,
+    ) -> Result<(), VmImageHashError> {
+        let actual = sha512_file_hex(image_path, buffer_size).await?;
+        self.verify_digest(image_index, &actual).await
+    }
 
-        /// Split `s` into two parts at the first run of ASCII whitespace:
-        /// (left, right-without-leading-whitespace).
-        fn split_once_whitespace(s: &str) -> Option<(&str, &str)> {
-            let bytes = s.as_bytes().iter().enumerate();
-            for (i, b) in bytes {
-                if b.is_ascii_whitespace() {
-                    // Skip all following whitespace to get start of right part
-                    let mut j = i;
-                    let sb = s.as_bytes();
-                    while j < sb.len() && sb[j].is_ascii_whitespace() {
-                        j += 1;
-                    }
-                    return Some((&s[..i], &s[j..]));
-                }
+    /// Check a digest computed elsewhere, e.g. by a [`Sha512Digest`] while
+    /// downloading, against the sums.
+    pub async fn verify_digest(
+        &self,
+        image_index: &VmImageIndex,
+        actual: &str,
+    ) -> Result<(), VmImageHashError> {
+        // Resolve the target name we need to look up in sums
+        let image_url = image_index.image.to_url();
+        let image_name = image_url.split('/').next_back().ok_or_else(|| {
+            VmImageHashError::MalformedFileName {
+                url: image_url.to_string(),
             }
-            None
-        }
+        })?;
+        self.verify(image_name, actual).await
+    }
 
+    async fn verify(&self, image_name: &str, actual: &str) -> Result<(), VmImageHashError> {
         match self {
             VmImageHash::Sha512Sums { path } => {
                 let sums = fs::read_file_to_string(path).await?;
 
-                // Resolve the target name we need to look up in sums
-                let image_url = image_index.image.to_url();
-                let image_name = image_url.split('/').next_back().ok_or_else(|| {
-                    VmImageHashError::MalformedFileName {
-                        url: image_url.to_string(),
-                    }
-                })?;
-
                 // Find expected hash for this image in the sums
                 let expected = lookup_sha512_for(&sums, image_name)?;
 
-                // Compute actual hash of the image
-                let actual = sha512_file_hex(&image_path).await?;
-
                 // Compare (case-insensitive to be safe)
-                if expected.eq_ignore_ascii_case(&actual) {
+                if expected.eq_ignore_ascii_case(actual) {
                     Ok(())
                 } else {
                     Err(VmImageHashError::HashMismatch {
                         name: image_name.to_string(),
                         expected,
-                        actual,
+                        actual: actual.to_string(),
                     })
                 }
             }
         }
     }
 }
+
+/// A SHA-512 digest fed incrementally, as a download is written.
+#[derive(Debug, Clone, Default)]
+pub struct Sha512Digest(Sha512);
+
+impl Sha512Digest {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn finish_hex(self) -> String {
+        to_hex(&self.0.finalize())
+    }
+}
+
+impl DownloadSink for Sha512Digest {
+    fn update(&mut self, bytes: &[u8]) {
+        self.0.update(bytes);
+    }
+
+    fn reset(&mut self) {
+        self.0 = Sha512::new();
+    }
+}
+
+fn to_hex(digest: &[u8]) -> String {
+    let mut hex = String::with_capacity(digest.len() * 2);
+    for b in digest {
+        hex.push_str(&format!("{:02x}", b));
+    }
+    hex
+}
+
+async fn sha512_file_hex<P: AsRef<Path>>(
+    path: P,
+    buffer_size: usize,
+) -> Result<String, VmImageHashError> {
+    let path = path.as_ref().to_path_buf();
+    let hex =
+        tokio::task::spawn_blocking(move || sha512_file_hex_blocking(&path, buffer_size)).await??;
+    Ok(hex)
+}
+
+/// SHA-512 can't be split across threads, so instead overlap reading the
+/// next chunk with hashing the last, on a pair of threads.
+fn sha512_file_hex_blocking(path: &Path, buffer_size: usize) -> Result<String, FsError> {
+    let buffer_size = buffer_size.max(1);
+    let mut file = std::fs::File::open(path).map_err(|source| FsError::OpenFile {
+        path: path.to_path_buf(),
+        source,
+    })?;
+
+    // Two buffers pass back and forth: one being read into, one being hashed.
+    let (full_tx, full_rx) = sync_channel::<Vec<u8>>(1);
+    let (empty_tx, empty_rx) = sync_channel::<Vec<u8>>(2);
+    for _ in 0..2 {
+        empty_tx
+            .send(vec![0; buffer_size])
+            .expect("receiver is alive");
+    }
+
+    std::thread::scope(|scope| {
+        let reader = scope.spawn(move || -> std::io::Result<()> {
+            while let Ok(mut buf) = empty_rx.recv() {
+                buf.resize(buffer_size, 0);
+                let n = file.read(&mut buf)?;
+                if n == 0 {
+                    break;
+                }
+                buf.truncate(n);
+                if full_tx.send(buf).is_err() {
+                    break;
+                }
+            }
+            Ok(())
+        });
+
+        let mut hasher = Sha512::new();
+        for buf in full_rx {
+            hasher.update(&buf);
+            // The reader may be done, and no longer want it back.
+            let _ = empty_tx.send(buf);
+        }
+
+        reader
+            .join()
+            .expect("image reader thread panicked")
+            .map_err(|source| FsError::ReadFile {
+                path: path.to_path_buf(),
+                source,
+            })?;
+        Ok(to_hex(&hasher.finalize()))
+    })
+}
+
+/// Parse the contents of a Debian-style sha512sums file and return the hash that
+/// corresponds to `image_name`.
+///
+/// Accepts lines like:
+/// <128-hex> [space][space or more][optional '*']<filename>
+/// Ignores empty lines and lines starting with '#'.
+fn lookup_sha512_for(sums: &str, image_name: &str) -> Result<String, VmImageHashError> {
+    for (idx, raw_line) in sums.lines().enumerate() {
+        let line = raw_line.trim_end_matches('\r').trim();
+        if line.is_empty() || line.starts_with('#') {
+            continue;
+        }
+
+        // Split into two parts: hash and the rest (file name). Using splitn to
+        // avoid splitting file names that might (rarely) contain spaces.
+        let (hash, name_part) = if let Some((h, rest)) = split_once_whitespace(line) {
+            (h, rest)
+        } else {
+            return Err(VmImageHashError::MalformedLine {
+                line_index: idx + 1,
+                line: raw_line.to_string(),
+            });
+        };
+
+        // Normalize filename token: handle optional leading '*' (binary mode).
+        let listed_name = name_part.trim_start_matches('*');
+
+        // Some sums may include paths. Compare only the file name component.
+        let listed_basename = std::path::Path::new(listed_name)
+            .file_name()
+            .and_then(|s| s.to_str())
+            .unwrap_or(listed_name);
+
+        // Validate hash shape: 128 hex chars
+        if hash.len() != 128 || !hash.chars().all(|c| c.is_ascii_hexdigit()) {
+            return Err(VmImageHashError::MalformedLine {
+                line_index: idx + 1,
+                line: raw_line.to_string(),
+            });
+        }
+
+        if listed_basename == image_name {
+            return Ok(hash.to_ascii_lowercase());
+        }
+    }
+
+    Err(VmImageHashError::HashNotFound {
+        name: image_name.to_string(),
+    })
+}
+
+/// Split `s` into two parts at the first run of ASCII whitespace:
+/// (left, right-without-leading-whitespace).
+fn split_once_whitespace(s: &str) -> Option<(&str, &str)> {
+    let bytes = s.as_bytes().iter().enumerate();
+    for (i, b) in bytes {
+        if b.is_ascii_whitespace() {
+            // Skip all following whitespace to get start of right part
+            let mut j = i;
+            let sb = s.as_bytes();
+            while j < sb.len() && sb[j].is_ascii_whitespace() {
+                j += 1;
+            }
+            return Some((&s[..i], &s[j..]));
+        }
+    }
+    None
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const IMAGE: &[u8] = b"not really a qcow2 image, but small enough to hash";
+
+    async fn sums_for(dir: &Path, digest: &str) -> std::path::PathBuf {
+        let path = dir.join("SHA512SUMS");
+        let sums = format!("{digest}  other.qcow2\n{digest}  image.qcow2\n");
+        tokio::fs::write(&path, sums).await.unwrap();
+        path
+    }
+
+    #[tokio::test]
+    async fn streaming_digest_matches() {
+        let mut digest = Sha512Digest::new();
+        for chunk in IMAGE.chunks(7) {
+            digest.update(chunk);
+        }
+        let actual = digest.finish_hex();
+        assert_eq!(actual, to_hex(&Sha512::digest(IMAGE)));
+
+        let dir = tempfile::tempdir().unwrap();
+        let sums = sums_for(dir.path(), &actual).await;
+        let hash = VmImageHash::Sha512Sums { path: &sums };
+        hash.verify("image.qcow2", &actual).await.unwrap();
+    }
+
+    #[tokio::test]
+    async fn buffered_file_hash_matches_reference() {
+        // A few MiB, not a multiple of any buffer size, so the last chunk is short.
+        let mut image = Vec::with_capacity(5 * 1024 * 1024 + 123);
+        let mut state: u32 = 1;
+        while image.len() < image.capacity() {
+            state = state.wrapping_mul(1_103_515_245).wrapping_add(12_345);
+            image.push((state >> 16) as u8);
+        }
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("image.qcow2");
+        tokio::fs::write(&path, &image).await.unwrap();
+
+        let expected = to_hex(&Sha512::digest(&image));
+        for buffer_size in [4097, 64 * 1024, DEFAULT_HASH_BUFFER_SIZE] {
+            assert_eq!(
+                sha512_file_hex(&path, buffer_size).await.unwrap(),
+                expected,
+                "buffer size {buffer_size}"
+            );
+        }
+    }
+
+    #[tokio::test]
+    async fn streaming_digest_detects_mismatch() {
+        let dir = tempfile::tempdir().unwrap();
+        let sums = sums_for(dir.path(), &to_hex(&Sha512::digest(IMAGE))).await;
+
+        let mut digest = Sha512Digest::new();
+        digest.update(b"stale partial download");
+        digest.reset();
+        digest.update(&IMAGE[..IMAGE.len() - 1]);
+
+        let hash = VmImageHash::Sha512Sums { path: &sums };
+        let error = hash
+            .verify("image.qcow2", &digest.finish_hex())
+            .await
+            .unwrap_err();
+        assert!(matches!(error, VmImageHashError::HashMismatch { .. }));
+    }
+}
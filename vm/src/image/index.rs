@@ -31,18 +31,28 @@ impl VmImageIndex {
 #[serde(tag = "type")]
 pub enum VmImageRef {
     #[serde(rename = "qcow2")]
-    Qcow2 { url: String },
+    Qcow2 {
+        url: String,
+        #[serde(default)]
+        mirrors: Vec<String>,
+    },
 }
 
 impl VmImageRef {
     pub fn to_url(&self) -> &str {
         match self {
-            VmImageRef::Qcow2 { url } => url,
+            VmImageRef::Qcow2 { url, .. } => url,
+        }
+    }
+    /// The primary url, then any mirrors, in the order to try them.
+    pub fn to_urls(&self) -> Vec<&str> {
+        match self {
+            VmImageRef::Qcow2 { url, mirrors } => with_mirrors(url, mirrors),
         }
     }
     fn to_extension(&self) -> &str {
         match self {
-            VmImageRef::Qcow2 { url: _ } => "qcow2",
+            VmImageRef::Qcow2 { .. } => "qcow2",
         }
     }
 }
@@ -51,22 +61,33 @@ impl VmImageRef {
 #[serde(tag = "type")]
 pub enum VmImageHashRef {
     #[serde(rename = "sha512sums")]
-    Sha512Sums { url: String },
+    Sha512Sums {
+        url: String,
+        #[serde(default)]
+        mirrors: Vec<String>,
+    },
 }
 
 impl VmImageHashRef {
-    pub fn to_url(&self) -> &str {
+    /// The primary url, then any mirrors, in the order to try them.
+    pub fn to_urls(&self) -> Vec<&str> {
         match self {
-            VmImageHashRef::Sha512Sums { url } => url,
+            VmImageHashRef::Sha512Sums { url, mirrors } => with_mirrors(url, mirrors),
         }
     }
     fn to_extension(&self) -> &str {
         match self {
-            VmImageHashRef::Sha512Sums { url: _ } => "sha512sums",
+            VmImageHashRef::Sha512Sums { .. } => "sha512sums",
         }
     }
 }
 
+fn with_mirrors<'a>(url: &'a str, mirrors: &'a [String]) -> Vec<&'a str> {
+    std::iter::once(url)
+        .chain(mirrors.iter().map(String::as_str))
+        .collect()
+}
+
 #[derive(Debug, Clone, Default, Serialize, Deserialize)]
 pub struct VmImagesList(HashMap<String, VmImageIndex>);
 
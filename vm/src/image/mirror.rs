@@ -0,0 +1,100 @@
+use std::path::Path;
+
+use lusid_http::{DownloadSink, HttpClient, HttpError};
+use tracing::{info, warn};
+
+/// Download from the first of `urls` that works, returning the url that served it.
+pub(super) async fn download_from_mirrors<'a>(
+    http_client: &HttpClient,
+    urls: &[&'a str],
+    path: &Path,
+    sink: &mut impl DownloadSink,
+) -> Result<&'a str, HttpError> {
+    let mut last_error = None;
+    for &url in urls {
+        // Each mirror starts from scratch.
+        sink.reset();
+        match http_client.download_file_to(url, path, sink).await {
+            Ok(()) => {
+                info!(url, path = %path.display(), "downloaded");
+                return Ok(url);
+            }
+            Err(error) => {
+                warn!(url, "mirror failed: {error}");
+                last_error = Some(error);
+            }
+        }
+    }
+    Err(last_error.expect("image index has at least one url"))
+}
+
+#[cfg(test)]
+mod tests {
+    use tokio::io::{AsyncReadExt, AsyncWriteExt};
+    use tokio::net::TcpListener;
+
+    use super::*;
+
+    #[derive(Default)]
+    struct Collect(Vec<u8>);
+
+    impl DownloadSink for Collect {
+        fn update(&mut self, bytes: &[u8]) {
+            self.0.extend_from_slice(bytes);
+        }
+
+        fn reset(&mut self) {
+            self.0.clear();
+        }
+    }
+
+    /// Serve `/missing` as a 404 and anything else as `body`.
+    async fn serve(body: &'static [u8]) -> String {
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let base = format!("http://{}", listener.local_addr().unwrap());
+        tokio::spawn(async move {
+            loop {
+                let (mut stream, _) = listener.accept().await.unwrap();
+                let mut buf = vec![0; 4096];
+                let len = stream.read(&mut buf).await.unwrap();
+                let request = String::from_utf8_lossy(&buf[..len]);
+                let (status, body) = if request.starts_with("GET /missing ") {
+                    ("404 Not Found", &b""[..])
+                } else {
+                    ("200 OK", body)
+                };
+                let head = format!(
+                    "HTTP/1.1 {status}\r\nContent-Length: {}\r\nConnection: close\r\n\r\n",
+                    body.len()
+                );
+                let _ = stream.write_all(head.as_bytes()).await;
+                let _ = stream.write_all(body).await;
+                let _ = stream.shutdown().await;
+            }
+        });
+        base
+    }
+
+    #[tokio::test]
+    async fn falls_back_to_next_mirror() {
+        let base = serve(b"image bytes").await;
+        let missing = format!("{base}/missing");
+        let mirror = format!("{base}/mirror/image.qcow2");
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("image.qcow2");
+        let mut sink = Collect::default();
+
+        let served = download_from_mirrors(
+            &HttpClient::new().unwrap(),
+            &[&missing, &mirror],
+            &path,
+            &mut sink,
+        )
+        .await
+        .unwrap();
+
+        assert_eq!(served, mirror);
+        assert_eq!(tokio::fs::read(&path).await.unwrap(), b"image bytes");
+        assert_eq!(sink.0, b"image bytes");
+    }
+}
@@ -7,14 +7,22 @@ use std::path::PathBuf;
 use thiserror::Error;
 use tracing::info;
 
+mod gc;
 mod hash;
 mod index;
+mod mirror;
+
+pub use gc::{
+    backing_image_files, list_cached_images, referenced_image_files, remove_cached_images,
+    select_for_removal, CachedImage,
+};
 
 use crate::{
     context::Context,
     image::{
-        hash::{VmImageHash, VmImageHashError},
+        hash::{Sha512Digest, VmImageHash, VmImageHashError},
         index::{VmImageIndex, VmImagesList},
+        mirror::download_from_mirrors,
     },
     paths::Paths,
 };
@@ -32,6 +40,27 @@ pub enum VmImageError {
 
     #[error(transparent)]
     Fs(#[from] FsError),
+
+    #[error(
+        "no image for {arch} {os}, available images: {}",
+        format_available(available)
+    )]
+    NoMatchingImage {
+        arch: Arch,
+        os: Os,
+        available: Vec<(Arch, Os)>,
+    },
+}
+
+fn format_available(available: &[(Arch, Os)]) -> String {
+    if available.is_empty() {
+        return "none".to_string();
+    }
+    available
+        .iter()
+        .map(|(arch, os)| format!("{arch} {os}"))
+        .collect::<Vec<_>>()
+        .join(", ")
 }
 
 pub async fn get_images_list() -> Result<VmImagesList, VmImageError> {
@@ -78,10 +107,6 @@ impl VmImage {
 pub async fn get_image(ctx: &mut Context, machine: &Machine) -> Result<VmImage, VmImageError> {
     let image_index = find_image_index_for_machine(machine).await?;
 
-    let Some(image_index) = image_index else {
-        panic!("Unable to find matching image for machine");
-    };
-
     info!("image: {:?}", image_index);
 
     info!("fetching...");
@@ -95,14 +120,29 @@ pub async fn get_image(ctx: &mut Context, machine: &Machine) -> Result<VmImage,
     Ok(image)
 }
 
-async fn find_image_index_for_machine(
-    machine: &Machine,
-) -> Result<Option<VmImageIndex>, VmImageError> {
+async fn find_image_index_for_machine(machine: &Machine) -> Result<VmImageIndex, VmImageError> {
     let images_list = get_images_list().await?;
-    let image_index = images_list
-        .into_values()
-        .find(|image_index| image_index.os == machine.os && image_index.arch == machine.arch);
-    Ok(image_index)
+    find_image_index(images_list, machine.arch, &machine.os)
+}
+
+fn find_image_index(
+    images_list: VmImagesList,
+    arch: Arch,
+    os: &Os,
+) -> Result<VmImageIndex, VmImageError> {
+    let mut available = Vec::new();
+    for image_index in images_list.into_values() {
+        if image_index.os == *os && image_index.arch == arch {
+            return Ok(image_index);
+        }
+        available.push((image_index.arch, image_index.os));
+    }
+    available.sort();
+    Err(VmImageError::NoMatchingImage {
+        arch,
+        os: os.clone(),
+        available,
+    })
 }
 
 async fn fetch_image(ctx: &mut Context, image_index: &VmImageIndex) -> Result<(), VmImageError> {
@@ -110,18 +150,39 @@ async fn fetch_image(ctx: &mut Context, image_index: &VmImageIndex) -> Result<()
 
     fs::setup_directory_access(ctx.paths().images_dir()).await?;
 
-    ctx.http_client()
-        .download_file(image_index.image.to_url(), &image_path)
-        .await?;
-
     let hash_path = ctx.paths().image_file(&image_index.to_hash_file_name());
 
-    ctx.http_client()
-        .download_file(image_index.hash.to_url(), &hash_path)
-        .await?;
+    download_from_mirrors(
+        &ctx.http_client(),
+        &image_index.hash.to_urls(),
+        &hash_path,
+        &mut (),
+    )
+    .await?;
 
     let hash = VmImageHash::new(&image_index.hash, &hash_path);
-    hash.validate(image_index, &image_path).await?;
+
+    if fs::path_exists(&image_path).await? {
+        hash.validate(image_index, &image_path).await?;
+        return Ok(());
+    }
+
+    // Hash the image as it downloads, rather than reading it all back again.
+    let mut digest = Sha512Digest::new();
+    let served_by = download_from_mirrors(
+        &ctx.http_client(),
+        &image_index.image.to_urls(),
+        &image_path,
+        &mut digest,
+    )
+    .await?;
+    info!(url = served_by, "image served by mirror");
+
+    if let Err(error) = hash.verify_digest(image_index, &digest.finish_hex()).await {
+        // Don't leave a bad image around to be picked up next time.
+        fs::remove_file(&image_path).await?;
+        return Err(error.into());
+    }
 
     Ok(())
 }
@@ -129,3 +190,29 @@ async fn fetch_image(ctx: &mut Context, image_index: &VmImageIndex) -> Result<()
 fn get_image_from_index(ctx: &mut Context, image_index: VmImageIndex) -> VmImage {
     VmImage::new(ctx.paths(), image_index)
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[tokio::test]
+    async fn no_matching_image_lists_available() {
+        let images_list = get_images_list().await.unwrap();
+        let os = Os::Linux(Linux::Debian { version: 13 });
+
+        let error = find_image_index(images_list, Arch::Aarch64, &os).unwrap_err();
+
+        let VmImageError::NoMatchingImage {
+            arch,
+            os: error_os,
+            available,
+        } = &error
+        else {
+            panic!("expected NoMatchingImage, got {error:?}");
+        };
+        assert_eq!(*arch, Arch::Aarch64);
+        assert_eq!(*error_os, os);
+        assert!(available.contains(&(Arch::X86_64, os.clone())));
+        assert!(error.to_string().starts_with("no image for aarch64"));
+    }
+}
@@ -1,29 +1,47 @@
 mod paths;
+mod ready;
 mod setup;
+mod shutdown;
+mod snapshot;
 mod start;
 
-use self::paths::*;
+pub(crate) use self::paths::VmPaths;
+use self::ready::*;
 use self::setup::*;
+use self::shutdown::*;
+use self::snapshot::*;
 use self::start::*;
 
+pub use self::snapshot::{VmSnapshot, VmSnapshotError};
 use lusid_ctx::Context as BaseContext;
 use lusid_fs::{self as fs, FsError};
 use lusid_machine::Machine;
-use lusid_ssh::{SshKeypair, SshKeypairError};
+pub use lusid_machine::VmPort;
+use lusid_ssh::{SshConnectOptions, SshKeypair, SshKeypairError};
 use lusid_system::{Arch, CpuCount, Linux, MemorySize};
 use nix::{
     sys::signal::{kill, Signal},
     unistd::Pid,
 };
 use serde::{Deserialize, Serialize};
+use std::net::Ipv4Addr;
 use std::num::ParseIntError;
+use std::sync::Arc;
 use std::time::Duration;
-use std::{fmt::Display, net::Ipv4Addr, path::PathBuf, str::FromStr};
+use std::{
+    path::{Path, PathBuf},
+    str::FromStr,
+};
 use thiserror::Error;
 use tokio::time::sleep;
+use tracing::{debug, warn};
 
 use crate::{
     context::{Context, ContextError},
+    ovmf::OvmfOverrides,
+    paths::Paths,
+    qemu::Accel,
+    tools::check_required_tools,
     utils::is_tcp_port_open,
 };
 
@@ -31,6 +49,11 @@ pub struct VmOptions<'a> {
     pub instance_id: &'a str,
     pub machine: &'a Machine,
     pub ports: Vec<VmPort>,
+    /// Remove any existing instance and set up a fresh one.
+    pub recreate: bool,
+    pub ovmf: OvmfOverrides,
+    /// Accelerator to use rather than picking one. Default: KVM if available, else TCG.
+    pub accel: Option<Accel>,
 }
 
 #[derive(Error, Debug)]
@@ -70,6 +93,24 @@ pub enum VmError {
 
     #[error("failed to kill pid")]
     KillPid(#[source] nix::errno::Errno),
+
+    #[error("failed to remove pid file")]
+    RemovePid(#[source] FsError),
+
+    #[error(transparent)]
+    Snapshot(#[from] VmSnapshotError),
+
+    #[error("vm did not finish booting within {timeout:?}")]
+    BootTimeout { timeout: Duration },
+
+    #[error("failed to read instances dir")]
+    ReadInstancesDir(#[source] FsError),
+
+    #[error("vm instance not found: {0}")]
+    NotFound(String),
+
+    #[error("missing required tool `{name}`: install the `{package}` package")]
+    MissingTool { name: String, package: String },
 }
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
@@ -87,46 +128,112 @@ pub struct Vm {
     pub ports: Vec<VmPort>,
     pub graphics: Option<bool>,
     pub kvm: Option<bool>,
+    /// QEMU machine type. Default: q35 on x86_64, virt on aarch64.
+    #[serde(default)]
+    pub machine_type: Option<String>,
+}
+
+/// Summary of an instance on disk, as shown by `lusid dev list`.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct VmStatus {
+    pub id: String,
+    pub ssh_port: u16,
+    pub is_running: bool,
+    pub is_ssh_open: bool,
+}
+
+/// What [`Vm::run`] needs to do to get a healthy instance.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum VmRunAction {
+    /// Instance is running, reuse it as is.
+    Reuse,
+    /// Instance exists but is stopped, start it.
+    Start,
+    /// No instance yet, set one up and start it.
+    Setup,
+    /// Throw away the existing instance and set up a fresh one.
+    Recreate,
 }
 
+impl VmRunAction {
+    fn decide(exists: bool, is_running: bool, recreate: bool) -> Self {
+        match (exists, is_running, recreate) {
+            (false, _, _) => VmRunAction::Setup,
+            (true, _, true) => VmRunAction::Recreate,
+            (true, true, false) => VmRunAction::Reuse,
+            (true, false, false) => VmRunAction::Start,
+        }
+    }
+}
+
+/// How long to wait for an instance to power down before recreating it.
+const RECREATE_SHUTDOWN_TIMEOUT: Duration = Duration::from_secs(30);
+
 impl Vm {
     pub async fn run(ctx: &mut BaseContext, options: VmOptions<'_>) -> Result<Vm, VmError> {
-        let mut ctx = Context::create(ctx)?;
+        check_required_tools()?;
 
         let VmOptions {
             instance_id,
             machine,
             ports,
+            recreate,
+            ovmf,
+            accel,
         } = options;
 
-        let instance = if Vm::exists(&mut ctx, instance_id).await? {
-            Vm::load(&mut ctx, instance_id).await?
+        let mut ctx = Context::create(ctx, &ovmf)?;
+
+        let existing = if Vm::exists(&mut ctx, instance_id).await? {
+            Some(Vm::load(&mut ctx, instance_id).await?)
         } else {
-            let setup_options = VmSetupOptions {
-                instance_id,
-                machine,
-                ports,
-            };
-            let inst = Vm::setup(&mut ctx, setup_options).await?;
-            inst.save().await?;
-            inst
+            None
+        };
+        let is_running = match &existing {
+            Some(instance) => instance.is_qemu_running().await?,
+            None => false,
+        };
+        let action = VmRunAction::decide(existing.is_some(), is_running, recreate);
+        debug!(instance = instance_id, ?action, "run vm");
+
+        let instance = match (action, existing) {
+            (VmRunAction::Reuse | VmRunAction::Start, Some(instance)) => instance,
+            (VmRunAction::Recreate, Some(instance)) => {
+                instance.shutdown(RECREATE_SHUTDOWN_TIMEOUT).await?;
+                instance.remove().await?;
+                Vm::setup_and_save(&mut ctx, instance_id, machine, ports).await?
+            }
+            _ => Vm::setup_and_save(&mut ctx, instance_id, machine, ports).await?,
         };
 
-        if !instance.is_qemu_running().await? {
-            instance.start(&mut ctx).await?;
-
-            loop {
-                if instance.is_ssh_open() {
-                    break;
-                }
+        if action != VmRunAction::Reuse {
+            instance.start(&mut ctx, accel).await?;
+        }
 
-                sleep(Duration::from_millis(100)).await;
-            }
+        // A reused instance may still be booting.
+        while !instance.is_ssh_open() {
+            sleep(Duration::from_millis(100)).await;
         }
 
         Ok(instance)
     }
 
+    async fn setup_and_save(
+        ctx: &mut Context,
+        instance_id: &str,
+        machine: &Machine,
+        ports: Vec<VmPort>,
+    ) -> Result<Self, VmError> {
+        let setup_options = VmSetupOptions {
+            instance_id,
+            machine,
+            ports,
+        };
+        let instance = Vm::setup(ctx, setup_options).await?;
+        instance.save().await?;
+        Ok(instance)
+    }
+
     fn paths(&self) -> VmPaths<'_> {
         VmPaths::new(&self.dir)
     }
@@ -145,7 +252,11 @@ impl Vm {
 
     async fn load(ctx: &mut Context, instance_id: &str) -> Result<Self, VmError> {
         let instance_dir = ctx.paths().instance_dir(instance_id);
-        let paths = VmPaths::new(&instance_dir);
+        Self::load_dir(&instance_dir).await
+    }
+
+    async fn load_dir(instance_dir: &Path) -> Result<Self, VmError> {
+        let paths = VmPaths::new(instance_dir);
         let state_path = paths.state();
         let state_str = fs::read_file_to_string(state_path)
             .await
@@ -154,6 +265,62 @@ impl Vm {
         Ok(instance)
     }
 
+    /// Find an existing instance by id, without setting up anything.
+    pub async fn find(ctx: &BaseContext, instance_id: &str) -> Result<Self, VmError> {
+        let paths = Paths::new(ctx.paths().clone());
+        Self::find_dir(&paths.instance_dir(instance_id), instance_id).await
+    }
+
+    async fn find_dir(instance_dir: &Path, instance_id: &str) -> Result<Self, VmError> {
+        let state_exists = fs::path_exists(VmPaths::new(instance_dir).state())
+            .await
+            .map_err(VmError::DirExists)?;
+        if !state_exists {
+            return Err(VmError::NotFound(instance_id.to_string()));
+        }
+        Self::load_dir(instance_dir).await
+    }
+
+    /// List all instances under the instances dir, with their running state.
+    pub async fn list(ctx: &BaseContext) -> Result<Vec<VmStatus>, VmError> {
+        let paths = Paths::new(ctx.paths().clone());
+        Self::list_dir(&paths.instances_dir()).await
+    }
+
+    async fn list_dir(instances_dir: &Path) -> Result<Vec<VmStatus>, VmError> {
+        if !fs::path_exists(instances_dir)
+            .await
+            .map_err(VmError::DirExists)?
+        {
+            return Ok(Vec::new());
+        }
+
+        let mut statuses = Vec::new();
+        for instance_dir in fs::read_dir(instances_dir)
+            .await
+            .map_err(VmError::ReadInstancesDir)?
+        {
+            // Skip anything that isn't a fully set up instance.
+            let state_exists = fs::path_exists(VmPaths::new(&instance_dir).state())
+                .await
+                .map_err(VmError::StateRead)?;
+            if !state_exists {
+                continue;
+            }
+
+            let instance = Self::load_dir(&instance_dir).await?;
+            statuses.push(VmStatus {
+                is_running: instance.is_qemu_running().await?,
+                is_ssh_open: instance.is_ssh_open(),
+                ssh_port: instance.ssh_port,
+                id: instance.id,
+            });
+        }
+
+        statuses.sort_by(|a, b| a.id.cmp(&b.id));
+        Ok(statuses)
+    }
+
     async fn save(&self) -> Result<(), VmError> {
         let state_path = self.paths().state();
         let state = serde_json::to_string_pretty(self).map_err(VmError::StateSerde)?;
@@ -168,8 +335,20 @@ impl Vm {
         Ok(())
     }
 
-    async fn start(&self, ctx: &mut Context) -> Result<(), VmError> {
-        Ok(instance_start(ctx.executables(), self).await?)
+    /// The QEMU command line that starts this instance, without starting it.
+    pub fn qemu_command(
+        &self,
+        ctx: &BaseContext,
+        ovmf: &OvmfOverrides,
+        accel: Option<Accel>,
+    ) -> Result<String, VmError> {
+        let ctx = Context::create(ctx, ovmf)?;
+        let InstanceQemu { qemu, .. } = instance_qemu(ctx.executables(), ctx.ovmf(), self, accel);
+        Ok(qemu.to_command_string())
+    }
+
+    async fn start(&self, ctx: &mut Context, accel: Option<Accel>) -> Result<(), VmError> {
+        Ok(instance_start(ctx.executables(), ctx.ovmf(), self, accel).await?)
     }
 
     async fn is_qemu_running(&self) -> Result<bool, VmError> {
@@ -183,14 +362,105 @@ impl Vm {
         is_tcp_port_open(self.ssh_port)
     }
 
+    /// Wait up to `timeout` for the guest to finish booting, which is later
+    /// than when SSH first accepts connections.
+    pub async fn wait_ready(&self, timeout: Duration) -> Result<(), VmError> {
+        let options = SshConnectOptions {
+            private_key: self.ssh_keypair().await?.private_key,
+            addrs: (Ipv4Addr::LOCALHOST, self.ssh_port),
+            username: self.user.clone(),
+            config: Arc::new(Default::default()),
+            timeout: Duration::from_secs(10),
+        };
+        let ready = poll_until_ready(
+            || check_system_running(options.clone()),
+            timeout,
+            READY_POLL_INTERVAL,
+        )
+        .await;
+        if !ready {
+            return Err(VmError::BootTimeout { timeout });
+        }
+        debug!(instance = %self.id, "vm ready");
+        Ok(())
+    }
+
+    /// Save the instance's disk and firmware state as snapshot `name`.
+    ///
+    /// The vm must be stopped, so the copy is consistent.
+    pub async fn snapshot(&self, name: &str) -> Result<(), VmError> {
+        if self.is_qemu_running().await? {
+            return Err(VmSnapshotError::Running.into());
+        }
+        save_snapshot(&self.paths(), name).await?;
+        Ok(())
+    }
+
+    /// Put the instance back as it was when snapshot `name` was taken.
+    ///
+    /// The vm must be stopped.
+    pub async fn restore(&self, name: &str) -> Result<(), VmError> {
+        if self.is_qemu_running().await? {
+            return Err(VmSnapshotError::Running.into());
+        }
+        restore_snapshot(&self.paths(), name).await?;
+        Ok(())
+    }
+
+    pub async fn snapshots(&self) -> Result<Vec<VmSnapshot>, VmError> {
+        Ok(list_snapshots(&self.paths().snapshots_dir())
+            .await
+            .map_err(VmSnapshotError::from)?)
+    }
+
     pub async fn stop(&self) -> Result<(), VmError> {
+        let pid = self.qemu_pid().await?;
+        kill(pid, Some(Signal::SIGKILL)).map_err(VmError::KillPid)?;
+        Ok(())
+    }
+
+    /// Gracefully power down the guest via QMP, waiting up to `timeout` for
+    /// QEMU to exit before falling back to `SIGKILL`.
+    ///
+    /// Does nothing if the vm is not running.
+    pub async fn shutdown(&self, timeout: Duration) -> Result<(), VmError> {
+        if !self.is_qemu_running().await? {
+            return Ok(());
+        }
+
+        let pid = self.qemu_pid().await?;
+
+        if is_pid_running(pid) {
+            let outcome = power_down_or_kill(
+                &self.paths().qemu_qmp_socket_path(),
+                || is_pid_running(pid),
+                || async { kill(pid, Some(Signal::SIGKILL)).map_err(VmError::KillPid) },
+                timeout,
+            )
+            .await?;
+
+            if outcome == ShutdownOutcome::Killed {
+                warn!(instance = %self.id, "vm did not power down in time, killed");
+            }
+        }
+
+        // QEMU only removes its pid file on a clean exit.
+        let pid_path = self.paths().qemu_pid_path();
+        if fs::path_exists(&pid_path).await.map_err(VmError::ReadPid)? {
+            fs::remove_file(&pid_path)
+                .await
+                .map_err(VmError::RemovePid)?;
+        }
+
+        Ok(())
+    }
+
+    async fn qemu_pid(&self) -> Result<Pid, VmError> {
         let pid_str = fs::read_file_to_string(&self.paths().qemu_pid_path())
             .await
             .map_err(VmError::ReadPid)?;
-        let pid_int: i32 = FromStr::from_str(&pid_str).map_err(VmError::ParsePid)?;
-        let pid = Pid::from_raw(pid_int);
-        kill(pid, Some(Signal::SIGKILL)).map_err(VmError::KillPid)?;
-        Ok(())
+        let pid_int: i32 = FromStr::from_str(pid_str.trim()).map_err(VmError::ParsePid)?;
+        Ok(Pid::from_raw(pid_int))
     }
 
     pub async fn ssh_keypair(&self) -> Result<SshKeypair, VmError> {
@@ -200,30 +470,113 @@ impl Vm {
     }
 }
 
-#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
-pub struct VmPort {
-    pub host_ip: Option<Ipv4Addr>,
-    pub host_port: Option<u16>,
-    pub vm_port: u16,
+/// Signal 0 checks whether the process exists without affecting it.
+fn is_pid_running(pid: Pid) -> bool {
+    kill(pid, None).is_ok()
 }
 
-impl Display for VmPort {
-    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
-        let mut wrote_left = false;
-        if let Some(ip) = self.host_ip {
-            write!(f, "{}", ip)?;
-            wrote_left = true;
-        }
-        if let Some(port) = self.host_port {
-            if wrote_left {
-                write!(f, ":")?;
-            }
-            write!(f, "{}", port)?;
-            wrote_left = true;
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn synthetic_vm(dir: &Path, id: &str, ssh_port: u16) -> Vm {
+        Vm {
+            id: id.to_string(),
+            dir: dir.join(id),
+            arch: Arch::X86_64,
+            linux: Linux::Arch,
+            kernel_root: "/dev/vda1".to_string(),
+            user: "lusid".to_string(),
+            has_initrd: false,
+            ssh_port,
+            memory_size: None,
+            cpu_count: None,
+            ports: Vec::new(),
+            graphics: None,
+            kvm: None,
+            machine_type: None,
         }
-        if wrote_left {
-            write!(f, "->")?;
+    }
+
+    #[tokio::test]
+    async fn list_dir_loads_instances() {
+        let dir = tempfile::tempdir().unwrap();
+        for (id, ssh_port) in [("beta", 2), ("alpha", 1)] {
+            let vm = synthetic_vm(dir.path(), id, ssh_port);
+            fs::create_dir(&vm.dir).await.unwrap();
+            vm.save().await.unwrap();
         }
-        write!(f, "{}/tcp", self.vm_port)
+        // Not an instance: no state file.
+        fs::create_dir(dir.path().join("partial")).await.unwrap();
+
+        let statuses = Vm::list_dir(dir.path()).await.unwrap();
+
+        assert_eq!(
+            statuses,
+            vec![
+                VmStatus {
+                    id: "alpha".to_string(),
+                    ssh_port: 1,
+                    is_running: false,
+                    is_ssh_open: false,
+                },
+                VmStatus {
+                    id: "beta".to_string(),
+                    ssh_port: 2,
+                    is_running: false,
+                    is_ssh_open: false,
+                },
+            ]
+        );
+    }
+
+    #[tokio::test]
+    async fn find_dir_missing_is_not_found() {
+        let dir = tempfile::tempdir().unwrap();
+        let result = Vm::find_dir(&dir.path().join("missing"), "missing").await;
+        assert!(matches!(result, Err(VmError::NotFound(id)) if id == "missing"));
+    }
+
+    #[tokio::test]
+    async fn stop_then_remove() {
+        let dir = tempfile::tempdir().unwrap();
+        let vm = synthetic_vm(dir.path(), "dev", 1);
+        fs::create_dir(&vm.dir).await.unwrap();
+        vm.save().await.unwrap();
+        // A stale pid file left behind by a crashed qemu.
+        let pid_path = vm.paths().qemu_pid_path();
+        fs::write_file(&pid_path, i32::MAX.to_string().as_bytes())
+            .await
+            .unwrap();
+
+        let vm = Vm::find_dir(&vm.dir, "dev").await.unwrap();
+        vm.shutdown(Duration::from_secs(1)).await.unwrap();
+        assert!(!fs::path_exists(&pid_path).await.unwrap());
+
+        // Stopping an already stopped vm is a no-op.
+        vm.shutdown(Duration::from_secs(1)).await.unwrap();
+
+        let instance_dir = vm.dir.clone();
+        vm.remove().await.unwrap();
+        assert!(!fs::path_exists(&instance_dir).await.unwrap());
+    }
+
+    #[test]
+    fn run_action_decide() {
+        use VmRunAction::*;
+
+        assert_eq!(VmRunAction::decide(false, false, false), Setup);
+        assert_eq!(VmRunAction::decide(false, false, true), Setup);
+        assert_eq!(VmRunAction::decide(true, true, false), Reuse);
+        assert_eq!(VmRunAction::decide(true, false, false), Start);
+        assert_eq!(VmRunAction::decide(true, true, true), Recreate);
+        assert_eq!(VmRunAction::decide(true, false, true), Recreate);
+    }
+
+    #[tokio::test]
+    async fn list_dir_missing_is_empty() {
+        let dir = tempfile::tempdir().unwrap();
+        let statuses = Vm::list_dir(&dir.path().join("missing")).await.unwrap();
+        assert!(statuses.is_empty());
     }
 }
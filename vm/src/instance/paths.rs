@@ -1,5 +1,4 @@
 use std::path::{Path, PathBuf};
-use std::sync::LazyLock;
 
 pub struct VmPaths<'a> {
     instance_dir: &'a Path,
@@ -22,24 +21,10 @@ impl<'a> VmPaths<'a> {
         self.instance_dir.join("overlay.qcow2")
     }
 
-    pub fn ovmf_vars_system_path(&self) -> &Path {
-        static OVMF_VARS_SYSTEM_FILE: LazyLock<PathBuf> =
-            LazyLock::new(|| PathBuf::from("/usr/share/OVMF/OVMF_VARS_4M.fd"));
-
-        OVMF_VARS_SYSTEM_FILE.as_path()
-    }
-
     pub fn ovmf_vars_path(&self) -> PathBuf {
         self.instance_dir.join("OVMF_VARS.4m.fd.qcow2")
     }
 
-    pub fn ovmf_code_system_path(&self) -> &Path {
-        static OVMF_CODE_SYSTEM_FILE: LazyLock<PathBuf> =
-            LazyLock::new(|| PathBuf::from("/usr/share/OVMF/OVMF_CODE_4M.fd"));
-
-        OVMF_CODE_SYSTEM_FILE.as_path()
-    }
-
     pub fn kernel_path(&self) -> PathBuf {
         self.instance_dir.join("vmlinuz")
     }
@@ -67,4 +52,13 @@ impl<'a> VmPaths<'a> {
     pub fn qemu_qmp_socket_path(&self) -> PathBuf {
         self.instance_dir.join("qmp.sock")
     }
+
+    pub fn snapshots_dir(&self) -> PathBuf {
+        self.instance_dir.join("snapshots")
+    }
+
+    /// Disk and firmware state: all that changes while an instance runs.
+    pub fn snapshot_files(&self) -> [PathBuf; 2] {
+        [self.overlay_image_path(), self.ovmf_vars_path()]
+    }
 }
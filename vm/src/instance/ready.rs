@@ -0,0 +1,133 @@
+use std::{future::Future, net::Ipv4Addr, time::Duration};
+
+use lusid_ssh::{Ssh, SshConnectOptions};
+use tokio::{
+    io::AsyncReadExt,
+    time::{sleep, timeout, Instant},
+};
+use tracing::{debug, warn};
+
+/// How often to check whether the guest has finished booting.
+pub(super) const READY_POLL_INTERVAL: Duration = Duration::from_secs(1);
+
+/// Whether `systemctl is-system-running` output means boot has finished.
+///
+/// "degraded" means some unit failed, but the system is otherwise up.
+pub(super) fn is_booted(state: &str) -> bool {
+    matches!(state.trim(), "running" | "degraded")
+}
+
+/// Poll `check` until it reports ready, giving up once `timeout` elapses.
+///
+/// A check that hangs is cut short at the deadline.
+pub(super) async fn poll_until_ready<Check, CheckFut>(
+    mut check: Check,
+    timeout_after: Duration,
+    poll_interval: Duration,
+) -> bool
+where
+    Check: FnMut() -> CheckFut,
+    CheckFut: Future<Output = bool>,
+{
+    let deadline = Instant::now() + timeout_after;
+
+    loop {
+        let remaining = deadline.saturating_duration_since(Instant::now());
+        if remaining.is_zero() {
+            return false;
+        }
+        if let Ok(true) = timeout(remaining, check()).await {
+            return true;
+        }
+        sleep(poll_interval.min(deadline.saturating_duration_since(Instant::now()))).await;
+    }
+}
+
+/// Ask the guest over SSH whether it has finished booting.
+pub(super) async fn check_system_running(options: SshConnectOptions<(Ipv4Addr, u16)>) -> bool {
+    let mut ssh = match Ssh::connect(options).await {
+        Ok(ssh) => ssh,
+        Err(error) => {
+            debug!("readiness check could not connect: {error}");
+            return false;
+        }
+    };
+
+    // Blocks until boot finishes, one way or another.
+    let state = match ssh.command("systemctl is-system-running --wait").await {
+        Ok(mut handle) => {
+            let mut state = String::new();
+            let read = handle.stdout.read_to_string(&mut state).await;
+            let _ = handle.channel.wait().await;
+            read.ok().map(|_| state)
+        }
+        Err(error) => {
+            debug!("readiness check failed: {error}");
+            None
+        }
+    };
+    let _ = ssh.disconnect().await;
+
+    let state = state.unwrap_or_default();
+    if state.trim() == "degraded" {
+        warn!("vm booted, but some units failed");
+    }
+    is_booted(&state)
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::atomic::{AtomicUsize, Ordering};
+
+    use super::*;
+
+    #[test]
+    fn booted_states() {
+        assert!(is_booted("running\n"));
+        assert!(is_booted("degraded\n"));
+        assert!(!is_booted("starting\n"));
+        assert!(!is_booted("initializing\n"));
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn ready_after_some_polls() {
+        let polls = AtomicUsize::new(0);
+
+        let ready = poll_until_ready(
+            || async { polls.fetch_add(1, Ordering::SeqCst) >= 2 },
+            Duration::from_secs(60),
+            READY_POLL_INTERVAL,
+        )
+        .await;
+
+        assert!(ready);
+        assert_eq!(polls.load(Ordering::SeqCst), 3);
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn times_out_when_never_ready() {
+        let started_at = Instant::now();
+
+        let ready = poll_until_ready(
+            || async { false },
+            Duration::from_secs(5),
+            READY_POLL_INTERVAL,
+        )
+        .await;
+
+        assert!(!ready);
+        assert_eq!(started_at.elapsed(), Duration::from_secs(5));
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn cuts_short_a_hung_check() {
+        let ready = poll_until_ready(
+            std::future::pending::<bool>,
+            Duration::from_secs(5),
+            READY_POLL_INTERVAL,
+        )
+        .await;
+
+        assert!(!ready);
+    }
+}
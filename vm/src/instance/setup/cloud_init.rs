@@ -3,6 +3,7 @@ use lusid_fs::{self as fs, FsError};
 use lusid_system::Hostname;
 use russh::keys::PublicKey;
 use serde::{Deserialize, Serialize};
+use serde_json::Value;
 use thiserror::Error;
 
 use crate::{instance::VmPaths, paths::ExecutablePaths};
@@ -20,6 +21,15 @@ pub enum CloudInitError {
 
     #[error(transparent)]
     Command(#[from] CommandError),
+
+    #[error("invalid extra cloud-init user-data")]
+    ParseExtraUserData(#[source] serde_saphyr::Error),
+
+    #[error("extra cloud-init user-data must be a mapping")]
+    ExtraUserDataNotMapping,
+
+    #[error("failed to convert cloud-init user-data")]
+    Json(#[from] serde_json::Error),
 }
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
@@ -41,6 +51,7 @@ pub(super) async fn setup_cloud_init(
     instance_id: &str,
     hostname: &Hostname,
     ssh_public_key: &PublicKey,
+    extra_user_data: Option<&str>,
 ) -> Result<(), CloudInitError> {
     let meta_data_path = paths.cloud_init_meta_data_path();
     let user_data_path = paths.cloud_init_user_data_path();
@@ -64,11 +75,8 @@ pub(super) async fn setup_cloud_init(
             ssh_authorized_keys: vec![ssh_public_key.to_openssh()?],
             packages: vec!["openssh".to_owned()],
         };
-        fs::write_file(
-            &user_data_path,
-            format!("#cloud-config\n{}", serde_saphyr::to_string(&user_data)?).as_bytes(),
-        )
-        .await?;
+        let user_data = render_user_data(&user_data, extra_user_data)?;
+        fs::write_file(&user_data_path, user_data.as_bytes()).await?;
     }
 
     if !fs::path_exists(&image_path).await? {
@@ -87,3 +95,73 @@ pub(super) async fn setup_cloud_init(
 
     Ok(())
 }
+
+/// Render `user-data`, merging in any extra user-provided config.
+///
+/// Lists (e.g. `packages`) are appended to, anything else in the extra config wins.
+fn render_user_data(
+    user_data: &CloudInitUserData,
+    extra_user_data: Option<&str>,
+) -> Result<String, CloudInitError> {
+    let mut user_data = serde_json::to_value(user_data)?;
+
+    if let Some(extra_user_data) = extra_user_data {
+        let extra: Value =
+            serde_saphyr::from_str(extra_user_data).map_err(CloudInitError::ParseExtraUserData)?;
+        let (Value::Object(user_data), Value::Object(extra)) = (&mut user_data, extra) else {
+            return Err(CloudInitError::ExtraUserDataNotMapping);
+        };
+        for (key, value) in extra {
+            match (user_data.get_mut(&key), value) {
+                (Some(Value::Array(existing)), Value::Array(items)) => existing.extend(items),
+                (_, value) => {
+                    user_data.insert(key, value);
+                }
+            }
+        }
+    }
+
+    Ok(format!(
+        "#cloud-config\n{}",
+        serde_saphyr::to_string(&user_data)?
+    ))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn render_user_data_merges_extra() {
+        let user_data = CloudInitUserData {
+            hostname: "dev".to_owned(),
+            ssh_authorized_keys: vec!["ssh-ed25519 AAAA lusid".to_owned()],
+            packages: vec!["openssh".to_owned()],
+        };
+        let extra = "timezone: Pacific/Auckland\npackages:\n  - git\n";
+
+        let rendered = render_user_data(&user_data, Some(extra)).unwrap();
+
+        assert!(rendered.starts_with("#cloud-config\n"));
+        assert!(rendered.contains("ssh-ed25519 AAAA lusid"));
+        assert!(rendered.contains("Pacific/Auckland"));
+        assert!(rendered.contains("openssh"));
+        assert!(rendered.contains("git"));
+    }
+
+    #[test]
+    fn render_user_data_rejects_non_mapping() {
+        let user_data = CloudInitUserData {
+            hostname: "dev".to_owned(),
+            ssh_authorized_keys: Vec::new(),
+            packages: Vec::new(),
+        };
+
+        let result = render_user_data(&user_data, Some("- not\n- a mapping\n"));
+
+        assert!(matches!(
+            result,
+            Err(CloudInitError::ExtraUserDataNotMapping)
+        ));
+    }
+}
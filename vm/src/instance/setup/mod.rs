@@ -71,6 +71,9 @@ pub async fn setup_instance(
         memory_size,
         cpu_count,
         graphics,
+        ports: _,
+        extra_user_data,
+        machine_type,
     } = machine.vm.clone().unwrap_or_default();
 
     let VmImage {
@@ -88,7 +91,7 @@ pub async fn setup_instance(
     let instance_paths = VmPaths::new(&instance_dir);
 
     setup_overlay(&instance_paths, &source_image_path).await?;
-    setup_ovmf_uefi_variables(executables, &instance_paths).await?;
+    setup_ovmf_uefi_variables(executables, ctx.ovmf(), &instance_paths).await?;
 
     let VmKernelDetails { has_initrd } =
         setup_kernel(executables, &instance_paths, &source_image_path).await?;
@@ -102,6 +105,7 @@ pub async fn setup_instance(
         instance_id,
         &machine.hostname,
         &ssh_keypair.public_key,
+        extra_user_data.as_deref(),
     )
     .await?;
 
@@ -120,5 +124,6 @@ pub async fn setup_instance(
         graphics,
         // TODO set via global lusid config
         kvm: None,
+        machine_type,
     })
 }
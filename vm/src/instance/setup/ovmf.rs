@@ -2,7 +2,7 @@ use lusid_cmd::{Command, CommandError};
 use lusid_fs::{self as fs, FsError};
 use thiserror::Error;
 
-use crate::{instance::VmPaths, paths::ExecutablePaths};
+use crate::{instance::VmPaths, ovmf::OvmfPaths, paths::ExecutablePaths};
 
 #[derive(Error, Debug)]
 pub enum ConvertOvmfVarsError {
@@ -25,9 +25,10 @@ pub enum ConvertOvmfVarsError {
 /// Original source: https://gitlab.archlinux.org/archlinux/vmexec/-/blob/03b649bdbcdc64d30b2943f61b51165f390b920d/src/qemu.rs#L93-124
 pub(super) async fn setup_ovmf_uefi_variables(
     executables: &ExecutablePaths,
+    ovmf: &OvmfPaths,
     paths: &VmPaths<'_>,
 ) -> Result<(), ConvertOvmfVarsError> {
-    let ovmf_vars_system_path = paths.ovmf_vars_system_path();
+    let ovmf_vars_system_path = ovmf.vars();
     let ovmf_vars_path = paths.ovmf_vars_path();
 
     if !fs::path_exists(&ovmf_vars_path).await? {
@@ -0,0 +1,203 @@
+use std::{future::Future, io, path::Path, time::Duration};
+
+use tokio::{
+    io::{AsyncBufReadExt, AsyncWriteExt, BufReader},
+    net::UnixStream,
+    time::{sleep, Instant},
+};
+use tracing::{debug, warn};
+
+/// How often to check whether QEMU has exited.
+pub(super) const SHUTDOWN_POLL_INTERVAL: Duration = Duration::from_millis(100);
+
+/// Ask the guest to power down via QEMU's QMP socket (an ACPI power button press).
+async fn qmp_system_powerdown(qmp_socket_path: &Path) -> Result<(), io::Error> {
+    let stream = UnixStream::connect(qmp_socket_path).await?;
+    let (reader, mut writer) = stream.into_split();
+    let mut lines = BufReader::new(reader).lines();
+
+    // QMP greets with a banner, then requires capabilities negotiation.
+    let greeting = lines.next_line().await?;
+    debug!(?greeting, "qmp greeting");
+
+    for command in [
+        r#"{"execute":"qmp_capabilities"}"#,
+        r#"{"execute":"system_powerdown"}"#,
+    ] {
+        writer.write_all(command.as_bytes()).await?;
+        writer.write_all(b"\n").await?;
+        writer.flush().await?;
+
+        // Skip asynchronous events until the command's response arrives.
+        loop {
+            let line = lines
+                .next_line()
+                .await?
+                .ok_or_else(|| io::Error::new(io::ErrorKind::UnexpectedEof, "qmp socket closed"))?;
+            if line.contains(r#""error""#) {
+                return Err(io::Error::other(format!("qmp command failed: {line}")));
+            }
+            if line.contains(r#""return""#) {
+                break;
+            }
+        }
+    }
+
+    Ok(())
+}
+
+/// Power down via QMP, then wait for the process to exit, killing it once
+/// `timeout` elapses.
+///
+/// The timeout covers the whole QMP exchange too, as a hung QEMU may accept
+/// the connection and never answer. If the exchange fails or runs out of time,
+/// the process is killed at once.
+pub(super) async fn power_down_or_kill<IsRunning, Kill, KillFut, Error>(
+    qmp_socket_path: &Path,
+    is_running: IsRunning,
+    kill: Kill,
+    timeout: Duration,
+) -> Result<ShutdownOutcome, Error>
+where
+    IsRunning: Fn() -> bool,
+    Kill: FnOnce() -> KillFut,
+    KillFut: Future<Output = Result<(), Error>>,
+{
+    let started_at = Instant::now();
+    let remaining = match tokio::time::timeout(timeout, qmp_system_powerdown(qmp_socket_path)).await
+    {
+        Ok(Ok(())) => timeout.saturating_sub(started_at.elapsed()),
+        Ok(Err(error)) => {
+            warn!("failed to request power down via qmp: {error}");
+            Duration::ZERO
+        }
+        Err(_elapsed) => {
+            warn!("qmp did not answer within {timeout:?}");
+            Duration::ZERO
+        }
+    };
+    wait_for_exit_or_kill(is_running, kill, remaining, SHUTDOWN_POLL_INTERVAL).await
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub(super) enum ShutdownOutcome {
+    Exited,
+    Killed,
+}
+
+/// Poll until the process exits, or kill it once the timeout elapses.
+pub(super) async fn wait_for_exit_or_kill<IsRunning, Kill, KillFut, Error>(
+    is_running: IsRunning,
+    kill: Kill,
+    timeout: Duration,
+    poll_interval: Duration,
+) -> Result<ShutdownOutcome, Error>
+where
+    IsRunning: Fn() -> bool,
+    Kill: FnOnce() -> KillFut,
+    KillFut: Future<Output = Result<(), Error>>,
+{
+    let deadline = Instant::now() + timeout;
+
+    while Instant::now() < deadline {
+        if !is_running() {
+            return Ok(ShutdownOutcome::Exited);
+        }
+        sleep(poll_interval).await;
+    }
+
+    if !is_running() {
+        return Ok(ShutdownOutcome::Exited);
+    }
+
+    kill().await?;
+    Ok(ShutdownOutcome::Killed)
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::atomic::{AtomicBool, AtomicUsize, Ordering};
+
+    use super::*;
+
+    #[tokio::test(start_paused = true)]
+    async fn kills_after_timeout() {
+        let killed = AtomicBool::new(false);
+
+        let outcome = wait_for_exit_or_kill(
+            || !killed.load(Ordering::SeqCst),
+            || async {
+                killed.store(true, Ordering::SeqCst);
+                Ok::<(), ()>(())
+            },
+            Duration::from_secs(5),
+            SHUTDOWN_POLL_INTERVAL,
+        )
+        .await;
+
+        assert_eq!(outcome, Ok(ShutdownOutcome::Killed));
+        assert!(killed.load(Ordering::SeqCst));
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn kills_when_qmp_never_answers() {
+        let dir = tempfile::tempdir().unwrap();
+        let socket_path = dir.path().join("qmp.sock");
+        // Accepts connections, but never sends the greeting.
+        let _listener = tokio::net::UnixListener::bind(&socket_path).unwrap();
+        let killed = AtomicBool::new(false);
+
+        let outcome = power_down_or_kill(
+            &socket_path,
+            || !killed.load(Ordering::SeqCst),
+            || async {
+                killed.store(true, Ordering::SeqCst);
+                Ok::<(), ()>(())
+            },
+            Duration::from_secs(5),
+        )
+        .await;
+
+        assert_eq!(outcome, Ok(ShutdownOutcome::Killed));
+        assert!(killed.load(Ordering::SeqCst));
+    }
+
+    #[tokio::test]
+    async fn kills_when_qmp_is_missing() {
+        let dir = tempfile::tempdir().unwrap();
+        let killed = AtomicBool::new(false);
+
+        let outcome = power_down_or_kill(
+            &dir.path().join("qmp.sock"),
+            || !killed.load(Ordering::SeqCst),
+            || async {
+                killed.store(true, Ordering::SeqCst);
+                Ok::<(), ()>(())
+            },
+            Duration::from_secs(5),
+        )
+        .await;
+
+        assert_eq!(outcome, Ok(ShutdownOutcome::Killed));
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn does_not_kill_when_exited() {
+        let polls = AtomicUsize::new(0);
+        let killed = AtomicBool::new(false);
+
+        let outcome = wait_for_exit_or_kill(
+            || polls.fetch_add(1, Ordering::SeqCst) < 3,
+            || async {
+                killed.store(true, Ordering::SeqCst);
+                Ok::<(), ()>(())
+            },
+            Duration::from_secs(5),
+            SHUTDOWN_POLL_INTERVAL,
+        )
+        .await;
+
+        assert_eq!(outcome, Ok(ShutdownOutcome::Exited));
+        assert!(!killed.load(Ordering::SeqCst));
+    }
+}
@@ -0,0 +1,198 @@
+use std::{
+    path::{Path, PathBuf},
+    time::SystemTime,
+};
+
+use lusid_fs::{self as fs, FsError};
+use thiserror::Error;
+
+use super::paths::VmPaths;
+
+#[derive(Error, Debug)]
+pub enum VmSnapshotError {
+    #[error("invalid snapshot name '{name}': use letters, digits, '-', '_', and '.'")]
+    InvalidName { name: String },
+
+    #[error("snapshot already exists: {name}")]
+    AlreadyExists { name: String },
+
+    #[error("snapshot not found: {name}")]
+    NotFound { name: String },
+
+    #[error("vm is running: stop it first")]
+    Running,
+
+    #[error(transparent)]
+    Fs(#[from] FsError),
+}
+
+/// A saved copy of an instance's disk and firmware state.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct VmSnapshot {
+    pub name: String,
+    pub created_at: Option<SystemTime>,
+}
+
+/// Names become directory names, so keep them to a safe set of characters.
+pub(super) fn validate_snapshot_name(name: &str) -> Result<(), VmSnapshotError> {
+    let is_valid = !name.is_empty()
+        && !name.starts_with('.')
+        && name
+            .chars()
+            .all(|c| c.is_ascii_alphanumeric() || matches!(c, '-' | '_' | '.'));
+    if is_valid {
+        Ok(())
+    } else {
+        Err(VmSnapshotError::InvalidName {
+            name: name.to_string(),
+        })
+    }
+}
+
+fn snapshot_dir(paths: &VmPaths<'_>, name: &str) -> PathBuf {
+    paths.snapshots_dir().join(name)
+}
+
+/// A snapshot is written here first, so a failed copy never looks complete.
+fn partial_snapshot_dir(paths: &VmPaths<'_>, name: &str) -> PathBuf {
+    paths.snapshots_dir().join(format!(".{name}.partial"))
+}
+
+/// Snapshots in `snapshots_dir`, sorted by name.
+pub(super) async fn list_snapshots(snapshots_dir: &Path) -> Result<Vec<VmSnapshot>, FsError> {
+    if !fs::path_exists(snapshots_dir).await? {
+        return Ok(Vec::new());
+    }
+
+    let mut snapshots = Vec::new();
+    for dir in fs::read_dir(snapshots_dir).await? {
+        let Some(name) = dir.file_name().and_then(|name| name.to_str()) else {
+            continue;
+        };
+        // Skip partial snapshots.
+        if validate_snapshot_name(name).is_err() {
+            continue;
+        }
+        snapshots.push(VmSnapshot {
+            name: name.to_string(),
+            created_at: fs::metadata(&dir).await?.modified().ok(),
+        });
+    }
+
+    snapshots.sort_by(|a, b| a.name.cmp(&b.name));
+    Ok(snapshots)
+}
+
+pub(super) async fn save_snapshot(paths: &VmPaths<'_>, name: &str) -> Result<(), VmSnapshotError> {
+    validate_snapshot_name(name)?;
+    let dir = snapshot_dir(paths, name);
+    if fs::path_exists(&dir).await? {
+        return Err(VmSnapshotError::AlreadyExists {
+            name: name.to_string(),
+        });
+    }
+
+    let partial_dir = partial_snapshot_dir(paths, name);
+    if fs::path_exists(&partial_dir).await? {
+        fs::remove_dir(&partial_dir).await?;
+    }
+    fs::create_dir(&partial_dir).await?;
+    for file in paths.snapshot_files() {
+        let file_name = file.file_name().expect("snapshot files have names");
+        fs::copy_file(&file, partial_dir.join(file_name)).await?;
+    }
+    fs::rename_file(&partial_dir, &dir).await?;
+    Ok(())
+}
+
+pub(super) async fn restore_snapshot(
+    paths: &VmPaths<'_>,
+    name: &str,
+) -> Result<(), VmSnapshotError> {
+    validate_snapshot_name(name)?;
+    let dir = snapshot_dir(paths, name);
+    if !fs::path_exists(&dir).await? {
+        return Err(VmSnapshotError::NotFound {
+            name: name.to_string(),
+        });
+    }
+
+    // Copy beside each file first, so a failed copy never leaves a torn disk,
+    // then rename the copies over the files.
+    let mut restored = Vec::new();
+    for file in paths.snapshot_files() {
+        let file_name = file.file_name().expect("snapshot files have names");
+        let mut temp_file = file.clone();
+        temp_file.add_extension("restore");
+        fs::copy_file(dir.join(file_name), &temp_file).await?;
+        restored.push((temp_file, file));
+    }
+    for (temp_file, file) in restored {
+        fs::rename_file(&temp_file, &file).await?;
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn snapshot_names() {
+        assert!(validate_snapshot_name("provisioned").is_ok());
+        assert!(validate_snapshot_name("base-2025.11_01").is_ok());
+        assert!(validate_snapshot_name("").is_err());
+        assert!(validate_snapshot_name(".hidden").is_err());
+        assert!(validate_snapshot_name("../escape").is_err());
+        assert!(validate_snapshot_name("with space").is_err());
+    }
+
+    #[tokio::test]
+    async fn save_list_and_restore() {
+        let dir = tempfile::tempdir().unwrap();
+        let paths = VmPaths::new(dir.path());
+        for file in paths.snapshot_files() {
+            tokio::fs::write(&file, b"before").await.unwrap();
+        }
+
+        assert!(list_snapshots(&paths.snapshots_dir())
+            .await
+            .unwrap()
+            .is_empty());
+
+        save_snapshot(&paths, "provisioned").await.unwrap();
+        save_snapshot(&paths, "base").await.unwrap();
+        assert!(matches!(
+            save_snapshot(&paths, "base").await,
+            Err(VmSnapshotError::AlreadyExists { .. })
+        ));
+        // Left over from an interrupted save.
+        tokio::fs::create_dir(partial_snapshot_dir(&paths, "broken"))
+            .await
+            .unwrap();
+
+        let names: Vec<_> = list_snapshots(&paths.snapshots_dir())
+            .await
+            .unwrap()
+            .into_iter()
+            .map(|snapshot| snapshot.name)
+            .collect();
+        assert_eq!(names, vec!["base", "provisioned"]);
+
+        for file in paths.snapshot_files() {
+            tokio::fs::write(&file, b"after").await.unwrap();
+        }
+        restore_snapshot(&paths, "provisioned").await.unwrap();
+        for file in paths.snapshot_files() {
+            assert_eq!(tokio::fs::read(&file).await.unwrap(), b"before");
+            let mut temp_file = file.clone();
+            temp_file.add_extension("restore");
+            assert!(!temp_file.exists());
+        }
+
+        assert!(matches!(
+            restore_snapshot(&paths, "missing").await,
+            Err(VmSnapshotError::NotFound { .. })
+        ));
+    }
+}
@@ -4,8 +4,12 @@ use thiserror::Error;
 
 use crate::{
     instance::{Vm, VmPort},
+    ovmf::OvmfPaths,
     paths::ExecutablePaths,
-    qemu::{Qemu, QemuError},
+    qemu::{
+        default_machine_type, host_arch, is_kvm_available, is_kvm_supported, select_accel, Accel,
+        Qemu, QemuError,
+    },
 };
 
 #[derive(Error, Debug)]
@@ -14,10 +18,21 @@ pub enum VmStartError {
     Qemu(#[from] QemuError),
 }
 
-pub(super) async fn instance_start(
+/// How an instance is launched, and what was decided along the way.
+pub(super) struct InstanceQemu {
+    pub(super) qemu: Qemu,
+    memory_size_in_gb: u64,
+    cpu_count: CpuCount,
+    graphics: bool,
+    accel: Accel,
+}
+
+pub(super) fn instance_qemu(
     executables: &ExecutablePaths,
+    ovmf: &OvmfPaths,
     instance: &Vm,
-) -> Result<(), VmStartError> {
+    accel: Option<Accel>,
+) -> InstanceQemu {
     let Vm {
         id: _instance_id,
         dir: _instance_dir,
@@ -32,6 +47,7 @@ pub(super) async fn instance_start(
         ports,
         graphics,
         kvm,
+        machine_type,
     } = instance;
     let paths = instance.paths();
 
@@ -47,18 +63,32 @@ pub(super) async fn instance_start(
     let memory_size_in_gb: u64 = u64::from(memory_size) / 1024 / 1024 / 1024;
     let cpu_count = cpu_count.unwrap_or_else(|| CpuCount::new(2));
     let graphics = graphics.unwrap_or(true);
-    let kvm = kvm.unwrap_or(true);
+    let requested = accel.or(match kvm {
+        Some(false) => Some(Accel::Tcg),
+        _ => None,
+    });
+    let kvm_supported = is_kvm_supported(*arch, host_arch());
+    let kvm_available = is_kvm_available();
+    let accel = select_accel(requested, kvm_supported, kvm_available);
+    if accel == Accel::Tcg && requested.is_none() && (kvm_supported || *kvm == Some(true)) {
+        tracing::warn!(
+            ?arch,
+            kvm_supported,
+            kvm_available,
+            "kvm is not available, falling back to tcg"
+        );
+    }
+    let machine_type = machine_type
+        .as_deref()
+        .unwrap_or_else(|| default_machine_type(*arch));
 
-    let qemu_executable = match arch {
-        lusid_system::Arch::X86_64 => executables.qemu_x86_64(),
-        lusid_system::Arch::Aarch64 => executables.qemu_aarch64(),
-    };
-    let mut qemu = Qemu::new(qemu_executable);
+    let mut qemu = Qemu::new(executables.qemu(*arch));
 
-    qemu.easy()
+    qemu.machine_type(machine_type)
+        .easy()
         .cpu_count(cpu_count.to_string())
         .memory(memory_size_in_gb)
-        .plash_drives(paths.ovmf_code_system_path(), &paths.ovmf_vars_path());
+        .plash_drives(ovmf.code(), &paths.ovmf_vars_path());
 
     qemu.kernel(
         &paths.kernel_path(),
@@ -69,7 +99,7 @@ pub(super) async fn instance_start(
     }
 
     qemu.qmp_socket(&paths.qemu_qmp_socket_path())
-        .kvm(kvm)
+        .accel(accel)
         .pid_file(paths.qemu_pid_path())
         .graphics(graphics)
         .ports(&ports);
@@ -78,17 +108,40 @@ pub(super) async fn instance_start(
     qemu.virtio_drive("overlay-disk", "qcow2", &paths.overlay_image_path())
         .virtio_drive("cloud-init", "raw", &paths.cloud_init_image_path());
 
+    InstanceQemu {
+        qemu,
+        memory_size_in_gb,
+        cpu_count,
+        graphics,
+        accel,
+    }
+}
+
+pub(super) async fn instance_start(
+    executables: &ExecutablePaths,
+    ovmf: &OvmfPaths,
+    instance: &Vm,
+    accel: Option<Accel>,
+) -> Result<(), VmStartError> {
+    let InstanceQemu {
+        qemu,
+        memory_size_in_gb,
+        cpu_count,
+        graphics,
+        accel,
+    } = instance_qemu(executables, ovmf, instance, accel);
+
     tracing::debug!(cmd = ?qemu, "spawning QEMU");
 
     let _child = qemu.spawn().await?;
 
     tracing::info!(
-        arch=?arch,
+        arch=?instance.arch,
         memory_gb=memory_size_in_gb,
         cpus=%cpu_count,
-        ssh_port=%ssh_port,
+        ssh_port=%instance.ssh_port,
         graphics=graphics,
-        kvm=kvm,
+        accel=%accel,
         "VM process started"
     );
 
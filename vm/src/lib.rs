@@ -1,8 +1,17 @@
 mod context;
 mod image;
 mod instance;
+mod ovmf;
 mod paths;
 mod qemu;
+mod tools;
 mod utils;
 
-pub use instance::{Vm, VmError, VmOptions};
+pub use image::{
+    backing_image_files, list_cached_images, referenced_image_files, remove_cached_images,
+    select_for_removal, CachedImage, VmImageError,
+};
+pub use instance::{Vm, VmError, VmOptions, VmPort, VmSnapshot, VmSnapshotError, VmStatus};
+pub use ovmf::{OvmfNotFoundError, OvmfOverrides};
+pub use qemu::{Accel, ParseAccelError};
+pub use tools::check_required_tools;
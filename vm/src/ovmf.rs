@@ -0,0 +1,174 @@
+use std::path::{Path, PathBuf};
+
+use thiserror::Error;
+
+/// Known locations of OVMF UEFI firmware, as `(code, vars)` pairs.
+///
+/// Code and vars images must come from the same build, so they're searched as pairs.
+const OVMF_CANDIDATES: &[(&str, &str)] = &[
+    // Debian, Ubuntu
+    (
+        "/usr/share/OVMF/OVMF_CODE_4M.fd",
+        "/usr/share/OVMF/OVMF_VARS_4M.fd",
+    ),
+    (
+        "/usr/share/OVMF/OVMF_CODE.fd",
+        "/usr/share/OVMF/OVMF_VARS.fd",
+    ),
+    // Arch
+    (
+        "/usr/share/edk2/x64/OVMF_CODE.4m.fd",
+        "/usr/share/edk2/x64/OVMF_VARS.4m.fd",
+    ),
+    // Fedora
+    (
+        "/usr/share/edk2/ovmf/OVMF_CODE.fd",
+        "/usr/share/edk2/ovmf/OVMF_VARS.fd",
+    ),
+    // NixOS, Homebrew
+    (
+        "/run/libvirt/nix-ovmf/OVMF_CODE.fd",
+        "/run/libvirt/nix-ovmf/OVMF_VARS.fd",
+    ),
+];
+
+/// User-provided OVMF firmware paths, which take precedence over known locations.
+#[derive(Debug, Clone, Default)]
+pub struct OvmfOverrides {
+    pub code: Option<PathBuf>,
+    pub vars: Option<PathBuf>,
+}
+
+#[derive(Error, Debug)]
+#[error(
+    "OVMF firmware not found (install ovmf or edk2-ovmf, or set --ovmf-code and --ovmf-vars), searched: {}",
+    searched.iter().map(|path| path.display().to_string()).collect::<Vec<_>>().join(", ")
+)]
+pub struct OvmfNotFoundError {
+    pub searched: Vec<PathBuf>,
+}
+
+/// System OVMF UEFI firmware images used to boot vms.
+#[derive(Debug, Clone)]
+pub struct OvmfPaths {
+    code: PathBuf,
+    vars: PathBuf,
+}
+
+impl OvmfPaths {
+    pub fn resolve(overrides: &OvmfOverrides) -> Result<Self, OvmfNotFoundError> {
+        Self::resolve_with(overrides, Path::exists)
+    }
+
+    fn resolve_with(
+        overrides: &OvmfOverrides,
+        exists: impl Fn(&Path) -> bool,
+    ) -> Result<Self, OvmfNotFoundError> {
+        let mut searched = Vec::new();
+
+        for (code, vars) in OVMF_CANDIDATES {
+            let code = overrides
+                .code
+                .clone()
+                .unwrap_or_else(|| PathBuf::from(code));
+            let vars = overrides
+                .vars
+                .clone()
+                .unwrap_or_else(|| PathBuf::from(vars));
+
+            let code_exists = exists(&code);
+            let vars_exists = exists(&vars);
+            if code_exists && vars_exists {
+                return Ok(Self { code, vars });
+            }
+
+            for (path, path_exists) in [(code, code_exists), (vars, vars_exists)] {
+                if !path_exists && !searched.contains(&path) {
+                    searched.push(path);
+                }
+            }
+        }
+
+        Err(OvmfNotFoundError { searched })
+    }
+
+    pub fn code(&self) -> &Path {
+        &self.code
+    }
+
+    pub fn vars(&self) -> &Path {
+        &self.vars
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::HashSet;
+
+    use super::*;
+
+    fn fake_fs(paths: &[&str]) -> impl Fn(&Path) -> bool {
+        let paths: HashSet<PathBuf> = paths.iter().map(PathBuf::from).collect();
+        move |path: &Path| paths.contains(path)
+    }
+
+    #[test]
+    fn resolve_prefers_earlier_candidates() {
+        let exists = fake_fs(&[
+            "/usr/share/OVMF/OVMF_CODE.fd",
+            "/usr/share/OVMF/OVMF_VARS.fd",
+            "/usr/share/edk2/x64/OVMF_CODE.4m.fd",
+            "/usr/share/edk2/x64/OVMF_VARS.4m.fd",
+        ]);
+
+        let ovmf = OvmfPaths::resolve_with(&OvmfOverrides::default(), exists).unwrap();
+
+        assert_eq!(ovmf.code(), Path::new("/usr/share/OVMF/OVMF_CODE.fd"));
+        assert_eq!(ovmf.vars(), Path::new("/usr/share/OVMF/OVMF_VARS.fd"));
+    }
+
+    #[test]
+    fn resolve_skips_incomplete_pairs() {
+        let exists = fake_fs(&[
+            "/usr/share/OVMF/OVMF_CODE_4M.fd",
+            "/usr/share/edk2/ovmf/OVMF_CODE.fd",
+            "/usr/share/edk2/ovmf/OVMF_VARS.fd",
+        ]);
+
+        let ovmf = OvmfPaths::resolve_with(&OvmfOverrides::default(), exists).unwrap();
+
+        assert_eq!(ovmf.vars(), Path::new("/usr/share/edk2/ovmf/OVMF_VARS.fd"));
+    }
+
+    #[test]
+    fn resolve_with_override() {
+        let exists = fake_fs(&[
+            "/opt/ovmf/VARS.fd",
+            "/usr/share/edk2/x64/OVMF_CODE.4m.fd",
+            "/usr/share/edk2/x64/OVMF_VARS.4m.fd",
+        ]);
+        let overrides = OvmfOverrides {
+            code: None,
+            vars: Some(PathBuf::from("/opt/ovmf/VARS.fd")),
+        };
+
+        let ovmf = OvmfPaths::resolve_with(&overrides, exists).unwrap();
+
+        assert_eq!(
+            ovmf.code(),
+            Path::new("/usr/share/edk2/x64/OVMF_CODE.4m.fd")
+        );
+        assert_eq!(ovmf.vars(), Path::new("/opt/ovmf/VARS.fd"));
+    }
+
+    #[test]
+    fn resolve_not_found_lists_searched() {
+        let error = OvmfPaths::resolve_with(&OvmfOverrides::default(), fake_fs(&[])).unwrap_err();
+
+        assert_eq!(error.searched.len(), OVMF_CANDIDATES.len() * 2);
+        assert_eq!(
+            error.searched[0],
+            PathBuf::from("/usr/share/OVMF/OVMF_CODE_4M.fd")
+        );
+    }
+}
@@ -1,8 +1,11 @@
 use lusid_ctx::Paths as BasePaths;
+use lusid_system::Arch;
 use std::path::{Path, PathBuf};
 use thiserror::Error;
 use which::which_global;
 
+use crate::qemu::qemu_binary_for;
+
 #[derive(Debug, Clone)]
 pub struct Paths {
     base: BasePaths,
@@ -46,8 +49,8 @@ pub struct ExecutablePaths {
 impl ExecutablePaths {
     pub fn new() -> Result<ExecutablePaths, ExecutablePathsError> {
         let virt_get_kernel = which_global("virt-get-kernel")?;
-        let qemu_x86_64 = which_global("qemu-system-x86_64")?;
-        let qemu_aarch64 = which_global("qemu-system-aarch64")?;
+        let qemu_x86_64 = which_global(qemu_binary_for(Arch::X86_64))?;
+        let qemu_aarch64 = which_global(qemu_binary_for(Arch::Aarch64))?;
         let qemu_img = which_global("qemu-img")?;
         let mkisofs = which_global("mkisofs")?;
 
@@ -64,12 +67,11 @@ impl ExecutablePaths {
         &self.virt_get_kernel
     }
 
-    pub fn qemu_x86_64(&self) -> &Path {
-        &self.qemu_x86_64
-    }
-
-    pub fn qemu_aarch64(&self) -> &Path {
-        &self.qemu_aarch64
+    pub fn qemu(&self, arch: Arch) -> &Path {
+        match arch {
+            Arch::X86_64 => &self.qemu_x86_64,
+            Arch::Aarch64 => &self.qemu_aarch64,
+        }
     }
 
     pub fn qemu_img(&self) -> &Path {
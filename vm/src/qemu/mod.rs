@@ -1,10 +1,94 @@
-use std::fmt::{Debug, Write};
-use std::{ffi::OsStr, net::Ipv4Addr, path::Path};
+use lusid_system::Arch;
+use std::fmt::{Debug, Display, Write};
+use std::{env, ffi::OsStr, net::Ipv4Addr, path::Path, str::FromStr};
 use thiserror::Error;
 use tokio::process::{Child, Command};
 
 use crate::instance::VmPort;
 
+/// Name of the QEMU system emulator binary for a guest architecture.
+pub fn qemu_binary_for(arch: Arch) -> &'static str {
+    match arch {
+        Arch::X86_64 => "qemu-system-x86_64",
+        Arch::Aarch64 => "qemu-system-aarch64",
+    }
+}
+
+/// Architecture of the machine we're running on, if it's one we support.
+pub fn host_arch() -> Option<Arch> {
+    match env::consts::ARCH {
+        "x86_64" => Some(Arch::X86_64),
+        "aarch64" => Some(Arch::Aarch64),
+        _ => None,
+    }
+}
+
+/// KVM can only accelerate guests of the same architecture as the host.
+pub fn is_kvm_supported(guest: Arch, host: Option<Arch>) -> bool {
+    host == Some(guest)
+}
+
+/// Whether this host lets us use KVM at all: often not in CI or containers.
+pub fn is_kvm_available() -> bool {
+    std::fs::OpenOptions::new()
+        .read(true)
+        .write(true)
+        .open("/dev/kvm")
+        .is_ok()
+}
+
+/// Default QEMU machine type for a guest architecture.
+pub fn default_machine_type(arch: Arch) -> &'static str {
+    match arch {
+        Arch::X86_64 => "q35",
+        Arch::Aarch64 => "virt",
+    }
+}
+
+/// How QEMU runs guest code.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Accel {
+    /// Hardware virtualization, for guests native to the host.
+    Kvm,
+    /// Software emulation: slow, but works anywhere.
+    Tcg,
+}
+
+impl Display for Accel {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Accel::Kvm => write!(f, "kvm"),
+            Accel::Tcg => write!(f, "tcg"),
+        }
+    }
+}
+
+#[derive(Error, Debug)]
+#[error("unknown accelerator '{0}', expected kvm or tcg")]
+pub struct ParseAccelError(String);
+
+impl FromStr for Accel {
+    type Err = ParseAccelError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "kvm" => Ok(Accel::Kvm),
+            "tcg" => Ok(Accel::Tcg),
+            _ => Err(ParseAccelError(s.to_string())),
+        }
+    }
+}
+
+/// Pick an accelerator: `requested` if given, otherwise KVM when it's both
+/// supported for this guest and available on this host, otherwise TCG.
+pub fn select_accel(requested: Option<Accel>, kvm_supported: bool, kvm_available: bool) -> Accel {
+    match requested {
+        Some(accel) => accel,
+        None if kvm_supported && kvm_available => Accel::Kvm,
+        None => Accel::Tcg,
+    }
+}
+
 #[derive(Error, Debug)]
 pub enum QemuError {
     #[error(transparent)]
@@ -39,11 +123,18 @@ impl Qemu {
         self
     }
 
-    // Enable KVM accelerator.
-    pub fn kvm(&mut self, enabled: bool) -> &mut Self {
-        if enabled {
-            self.command.args(["-accel", "kvm"]).args(["-cpu", "host"]);
-        }
+    /// Set accelerator: -accel <kvm|tcg>, with the best CPU model for it.
+    pub fn accel(&mut self, accel: Accel) -> &mut Self {
+        match accel {
+            Accel::Kvm => self.command.args(["-accel", "kvm"]).args(["-cpu", "host"]),
+            Accel::Tcg => self.command.args(["-accel", "tcg"]).args(["-cpu", "max"]),
+        };
+        self
+    }
+
+    /// Set machine type: -machine <type>, such as q35 or virt.
+    pub fn machine_type(&mut self, machine_type: &str) -> &mut Self {
+        self.command.args(["-machine", machine_type]);
         self
     }
 
@@ -157,6 +248,13 @@ impl Qemu {
         self
     }
 
+    /// The command line as it would run, for reproducing a launch by hand.
+    ///
+    /// Leaves out `-daemonize`, which only [`Qemu::spawn`] adds.
+    pub fn to_command_string(&self) -> String {
+        format!("{:?}", self.command.as_std())
+    }
+
     pub async fn spawn(self) -> Result<Child, QemuError> {
         let mut command = self.command;
 
@@ -167,3 +265,56 @@ impl Qemu {
         Ok(child)
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn command_string_has_launch_args() {
+        let mut qemu = Qemu::new("qemu-system-x86_64");
+        qemu.easy()
+            .cpu_count(2)
+            .memory(8)
+            .kernel(Path::new("/vm/vmlinuz"), Some("rw root=/dev/vda1"))
+            .ports(&[VmPort {
+                host_ip: Some(Ipv4Addr::LOCALHOST),
+                host_port: Some(2222),
+                vm_port: 22,
+            }]);
+
+        let command = qemu.to_command_string();
+        assert!(command.starts_with("\"qemu-system-x86_64\""));
+        assert!(command.contains("\"-kernel\" \"/vm/vmlinuz\""));
+        assert!(command.contains("\"-append\" \"rw root=/dev/vda1\""));
+        assert!(command.contains("\"-m\" \"8G\""));
+        assert!(command.contains("hostfwd=:127.0.0.1:2222-:22"));
+        assert!(!command.contains("-daemonize"));
+    }
+
+    #[test]
+    fn accel_falls_back_to_tcg() {
+        assert_eq!(select_accel(None, true, true), Accel::Kvm);
+        assert_eq!(select_accel(None, true, false), Accel::Tcg);
+        assert_eq!(select_accel(None, false, true), Accel::Tcg);
+        assert_eq!(select_accel(Some(Accel::Tcg), true, true), Accel::Tcg);
+        assert_eq!(select_accel(Some(Accel::Kvm), true, false), Accel::Kvm);
+        assert_eq!("tcg".parse::<Accel>().unwrap(), Accel::Tcg);
+        assert!("hvf".parse::<Accel>().is_err());
+    }
+
+    #[test]
+    fn binary_for_arch() {
+        assert_eq!(qemu_binary_for(Arch::X86_64), "qemu-system-x86_64");
+        assert_eq!(qemu_binary_for(Arch::Aarch64), "qemu-system-aarch64");
+    }
+
+    #[test]
+    fn kvm_only_for_native_arch() {
+        assert!(is_kvm_supported(Arch::X86_64, Some(Arch::X86_64)));
+        assert!(is_kvm_supported(Arch::Aarch64, Some(Arch::Aarch64)));
+        assert!(!is_kvm_supported(Arch::Aarch64, Some(Arch::X86_64)));
+        assert!(!is_kvm_supported(Arch::X86_64, Some(Arch::Aarch64)));
+        assert!(!is_kvm_supported(Arch::X86_64, None));
+    }
+}
@@ -0,0 +1,61 @@
+use std::{env, ffi::OsStr, path::Path};
+
+use which::which_in;
+
+use crate::instance::VmError;
+
+/// Executables needed to set up and run a vm, with the package that provides each.
+const REQUIRED_TOOLS: &[(&str, &str)] = &[
+    ("virt-get-kernel", "guestfs-tools"),
+    ("qemu-system-x86_64", "qemu-system-x86"),
+    ("qemu-system-aarch64", "qemu-system-arm"),
+    ("qemu-img", "qemu-utils"),
+    ("mkisofs", "genisoimage"),
+];
+
+/// Check every tool needed to run a vm is on `PATH`, before doing any work.
+pub fn check_required_tools() -> Result<(), VmError> {
+    let cwd = env::current_dir().unwrap_or_default();
+    check_required_tools_in(env::var_os("PATH"), &cwd)
+}
+
+fn check_required_tools_in<P: AsRef<OsStr>>(paths: Option<P>, cwd: &Path) -> Result<(), VmError> {
+    let paths: Option<&OsStr> = paths.as_ref().map(|paths| paths.as_ref());
+    for (name, package) in REQUIRED_TOOLS {
+        if which_in(name, paths, cwd).is_err() {
+            return Err(VmError::MissingTool {
+                name: name.to_string(),
+                package: package.to_string(),
+            });
+        }
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{fs, os::unix::fs::PermissionsExt};
+
+    use super::*;
+
+    #[test]
+    fn missing_tool() {
+        let dir = tempfile::tempdir().unwrap();
+        for (name, _) in REQUIRED_TOOLS {
+            if *name == "qemu-img" {
+                continue;
+            }
+            let path = dir.path().join(name);
+            fs::write(&path, "#!/bin/sh\n").unwrap();
+            fs::set_permissions(&path, fs::Permissions::from_mode(0o755)).unwrap();
+        }
+
+        let result = check_required_tools_in(Some(dir.path()), dir.path());
+
+        assert!(matches!(
+            result,
+            Err(VmError::MissingTool { name, package })
+                if name == "qemu-img" && package == "qemu-utils"
+        ));
+    }
+}